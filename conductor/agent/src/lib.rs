@@ -80,6 +80,13 @@ fn message_event(engine: &str, text: &str) -> Value {
     agent_event(engine, "message", payload)
 }
 
+// Tools the agent wanted but was not allowed to run without the user's go-ahead
+fn approval_event(engine: &str, tools: Vec<Value>) -> Value {
+    let mut payload = Map::new();
+    payload.insert("tools".to_string(), Value::Array(tools));
+    agent_event(engine, "approval", payload)
+}
+
 fn completed_event(engine: &str, ok: bool, answer: &str, resume: Option<&str>, error: Option<&str>, usage: Option<Value>) -> Value {
     let mut payload = Map::new();
     payload.insert("ok".to_string(), Value::Bool(ok));
@@ -588,7 +595,22 @@ fn parse_claude_event(value: &Value, state: &mut ClaudeState) -> Option<Vec<Valu
             let usage = value.get("usage").cloned();
             let error = if ok { None } else { Some(answer) };
             let resume = state.resume.as_deref();
-            Some(vec![completed_event("claude", ok, answer, resume, error, usage)])
+            let mut events = Vec::new();
+            let denied: Vec<Value> = value
+                .get("permission_denials")
+                .and_then(Value::as_array)
+                .map(|denials| {
+                    denials
+                        .iter()
+                        .filter_map(|denial| denial.get("tool_name").cloned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if !denied.is_empty() {
+                events.push(approval_event("claude", denied));
+            }
+            events.push(completed_event("claude", ok, answer, resume, error, usage));
+            Some(events)
         }
         _ => None,
     }
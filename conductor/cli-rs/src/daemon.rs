@@ -0,0 +1,528 @@
+//! Commands that talk to a running conductor-daemon
+
+use anyhow::{anyhow, bail, Result};
+use conductor_core as core;
+use conductor_daemon::auth::DaemonClient;
+use conductor_daemon::client;
+use conductor_daemon::{
+    AgentEvent, AttachAgentRequest, GetStatusRequest, GetWorkspaceStatusRequest,
+    ListActiveAgentsRequest, ListWorkspacesRequest, RunAgentRequest, StopAgentRequest, Workspace,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use tonic::Code;
+use uuid::Uuid;
+
+use crate::format::Format;
+use crate::print_json_value;
+
+// The CLI is synchronous; daemon calls get a throwaway single-threaded runtime
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    Ok(runtime.block_on(future))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// `conductor status`: daemon health when it is reachable, local home stats otherwise
+pub fn status(home: &Path, format: Format) -> Result<()> {
+    let remote = block_on(async {
+        let mut client = client::connect(home).await?;
+        let status = client.get_status(GetStatusRequest {}).await?.into_inner();
+        anyhow::Ok(status)
+    })?;
+
+    let status = match remote {
+        Ok(status) => status,
+        Err(err) => {
+            let conn = core::connect(home)?;
+            let local = core::home_status(&conn, home)?;
+            if format.is_structured() {
+                return format.value(&json!({
+                    "daemon": { "running": false, "error": format!("{err:#}") },
+                    "home": local,
+                }));
+            }
+            println!("daemon\tunavailable: {err:#}");
+            println!("home\t{}", local.home);
+            println!("db\t{} (schema {})", local.db_path, local.schema_version);
+            println!("disk\t{}", format_bytes(local.disk_bytes));
+            println!("repos\t{}", local.repo_count);
+            println!(
+                "workspaces\t{} ({} archived)",
+                local.workspace_count, local.archived_workspace_count
+            );
+            return Ok(());
+        }
+    };
+
+    if format.is_structured() {
+        let agents: Vec<_> = status
+            .agents
+            .iter()
+            .map(|a| {
+                json!({
+                    "session_id": a.session_id,
+                    "engine": a.engine,
+                    "cwd": a.cwd,
+                    "elapsed_secs": a.elapsed_secs,
+                })
+            })
+            .collect();
+        let errors: Vec<_> = status
+            .recent_errors
+            .iter()
+            .map(|e| json!({ "timestamp": e.timestamp, "context": e.context, "message": e.message }))
+            .collect();
+        return format.value(&json!({
+            "daemon": {
+                "running": true,
+                "version": status.version,
+                "uptime_secs": status.uptime_secs,
+                "served_homes": status.served_homes,
+            },
+            "home": {
+                "home": status.home,
+                "db_path": status.db_path,
+                "schema_version": status.schema_version,
+                "disk_bytes": status.home_disk_bytes,
+                "repo_count": status.repo_count,
+                "workspace_count": status.workspace_count,
+                "archived_workspace_count": status.archived_workspace_count,
+            },
+            "agents": agents,
+            "recent_errors": errors,
+        }));
+    }
+
+    println!("daemon\tv{} (up {}s)", status.version, status.uptime_secs);
+    println!("home\t{}", status.home);
+    // Only worth a line when the daemon serves more than the home asked about
+    if status.served_homes.len() > 1 {
+        println!("served\t{}", status.served_homes.join(", "));
+    }
+    println!("db\t{} (schema {})", status.db_path, status.schema_version);
+    println!("disk\t{}", format_bytes(status.home_disk_bytes));
+    println!("repos\t{}", status.repo_count);
+    println!(
+        "workspaces\t{} ({} archived)",
+        status.workspace_count, status.archived_workspace_count
+    );
+    println!("agents\t{}", status.agents.len());
+    for agent in &status.agents {
+        println!(
+            "  {}\t{}\t{}s\t{}",
+            agent.session_id, agent.engine, agent.elapsed_secs, agent.cwd
+        );
+    }
+    println!("errors\t{}", status.recent_errors.len());
+    for error in &status.recent_errors {
+        println!("  {}\t{}\t{}", error.timestamp, error.context, error.message);
+    }
+    Ok(())
+}
+
+// Same matching as core's workspace refs (id, then unique id prefix), plus unique directory name
+async fn resolve_workspace(client: &mut DaemonClient, ws_ref: &str) -> Result<Workspace> {
+    let workspaces = client
+        .list_workspaces(ListWorkspacesRequest {
+            repo_id: None,
+            enriched: false,
+            sort: String::new(),
+            states: Vec::new(),
+            stale: None,
+        })
+        .await?
+        .into_inner()
+        .workspaces;
+    if let Some(ws) = workspaces.iter().find(|ws| ws.id == ws_ref) {
+        return Ok(ws.clone());
+    }
+    for matches in [
+        |ws: &Workspace, r: &str| ws.id.starts_with(r),
+        |ws: &Workspace, r: &str| ws.directory_name == r,
+    ] {
+        let found: Vec<_> = workspaces.iter().filter(|ws| matches(ws, ws_ref)).collect();
+        match found.as_slice() {
+            [ws] => return Ok((*ws).clone()),
+            [] => continue,
+            _ => bail!("ambiguous workspace reference: {ws_ref}"),
+        }
+    }
+    Err(anyhow!("workspace not found: {ws_ref}"))
+}
+
+/// One line per normalized agent.* event
+pub fn render_event(event: &Value) {
+    let str_field = |key: &str| event.get(key).and_then(Value::as_str).unwrap_or("");
+    match str_field("type") {
+        "agent.started" => println!("▸ {} session {}", str_field("engine"), str_field("resume")),
+        "agent.message" => println!("{}", str_field("text")),
+        "agent.action" => {
+            let title = event
+                .pointer("/action/title")
+                .and_then(Value::as_str)
+                .unwrap_or("action");
+            match (str_field("phase"), event.get("ok").and_then(Value::as_bool)) {
+                ("started", _) => println!("  ▸ {title}"),
+                (_, Some(false)) => println!("  ✗ {title}"),
+                ("completed", _) => println!("  ✓ {title}"),
+                _ => {}
+            }
+        }
+        "agent.fallback" => {
+            let (from, to) = (str_field("from"), str_field("engine"));
+            println!("  ⚠ {from} failed ({}); falling back to {to}", str_field("error"));
+        }
+        "agent.policy_violation" => {
+            let verb = if str_field("decision") == "block" { "blocked" } else { "flagged" };
+            println!("  ⚠ {verb} by policy {}: {}", str_field("rule"), str_field("command"));
+        }
+        "checkpoint" => {
+            let field = |key: &str| event.pointer(&format!("/checkpoint/{key}")).cloned().unwrap_or_default();
+            println!("  ◆ checkpoint {}: {}", field("id"), field("label").as_str().unwrap_or(""));
+        }
+        "agent.diffstat" => {
+            let count = |key: &str| event.get(key).and_then(Value::as_u64).unwrap_or(0);
+            let files = count("files");
+            let plural = if files == 1 { "" } else { "s" };
+            let untracked = event.get("untracked").and_then(Value::as_array).map_or(0, Vec::len);
+            let new = if untracked > 0 { format!(" ({untracked} new)") } else { String::new() };
+            println!("  Δ {files} file{plural} changed, +{} -{}{new}", count("insertions"), count("deletions"));
+        }
+        "verification" => match (str_field("phase"), event.get("ok").and_then(Value::as_bool)) {
+            ("started", _) => println!("  ▸ verify: {}", str_field("command")),
+            ("output", _) => println!("    {}", str_field("line")),
+            ("completed", Some(true)) => println!("  ✓ verify: {}", str_field("command")),
+            ("completed", _) => {
+                let why = match event.get("exit_code").and_then(Value::as_i64) {
+                    Some(code) => format!("exit {code}"),
+                    None => event.get("error").and_then(Value::as_str).unwrap_or("killed").to_string(),
+                };
+                println!("  ✗ verify: {} ({why})", str_field("command"));
+            }
+            _ => {}
+        },
+        "agent.completed" if event.get("ok").and_then(Value::as_bool) == Some(false) => {
+            println!("✗ {}", str_field("error"));
+        }
+        _ => {}
+    }
+}
+
+// What a client has seen of one agent session's event stream
+#[derive(Default)]
+struct RunOutcome {
+    last_seq: u64,
+    messages: Vec<String>,
+    answer: Option<String>,
+    agent_ok: Option<bool>,
+    exit_code: Option<i32>,
+}
+
+impl RunOutcome {
+    /// Print (unless `echo` is None) and record one daemon event; `echo` picks JSON lines
+    /// over rendered text. Returns true once the session has completed.
+    /// Events at or below the last seen seq (replays after a reconnect) are skipped.
+    fn observe(&mut self, event: &AgentEvent, echo: Option<bool>) -> Result<bool> {
+        if event.seq != 0 && event.seq <= self.last_seq {
+            return Ok(false);
+        }
+        self.last_seq = event.seq;
+        let payload: Value = serde_json::from_str(&event.payload).unwrap_or(Value::Null);
+
+        match echo {
+            Some(true) => {
+                let mut line = json!({ "session_id": event.session_id, "type": event.event_type });
+                if let (Value::Object(line), Value::Object(payload)) = (&mut line, payload.clone()) {
+                    line.extend(payload);
+                }
+                print_json_value(&line)?;
+            }
+            Some(false) if event.event_type == "event" => render_event(&payload),
+            _ => {}
+        }
+
+        match event.event_type.as_str() {
+            "event" => match payload.get("type").and_then(Value::as_str) {
+                Some("agent.message") => {
+                    if let Some(text) = payload.get("text").and_then(Value::as_str) {
+                        self.messages.push(text.to_string());
+                    }
+                }
+                // What the failed engine said isn't the run's reply
+                Some("agent.fallback") => {
+                    self.messages.clear();
+                    self.agent_ok = None;
+                    self.answer = None;
+                }
+                Some("agent.completed") => {
+                    self.agent_ok = payload.get("ok").and_then(Value::as_bool);
+                    self.answer = payload
+                        .get("answer")
+                        .and_then(Value::as_str)
+                        .filter(|a| !a.is_empty())
+                        .map(str::to_string);
+                }
+                _ => {}
+            },
+            "completed" => {
+                self.exit_code = payload
+                    .get("exit_code")
+                    .and_then(Value::as_i64)
+                    .map(|code| code as i32);
+                // Killed by a signal (e.g. a blocked command) has no code but still failed
+                if self.exit_code.is_none() && payload.get("success").and_then(Value::as_bool) == Some(false) {
+                    self.exit_code = Some(1);
+                }
+                if payload.get("stopped").is_some() {
+                    self.exit_code = Some(130);
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn reply(&self) -> String {
+        self.answer
+            .clone()
+            .unwrap_or_else(|| self.messages.join("\n\n"))
+    }
+
+    // Prefer the process status; fall back to what the agent itself reported
+    fn exit_code(&self) -> i32 {
+        self.exit_code.unwrap_or(match self.agent_ok {
+            Some(false) => 1,
+            _ => 0,
+        })
+    }
+}
+
+/// `conductor agent run`: stream a daemon-run agent to the terminal and return its
+/// exit code (the daemon records the exchange in the workspace chat)
+/// `run` names the engine, prompt, isolation, resume id and attachments; the
+/// workspace's path and a new session id are filled in
+pub fn agent_run(home: &Path, as_json: bool, workspace: &str, run: RunAgentRequest) -> Result<i32> {
+    let (_, outcome) = block_on(drive_agent(home, Some(as_json), workspace, run))??;
+    Ok(outcome.exit_code())
+}
+
+/// How a run driven without a terminal ended (MCP tool calls)
+#[derive(Debug, Serialize)]
+pub struct AgentReply {
+    pub session_id: String,
+    pub exit_code: i32,
+    pub reply: String,
+}
+
+/// Like `agent run`, but silent: waits for the agent and returns its reply
+pub fn agent_run_captured(home: &Path, workspace: &str, engine: &str, isolation: &str, prompt: &str) -> Result<AgentReply> {
+    let run = RunAgentRequest {
+        engine: engine.to_string(),
+        prompt: prompt.to_string(),
+        isolation: isolation.to_string(),
+        ..Default::default()
+    };
+    let (session_id, outcome) = block_on(drive_agent(home, None, workspace, run))??;
+    Ok(AgentReply {
+        session_id,
+        exit_code: outcome.exit_code(),
+        reply: outcome.reply(),
+    })
+}
+
+// Run an agent in a workspace to completion. With `echo` set, events are printed and Ctrl-C stops the agent.
+async fn drive_agent(
+    home: &Path,
+    echo: Option<bool>,
+    workspace: &str,
+    run: RunAgentRequest,
+) -> Result<(String, RunOutcome)> {
+    let mut client = client::connect(home).await?;
+    let ws = resolve_workspace(&mut client, workspace).await?;
+    let session_id = Uuid::new_v4().to_string();
+
+    let mut stream = client
+        .run_agent(RunAgentRequest {
+            cwd: ws.path.clone(),
+            session_id: session_id.clone(),
+            ..run
+        })
+        .await?
+        .into_inner();
+
+    let mut outcome = RunOutcome::default();
+    let mut stopping = echo.is_none();
+
+    loop {
+        let event = tokio::select! {
+            event = stream.message() => event?,
+            // First Ctrl-C stops the agent; keep reading until the daemon reports completion
+            _ = tokio::signal::ctrl_c(), if !stopping => {
+                stopping = true;
+                eprintln!("stopping agent {session_id}...");
+                client
+                    .stop_agent(StopAgentRequest { session_id: session_id.clone() })
+                    .await?;
+                continue;
+            }
+        };
+        let Some(event) = event else { break };
+        if outcome.observe(&event, echo)? {
+            break;
+        }
+    }
+
+    Ok((session_id, outcome))
+}
+
+/// Refuse `action` while a daemon serves `home`: it holds the DB open and would keep
+/// working on what the action changes
+pub fn ensure_stopped(home: &Path, action: &str) -> Result<()> {
+    if block_on(async { client::connect(home).await.is_ok() })? {
+        bail!("cannot {action} while the daemon is running; stop it first");
+    }
+    Ok(())
+}
+
+/// Honour the daemon's soft lock on a workspace before changing it behind the daemon's
+/// back: refuse `action` while a run holds it unless forced. Forcing with `stop_holders`
+/// stops those runs first. Nothing can hold the lock when the daemon isn't running.
+pub fn check_workspace_lock(
+    home: &Path,
+    workspace: &str,
+    action: &str,
+    force: bool,
+    stop_holders: bool,
+) -> Result<()> {
+    block_on(async {
+        let Ok(mut client) = client::connect(home).await else {
+            return Ok(());
+        };
+        loop {
+            let status = client
+                .get_workspace_status(GetWorkspaceStatusRequest {
+                    workspace_id: workspace.to_string(),
+                    refresh_pr: false,
+                })
+                .await?
+                .into_inner();
+            let Some(lock) = status.lock else {
+                return Ok(());
+            };
+            if !force {
+                bail!(
+                    "cannot {action}: agent session {} ({}) has been running in the workspace for {}s; stop it or pass --force",
+                    lock.session_id,
+                    lock.engine,
+                    lock.held_secs
+                );
+            }
+            if !stop_holders {
+                return Ok(());
+            }
+            eprintln!("stopping agent {}...", lock.session_id);
+            client
+                .stop_agent(StopAgentRequest { session_id: lock.session_id })
+                .await?;
+        }
+    })?
+}
+
+/// `conductor agent list`: sessions currently running in the daemon
+pub fn agent_list(home: &Path, format: Format) -> Result<()> {
+    let agents = block_on(async {
+        let mut client = client::connect(home).await?;
+        let response = client.list_active_agents(ListActiveAgentsRequest {}).await?;
+        anyhow::Ok(response.into_inner().agents)
+    })??;
+
+    let agents: Vec<_> = agents
+        .iter()
+        .map(|a| {
+            json!({
+                "session_id": a.session_id,
+                "engine": a.engine,
+                "cwd": a.cwd,
+                "elapsed_secs": a.started_at.parse::<u64>().ok(),
+            })
+        })
+        .collect();
+    format.list(&agents, &["session_id", "engine", "elapsed_secs", "cwd"])
+}
+
+/// `conductor agent attach`: tail a running session (after replaying its history).
+/// With `follow`, a dropped connection (e.g. daemon restart) is retried until the
+/// session completes or the daemon no longer knows it.
+pub fn agent_attach(
+    home: &Path,
+    as_json: bool,
+    session_id: &str,
+    replay: bool,
+    follow: bool,
+) -> Result<i32> {
+    block_on(async {
+        let mut outcome = RunOutcome::default();
+        let mut replay = replay;
+        loop {
+            let mut client = match client::connect(home).await {
+                Ok(client) => client,
+                Err(err) if follow => {
+                    eprintln!("waiting for daemon: {err:#}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let request = AttachAgentRequest {
+                session_id: session_id.to_string(),
+                replay,
+            };
+            let mut stream = match client.attach_agent(request).await {
+                Ok(response) => response.into_inner(),
+                Err(status) if status.code() == Code::NotFound => {
+                    bail!("agent session {session_id} is not running")
+                }
+                Err(status) if follow => {
+                    eprintln!("attach failed: {}", status.message());
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                Err(status) => return Err(status.into()),
+            };
+
+            while let Ok(Some(event)) = stream.message().await {
+                if outcome.observe(&event, Some(as_json))? {
+                    return Ok(outcome.exit_code());
+                }
+            }
+            if !follow {
+                bail!("lost connection to agent session {session_id}");
+            }
+            // Replay on reconnect to catch up; already-seen seqs are skipped
+            eprintln!("connection lost; reconnecting...");
+            replay = true;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    })?
+}
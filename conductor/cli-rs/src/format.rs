@@ -0,0 +1,204 @@
+//! `--format` output layer: commands hand their data here instead of printing directly
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for reading
+    #[default]
+    Table,
+    /// A single JSON document
+    Json,
+    /// One JSON value per line (list items are split out)
+    Jsonl,
+    Yaml,
+    /// Tab-separated with a header row
+    Tsv,
+}
+
+impl Format {
+    /// Machine-readable formats; streaming commands emit JSON lines for all of them
+    pub fn is_structured(self) -> bool {
+        matches!(self, Format::Json | Format::Jsonl | Format::Yaml)
+    }
+
+    /// A list of records; `columns` picks and orders the fields shown by table/tsv.
+    /// Empty lists print nothing in table/tsv.
+    pub fn list<T: Serialize>(self, items: &[T], columns: &[&str]) -> Result<()> {
+        let values = items
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        match self {
+            Format::Table | Format::Tsv => {
+                if values.is_empty() {
+                    return Ok(());
+                }
+                let rows: Vec<Vec<String>> = values
+                    .iter()
+                    .map(|value| columns.iter().map(|column| cell(value.get(column))).collect())
+                    .collect();
+                print_rows(self, columns, &rows);
+            }
+            Format::Jsonl => {
+                for value in &values {
+                    println!("{}", serde_json::to_string(value)?);
+                }
+            }
+            Format::Json | Format::Yaml => self.value(&Value::Array(values))?,
+        }
+        Ok(())
+    }
+
+    /// A single record, shown as a one-row table
+    pub fn record<T: Serialize>(self, item: &T, columns: &[&str]) -> Result<()> {
+        if self.is_structured() {
+            return self.value(&serde_json::to_value(item)?);
+        }
+        self.list(std::slice::from_ref(item), columns)
+    }
+
+    /// Plain lines for table/tsv (file lists and the like)
+    pub fn lines(self, items: &[String]) -> Result<()> {
+        if self.is_structured() {
+            return self.list(items, &[]);
+        }
+        for item in items {
+            println!("{item}");
+        }
+        Ok(())
+    }
+
+    /// Data whose human form is free text (file contents, paths, tokens)
+    pub fn text<T: Serialize>(self, value: &T, text: &str) -> Result<()> {
+        if self.is_structured() {
+            return self.value(&serde_json::to_value(value)?);
+        }
+        println!("{text}");
+        Ok(())
+    }
+
+    /// One value as JSON (a single line) or YAML; other formats fall back to JSON
+    pub fn value(self, value: &Value) -> Result<()> {
+        if self == Format::Yaml {
+            print!("{}", to_yaml(value));
+        } else {
+            println!("{}", serde_json::to_string(value)?);
+        }
+        Ok(())
+    }
+}
+
+fn cell(value: Option<&Value>) -> String {
+    let text = match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    };
+    // Keep one record per line whatever the content
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+fn print_rows(format: Format, header: &[&str], rows: &[Vec<String>]) {
+    if format == Format::Tsv {
+        println!("{}", header.join("\t"));
+        for row in rows {
+            println!("{}", row.join("\t"));
+        }
+        return;
+    }
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let render = |cells: Vec<&str>| {
+        let last = cells.len().saturating_sub(1);
+        let mut line = String::new();
+        for (i, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+            line.push_str(cell);
+            if i < last {
+                let pad = width - cell.chars().count() + 2;
+                line.extend(std::iter::repeat_n(' ', pad));
+            }
+        }
+        line
+    };
+    println!("{}", render(header.to_vec()));
+    for row in rows {
+        println!("{}", render(row.iter().map(String::as_str).collect()));
+    }
+}
+
+// Block-style YAML; strings are emitted as JSON strings, which YAML reads as double-quoted scalars
+fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    write_yaml(&mut out, value, 0);
+    out
+}
+
+fn write_yaml(out: &mut String, value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, item) in map {
+                let key = yaml_key(key);
+                match item {
+                    Value::Object(m) if !m.is_empty() => {
+                        out.push_str(&format!("{pad}{key}:\n"));
+                        write_yaml(out, item, indent + 1);
+                    }
+                    Value::Array(a) if !a.is_empty() => {
+                        out.push_str(&format!("{pad}{key}:\n"));
+                        write_yaml(out, item, indent);
+                    }
+                    _ => out.push_str(&format!("{pad}{key}: {}\n", yaml_scalar(item))),
+                }
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                if is_block(item) {
+                    // First line of the nested block shares the "- " line
+                    let mut nested = String::new();
+                    write_yaml(&mut nested, item, indent + 1);
+                    out.push_str(&format!("{pad}- {}", &nested[pad.len() + 2..]));
+                } else {
+                    out.push_str(&format!("{pad}- {}\n", yaml_scalar(item)));
+                }
+            }
+        }
+        _ => out.push_str(&format!("{pad}{}\n", yaml_scalar(value))),
+    }
+}
+
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+// Scalars and empty collections ({} / [] are valid YAML flow style)
+fn yaml_scalar(value: &Value) -> String {
+    value.to_string()
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if plain {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
@@ -1,22 +1,35 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::{Parser, Subcommand};
 use conductor_agent::AgentParser;
 use conductor_core as core;
+use conductor_daemon::{compat, RunAgentRequest};
 use regex::Regex;
-use serde::Serialize;
 use serde_json::{json, Value};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::mpsc::{self, Sender};
 use std::thread;
 
+mod daemon;
+mod format;
+mod mcp;
+mod pty;
+mod service;
+mod shell;
+
+use format::Format;
+
 #[derive(Parser)]
 #[command(name = "conductor", version, about = "Conductor workspace manager")]
 struct Cli {
     #[arg(long)]
     home: Option<PathBuf>,
-    #[arg(long)]
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Shorthand for --format json
+    #[arg(long, global = true, conflicts_with = "format")]
     json: bool,
     #[command(subcommand)]
     command: Commands,
@@ -33,14 +46,97 @@ enum Commands {
         #[command(subcommand)]
         command: WorkspaceCommands,
     },
+    Open {
+        workspace: String,
+        /// Open a terminal window at the workspace instead of the editor
+        #[arg(long, conflicts_with = "reveal")]
+        terminal: bool,
+        /// Show the workspace in the file manager instead of the editor
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// Run a command in a workspace: --workspace, else the workspace the current
+    /// directory is in; --cwd runs it anywhere
     Exec {
         #[arg(long)]
         workspace: Option<String>,
         #[arg(long)]
         cwd: Option<PathBuf>,
+        /// Run on a pseudo-terminal for interactive programs
+        #[arg(long)]
+        pty: bool,
         #[arg(last = true)]
         cmd: Vec<String>,
     },
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommands,
+    },
+    /// The conductor home itself
+    Home {
+        #[command(subcommand)]
+        command: HomeCommands,
+    },
+    Status,
+    /// The workspace the current directory is in (its id; --json for the whole record)
+    Which,
+    /// Print shell functions to eval from your rc file: `cw <workspace>` cds into a workspace
+    #[command(name = "shell-init")]
+    ShellInit {
+        /// bash, zsh or fish
+        shell: String,
+    },
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+    /// Serve Conductor operations as MCP tools (stdio unless --socket is given)
+    #[command(name = "mcp-serve")]
+    McpServe {
+        /// Listen on this Unix socket instead of stdio
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    /// A workspace's agent chat
+    Chat {
+        #[command(subcommand)]
+        command: ChatCommands,
+    },
+    /// Review comments on workspace files, and the follow-up prompt they make
+    Review {
+        #[command(subcommand)]
+        command: ReviewCommands,
+    },
+    /// Tasks: a unit of work fanned out over candidate workspaces, one of which is chosen
+    Task {
+        #[command(subcommand)]
+        command: TaskCommands,
+    },
+    /// Find repos, workspaces, files and chat messages matching a query, best first
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Sessions and chats saved when workspaces were archived
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommands,
+    },
+    Audit {
+        #[arg(long)]
+        action: Option<String>,
+        #[arg(long)]
+        target: Option<String>,
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -53,8 +149,39 @@ enum RepoCommands {
         name: Option<String>,
         #[arg(long = "default-branch")]
         default_branch: Option<String>,
+        /// Shallow clone with this many commits (--url only)
+        #[arg(long, requires = "url")]
+        depth: Option<u32>,
+        /// Partial clone filter, e.g. blob:none (--url only)
+        #[arg(long, requires = "url")]
+        filter: Option<String>,
+        /// Clone only the default branch (--url only)
+        #[arg(long = "single-branch", requires = "url")]
+        single_branch: bool,
+        /// Clone bare, so workspaces are the only checkouts (--url only)
+        #[arg(long, requires = "url")]
+        bare: bool,
     },
     List,
+    /// List these repos first, in this order; the rest follow newest first
+    Reorder {
+        #[arg(required = true)]
+        repos: Vec<String>,
+    },
+    /// Show the repo's git remotes, or add, remove or prefer one
+    Remotes {
+        repo: String,
+        #[command(subcommand)]
+        command: Option<RemoteCommands>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemoteCommands {
+    Add { name: String, url: String },
+    Remove { name: String },
+    /// Use this remote's branch when a base branch exists on several remotes
+    Prefer { name: String },
 }
 
 #[derive(Subcommand)]
@@ -66,36 +193,397 @@ enum WorkspaceCommands {
         base: Option<String>,
         #[arg(long)]
         branch: Option<String>,
+        /// Add -2, -3, ... to the name and branch when they are taken instead of failing
+        #[arg(long = "auto-suffix")]
+        auto_suffix: bool,
+        /// Copy the repo checkout's uncommitted changes into the new workspace
+        #[arg(long = "carry-changes")]
+        carry_changes: bool,
+        /// Start from a GitHub (123, owner/repo#123) or Linear (ENG-123) issue, or its URL:
+        /// named after it unless a name is given, with its chat and first agent run opening with it
+        #[arg(long = "from-issue")]
+        from_issue: Option<String>,
     },
     List {
         #[arg(long)]
         repo: Option<String>,
+        /// Ask GitHub for fresh PR status instead of using the cache
+        #[arg(long = "refresh-pr")]
+        refresh_pr: bool,
+        /// created (newest first, the default) or recent (latest agent run, commit or open first)
+        #[arg(long, default_value = "created")]
+        sort: String,
+        /// Only workspaces in this state (repeatable): ready, in-review, merged, abandoned, archived, error
+        #[arg(long = "state")]
+        states: Vec<String>,
+        /// Only ready and in-review workspaces without commits or agent runs for this
+        /// long, e.g. 14d
+        #[arg(long)]
+        stale: Option<String>,
     },
+    /// Move a workspace along its pipeline: ready, in-review, merged or abandoned
+    State { workspace: String, state: String },
+    /// Keep a workspace at the top of listings
+    Pin { workspace: String },
+    Unpin { workspace: String },
+    /// GitHub PR state, reviews and checks for the workspace branch
+    Pr {
+        workspace: String,
+        /// Skip the cache
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// The workspace's directory
+    Path { workspace: String },
+    /// The repo context file (.conductor/context.md or CONDUCTOR.md) new agent runs get ahead of the prompt
+    Context { workspace: String },
+    /// Remove the worktree, keeping its session data; refused while `archive_checks`
+    /// in config.json fail, unless forced
     Archive {
         workspace: String,
         #[arg(long)]
         force: bool,
+        /// Only evaluate the pre-archive checks
+        #[arg(long, conflicts_with = "force")]
+        check: bool,
+    },
+    /// List workspaces whose branches are fully merged into their base
+    Cleanup {
+        /// Archive them too (pinned, dirty and still-running ones are kept)
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Forget archived workspaces and their saved sessions once they are old enough
+    Purge {
+        /// Age since archiving, e.g. 30d, 12h or 2w (defaults to archive_retention in config.json)
+        #[arg(long = "older-than")]
+        older_than: Option<String>,
+        /// List what would be purged without removing anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
     },
+    /// Fix recorded paths and git worktree links after the conductor home was moved or restored
+    Repair,
+    /// Find workspaces, directories and git worktrees that disagree; report only unless asked to fix
+    Reconcile {
+        /// Track git worktrees of registered repos as workspaces
+        #[arg(long)]
+        adopt: bool,
+        /// Archive missing workspaces and remove stale, untracked and unregistered worktrees
+        /// (worktrees outside the workspaces directory are only reported)
+        #[arg(long)]
+        prune: bool,
+        /// Put missing workspaces in the error state
+        #[arg(long = "mark-error")]
+        mark_error: bool,
+    },
+    /// Tracked and untracked files; the workspace defaults to the one the current directory is in
     Files {
+        workspace: Option<String>,
+        /// Only paths starting with this
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Git glob pathspec; `**/` crosses directories (e.g. '**/*.rs')
+        #[arg(long)]
+        glob: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// One directory level with kinds, sizes and git status
+    Tree {
         workspace: String,
+        /// Directory relative to the workspace root
+        #[arg(default_value = "")]
+        dir: String,
     },
+    /// Uncommitted changes; the workspace defaults to the one the current directory is in
     Changes {
+        workspace: Option<String>,
+    },
+    /// Commits, lines changed vs base, agent runs, tokens used and age
+    Stats {
         workspace: String,
     },
     File {
         workspace: String,
         path: String,
     },
-    Diff {
+    /// Stage files or directories for the next commit
+    Stage {
+        workspace: String,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Take paths out of the index, keeping the edits
+    Unstage {
+        workspace: String,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Throw away uncommitted changes to paths (back to HEAD)
+    Discard {
+        workspace: String,
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Also delete untracked files
+        #[arg(long)]
+        untracked: bool,
+    },
+    /// Commits on the workspace branch since its base, newest first
+    Log {
+        workspace: String,
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+        /// Include history from before the base branch
+        #[arg(long)]
+        all: bool,
+    },
+    /// Who last changed each line of a file (uncommitted lines show a zero commit)
+    Blame {
         workspace: String,
         path: String,
     },
+    /// Apply a patch file (or stdin with "-"), or a bundle, as uncommitted changes
+    Apply {
+        workspace: String,
+        #[arg(required_unless_present = "bundle")]
+        patch: Option<PathBuf>,
+        #[arg(long, conflicts_with = "patch")]
+        bundle: Option<PathBuf>,
+        /// Fall back to a 3-way merge and leave conflict markers
+        #[arg(long = "3way")]
+        three_way: bool,
+    },
+    /// Package the workspace's work for someone without Conductor
+    Export {
+        workspace: String,
+        #[arg(value_parser = ["bundle", "patches", "tarball"])]
+        kind: String,
+        /// Defaults to <name>.bundle, <name>-patches/ or <name>.tar.gz here
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Patch of one file, or of the whole workspace (committed and uncommitted) without a path.
+    /// Inside a workspace the workspace may be left out; a lone argument that doesn't name
+    /// a workspace is then the path
+    Diff {
+        workspace: Option<String>,
+        path: Option<String>,
+    },
+    /// Snapshot the workspace's files without committing to its branch
+    Checkpoint {
+        workspace: String,
+        #[arg(long, short, default_value = "manual")]
+        message: String,
+    },
+    /// List the workspace's checkpoints, oldest first
+    Checkpoints {
+        workspace: String,
+    },
+    /// Undo agent work: reset the workspace to its base, a checkpoint, or before its
+    /// last commits. Uncommitted work is stashed first
+    Rollback {
+        workspace: String,
+        /// "base", a checkpoint id, or ~N to drop the last N commits
+        target: String,
+        /// Roll back even while an agent run holds the workspace
+        #[arg(long)]
+        force: bool,
+    },
+    /// Commit the staged changes, or everything when nothing is staged. With --suggest,
+    /// print a message for them from the commit_messages engine instead of committing
+    Commit {
+        /// Defaults to the workspace the current directory is in
+        workspace: Option<String>,
+        #[arg(long, short, required_unless_present = "suggest", conflicts_with = "suggest")]
+        message: Option<String>,
+        #[arg(long)]
+        suggest: bool,
+    },
 }
 
-fn print_json<T: Serialize>(value: &T) -> Result<()> {
-    let text = serde_json::to_string(value)?;
-    println!("{text}");
-    Ok(())
+#[derive(Subcommand)]
+enum AgentCommands {
+    Run {
+        /// Defaults to the workspace the current directory is in
+        #[arg(long)]
+        workspace: Option<String>,
+        /// An engine, a fallback list such as "claude,codex", or a chain from
+        /// engine_chains in config.json
+        #[arg(long, default_value = "claude")]
+        engine: String,
+        /// Where the engine runs: "host", "docker" (image from config.json),
+        /// "devcontainer" or "sandbox"; the repo's default when omitted
+        #[arg(long, default_value = "")]
+        isolation: String,
+        #[arg(long)]
+        resume: Option<String>,
+        /// File in the workspace to hand the agent (repeatable); images are sent as images
+        #[arg(long = "attach")]
+        attachments: Vec<String>,
+        /// Start even while another run holds the workspace
+        #[arg(long)]
+        force: bool,
+        prompt: String,
+    },
+    List,
+    /// Engines this machine can run: installed, version, logged in
+    Engines,
+    /// Run a recorded engine stream (a transcript or any JSONL engine output) through
+    /// the parser and print the normalized events
+    Replay {
+        /// JSONL file, or - for stdin
+        #[arg(required_unless_present = "workspace")]
+        input: Option<PathBuf>,
+        /// Replay the workspace's most recent transcript instead
+        #[arg(long, conflicts_with = "input")]
+        workspace: Option<String>,
+    },
+    Attach {
+        session_id: String,
+        #[arg(long = "no-history")]
+        no_history: bool,
+        #[arg(long)]
+        follow: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommands {
+    Socket,
+    Token,
+    /// Start the daemon at login as a LaunchAgent (macOS) or systemd user unit (Linux)
+    Install,
+    /// Stop the login service and remove it; clients go back to spawning the daemon on demand
+    Uninstall,
+}
+
+#[derive(Subcommand)]
+enum HomeCommands {
+    /// Move the home (DB, repos, workspaces) to a new path and repoint everything at it;
+    /// stop the daemon first
+    Migrate { to: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum ChatCommands {
+    /// Render the chat, with the workspace's actions folded in, for sharing
+    Export {
+        workspace: String,
+        #[arg(default_value = "markdown", value_parser = ["markdown", "html"])]
+        kind: String,
+        /// Write here instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Bring in a Claude Code or Codex session started outside Conductor, so it resumes here
+    Import {
+        workspace: String,
+        #[arg(long, value_parser = ["claude", "codex"])]
+        engine: Option<String>,
+        /// Session or thread id (the most recently active one when omitted)
+        #[arg(long)]
+        session: Option<String>,
+        /// Show the sessions found instead of importing
+        #[arg(long, conflicts_with = "session")]
+        list: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReviewCommands {
+    List {
+        workspace: String,
+        /// Include resolved comments
+        #[arg(long)]
+        all: bool,
+    },
+    /// Comment on a line (12) or line range (12-20) of a workspace file
+    Add {
+        workspace: String,
+        file: String,
+        lines: String,
+        body: String,
+        /// Defaults to the workspace's git user.name
+        #[arg(long)]
+        author: Option<String>,
+    },
+    Edit { id: String, body: String },
+    Resolve { id: String },
+    Reopen { id: String },
+    Delete { id: String },
+    /// Print the unresolved comments as a prompt for the next agent run
+    Prompt { workspace: String },
+    /// Fetch reviewers' comments from the workspace branch's GitHub PR
+    Sync { workspace: String },
+}
+
+#[derive(Subcommand)]
+enum TaskCommands {
+    Create {
+        title: String,
+        #[arg(long, short, default_value = "")]
+        description: String,
+        /// Existing workspace to link as a candidate (repeatable)
+        #[arg(long = "workspace")]
+        workspaces: Vec<String>,
+        /// Create this many new candidate workspaces in --repo
+        #[arg(long, requires = "repo")]
+        candidates: Option<u32>,
+        #[arg(long)]
+        repo: Option<String>,
+    },
+    List {
+        /// open, in-progress, done or abandoned
+        #[arg(long)]
+        status: Option<String>,
+    },
+    Show { id: String },
+    Update {
+        id: String,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long, short)]
+        description: Option<String>,
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Add a candidate workspace; agent runs in it are recorded against the task
+    Link { id: String, workspace: String },
+    Unlink { id: String, workspace: String },
+    /// Keep this candidate's work and mark the task done
+    Choose { id: String, workspace: String },
+    Delete { id: String },
+}
+
+#[derive(Subcommand)]
+enum ArchiveCommands {
+    List {
+        #[arg(long)]
+        workspace: Option<String>,
+    },
+    /// Print a saved session and its chat
+    Show {
+        workspace: String,
+        /// Snapshot to show, from `archive list` (the newest when omitted)
+        timestamp: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Migrate the schema up to this conductor's version, or to --to
+    Migrate {
+        /// Print the pending steps and their SQL without running them
+        #[arg(long)]
+        dry_run: bool,
+        /// Target schema version; lower than the current one rolls back, for running an
+        /// older conductor against this home (any newer command migrates it up again)
+        #[arg(long)]
+        to: Option<i64>,
+    },
 }
 
 fn print_json_value(value: &Value) -> Result<()> {
@@ -104,18 +592,76 @@ fn print_json_value(value: &Value) -> Result<()> {
     Ok(())
 }
 
+// Record a CLI mutation in the audit log; a failed write only warns
+fn audit<T>(conn: &rusqlite::Connection, action: &str, target: Option<&str>, result: &Result<T>, mut metadata: Value) {
+    if let (Err(e), Value::Object(map)) = (result, &mut metadata) {
+        map.insert("error".to_string(), Value::String(e.to_string()));
+    }
+    if let Err(e) = core::audit_record(conn, "cli", action, target, result.is_ok(), &metadata) {
+        eprintln!("warning: failed to record audit event {action}: {e}");
+    }
+}
+
+// "12" or "12-20"
+fn parse_line_range(lines: &str) -> Result<(u32, u32)> {
+    let parse = |n: &str| n.trim().parse::<u32>().with_context(|| format!("invalid line range: {lines}"));
+    match lines.split_once('-') {
+        Some((start, end)) => Ok((parse(start)?, parse(end)?)),
+        None => parse(lines).map(|line| (line, line)),
+    }
+}
+
+// Audit a change to a review comment against its workspace, once it has been found
+fn review_audit(
+    conn: &rusqlite::Connection,
+    action: &str,
+    id: &str,
+    result: &Result<core::ReviewComment>,
+    mut metadata: Value,
+) {
+    metadata["comment_id"] = result.as_ref().map_or(id, |comment| comment.id.as_str()).into();
+    let target = result.as_ref().ok().map(|comment| comment.workspace_id.as_str());
+    audit(conn, action, target, result, metadata);
+}
+
+// Audit a change to a task against the task, once it has been found
+fn task_audit(conn: &rusqlite::Connection, action: &str, result: &Result<core::Task>, metadata: Value) {
+    let target = result.as_ref().ok().map(|task| task.id.as_str());
+    audit(conn, action, target, result, metadata);
+}
+
+/// The workspace the current directory is in, failing when it's not a managed worktree
+fn current_workspace(conn: &rusqlite::Connection) -> Result<core::Workspace> {
+    let cwd = std::env::current_dir()?;
+    core::workspace_for_path(conn, &cwd)?.ok_or_else(|| {
+        anyhow!("{} is not inside a conductor workspace; name the workspace explicitly", cwd.display())
+    })
+}
+
+/// The workspace named on the command line, or else the one the current directory is in
+fn workspace_or_cwd(conn: &rusqlite::Connection, workspace: Option<String>) -> Result<String> {
+    match workspace {
+        Some(workspace) => Ok(workspace),
+        None => Ok(current_workspace(conn)?.id),
+    }
+}
+
 fn main() -> Result<()> {
+    run().map_err(compat::explain)
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
     let home = cli.home.unwrap_or_else(core::default_home);
+    let format = if cli.json { Format::Json } else { cli.format };
 
     match cli.command {
         Commands::Init => {
             let db_path = core::init(&home)?;
-            if cli.json {
-                print_json(&json!({"home": home, "db_path": db_path}))?;
-            } else {
-                println!("{}", db_path.display());
-            }
+            format.text(
+                &json!({"home": home, "db_path": db_path}),
+                &db_path.display().to_string(),
+            )?;
         }
         Commands::Repo { command } => {
             let conn = core::connect(&home)?;
@@ -125,46 +671,79 @@ fn main() -> Result<()> {
                     url,
                     name,
                     default_branch,
+                    depth,
+                    filter,
+                    single_branch,
+                    bare,
                 } => {
                     let repo = if let Some(url) = url {
                         if path.is_some() {
                             return Err(anyhow!("repo add: use either a path or --url"));
                         }
-                        core::repo_add_url(
+                        let clone = core::CloneOptions {
+                            depth,
+                            filter,
+                            single_branch,
+                            bare,
+                        };
+                        let result = core::repo_add_url(
                             &conn,
                             &home,
                             &url,
                             name.as_deref(),
                             default_branch.as_deref(),
-                        )?
+                            &clone,
+                        );
+                        let target = result.as_ref().ok().map(|r| r.id.clone());
+                        let metadata = json!({ "url": url, "clone": clone });
+                        audit(&conn, "repo.add_url", target.as_deref(), &result, metadata);
+                        result?
                     } else {
                         let path = path.unwrap_or_else(|| PathBuf::from("."));
-                        core::repo_add(
+                        let result = core::repo_add(
                             &conn,
                             &path,
                             name.as_deref(),
                             default_branch.as_deref(),
-                        )?
+                        );
+                        let target = result.as_ref().ok().map(|r| r.id.clone());
+                        audit(&conn, "repo.add", target.as_deref(), &result, json!({ "path": path }));
+                        result?
                     };
-                    if cli.json {
-                        print_json(&repo)?;
-                    } else {
-                        println!("{}\t{}\t{}", repo.id, repo.name, repo.root_path);
-                    }
+                    format.record(&repo, &["id", "name", "root_path"])?;
                 }
                 RepoCommands::List => {
                     let repos = core::repo_list(&conn)?;
-                    if cli.json {
-                        print_json(&repos)?;
-                    } else if !repos.is_empty() {
-                        println!("id\tname\tdefault_branch\troot_path");
-                        for repo in repos {
-                            println!(
-                                "{}\t{}\t{}\t{}",
-                                repo.id, repo.name, repo.default_branch, repo.root_path
-                            );
+                    format.list(&repos, &["id", "name", "default_branch", "root_path"])?;
+                }
+                RepoCommands::Reorder { repos } => {
+                    let result = core::repo_reorder(&conn, &repos);
+                    audit(&conn, "repo.reorder", None, &result, json!({ "repos": repos }));
+                    format.list(&result?, &["id", "name", "default_branch", "root_path"])?;
+                }
+                RepoCommands::Remotes { repo, command } => {
+                    let remotes = match command {
+                        None => core::repo_remotes(&conn, &repo)?,
+                        Some(command) => {
+                            let (action, name, result) = match command {
+                                RemoteCommands::Add { name, url } => {
+                                    let result = core::repo_remote_add(&conn, &repo, &name, &url);
+                                    ("repo.remote_add", name, result)
+                                }
+                                RemoteCommands::Remove { name } => {
+                                    let result = core::repo_remote_remove(&conn, &repo, &name);
+                                    ("repo.remote_remove", name, result)
+                                }
+                                RemoteCommands::Prefer { name } => {
+                                    let result = core::repo_remote_prefer(&conn, &repo, &name);
+                                    ("repo.remote_prefer", name, result)
+                                }
+                            };
+                            audit(&conn, action, Some(&repo), &result, json!({ "remote": name }));
+                            result?
                         }
-                    }
+                    };
+                    format.list(&remotes, &["name", "fetch_url", "push_url", "preferred"])?;
                 }
             }
         }
@@ -176,106 +755,957 @@ fn main() -> Result<()> {
                     name,
                     base,
                     branch,
+                    auto_suffix,
+                    carry_changes,
+                    from_issue,
                 } => {
-                    let ws = core::workspace_create(
+                    let options = core::CreateOptions {
+                        auto_suffix,
+                        carry_changes,
+                    };
+                    let issue = match &from_issue {
+                        Some(reference) => {
+                            let repo_root = PathBuf::from(core::repo_get(&conn, &repo)?.root_path);
+                            Some(core::issues::issue_fetch(&repo_root, reference)?)
+                        }
+                        None => None,
+                    };
+                    let issue_name = issue.as_ref().map(core::issues::Issue::workspace_name);
+                    let mut result = core::workspace_create(
                         &conn,
                         &home,
                         &repo,
-                        name.as_deref(),
+                        name.as_deref().or(issue_name.as_deref()),
                         base.as_deref(),
                         branch.as_deref(),
-                    )?;
-                    if cli.json {
-                        print_json(&ws)?;
+                        &options,
+                    );
+                    // The workspace is there either way; only the link and chat entry are missing
+                    if let (Ok(ws), Some(issue)) = (&mut result, &issue) {
+                        match core::issues::workspace_issue_attach(&conn, ws, issue) {
+                            Ok(()) => ws.issue = Some(issue.link()),
+                            Err(e) => eprintln!("warning: failed to link the workspace to its issue: {e}"),
+                        }
+                    }
+                    let target = result.as_ref().ok().map(|ws| ws.id.clone());
+                    audit(
+                        &conn,
+                        "workspace.create",
+                        target.as_deref(),
+                        &result,
+                        json!({
+                            "repo": repo,
+                            "name": name,
+                            "base": base,
+                            "branch": branch,
+                            "auto_suffix": auto_suffix,
+                            "carry_changes": carry_changes,
+                            "from_issue": from_issue,
+                        }),
+                    );
+                    let ws = result?;
+                    format.record(&ws, &["id", "path", "branch", "base_branch"])?;
+                }
+                WorkspaceCommands::List { repo, refresh_pr, sort, states, stale } => {
+                    let sort: core::WorkspaceSort = sort.parse()?;
+                    let states = states.iter().map(|state| state.parse()).collect::<Result<Vec<core::WorkspaceState>>>()?;
+                    let idle = stale.as_deref().map(core::parse_age).transpose()?;
+                    let mut workspaces = Vec::new();
+                    for ws in core::workspace_list_sorted(&conn, repo.as_deref(), sort)? {
+                        let stale = match idle {
+                            Some(idle) => core::workspace_is_stale(&conn, &ws, idle)?,
+                            None => true,
+                        };
+                        if stale && (states.is_empty() || states.contains(&ws.state)) {
+                            workspaces.push(ws);
+                        }
+                    }
+                    if refresh_pr {
+                        for ws in workspaces.iter().filter(|ws| !matches!(ws.state, core::WorkspaceState::Archived)) {
+                            if let Err(e) = core::github::pr_status_refresh(&conn, &ws.id) {
+                                eprintln!("warning: {}: {e}", ws.name);
+                            }
+                        }
+                    }
+                    let prs = core::github::pr_status_cached_all(&conn)?;
+                    let rows = workspaces
+                        .iter()
+                        .map(|ws| {
+                            let mut row = serde_json::to_value(ws)?;
+                            let pr = prs.iter().find(|pr| pr.workspace_id == ws.id);
+                            if format.is_structured() {
+                                row["pull_request"] = serde_json::to_value(pr)?;
+                            } else {
+                                row["pr"] = Value::String(pr.map_or("-".to_string(), |pr| pr.summary()));
+                                if ws.pinned {
+                                    row["name"] = Value::String(format!("* {}", ws.name));
+                                }
+                                if ws.link_error.is_some() {
+                                    row["state"] = Value::String(format!("{} (broken)", ws.state));
+                                }
+                                row["active"] = Value::String(ws.activity.latest().unwrap_or("-").to_string());
+                                row["checks"] = Value::String(match &ws.verification {
+                                    Some(verification) if verification.ok => "pass".to_string(),
+                                    Some(_) => "fail".to_string(),
+                                    None => "-".to_string(),
+                                });
+                            }
+                            Ok(row)
+                        })
+                        .collect::<Result<Vec<Value>>>()?;
+                    let columns: &[&str] = match sort {
+                        core::WorkspaceSort::Created => {
+                            &["id", "repo", "name", "branch", "base_branch", "state", "pr", "checks", "title", "path"]
+                        }
+                        core::WorkspaceSort::Recent => {
+                            &["id", "repo", "name", "branch", "state", "pr", "checks", "active", "title", "path"]
+                        }
+                    };
+                    format.list(&rows, columns)?;
+                }
+                WorkspaceCommands::Pr { workspace, refresh } => {
+                    let pr = if refresh {
+                        core::github::pr_status_refresh(&conn, &workspace)?
+                    } else {
+                        core::github::pr_status(&conn, &workspace)?
+                    };
+                    if format.is_structured() {
+                        format.value(&serde_json::to_value(&pr)?)?;
                     } else {
-                        println!("{}\t{}\t{}\t{}", ws.id, ws.path, ws.branch, ws.base_branch);
+                        println!("pr\t{}", pr.summary());
+                        if let Some(url) = &pr.url {
+                            println!("url\t{url}");
+                        }
+                        println!(
+                            "checks\t{} ({} passed, {} failed, {} pending)",
+                            pr.checks.state, pr.checks.passed, pr.checks.failed, pr.checks.pending
+                        );
+                        println!("fetched\t{}", pr.fetched_at);
                     }
                 }
-                WorkspaceCommands::List { repo } => {
-                    let workspaces = core::workspace_list(&conn, repo.as_deref())?;
-                    if cli.json {
-                        print_json(&workspaces)?;
-                    } else if !workspaces.is_empty() {
-                        println!("id\trepo\tname\tbranch\tbase\tstate\tpath");
-                        for ws in workspaces {
-                            println!(
-                                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
-                                ws.id, ws.repo, ws.name, ws.branch, ws.base_branch, ws.state, ws.path
-                            );
+                WorkspaceCommands::Path { workspace } => {
+                    let path = core::workspace_path(&conn, &workspace)?;
+                    format.text(&json!({ "path": path }), &path.display().to_string())?;
+                }
+                WorkspaceCommands::Context { workspace } => {
+                    let ws = core::workspace_get(&conn, &workspace)?;
+                    match core::repo_context(&conn, Path::new(&ws.path))? {
+                        Some(context) if format.is_structured() => format.value(&serde_json::to_value(&context)?)?,
+                        Some(context) => {
+                            eprintln!("# {}", context.path);
+                            print!("{}", context.content);
                         }
+                        None if format.is_structured() => format.value(&json!({ "path": null, "content": "" }))?,
+                        None => eprintln!("no context file; add one of {}", core::CONTEXT_FILES.join(" or ")),
                     }
                 }
-                WorkspaceCommands::Archive { workspace, force } => {
-                    let result = core::workspace_archive(&conn, &home, &workspace, force)?;
-                    if cli.json {
-                        print_json(&result)?;
+                WorkspaceCommands::State { workspace, state } => {
+                    let result = state.parse().and_then(|state| core::workspace_set_state(&conn, &workspace, state));
+                    audit(&conn, "workspace.set_state", Some(&workspace), &result, json!({ "state": state }));
+                    let ws = result?;
+                    format.text(&ws, &format!("{} is {}", ws.name, ws.state))?;
+                }
+                WorkspaceCommands::Pin { ref workspace } | WorkspaceCommands::Unpin { ref workspace } => {
+                    let pinned = matches!(command, WorkspaceCommands::Pin { .. });
+                    let result = core::workspace_set_pinned(&conn, workspace, pinned);
+                    audit(&conn, "workspace.set_pinned", Some(workspace), &result, json!({ "pinned": pinned }));
+                    let ws = result?;
+                    let text = if ws.pinned { format!("pinned {}", ws.name) } else { format!("unpinned {}", ws.name) };
+                    format.text(&ws, &text)?;
+                }
+                WorkspaceCommands::Archive { workspace, check: true, .. } => {
+                    let checks = core::workspace_archive_checks(&conn, &home, &workspace)?;
+                    format.list(&checks, &["check", "ok", "detail"])?;
+                }
+                WorkspaceCommands::Archive { workspace, force, .. } => {
+                    let result = daemon::check_workspace_lock(&home, &workspace, "archive", force, true)
+                        .and_then(|()| core::workspace_archive(&conn, &home, &workspace, force));
+                    let overridden = result.as_ref().ok().map(|result| {
+                        result.checks.iter().filter(|c| !c.ok).map(|c| c.check.as_str()).collect::<Vec<_>>()
+                    });
+                    let metadata = json!({ "force": force, "overridden_checks": overridden });
+                    audit(&conn, "workspace.archive", Some(&workspace), &result, metadata);
+                    let result = result?;
+                    for check in result.checks.iter().filter(|c| !c.ok) {
+                        eprintln!("warning: archived despite failed check {}: {}", check.check.as_str(), check.detail);
+                    }
+                    format.text(&result, &result.id)?;
+                }
+                WorkspaceCommands::Cleanup { archive } => {
+                    let mut merged = core::workspaces_merged(&conn)?;
+                    if archive {
+                        for ws in &mut merged {
+                            if ws.pinned {
+                                ws.action = Some("kept: pinned".to_string());
+                                continue;
+                            }
+                            let result = daemon::check_workspace_lock(&home, &ws.id, "archive", false, true)
+                                .and_then(|()| core::workspace_archive(&conn, &home, &ws.id, false));
+                            let metadata = json!({ "force": false, "cleanup": true, "reason": ws.reason });
+                            audit(&conn, "workspace.archive", Some(&ws.id), &result, metadata);
+                            ws.action = Some(match result {
+                                Ok(_) => "archived".to_string(),
+                                Err(e) => format!("failed: {e}"),
+                            });
+                        }
+                    }
+                    let columns: &[&str] = if archive {
+                        &["id", "repo", "name", "reason", "action"]
                     } else {
-                        println!("{}", result.id);
+                        &["id", "repo", "name", "branch", "reason"]
+                    };
+                    format.list(&merged, columns)?;
+                }
+                WorkspaceCommands::Purge { older_than, dry_run } => {
+                    let older_than = match older_than.or(core::config_load(&home)?.archive_retention) {
+                        Some(age) => age,
+                        None => return Err(anyhow!("workspace purge: pass --older-than or set archive_retention")),
+                    };
+                    let age = core::parse_age(&older_than)?;
+                    let result = core::workspace_purge(&conn, &home, age, dry_run);
+                    if !dry_run {
+                        let purged = result.as_ref().ok().map(|purged| purged.iter().map(|ws| &ws.id).collect::<Vec<_>>());
+                        audit(&conn, "workspace.purge", None, &result, json!({ "older_than": older_than, "purged": purged }));
+                    }
+                    format.list(&result?, &["id", "repo", "name", "archived_at", "archive_bytes"])?;
+                }
+                WorkspaceCommands::Repair => {
+                    let result = core::workspace_repair(&conn, &home);
+                    let metadata = match &result {
+                        Ok(actions) => json!({ "actions": actions }),
+                        Err(_) => json!({}),
+                    };
+                    audit(&conn, "workspace.repair", None, &result, metadata);
+                    format.list(&result?, &["target", "name", "action", "detail"])?;
+                }
+                WorkspaceCommands::Reconcile { adopt, prune, mark_error } => {
+                    let options = core::ReconcileOptions { adopt, prune, mark_error };
+                    let result = core::workspace_reconcile(&conn, &home, options);
+                    if adopt || prune || mark_error {
+                        let issues = result.as_ref().ok();
+                        let metadata = json!({ "adopt": adopt, "prune": prune, "mark_error": mark_error, "issues": issues });
+                        audit(&conn, "workspace.reconcile", None, &result, metadata);
                     }
+                    format.list(&result?, &["kind", "path", "branch", "action"])?;
+                }
+                WorkspaceCommands::Files { workspace, prefix, glob, offset, limit } => {
+                    let workspace = workspace_or_cwd(&conn, workspace)?;
+                    let query = core::FileQuery { prefix, glob, offset, limit };
+                    let page = core::workspace_files(&conn, &workspace, &query)?;
+                    format.lines(&page.files)?;
                 }
-                WorkspaceCommands::Files { workspace } => {
-                    let files = core::workspace_files(&conn, &workspace)?;
-                    if cli.json {
-                        print_json(&files)?;
+                WorkspaceCommands::Tree { workspace, dir } => {
+                    let entries = core::workspace_tree(&conn, &workspace, &dir)?;
+                    format.list(&entries, &["status", "kind", "size", "path"])?;
+                }
+                WorkspaceCommands::Stats { workspace } => {
+                    let stats = core::workspace_stats(&conn, &workspace)?;
+                    if format.is_structured() {
+                        format.value(&serde_json::to_value(&stats)?)?;
                     } else {
-                        for path in files {
-                            println!("{path}");
-                        }
+                        println!("commits\t{}", stats.commits);
+                        println!("diff\t{}", stats.diff);
+                        println!("agent runs\t{}", stats.agent_runs);
+                        println!(
+                            "tokens\t{} ({} in, {} out)",
+                            stats.usage.total(),
+                            stats.usage.input_tokens,
+                            stats.usage.output_tokens
+                        );
+                        println!("age\t{}d {}h (created {})", stats.age_secs / 86400, stats.age_secs % 86400 / 3600, stats.created_at);
                     }
                 }
                 WorkspaceCommands::Changes { workspace } => {
+                    let workspace = workspace_or_cwd(&conn, workspace)?;
                     let changes = core::workspace_changes(&conn, &workspace)?;
-                    if cli.json {
-                        print_json(&changes)?;
+                    format.list(&changes, &["status", "path", "old_path"])?;
+                }
+                WorkspaceCommands::File { workspace, path } => {
+                    let content = core::workspace_file_content(&conn, &workspace, &path)?;
+                    format.text(&json!({ "content": content }), &content)?;
+                }
+                WorkspaceCommands::Stage { workspace, paths } => {
+                    let result = core::workspace_stage(&conn, &workspace, &paths);
+                    audit(&conn, "workspace.stage", Some(&workspace), &result, json!({ "paths": paths }));
+                    result?;
+                }
+                WorkspaceCommands::Unstage { workspace, paths } => {
+                    let result = core::workspace_unstage(&conn, &workspace, &paths);
+                    audit(&conn, "workspace.unstage", Some(&workspace), &result, json!({ "paths": paths }));
+                    result?;
+                }
+                WorkspaceCommands::Discard {
+                    workspace,
+                    paths,
+                    untracked,
+                } => {
+                    let result = core::workspace_discard(&conn, &workspace, &paths, untracked);
+                    let metadata = json!({ "paths": paths, "include_untracked": untracked });
+                    audit(&conn, "workspace.discard", Some(&workspace), &result, metadata);
+                    result?;
+                }
+                WorkspaceCommands::Log { workspace, limit, all } => {
+                    let options = core::LogOptions { limit, since_base: !all };
+                    let commits = core::workspace_log(&conn, &workspace, &options)?;
+                    if format.is_structured() {
+                        format.list(&commits, &[])?;
                     } else {
-                        for change in changes {
-                            if let Some(old_path) = change.old_path {
-                                println!("{}\t{}\t{}", change.status, old_path, change.path);
+                        for commit in &commits {
+                            println!("{} {} ({}, {}) {}", &commit.sha[..7], commit.subject, commit.author, commit.date, commit.stat);
+                        }
+                    }
+                }
+                WorkspaceCommands::Blame { workspace, path } => {
+                    let lines = core::workspace_file_blame(&conn, &workspace, &path)?;
+                    format.list(&lines, &["line", "commit", "author", "time", "content"])?;
+                }
+                WorkspaceCommands::Apply {
+                    workspace,
+                    patch,
+                    bundle,
+                    three_way,
+                } => {
+                    let result = match (&bundle, &patch) {
+                        (Some(bundle), _) => core::workspace_apply_bundle(&conn, &workspace, bundle, three_way),
+                        (None, Some(path)) => {
+                            let mut text = String::new();
+                            if path.as_os_str() == "-" {
+                                std::io::stdin().read_to_string(&mut text)?;
                             } else {
-                                println!("{}\t{}", change.status, change.path);
+                                text = std::fs::read_to_string(path)
+                                    .with_context(|| format!("failed to read {}", path.display()))?;
                             }
+                            core::workspace_apply_patch(&conn, &workspace, &text, three_way)
+                        }
+                        (None, None) => Err(anyhow!("workspace apply: give a patch file or --bundle")),
+                    };
+                    let metadata = json!({ "three_way": three_way, "patch": patch, "bundle": bundle });
+                    audit(&conn, "workspace.apply_patch", Some(&workspace), &result, metadata);
+                    let result = result?;
+                    if format.is_structured() {
+                        format.value(&serde_json::to_value(&result)?)?;
+                    } else {
+                        for file in &result.files {
+                            let mark = match (result.conflicts.contains(file), result.applied) {
+                                (true, _) => "C",
+                                (false, true) => "M",
+                                (false, false) => "-",
+                            };
+                            println!("{mark}\t{file}");
+                        }
+                        if !result.message.is_empty() {
+                            eprintln!("{}", result.message);
                         }
                     }
+                    if !result.applied || !result.conflicts.is_empty() {
+                        std::process::exit(1);
+                    }
                 }
-                WorkspaceCommands::File { workspace, path } => {
-                    let content = core::workspace_file_content(&conn, &workspace, &path)?;
-                    if cli.json {
-                        print_json(&json!({ "content": content }))?;
+                WorkspaceCommands::Export {
+                    workspace,
+                    kind,
+                    output,
+                } => {
+                    let export_format: core::ExportFormat = kind.parse()?;
+                    let output = match output {
+                        Some(output) => output,
+                        None => {
+                            let path = core::workspace_path(&conn, &workspace)?;
+                            let name = path
+                                .file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                .unwrap_or_else(|| workspace.clone());
+                            PathBuf::from(match export_format {
+                                core::ExportFormat::Bundle => format!("{name}.bundle"),
+                                core::ExportFormat::Patches => format!("{name}-patches"),
+                                core::ExportFormat::Tarball => format!("{name}.tar.gz"),
+                            })
+                        }
+                    };
+                    let result = core::workspace_export(&conn, &workspace, export_format, &output);
+                    let metadata = json!({ "format": kind, "path": output });
+                    audit(&conn, "workspace.export", Some(&workspace), &result, metadata);
+                    let export = result?;
+                    format.text(&export, &export.path)?;
+                }
+                WorkspaceCommands::Diff { workspace, path } => {
+                    let (workspace, path) = match (workspace, path) {
+                        (Some(only), None) if core::workspace_get(&conn, &only).is_err() => {
+                            (current_workspace(&conn)?.id, Some(only))
+                        }
+                        (workspace, path) => (workspace_or_cwd(&conn, workspace)?, path),
+                    };
+                    let diff = match path {
+                        Some(path) => core::workspace_file_diff(&conn, &workspace, &path)?,
+                        None => core::workspace_diff(&conn, &workspace)?,
+                    };
+                    // print!, not println!: the patch already ends in its own newline
+                    if format.is_structured() {
+                        format.value(&json!({ "patch": diff }))?;
                     } else {
-                        println!("{content}");
+                        print!("{diff}");
                     }
                 }
-                WorkspaceCommands::Diff { workspace, path } => {
-                    let diff = core::workspace_file_diff(&conn, &workspace, &path)?;
-                    if cli.json {
-                        print_json(&json!({ "patch": diff }))?;
+                WorkspaceCommands::Checkpoint { workspace, message } => {
+                    let result = core::checkpoint_create(&conn, &workspace, &message);
+                    audit(&conn, "workspace.checkpoint", Some(&workspace), &result, json!({ "label": message }));
+                    match result? {
+                        Some(checkpoint) => format.text(&checkpoint, &checkpoint.id.to_string())?,
+                        None if format.is_structured() => format.value(&Value::Null)?,
+                        None => eprintln!("no changes since the last checkpoint"),
+                    }
+                }
+                WorkspaceCommands::Checkpoints { workspace } => {
+                    format.list(&core::checkpoint_list(&conn, &workspace)?, &["id", "created_at", "head", "label"])?;
+                }
+                WorkspaceCommands::Rollback { workspace, target, force } => {
+                    let result = daemon::check_workspace_lock(&home, &workspace, "roll back", force, false)
+                        .and_then(|()| target.parse())
+                        .and_then(|target| core::workspace_rollback(&conn, &workspace, target));
+                    let metadata = json!({ "target": target, "force": force });
+                    audit(&conn, "workspace.rollback", Some(&workspace), &result, metadata);
+                    let rollback = result?;
+                    let mut text = format!(
+                        "rolled back to {} ({} dropped commit(s); was at {})",
+                        rollback.target, rollback.dropped_commits, rollback.previous_head
+                    );
+                    if let Some(stash) = &rollback.stash {
+                        text.push_str(&format!("\nuncommitted work stashed as {stash}"));
+                    }
+                    format.text(&rollback, &text)?;
+                }
+                WorkspaceCommands::Commit { workspace, message, suggest } => {
+                    let workspace = workspace_or_cwd(&conn, workspace)?;
+                    if suggest {
+                        let message = core::commit_message_suggest(&conn, &home, &workspace)?;
+                        format.text(&json!({ "message": message }), &message)?;
                     } else {
-                        println!("{diff}");
+                        let message = message.unwrap_or_default();
+                        let result = core::workspace_commit(&conn, &workspace, &message);
+                        audit(&conn, "workspace.commit", Some(&workspace), &result, json!({ "message": message }));
+                        let sha = result?;
+                        format.text(&json!({ "sha": sha }), &sha)?;
                     }
                 }
             }
         }
-        Commands::Exec { workspace, cwd, mut cmd } => {
+        Commands::Status => daemon::status(&home, format)?,
+        Commands::Which => {
+            let ws = current_workspace(&core::connect(&home)?)?;
+            format.text(&ws, &ws.id)?;
+        }
+        Commands::ShellInit { shell } => {
+            let custom_home = (home != core::default_home()).then_some(home.as_path());
+            print!("{}", shell::init(&shell, custom_home)?);
+        }
+        Commands::Agent { command } => match command {
+            AgentCommands::Run {
+                workspace,
+                engine,
+                isolation,
+                resume,
+                attachments,
+                force,
+                prompt,
+            } => {
+                let run = RunAgentRequest {
+                    engine,
+                    prompt,
+                    resume_id: resume,
+                    isolation,
+                    attachments,
+                    force,
+                    ..Default::default()
+                };
+                let workspace = workspace_or_cwd(&core::connect(&home)?, workspace)?;
+                let exit_code = daemon::agent_run(&home, format.is_structured(), &workspace, run)?;
+                std::process::exit(exit_code);
+            }
+            AgentCommands::List => daemon::agent_list(&home, format)?,
+            AgentCommands::Engines => {
+                let (custom, _) = core::custom_engines(&core::config_load(&home)?);
+                format.list(&core::engine_list(&custom), &["name", "available", "version", "authenticated", "supports_resume"])?;
+            }
+            AgentCommands::Replay { input, workspace } => {
+                let input = match (input, workspace) {
+                    (Some(input), _) => input,
+                    (None, Some(workspace)) => {
+                        let path = core::workspace_path(&core::connect(&home)?, &workspace)?;
+                        core::transcript_list(&path)?
+                            .into_iter()
+                            .next()
+                            .ok_or_else(|| anyhow!("no transcripts recorded for workspace {workspace}"))?
+                    }
+                    (None, None) => unreachable!("clap requires input or --workspace"),
+                };
+                agent_replay(&input, format)?;
+            }
+            AgentCommands::Attach {
+                session_id,
+                no_history,
+                follow,
+            } => {
+                let exit_code =
+                    daemon::agent_attach(&home, format.is_structured(), &session_id, !no_history, follow)?;
+                std::process::exit(exit_code);
+            }
+        },
+        Commands::Daemon { command } => match command {
+            DaemonCommands::Socket => {
+                let socket = core::socket_path(&home)?;
+                format.text(&json!({ "socket_path": socket }), &socket.display().to_string())?;
+            }
+            DaemonCommands::Token => {
+                let token = core::auth_token_ensure(&home)?;
+                format.text(&json!({ "token": token }), &token)?;
+            }
+            DaemonCommands::Install => {
+                // The daemon has no --home: it starts from the default home and serves the others
+                // listed under `homes` there, so a service installed for another home would mislead
+                if home != core::default_home() {
+                    return Err(anyhow!(
+                        "daemon install: the daemon starts from {}; install without --home and list {} under \
+                         \"homes\" in that home's config.json",
+                        core::default_home().display(),
+                        home.display()
+                    ));
+                }
+                let service = service::install()?;
+                format.text(&service, &format!("installed {} service at {}", service.manager, service.path.display()))?;
+            }
+            DaemonCommands::Uninstall => {
+                let service = service::uninstall()?;
+                format.text(&service, &format!("removed {} service {}", service.manager, service.path.display()))?;
+            }
+        },
+        Commands::Chat { command } => {
+            let conn = core::connect(&home)?;
+            match command {
+                ChatCommands::Export { workspace, kind, output } => {
+                    let path = core::workspace_path(&conn, &workspace)?;
+                    let content = core::chat_export(&conn, &path, kind.parse()?)?;
+                    match output {
+                        Some(output) => {
+                            std::fs::write(&output, content)
+                                .with_context(|| format!("failed to write {}", output.display()))?;
+                            format.text(&output, &output.display().to_string())?;
+                        }
+                        None => print!("{content}"),
+                    }
+                }
+                ChatCommands::Import { workspace, engine, session, list } => {
+                    let path = core::workspace_path(&conn, &workspace)?;
+                    if list {
+                        let sessions: Vec<_> = core::engine_sessions(&path)
+                            .into_iter()
+                            .filter(|found| engine.as_ref().is_none_or(|engine| &found.engine == engine))
+                            .collect();
+                        format.list(&sessions, &["engine", "resume_id", "updated_at", "message_count"])?;
+                    } else {
+                        let result = core::session_import(&path, engine.as_deref(), session.as_deref());
+                        let metadata = match &result {
+                            Ok(import) => json!({ "engine": import.engine, "resume_id": import.resume_id, "source": import.source }),
+                            Err(_) => json!({ "engine": engine, "session": session }),
+                        };
+                        audit(&conn, "chat.import", Some(&workspace), &result, metadata);
+                        let import = result?;
+                        let text = format!("{} {} ({} messages)", import.engine, import.resume_id, import.imported);
+                        format.text(&import, &text)?;
+                    }
+                }
+            }
+        }
+        Commands::Task { command } => {
+            let conn = core::connect(&home)?;
+            let columns = ["id", "status", "title", "workspaces", "runs", "chosen_workspace_id"];
+            let task = match command {
+                TaskCommands::Create { title, description, mut workspaces, candidates, repo } => {
+                    if let Some(repo) = repo.as_deref() {
+                        for _ in 0..candidates.unwrap_or(0) {
+                            let result = core::workspace_create(&conn, &home, repo, None, None, None, &Default::default());
+                            let target = result.as_ref().ok().map(|ws| ws.id.clone());
+                            audit(&conn, "workspace.create", target.as_deref(), &result, json!({ "repo": repo, "task": title }));
+                            workspaces.push(result?.id);
+                        }
+                    }
+                    let result = core::task_create(&conn, &title, &description, &workspaces);
+                    let target = result.as_ref().ok().map(|task| task.id.clone());
+                    audit(&conn, "task.create", target.as_deref(), &result, json!({ "title": title, "workspaces": workspaces }));
+                    result?
+                }
+                TaskCommands::List { status } => {
+                    let status = status.map(|status| status.parse()).transpose()?;
+                    let tasks: Vec<_> = core::task_list(&conn, status)?
+                        .into_iter()
+                        .map(|task| {
+                            let names: Vec<_> = task.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+                            json!({
+                                "id": task.id,
+                                "status": task.status,
+                                "title": task.title,
+                                "workspaces": names.join(","),
+                                "runs": task.runs.len(),
+                                "chosen_workspace_id": task.chosen_workspace_id,
+                            })
+                        })
+                        .collect();
+                    format.list(&tasks, &columns)?;
+                    return Ok(());
+                }
+                TaskCommands::Show { id } => core::task_get(&conn, &id)?,
+                TaskCommands::Update { id, title, description, status } => {
+                    let result = status
+                        .as_deref()
+                        .map(str::parse)
+                        .transpose()
+                        .and_then(|status| core::task_update(&conn, &id, title.as_deref(), description.as_deref(), status));
+                    let metadata = json!({ "title": title, "description_changed": description.is_some(), "status": status });
+                    task_audit(&conn, "task.update", &result, metadata);
+                    result?
+                }
+                TaskCommands::Link { id, workspace } => {
+                    let result = core::task_link(&conn, &id, &workspace);
+                    task_audit(&conn, "task.link", &result, json!({ "workspace_id": workspace, "unlink": false }));
+                    result?
+                }
+                TaskCommands::Unlink { id, workspace } => {
+                    let result = core::task_unlink(&conn, &id, &workspace);
+                    task_audit(&conn, "task.link", &result, json!({ "workspace_id": workspace, "unlink": true }));
+                    result?
+                }
+                TaskCommands::Choose { id, workspace } => {
+                    let result = core::task_choose(&conn, &id, &workspace);
+                    task_audit(&conn, "task.choose", &result, json!({ "workspace_id": workspace }));
+                    result?
+                }
+                TaskCommands::Delete { id } => {
+                    let result = core::task_delete(&conn, &id);
+                    task_audit(&conn, "task.delete", &result, json!({}));
+                    let task = result?;
+                    format.text(&task, &task.id)?;
+                    return Ok(());
+                }
+            };
+            let mut text = format!("{}\t{}\t{}", task.id, task.status, task.title);
+            if !task.description.is_empty() {
+                text.push_str(&format!("\n{}", task.description));
+            }
+            for ws in &task.workspaces {
+                let runs = task.runs.iter().filter(|run| run.workspace_id == ws.workspace_id).count();
+                let chosen = if task.chosen_workspace_id.as_ref() == Some(&ws.workspace_id) { "  (chosen)" } else { "" };
+                text.push_str(&format!("\n  {}\t{}\t{} run(s){chosen}", ws.name, ws.branch, runs));
+            }
+            format.text(&task, &text)?;
+        }
+        Commands::Review { command } => {
+            let conn = core::connect(&home)?;
+            let columns = ["id", "file_path", "start_line", "end_line", "author", "resolved", "body"];
+            match command {
+                ReviewCommands::List { workspace, all } => {
+                    format.list(&core::review_comment_list(&conn, &workspace, all)?, &columns)?;
+                }
+                ReviewCommands::Add { workspace, file, lines, body, author } => {
+                    let result = parse_line_range(&lines).and_then(|lines| {
+                        core::review_comment_add(&conn, &workspace, &file, lines, author.as_deref(), &body)
+                    });
+                    let metadata = json!({ "file_path": file, "lines": lines });
+                    audit(&conn, "review.comment_add", Some(&workspace), &result, metadata);
+                    let comment = result?;
+                    format.text(&comment, &comment.id)?;
+                }
+                ReviewCommands::Edit { id, body } => {
+                    let result = core::review_comment_update(&conn, &id, Some(&body), None);
+                    review_audit(&conn, "review.comment_update", &id, &result, json!({ "edited": true }));
+                    let comment = result?;
+                    format.text(&comment, &comment.id)?;
+                }
+                ReviewCommands::Resolve { ref id } | ReviewCommands::Reopen { ref id } => {
+                    let resolved = matches!(command, ReviewCommands::Resolve { .. });
+                    let result = core::review_comment_update(&conn, id, None, Some(resolved));
+                    review_audit(&conn, "review.comment_update", id, &result, json!({ "resolved": resolved }));
+                    let comment = result?;
+                    format.text(&comment, &comment.id)?;
+                }
+                ReviewCommands::Delete { id } => {
+                    let result = core::review_comment_delete(&conn, &id);
+                    review_audit(&conn, "review.comment_delete", &id, &result, json!({}));
+                    let comment = result?;
+                    format.text(&comment, &comment.id)?;
+                }
+                ReviewCommands::Prompt { workspace } => match core::review_prompt(&conn, &workspace)? {
+                    Some(prompt) if format.is_structured() => format.value(&json!({ "prompt": prompt }))?,
+                    Some(prompt) => println!("{prompt}"),
+                    None if format.is_structured() => format.value(&json!({ "prompt": null }))?,
+                    None => eprintln!("no unresolved review comments"),
+                },
+                ReviewCommands::Sync { workspace } => {
+                    let result = core::review_comments_sync(&conn, &workspace);
+                    let metadata = match &result {
+                        Ok(sync) => json!({ "pr": sync.pr_number, "added": sync.added.len(), "updated": sync.updated }),
+                        Err(_) => json!({}),
+                    };
+                    audit(&conn, "review.sync", Some(&workspace), &result, metadata);
+                    let sync = result?;
+                    if format.is_structured() {
+                        format.value(&serde_json::to_value(&sync)?)?;
+                    } else {
+                        if !sync.added.is_empty() {
+                            format.list(&sync.added, &columns)?;
+                        }
+                        eprintln!(
+                            "PR #{}: {} new comment(s), {} updated",
+                            sync.pr_number,
+                            sync.added.len(),
+                            sync.updated
+                        );
+                    }
+                }
+            }
+        }
+        Commands::Search { query, limit } => {
+            let conn = core::connect(&home)?;
+            let hits = core::search(&conn, &query, limit)?;
+            format.list(&hits, &["kind", "repo", "workspace", "label", "snippet"])?;
+        }
+        Commands::Archive { command } => {
+            let conn = core::connect(&home)?;
+            // Archived workspaces keep their rows until purged, so prefixes still resolve
+            let resolve = |ws_ref: &str| {
+                core::workspace_get(&conn, ws_ref).map_or_else(|_| ws_ref.to_string(), |ws| ws.id)
+            };
+            match command {
+                ArchiveCommands::List { workspace } => {
+                    let ws_id = workspace.as_deref().map(resolve);
+                    let archives = core::archive_list(&home, ws_id.as_deref())?;
+                    format.list(&archives, &["workspace_id", "timestamp", "has_session", "has_chat", "bytes"])?;
+                }
+                ArchiveCommands::Show { workspace, timestamp } => {
+                    let ws_id = resolve(&workspace);
+                    let timestamp = match timestamp {
+                        Some(timestamp) => timestamp,
+                        None => match core::archive_list(&home, Some(&ws_id))?.into_iter().next() {
+                            Some(entry) => entry.timestamp,
+                            None => return Err(anyhow!("no archived sessions for workspace {workspace}")),
+                        },
+                    };
+                    let archived = core::archive_read(&home, &ws_id, &timestamp)?;
+                    if format.is_structured() {
+                        format.value(&serde_json::to_value(&archived)?)?;
+                    } else {
+                        println!("{} {}", archived.workspace_id, archived.timestamp);
+                        if let Some(session) = &archived.session {
+                            let resume = session.resume_id.as_deref().unwrap_or("-");
+                            println!(
+                                "session {} (resume {resume}), {} to {}",
+                                session.agent_id, session.started_at, session.updated_at
+                            );
+                            let provenance: Vec<String> = [
+                                ("engine", &session.engine_version),
+                                ("model", &session.model),
+                                ("base", &session.base_sha),
+                                ("conductor", &session.conductor_version),
+                            ]
+                            .into_iter()
+                            .filter_map(|(label, value)| value.as_ref().map(|value| format!("{label} {value}")))
+                            .collect();
+                            if !provenance.is_empty() {
+                                println!("last run: {}", provenance.join(", "));
+                            }
+                        }
+                        for entry in &archived.chat {
+                            println!("\n## {} ({})\n\n{}", entry.role, entry.timestamp, entry.content);
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Home { command } => match command {
+            HomeCommands::Migrate { to } => {
+                daemon::ensure_stopped(&home, "move the home")?;
+                let result = core::home_migrate(&home, &to);
+                // Recorded in whichever home ended up with the DB
+                let audit_home = if result.is_ok() { &to } else { &home };
+                if core::db_path(audit_home).exists() {
+                    let conn = core::connect(audit_home)?;
+                    audit(&conn, "home.migrate", None, &result, json!({ "from": home, "to": to }));
+                }
+                let migration = result?;
+                let mut text = format!(
+                    "moved {} -> {}{}\n{} repo(s) and {} workspace(s) repointed",
+                    migration.from,
+                    migration.to,
+                    if migration.copied { " (copied across filesystems)" } else { "" },
+                    migration.repos_rewritten,
+                    migration.workspaces_rewritten,
+                );
+                for repair in migration.repairs.iter().filter(|repair| repair.action == "broken") {
+                    text.push_str(&format!("\nbroken: {} {}: {}", repair.target, repair.name, repair.detail));
+                }
+                if migration.old_home_kept {
+                    text.push_str(&format!("\nthe old home was kept at {} until the above is sorted out", migration.from));
+                }
+                text.push_str(&format!("\nuse --home {} (and point the desktop app at it) from now on", migration.to));
+                format.text(&migration, &text)?;
+            }
+        },
+        Commands::Db { command } => match command {
+            DbCommands::Migrate { dry_run, to } => {
+                let mut conn = core::open(&home)?;
+                let from = core::migrate::current_version(&conn)?;
+                let to = to.unwrap_or(core::SCHEMA_VERSION);
+                let steps = if dry_run {
+                    core::migrate::pending(&conn, to)?
+                } else {
+                    let result = core::migrate::migrate_to(&mut conn, to);
+                    // A rollback past the audit log's version has nowhere to record itself
+                    let has_audit: bool = conn
+                        .query_row(
+                            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'audit_events')",
+                            [],
+                            |row| row.get(0),
+                        )
+                        .unwrap_or(false);
+                    if has_audit && !matches!(&result, Ok(steps) if steps.is_empty()) {
+                        audit(&conn, "db.migrate", None, &result, json!({ "from": from, "to": to }));
+                    }
+                    result?
+                };
+                if format.is_structured() {
+                    format.value(&json!({ "from": from, "to": to, "dry_run": dry_run, "steps": steps }))?;
+                } else if steps.is_empty() {
+                    println!("schema is at version {from}");
+                } else {
+                    for step in &steps {
+                        println!("{}\t{}\t{}", step.direction, step.to_version, step.name);
+                        if dry_run {
+                            for line in step.sql.trim_end().lines().skip_while(|line| line.trim().is_empty()) {
+                                let line = line.trim_start_matches("            ");
+                                println!("{}", if line.is_empty() { String::new() } else { format!("    {line}") });
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        Commands::Audit {
+            action,
+            target,
+            since,
+            limit,
+        } => {
+            let conn = core::connect(&home)?;
+            let filter = core::AuditFilter {
+                action,
+                target,
+                since,
+                limit,
+            };
+            let events = core::audit_list(&conn, &filter)?;
+            format.list(&events, &["id", "created_at", "source", "action", "target", "ok"])?;
+        }
+        Commands::McpServe { socket } => match socket {
+            Some(socket) => mcp::serve_socket(&home, &socket)?,
+            None => mcp::serve_stdio(&home)?,
+        },
+        Commands::Open {
+            workspace,
+            terminal,
+            reveal,
+        } => {
+            let conn = core::connect(&home)?;
+            let path = core::workspace_path(&conn, &workspace)?;
+            if let Err(e) = core::workspace_activity_record(&conn, &workspace, core::ActivityKind::Opened) {
+                eprintln!("warning: failed to record workspace activity: {e}");
+            }
+            if terminal || reveal {
+                let command = if terminal {
+                    core::terminal_command(&core::config_load(&home)?, &path)?
+                } else {
+                    core::reveal_command(&path)
+                };
+                // GUI launchers: detach rather than wait on the window
+                Command::new(&command[0])
+                    .args(&command[1..])
+                    .current_dir(&path)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .with_context(|| format!("failed to launch {}", command[0]))?;
+                if format.is_structured() {
+                    format.value(&json!({ "path": path, "command": command }))?;
+                }
+            } else {
+                let editor = core::editor_command(&core::config_load(&home)?, true)?;
+                if format.is_structured() {
+                    format.value(&json!({ "path": path, "editor": editor[0] }))?;
+                }
+                // Run in the foreground so terminal editors get the tty
+                let status = Command::new(&editor[0])
+                    .args(&editor[1..])
+                    .arg(&path)
+                    .current_dir(&path)
+                    .status()
+                    .with_context(|| format!("failed to launch {}", editor[0]))?;
+                if !status.success() {
+                    return Err(anyhow!("{} exited with {status}", editor[0]));
+                }
+            }
+        }
+        Commands::Exec {
+            workspace,
+            cwd,
+            pty,
+            mut cmd,
+        } => {
             if cmd.first().map(|s| s.as_str()) == Some("--") {
                 cmd.remove(0);
             }
             if cmd.is_empty() {
-                return Err(anyhow!("Usage: conductor exec [--workspace <id>|--cwd <path>] -- <command...>"));
+                return Err(anyhow!(
+                    "Usage: conductor exec [--workspace <id>|--cwd <path>] [--pty] -- <command...>"
+                ));
             }
             if workspace.is_some() && cwd.is_some() {
                 return Err(anyhow!("exec: only one of --workspace or --cwd may be set"));
             }
 
-            let cwd = match (workspace, cwd) {
+            if pty && format.is_structured() {
+                return Err(anyhow!("exec: --pty output goes to the terminal; drop --format/--json"));
+            }
+            // Without --cwd the command runs in a workspace: the named one at its root, or
+            // the one the current directory is in, right here
+            let (cwd, ws_path) = match (workspace, cwd) {
                 (Some(ws), None) => {
-                    let conn = core::connect(&home)?;
-                    Some(core::workspace_path(&conn, &ws)?)
+                    let path = core::workspace_path(&core::connect(&home)?, &ws)?;
+                    (Some(path.clone()), Some(path))
+                }
+                (None, Some(path)) => (Some(path), None),
+                _ => {
+                    let ws = current_workspace(&core::connect(&home)?)?;
+                    (Some(std::env::current_dir()?), Some(PathBuf::from(ws.path)))
                 }
-                (None, Some(path)) => Some(path),
-                _ => None,
+            };
+            // Repos that opt into their dev container run commands there too
+            let cmd = match cwd.as_deref() {
+                Some(dir) => core::isolation::shell_command(&core::connect(&home)?, &home, dir, cmd)?,
+                None => cmd,
             };
 
-            if cli.json {
+            if pty {
+                let (exit_code, tokens) = pty::exec_pty(&cmd, cwd.as_deref())?;
+                for resume in tokens {
+                    // Inside a workspace the latest token becomes the session's resume id
+                    if let Some(ws_path) = ws_path.as_deref() {
+                        if let Err(e) = core::session_upsert_resume_id(ws_path, resume.engine, &resume.token) {
+                            eprintln!("warning: failed to save resume id: {e}");
+                        }
+                    }
+                    eprintln!("resume\t{}\t{}", resume.engine, resume.token);
+                }
+                std::process::exit(exit_code);
+            }
+            if format.is_structured() {
                 let exit_code = exec_json(&cmd, cwd.as_deref())?;
                 std::process::exit(exit_code);
             } else {
@@ -370,6 +1800,28 @@ fn route_stdout_line(parser: &mut AgentParser, line: &str) -> Vec<Value> {
     Vec::new()
 }
 
+// `agent replay`: structured formats get every event (unrecognized JSON included),
+// text gets the same rendering as `agent run`
+fn agent_replay(input: &Path, format: Format) -> Result<()> {
+    let reader: Box<dyn BufRead> = if input == Path::new("-") {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        let file = std::fs::File::open(input).with_context(|| format!("failed to open {}", input.display()))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut parser = AgentParser::new();
+    for line in reader.lines() {
+        for event in route_stdout_line(&mut parser, &line?) {
+            if format.is_structured() {
+                format.value(&event)?;
+            } else {
+                daemon::render_event(&event);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn exec_json(cmd: &[String], cwd: Option<&Path>) -> Result<i32> {
     let mut command = Command::new(&cmd[0]);
     command.args(&cmd[1..]);
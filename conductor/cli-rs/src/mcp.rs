@@ -0,0 +1,212 @@
+//! `conductor mcp-serve`: Conductor operations as Model Context Protocol tools, over
+//! newline-delimited JSON-RPC on stdio or a Unix socket. Reads go straight to core;
+//! agent runs go through the daemon like `conductor agent run`.
+
+use anyhow::{anyhow, Context, Result};
+use conductor_core as core;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::daemon;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Serve one client on stdin/stdout until it hangs up
+pub fn serve_stdio(home: &Path) -> Result<()> {
+    let stdin = std::io::stdin();
+    serve(home, stdin.lock(), std::io::stdout())
+}
+
+/// Accept clients on a Unix socket, one thread per connection
+pub fn serve_socket(home: &Path, socket: &Path) -> Result<()> {
+    if socket.exists() {
+        // Only clear out a stale socket; never delete some other file
+        let stale = std::fs::metadata(socket).map(|meta| {
+            use std::os::unix::fs::FileTypeExt;
+            meta.file_type().is_socket()
+        })?;
+        if !stale {
+            anyhow::bail!("{} exists and is not a socket", socket.display());
+        }
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket).with_context(|| format!("failed to listen on {}", socket.display()))?;
+    // User only, like the daemon's socket: every client acts with the daemon token
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    }
+    eprintln!("conductor mcp-serve listening on {}", socket.display());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let home: PathBuf = home.to_path_buf();
+        thread::spawn(move || {
+            let reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(e) => return eprintln!("mcp-serve: {e}"),
+            };
+            if let Err(e) = serve(&home, reader, stream) {
+                eprintln!("mcp-serve: {e:#}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve(home: &Path, reader: impl BufRead, mut writer: impl Write) -> Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(home, &message),
+            Err(e) => Some(error_response(Value::Null, -32700, &format!("parse error: {e}"))),
+        };
+        if let Some(response) = response {
+            writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// One JSON-RPC message; notifications (no id) get no response
+fn handle(home: &Path, message: &Value) -> Option<Value> {
+    let id = message.get("id").cloned()?;
+    let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    let result = match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": params
+                .get("protocolVersion")
+                .and_then(Value::as_str)
+                .unwrap_or(PROTOCOL_VERSION),
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "conductor", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tool_definitions() })),
+        "tools/call" => {
+            let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+            let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            // Tool failures are results the model can read, not protocol errors
+            Ok(match call_tool(home, name, &args) {
+                Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+                Err(e) => json!({ "content": [{ "type": "text", "text": format!("{e:#}") }], "isError": true }),
+            })
+        }
+        _ => Err(error_response(id.clone(), -32601, &format!("method not found: {method}"))),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => error,
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn tool_definitions() -> Value {
+    let workspace = json!({ "type": "string", "description": "Workspace id or unique id prefix" });
+    json!([
+        {
+            "name": "list_repos",
+            "description": "List repositories registered with Conductor",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "list_workspaces",
+            "description": "List workspaces (isolated git worktrees), optionally for one repo",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "repo": { "type": "string", "description": "Repo id or name" } },
+            },
+        },
+        {
+            "name": "get_workspace_changes",
+            "description": "Files changed in a workspace relative to its base branch",
+            "inputSchema": { "type": "object", "properties": { "workspace": workspace }, "required": ["workspace"] },
+        },
+        {
+            "name": "get_workspace_diff",
+            "description": "Unified diff of a workspace against its base branch, or of one file in it",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "workspace": workspace,
+                    "path": { "type": "string", "description": "Limit the diff to this file" },
+                },
+                "required": ["workspace"],
+            },
+        },
+        {
+            "name": "read_chat",
+            "description": "The workspace's chat transcript (markdown)",
+            "inputSchema": { "type": "object", "properties": { "workspace": workspace }, "required": ["workspace"] },
+        },
+        {
+            "name": "run_agent",
+            "description": "Run a coding agent in a workspace and wait for its reply (needs the daemon)",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "workspace": workspace,
+                    "prompt": { "type": "string" },
+                    "engine": { "type": "string", "enum": ["claude", "codex", "gemini"], "default": "claude" },
+                    "isolation": {
+                        "type": "string",
+                        "enum": ["host", "docker", "devcontainer", "sandbox"],
+                        "description": "Where the engine runs (default: the repo's setting)",
+                    },
+                },
+                "required": ["workspace", "prompt"],
+            },
+        },
+    ])
+}
+
+fn call_tool(home: &Path, name: &str, args: &Value) -> Result<String> {
+    let arg = |key: &str| args.get(key).and_then(Value::as_str);
+    let required = |key: &str| arg(key).ok_or_else(|| anyhow!("missing argument: {key}"));
+    let pretty = |value: Value| Ok(serde_json::to_string_pretty(&value)?);
+
+    match name {
+        "list_repos" => {
+            let conn = core::connect(home)?;
+            pretty(serde_json::to_value(core::repo_list(&conn)?)?)
+        }
+        "list_workspaces" => {
+            let conn = core::connect(home)?;
+            pretty(serde_json::to_value(core::workspace_list(&conn, arg("repo"))?)?)
+        }
+        "get_workspace_changes" => {
+            let conn = core::connect(home)?;
+            pretty(serde_json::to_value(core::workspace_changes(&conn, required("workspace")?)?)?)
+        }
+        "get_workspace_diff" => {
+            let conn = core::connect(home)?;
+            let workspace = required("workspace")?;
+            let diff = match arg("path") {
+                Some(path) => core::workspace_file_diff(&conn, workspace, path)?,
+                None => core::workspace_diff(&conn, workspace)?,
+            };
+            Ok(if diff.is_empty() { "(no changes)".to_string() } else { diff })
+        }
+        "read_chat" => {
+            let conn = core::connect(home)?;
+            core::chat_read(&core::workspace_path(&conn, required("workspace")?)?)
+        }
+        "run_agent" => {
+            let engine = arg("engine").unwrap_or("claude");
+            let isolation = arg("isolation").unwrap_or("");
+            let reply = daemon::agent_run_captured(home, required("workspace")?, engine, isolation, required("prompt")?)?;
+            pretty(serde_json::to_value(reply)?)
+        }
+        _ => Err(anyhow!("unknown tool: {name}")),
+    }
+}
@@ -0,0 +1,159 @@
+//! `conductor exec --pty`: run a command on a pseudo-terminal, proxying our tty in raw mode
+
+use anyhow::{anyhow, Result};
+use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use regex::Regex;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{extract_resume_tokens, resume_patterns, ResumeEvent};
+
+/// Run `cmd` interactively; returns its exit code and the resume tokens seen in its output
+pub fn exec_pty(cmd: &[String], cwd: Option<&Path>) -> Result<(i32, Vec<ResumeEvent>)> {
+    let pair = native_pty_system()
+        .openpty(terminal_size())
+        .map_err(|e| anyhow!("exec: failed to open pty: {e}"))?;
+
+    let mut command = CommandBuilder::new(&cmd[0]);
+    command.args(&cmd[1..]);
+    if let Some(cwd) = cwd {
+        command.cwd(cwd);
+    }
+    let mut child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|e| anyhow!("exec: failed to run {}: {e}", cmd[0]))?;
+    // Keeping the slave open here would stop reads from ever seeing EOF
+    drop(pair.slave);
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let mut writer = pair.master.take_writer()?;
+    let master: Arc<Mutex<Box<dyn MasterPty + Send>>> = Arc::new(Mutex::new(pair.master));
+
+    let _raw = RawMode::enable();
+    forward_resizes(master.clone());
+
+    // Never joined: it sits in read() until the process exits
+    thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || writer.write_all(&buf[..n]).is_err() {
+                break;
+            }
+            let _ = writer.flush();
+        }
+    });
+
+    let patterns = resume_patterns()?;
+    let ansi = Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-_]")?;
+    let mut tokens: Vec<ResumeEvent> = Vec::new();
+    let mut scan = |line: &[u8]| {
+        let text = String::from_utf8_lossy(line);
+        let text = ansi.replace_all(&text, "").replace('\r', "");
+        for event in extract_resume_tokens(&text, &patterns) {
+            if !tokens.iter().any(|t| t.engine == event.engine && t.token == event.token) {
+                tokens.push(event);
+            }
+        }
+    };
+
+    let mut stdout = std::io::stdout();
+    let mut line = Vec::new();
+    let mut buf = [0u8; 8192];
+    // Linux reports EIO rather than EOF once the child side closes
+    while let Ok(n) = reader.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        stdout.write_all(&buf[..n])?;
+        stdout.flush()?;
+        for &byte in &buf[..n] {
+            if byte == b'\n' {
+                scan(&line);
+                line.clear();
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+    scan(&line);
+
+    let status = child.wait()?;
+    Ok((status.exit_code() as i32, tokens))
+}
+
+fn terminal_size() -> PtySize {
+    let mut size = PtySize {
+        rows: 24,
+        cols: 80,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes into the winsize we pass
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_col > 0 {
+        size.rows = ws.ws_row;
+        size.cols = ws.ws_col;
+    }
+    size
+}
+
+// Follow SIGWINCH so full-screen programs redraw at the new size
+fn forward_resizes(master: Arc<Mutex<Box<dyn MasterPty + Send>>>) {
+    thread::spawn(move || {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+            return;
+        };
+        runtime.block_on(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let Ok(mut winch) = signal(SignalKind::window_change()) else {
+                return;
+            };
+            while winch.recv().await.is_some() {
+                if let Ok(master) = master.lock() {
+                    let _ = master.resize(terminal_size());
+                }
+            }
+        });
+    });
+}
+
+/// Puts stdin into raw mode (when it is a tty) and restores it on drop
+struct RawMode {
+    original: Option<libc::termios>,
+}
+
+impl RawMode {
+    fn enable() -> Self {
+        // SAFETY: termios calls on our own stdin; `original` is only used if tcgetattr filled it
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return Self { original: None };
+            }
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Self { original: None };
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            Self {
+                original: Some(original),
+            }
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(original) = &self.original {
+            // SAFETY: restores the attributes saved in enable()
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
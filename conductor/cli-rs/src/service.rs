@@ -0,0 +1,198 @@
+//! `conductor daemon install` / `uninstall`: run conductor-daemon as a login service
+//! (a LaunchAgent on macOS, a systemd user unit on Linux) instead of having whichever
+//! client connects first spawn it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const LABEL: &str = "com.conductor.daemon";
+const UNIT: &str = "conductor-daemon.service";
+
+#[derive(Serialize)]
+pub struct Service {
+    pub manager: &'static str,
+    pub path: PathBuf,
+    pub daemon: Option<PathBuf>,
+}
+
+/// Write the service definition and start it; reinstalling replaces the old definition
+pub fn install() -> Result<Service> {
+    let daemon = daemon_binary()?;
+    let path = service_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let search_path = env::var("PATH").unwrap_or_default();
+
+    if cfg!(target_os = "macos") {
+        let logs = user_home()?.join("Library/Logs/conductor-daemon.log");
+        // bootstrap refuses a label that is already loaded, so unload any previous install first
+        let _ = launchctl(&["bootout", &format!("{}/{LABEL}", launchd_domain())]);
+        write(&path, &launch_agent(&daemon, &search_path, &logs))?;
+        launchctl(&["bootstrap", &launchd_domain(), &path.to_string_lossy()])?;
+    } else {
+        write(&path, &systemd_unit(&daemon, &search_path))?;
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", UNIT])?;
+    }
+    Ok(Service { manager: manager(), path, daemon: Some(daemon) })
+}
+
+/// Stop the service and remove its definition; a no-op when it was never installed
+pub fn uninstall() -> Result<Service> {
+    let path = service_path()?;
+    if path.exists() {
+        if cfg!(target_os = "macos") {
+            // Already unloaded is fine; what matters is that it won't come back at login
+            let _ = launchctl(&["bootout", &format!("{}/{LABEL}", launchd_domain())]);
+        } else {
+            let _ = systemctl(&["disable", "--now", UNIT]);
+        }
+        std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+        if !cfg!(target_os = "macos") {
+            systemctl(&["daemon-reload"])?;
+        }
+    }
+    Ok(Service { manager: manager(), path, daemon: None })
+}
+
+fn manager() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "launchd"
+    } else {
+        "systemd"
+    }
+}
+
+fn user_home() -> Result<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from).ok_or_else(|| anyhow!("HOME is not set"))
+}
+
+fn service_path() -> Result<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return Ok(user_home()?.join("Library/LaunchAgents").join(format!("{LABEL}.plist")));
+    }
+    if !cfg!(target_os = "linux") {
+        bail!("daemon install supports macOS (launchd) and Linux (systemd) only");
+    }
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .map_or_else(|| user_home().map(|home| home.join(".config")), Ok)?;
+    Ok(config.join("systemd/user").join(UNIT))
+}
+
+/// The service needs an absolute path: prefer the daemon shipped next to this binary, then PATH
+fn daemon_binary() -> Result<PathBuf> {
+    let sibling = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.canonicalize().ok())
+        .and_then(|exe| exe.parent().map(|dir| dir.join("conductor-daemon")));
+    let on_path = env::var_os("PATH")
+        .into_iter()
+        .flat_map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .map(|dir| dir.join("conductor-daemon"));
+    sibling
+        .into_iter()
+        .chain(on_path)
+        .find(|candidate| candidate.is_absolute() && candidate.is_file())
+        .ok_or_else(|| anyhow!("conductor-daemon not found next to this binary or on PATH"))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn launchd_domain() -> String {
+    // SAFETY: getuid has no preconditions and cannot fail
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    manage("launchctl", args)
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let mut full = vec!["--user"];
+    full.extend_from_slice(args);
+    manage("systemctl", &full)
+}
+
+fn manage(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !output.status.success() {
+        bail!("{program} {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// --replace takes over from a daemon some client already spawned; restarting only after a
+// failure keeps launchd from fighting a client that deliberately replaced this one
+fn launch_agent(daemon: &Path, search_path: &str, logs: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{daemon}</string>
+        <string>--replace</string>
+    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>PATH</key>
+        <string>{path}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>{logs}</string>
+    <key>StandardErrorPath</key>
+    <string>{logs}</string>
+</dict>
+</plist>
+"#,
+        daemon = xml_escape(&daemon.to_string_lossy()),
+        path = xml_escape(search_path),
+        logs = xml_escape(&logs.to_string_lossy()),
+    )
+}
+
+fn systemd_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%"))
+}
+
+fn systemd_unit(daemon: &Path, search_path: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Conductor daemon\n\
+         \n\
+         [Service]\n\
+         ExecStart={} --replace\n\
+         Environment={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        systemd_quote(&daemon.to_string_lossy()),
+        systemd_quote(&format!("PATH={search_path}")),
+    )
+}
@@ -0,0 +1,54 @@
+//! `conductor shell-init <shell>`: shell functions for moving between workspaces, meant
+//! to be eval'd from the shell's rc file
+
+use anyhow::{bail, Result};
+use std::path::Path;
+
+/// The integration script for `shell`. A non-default home is baked into the conductor
+/// invocations, so `conductor --home X shell-init` keeps talking to X.
+pub fn init(shell: &str, home: Option<&Path>) -> Result<String> {
+    let conductor = match home {
+        Some(home) => format!("command conductor --home {}", quote(&home.to_string_lossy())),
+        None => "command conductor".to_string(),
+    };
+    let script = match shell {
+        "bash" | "zsh" => format!(
+            r#"# conductor shell integration: eval "$({conductor_bare} shell-init {shell})"
+# cw <workspace>: cd into a workspace and export CONDUCTOR_WORKSPACE (its id) for prompts and scripts
+cw() {{
+    if [ "$#" -ne 1 ]; then
+        echo "usage: cw <workspace>" >&2
+        return 2
+    fi
+    local dir
+    dir="$({conductor} workspace path "$1")" || return
+    builtin cd -- "$dir" || return
+    export CONDUCTOR_WORKSPACE="$({conductor} which)"
+}}
+"#,
+            conductor_bare = conductor.trim_start_matches("command "),
+        ),
+        "fish" => format!(
+            r#"# conductor shell integration: {conductor_bare} shell-init fish | source
+# cw <workspace>: cd into a workspace and export CONDUCTOR_WORKSPACE (its id) for prompts and scripts
+function cw --description 'cd into a conductor workspace'
+    if test (count $argv) -ne 1
+        echo "usage: cw <workspace>" >&2
+        return 2
+    end
+    set -l dir ({conductor} workspace path $argv[1]); or return
+    builtin cd $dir; or return
+    set -gx CONDUCTOR_WORKSPACE ({conductor} which)
+end
+"#,
+            conductor_bare = conductor.trim_start_matches("command "),
+        ),
+        other => bail!("unsupported shell {other:?}; use bash, zsh or fish"),
+    };
+    Ok(script)
+}
+
+// Single-quoted, with embedded quotes written '\'' -- read the same by bash, zsh and fish
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
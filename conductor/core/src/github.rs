@@ -0,0 +1,340 @@
+//! GitHub pull request status for workspace branches, via the `gh` CLI (which owns auth).
+//! Results are cached in the `workspace_prs` table so listings never wait on the network.
+//! Reviewers' comments on the PR are fetched on request (`review_comments_sync`).
+
+use crate::{collect_rows, db, get_workspace, workspace_head};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// How long a cached status counts as fresh for `pr_status`
+pub const PR_STATUS_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksSummary {
+    /// "passing", "failing", "pending" or "none"
+    pub state: String,
+    pub passed: u32,
+    pub failed: u32,
+    pub pending: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrStatus {
+    pub workspace_id: String,
+    pub branch: String,
+    /// "open", "merged", "closed", or "none" when the branch has no PR
+    pub state: String,
+    pub number: Option<i64>,
+    pub url: Option<String>,
+    /// GitHub's reviewDecision: "approved", "changes_requested", "review_required"
+    pub review_decision: Option<String>,
+    pub checks: ChecksSummary,
+    pub fetched_at: String,
+}
+
+impl PrStatus {
+    /// Short form for tables, e.g. "#12 open approved passing"
+    pub fn summary(&self) -> String {
+        let Some(number) = self.number else {
+            return "-".to_string();
+        };
+        let mut parts = vec![format!("#{number}"), self.state.clone()];
+        if let Some(review) = &self.review_decision {
+            parts.push(review.clone());
+        }
+        if self.checks.state != "none" {
+            parts.push(self.checks.state.clone());
+        }
+        parts.join(" ")
+    }
+}
+
+/// A reviewer's comment on a PR, from any of the three places GitHub keeps them
+#[derive(Debug, Clone)]
+pub struct PrComment {
+    /// Unique across kinds, e.g. "comment:123" (on lines), "review:45", "issue-comment:67"
+    pub github_id: String,
+    /// None for comments on the PR as a whole
+    pub path: Option<String>,
+    /// 0 when the comment is on a whole file or the whole PR
+    pub start_line: u32,
+    pub end_line: u32,
+    pub author: String,
+    pub body: String,
+    pub url: Option<String>,
+}
+
+fn pr_status_from_row(row: &Row) -> rusqlite::Result<PrStatus> {
+    let checks: String = row.get(6)?;
+    Ok(PrStatus {
+        workspace_id: row.get(0)?,
+        branch: row.get(1)?,
+        state: row.get(2)?,
+        number: row.get(3)?,
+        url: row.get(4)?,
+        review_decision: row.get(5)?,
+        checks: serde_json::from_str(&checks).unwrap_or_default(),
+        fetched_at: row.get(7)?,
+    })
+}
+
+const SELECT_PR: &str =
+    "SELECT workspace_id, branch, state, number, url, review_decision, checks, fetched_at FROM workspace_prs";
+
+/// Cached status of one workspace, however old
+pub fn pr_status_cached(conn: &Connection, workspace_id: &str) -> Result<Option<PrStatus>> {
+    let sql = format!("{SELECT_PR} WHERE workspace_id = ?");
+    db(conn.query_row(&sql, [workspace_id], pr_status_from_row).optional())
+}
+
+/// Every cached status, for listings
+pub fn pr_status_cached_all(conn: &Connection) -> Result<Vec<PrStatus>> {
+    let mut stmt = db(conn.prepare(SELECT_PR))?;
+    let rows = db(stmt.query_map([], pr_status_from_row))?;
+    collect_rows(rows)
+}
+
+/// Cached status if younger than `PR_STATUS_TTL`, otherwise ask GitHub
+pub fn pr_status(conn: &Connection, ws_ref: &str) -> Result<PrStatus> {
+    let ws = get_workspace(conn, ws_ref)?;
+    if let Some(cached) = pr_status_cached(conn, &ws.id)? {
+        let fresh = DateTime::parse_from_rfc3339(&cached.fetched_at)
+            .ok()
+            .and_then(|at| (Utc::now() - at.with_timezone(&Utc)).to_std().ok())
+            .is_some_and(|age| age < PR_STATUS_TTL);
+        if fresh {
+            return Ok(cached);
+        }
+    }
+    pr_status_refresh(conn, ws_ref)
+}
+
+/// Query GitHub for the workspace branch's PR and update the cache
+pub fn pr_status_refresh(conn: &Connection, ws_ref: &str) -> Result<PrStatus> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    let branch: String = match workspace_head(ws_path)?.branch {
+        Some(branch) => branch,
+        None => db(conn.query_row("SELECT branch FROM workspaces WHERE id = ?", [&ws.id], |row| row.get(0)))?,
+    };
+
+    let pr = gh_pr_view(ws_path, &branch)?;
+    let status = PrStatus {
+        workspace_id: ws.id.clone(),
+        branch,
+        state: pr
+            .as_ref()
+            .and_then(|pr| pr.get("state").and_then(Value::as_str))
+            .map_or("none".to_string(), str::to_lowercase),
+        number: pr.as_ref().and_then(|pr| pr.get("number").and_then(Value::as_i64)),
+        url: pr
+            .as_ref()
+            .and_then(|pr| pr.get("url").and_then(Value::as_str))
+            .map(str::to_string),
+        review_decision: pr
+            .as_ref()
+            .and_then(|pr| pr.get("reviewDecision").and_then(Value::as_str))
+            .filter(|decision| !decision.is_empty())
+            .map(str::to_lowercase),
+        checks: pr
+            .as_ref()
+            .and_then(|pr| pr.get("statusCheckRollup").and_then(Value::as_array))
+            .map(|checks| summarize_checks(checks))
+            .unwrap_or_else(|| ChecksSummary {
+                state: "none".to_string(),
+                ..Default::default()
+            }),
+        fetched_at: Utc::now().to_rfc3339(),
+    };
+
+    let checks = serde_json::to_string(&status.checks)?;
+    db(conn.execute(
+        "INSERT INTO workspace_prs (workspace_id, branch, state, number, url, review_decision, checks, fetched_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(workspace_id) DO UPDATE SET
+            branch = excluded.branch, state = excluded.state, number = excluded.number, url = excluded.url,
+            review_decision = excluded.review_decision, checks = excluded.checks, fetched_at = excluded.fetched_at",
+        params![
+            status.workspace_id,
+            status.branch,
+            status.state,
+            status.number,
+            status.url,
+            status.review_decision,
+            checks,
+            status.fetched_at
+        ],
+    ))?;
+    Ok(status)
+}
+
+// `gh pr view` for a branch; Ok(None) when the branch has no PR
+fn gh_pr_view(ws_path: &Path, branch: &str) -> Result<Option<Value>> {
+    if branch.starts_with('-') {
+        bail!("branch must not start with '-'");
+    }
+    let fields = "number,url,state,reviewDecision,statusCheckRollup";
+    let output = Command::new("gh")
+        .args(["pr", "view", branch, "--json", fields])
+        .current_dir(ws_path)
+        .output()
+        .context("failed to run gh (install the GitHub CLI and run `gh auth login`)")?;
+    if output.status.success() {
+        let pr: Value = serde_json::from_slice(&output.stdout).context("unexpected gh output")?;
+        return Ok(Some(pr));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if stderr.contains("no pull requests found") {
+        return Ok(None);
+    }
+    bail!("gh pr view {branch}: {stderr}")
+}
+
+// statusCheckRollup mixes CheckRuns (status + conclusion) and legacy StatusContexts (state)
+fn summarize_checks(checks: &[Value]) -> ChecksSummary {
+    let mut summary = ChecksSummary::default();
+    for check in checks {
+        let field = |key: &str| check.get(key).and_then(Value::as_str).unwrap_or("");
+        let outcome = match (field("status"), field("conclusion"), field("state")) {
+            (_, _, "SUCCESS") => "passed",
+            (_, _, "FAILURE" | "ERROR") => "failed",
+            (_, _, "PENDING" | "EXPECTED") => "pending",
+            ("COMPLETED", "SUCCESS" | "NEUTRAL" | "SKIPPED", _) => "passed",
+            ("COMPLETED", _, _) => "failed",
+            _ => "pending",
+        };
+        match outcome {
+            "passed" => summary.passed += 1,
+            "failed" => summary.failed += 1,
+            _ => summary.pending += 1,
+        }
+    }
+    summary.state = if checks.is_empty() {
+        "none"
+    } else if summary.failed > 0 {
+        "failing"
+    } else if summary.pending > 0 {
+        "pending"
+    } else {
+        "passing"
+    }
+    .to_string();
+    summary
+}
+
+/// Every reviewer comment on PR `number` of the repo checked out at `ws_path`: line
+/// comments, review summaries and the conversation, skipping empty review bodies
+pub fn pr_comments(ws_path: &Path, number: i64) -> Result<Vec<PrComment>> {
+    let mut comments = Vec::new();
+    for comment in gh_api_list(ws_path, &format!("repos/{{owner}}/{{repo}}/pulls/{number}/comments"))? {
+        let line = |key: &str, original: &str| {
+            let value = comment.get(key).and_then(Value::as_u64);
+            let value = value.or_else(|| comment.get(original).and_then(Value::as_u64));
+            value.and_then(|line| u32::try_from(line).ok()).unwrap_or(0)
+        };
+        // Outdated comments lose `line`; the original line is still the best anchor there is
+        let end_line = line("line", "original_line");
+        let start_line = match line("start_line", "original_start_line") {
+            0 => end_line,
+            start => start.min(end_line),
+        };
+        comments.push(PrComment {
+            github_id: format!("comment:{}", field_id(&comment)),
+            path: Some(text_field(&comment, "path")).filter(|path| !path.is_empty()),
+            start_line,
+            end_line,
+            ..pr_comment_common(&comment)
+        });
+    }
+    for review in gh_api_list(ws_path, &format!("repos/{{owner}}/{{repo}}/pulls/{number}/reviews"))? {
+        let mut comment = pr_comment_common(&review);
+        if comment.body.trim().is_empty() {
+            continue;
+        }
+        if text_field(&review, "state") == "CHANGES_REQUESTED" {
+            comment.body = format!("Requested changes: {}", comment.body);
+        }
+        comments.push(PrComment { github_id: format!("review:{}", field_id(&review)), ..comment });
+    }
+    for comment in gh_api_list(ws_path, &format!("repos/{{owner}}/{{repo}}/issues/{number}/comments"))? {
+        let github_id = format!("issue-comment:{}", field_id(&comment));
+        comments.push(PrComment { github_id, ..pr_comment_common(&comment) });
+    }
+    Ok(comments)
+}
+
+fn text_field(item: &Value, key: &str) -> String {
+    item.get(key).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+fn field_id(item: &Value) -> i64 {
+    item.get("id").and_then(Value::as_i64).unwrap_or_default()
+}
+
+// Author, body and link; the id and anchor are up to the caller
+fn pr_comment_common(item: &Value) -> PrComment {
+    PrComment {
+        github_id: String::new(),
+        path: None,
+        start_line: 0,
+        end_line: 0,
+        author: item.pointer("/user/login").and_then(Value::as_str).unwrap_or("ghost").to_string(),
+        body: text_field(item, "body").trim().to_string(),
+        url: Some(text_field(item, "html_url")).filter(|url| !url.is_empty()),
+    }
+}
+
+// Every page of a list endpoint. `gh api --paginate` prints each page as its own JSON
+// array, so the output is read as a stream of arrays
+fn gh_api_list(ws_path: &Path, endpoint: &str) -> Result<Vec<Value>> {
+    let output = Command::new("gh")
+        .args(["api", "--paginate", endpoint])
+        .current_dir(ws_path)
+        .output()
+        .context("failed to run gh (install the GitHub CLI and run `gh auth login`)")?;
+    if !output.status.success() {
+        bail!("gh api {endpoint}: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let mut items = Vec::new();
+    for page in serde_json::Deserializer::from_slice(&output.stdout).into_iter::<Vec<Value>>() {
+        items.extend(page.context("unexpected gh api output")?);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn summary(state: &str, passed: u32, failed: u32, pending: u32) -> ChecksSummary {
+        ChecksSummary { state: state.to_string(), passed, failed, pending }
+    }
+
+    #[test]
+    fn no_checks() {
+        assert_eq!(summarize_checks(&[]), summary("none", 0, 0, 0));
+    }
+
+    #[test]
+    fn check_runs_and_status_contexts() {
+        let run = |status: &str, conclusion: &str| json!({ "status": status, "conclusion": conclusion });
+        let context = |state: &str| json!({ "state": state });
+        let passing = [run("COMPLETED", "SUCCESS"), run("COMPLETED", "SKIPPED"), run("COMPLETED", "NEUTRAL")];
+        assert_eq!(summarize_checks(&passing), summary("passing", 3, 0, 0));
+
+        let pending = [run("COMPLETED", "SUCCESS"), run("IN_PROGRESS", ""), context("EXPECTED")];
+        assert_eq!(summarize_checks(&pending), summary("pending", 1, 0, 2));
+
+        let failing = [run("COMPLETED", "FAILURE"), run("QUEUED", ""), context("SUCCESS"), context("ERROR")];
+        assert_eq!(summarize_checks(&failing), summary("failing", 1, 2, 1));
+
+        assert_eq!(summarize_checks(&[run("COMPLETED", "TIMED_OUT")]), summary("failing", 0, 1, 0));
+    }
+}
@@ -0,0 +1,605 @@
+//! Where agent engines run. "host" spawns the engine directly in the workspace;
+//! "docker" wraps the same command line in `docker run` with only the worktree (and
+//! the git metadata it points at) mounted; "devcontainer" runs it through
+//! `devcontainer exec` in the repo's own dev container; "sandbox" runs it on the host
+//! under bubblewrap (Linux) or sandbox-exec (macOS) with writes confined to the
+//! workspace and the engine CLIs' own state. The daemon owns the processes; this module
+//! turns settings into command lines.
+
+use crate::{
+    config_load, find_in_path, run, workspace_for_path, worktree_git_dir, Config, GitCmd, RepoConfig, Workspace,
+};
+use anyhow::{anyhow, bail, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolation {
+    #[default]
+    Host,
+    Docker,
+    Devcontainer,
+    Sandbox,
+}
+
+impl Isolation {
+    /// The engine runs as the program on this machine's PATH, not one in a container
+    pub fn on_host(self) -> bool {
+        matches!(self, Self::Host | Self::Sandbox)
+    }
+}
+
+impl fmt::Display for Isolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Host => "host",
+            Self::Docker => "docker",
+            Self::Devcontainer => "devcontainer",
+            Self::Sandbox => "sandbox",
+        })
+    }
+}
+
+impl std::str::FromStr for Isolation {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "host" => Ok(Self::Host),
+            "docker" => Ok(Self::Docker),
+            "devcontainer" => Ok(Self::Devcontainer),
+            "sandbox" => Ok(Self::Sandbox),
+            other => bail!("unknown isolation: {other} (expected host, docker, devcontainer or sandbox)"),
+        }
+    }
+}
+
+/// Container settings for `docker` runs, globally or per repo under `repos`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerConfig {
+    /// Image with the engine CLIs installed, e.g. "ghcr.io/acme/agent-toolchain:latest"
+    pub image: String,
+    /// `docker run --network`: "none" to cut the agent off, or a named network
+    /// that only reaches what it needs (default "bridge")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// `--cpus`, e.g. "2"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<String>,
+    /// `--memory`, e.g. "4g"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// `--pids-limit`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<u32>,
+    /// Host environment variables passed through, e.g. "ANTHROPIC_API_KEY"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    /// Extra `--volume` specs, e.g. "/home/me/.claude:/home/agent/.claude"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+}
+
+/// Extra paths sandboxed engines may write to, per repo under `repos`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub writable: Vec<PathBuf>,
+}
+
+// The workspace's repo entry under `repos`, by name or id
+fn repo_config<'a>(config: &'a Config, ws: &Workspace) -> Option<&'a RepoConfig> {
+    config.repos.get(&ws.repo).or_else(|| config.repos.get(&ws.repo_id))
+}
+
+/// Docker settings for a workspace: its repo's `repos` entry (by name or id) over the
+/// global ones
+pub fn docker_config(config: &Config, ws: &Workspace) -> Result<DockerConfig> {
+    let docker = repo_config(config, ws)
+        .and_then(|settings| settings.docker.clone())
+        .or_else(|| config.docker.clone())
+        .unwrap_or_default();
+    if docker.image.is_empty() {
+        bail!(
+            "no docker image configured for {}: set docker.image (or repos.{}.docker.image) in config.json",
+            ws.repo,
+            ws.repo
+        );
+    }
+    Ok(docker)
+}
+
+/// Container name for an agent session, so it can be removed when the run is stopped
+pub fn container_name(session_id: &str) -> String {
+    let id: String = session_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("conductor-agent-{id}")
+}
+
+/// `docker run` line that runs `program args` in `cwd` with `workspace` mounted at the
+/// same path, so paths in agent output match the host
+pub fn docker_command(
+    docker: &DockerConfig,
+    container: &str,
+    workspace: &Path,
+    cwd: &Path,
+    program: &str,
+    args: &[String],
+) -> Result<Vec<String>> {
+    let path = workspace.to_string_lossy().to_string();
+    // Files the agent writes should belong to whoever owns the worktree, not root
+    let meta = std::fs::metadata(workspace)?;
+    let mut command: Vec<String> = vec![
+        "docker".into(),
+        "run".into(),
+        "--rm".into(),
+        "-i".into(),
+        "--init".into(),
+        "--name".into(),
+        container.to_string(),
+        "--user".into(),
+        format!("{}:{}", meta.uid(), meta.gid()),
+        "--env".into(),
+        "HOME=/tmp".into(),
+        "--volume".into(),
+        format!("{path}:{path}"),
+        "--workdir".into(),
+        cwd.to_string_lossy().to_string(),
+        "--network".into(),
+        docker.network.clone().unwrap_or_else(|| "bridge".to_string()),
+    ];
+    // A worktree's .git file points into the main repo's .git; git is useless without it.
+    // Mounted read-only but for what committing writes, so hooks and config stay the host's
+    let git = git_paths(workspace);
+    if let Some(common) = &git.common {
+        let common = common.to_string_lossy();
+        command.extend(["--volume".into(), format!("{common}:{common}:ro")]);
+    }
+    for path in &git.writable {
+        let path = path.to_string_lossy();
+        command.extend(["--volume".into(), format!("{path}:{path}")]);
+    }
+    // Docker would create a missing source as a directory
+    for path in git.read_only.iter().filter(|path| path.exists()) {
+        let path = path.to_string_lossy();
+        command.extend(["--volume".into(), format!("{path}:{path}:ro")]);
+    }
+    if let Some(cpus) = &docker.cpus {
+        command.extend(["--cpus".into(), cpus.clone()]);
+    }
+    if let Some(memory) = &docker.memory {
+        command.extend(["--memory".into(), memory.clone()]);
+    }
+    if let Some(pids) = docker.pids_limit {
+        command.extend(["--pids-limit".into(), pids.to_string()]);
+    }
+    for name in &docker.env {
+        // Bare `--env NAME` copies the value from the docker client's environment
+        command.extend(["--env".into(), name.clone()]);
+    }
+    for volume in &docker.volumes {
+        command.extend(["--volume".into(), volume.clone()]);
+    }
+    command.push(docker.image.clone());
+    command.push(program.to_string());
+    command.extend(args.iter().cloned());
+    Ok(command)
+}
+
+fn git_common_dir(workspace: &Path) -> Option<PathBuf> {
+    let dir = GitCmd::new(workspace).args(&["rev-parse", "--path-format=absolute", "--git-common-dir"]).try_run()?;
+    Some(PathBuf::from(dir.trim()))
+}
+
+/// The git metadata an isolated engine in a worktree gets, split by access. Whatever
+/// git runs later on the host -- hooks, and config such as core.hooksPath,
+/// core.fsmonitor or aliases -- must not be writable from inside.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitPaths {
+    /// The shared .git when it is outside the worktree, to be made readable
+    pub common: Option<PathBuf>,
+    /// What committing writes: objects, refs, reflogs and the worktree's own admin dir
+    /// (HEAD, index). packed-refs is rewritten by a rename in the .git dir itself, so
+    /// it stays read-only
+    pub writable: Vec<PathBuf>,
+    /// Kept read-only even where a writable path covers them
+    pub read_only: Vec<PathBuf>,
+}
+
+impl GitPaths {
+    /// Split for a worktree at `workspace` whose admin dir is `git_dir` and shared .git
+    /// is `common`; a main checkout has both at its own .git
+    pub fn new(workspace: &Path, common: &Path, git_dir: &Path) -> Self {
+        let mut read_only = vec![common.join("config"), common.join("hooks")];
+        if git_dir != common {
+            // And what points git at them: rewritten, these could swap in another .git
+            read_only.push(workspace.join(".git"));
+            read_only.extend(["config.worktree", "commondir", "gitdir"].iter().map(|name| git_dir.join(name)));
+        }
+        if common.starts_with(workspace) {
+            // Inside the worktree, which is writable anyway
+            return GitPaths { common: None, writable: Vec::new(), read_only };
+        }
+        let mut writable: Vec<PathBuf> = ["objects", "refs", "logs"].iter().map(|dir| common.join(dir)).collect();
+        if git_dir != common {
+            writable.push(git_dir.to_path_buf());
+        }
+        GitPaths { common: Some(common.to_path_buf()), writable, read_only }
+    }
+}
+
+// `GitPaths` for the worktree at `workspace`; empty when it isn't one
+fn git_paths(workspace: &Path) -> GitPaths {
+    let (Some(common), Ok(git_dir)) = (git_common_dir(workspace), worktree_git_dir(workspace)) else {
+        return GitPaths::default();
+    };
+    // Reflogs go under logs/, which git only makes on first use and can't make from inside
+    let _ = std::fs::create_dir_all(common.join("logs"));
+    GitPaths::new(workspace, &common, &git_dir)
+}
+
+/// The repo's dev container definition, if it has one
+pub fn devcontainer_config(workspace: &Path) -> Option<PathBuf> {
+    [".devcontainer/devcontainer.json", ".devcontainer.json"]
+        .iter()
+        .map(|file| workspace.join(file))
+        .find(|path| path.is_file())
+}
+
+/// Whether shells and agents in this workspace go through its dev container
+/// (`repos.<name>.devcontainer` is on and the repo defines one)
+pub fn uses_devcontainer(config: &Config, ws: &Workspace) -> bool {
+    let enabled = repo_config(config, ws).is_some_and(|settings| settings.devcontainer);
+    enabled && devcontainer_config(Path::new(&ws.path)).is_some()
+}
+
+/// Start (building if needed) a new workspace's dev container when its repo opts in
+pub fn provision(home: &Path, ws: &Workspace) -> Result<()> {
+    if !uses_devcontainer(&config_load(home)?, ws) {
+        return Ok(());
+    }
+    run("devcontainer", &["up", "--workspace-folder", &ws.path], None)?;
+    Ok(())
+}
+
+/// `command` run inside the workspace's dev container. The process starts in the
+/// container's workspace folder, wherever that is mounted.
+pub fn devcontainer_exec(workspace: &Path, command: Vec<String>) -> Vec<String> {
+    let mut exec = vec![
+        "devcontainer".to_string(),
+        "exec".to_string(),
+        "--workspace-folder".to_string(),
+        workspace.to_string_lossy().to_string(),
+    ];
+    exec.extend(command);
+    exec
+}
+
+// Workspace containing `cwd` whose repo runs things in its dev container
+fn devcontainer_workspace(conn: &Connection, home: &Path, cwd: &Path) -> Result<Option<Workspace>> {
+    let config = config_load(home)?;
+    Ok(workspace_for_path(conn, cwd)?.filter(|ws| uses_devcontainer(&config, ws)))
+}
+
+/// A one-off command in `cwd`, routed through the dev container when its repo opts in
+pub fn shell_command(conn: &Connection, home: &Path, cwd: &Path, command: Vec<String>) -> Result<Vec<String>> {
+    Ok(match devcontainer_workspace(conn, home, cwd)? {
+        Some(ws) => devcontainer_exec(Path::new(&ws.path), command),
+        None => command,
+    })
+}
+
+/// Interactive shell for a terminal in `cwd`: the user's `$SHELL` on the host, or
+/// whatever login shell the dev container has
+pub fn interactive_shell(conn: &Connection, home: &Path, cwd: &Path) -> Result<Vec<String>> {
+    Ok(match devcontainer_workspace(conn, home, cwd)? {
+        Some(ws) => {
+            let shell = "if command -v bash >/dev/null; then exec bash -l; else exec sh -l; fi";
+            devcontainer_exec(Path::new(&ws.path), vec!["sh".into(), "-c".into(), shell.into()])
+        }
+        None => vec![std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string())],
+    })
+}
+
+// Where a run in `ws` goes when none is asked for; see `engine_launch`
+fn default_isolation(config: &Config, ws: Option<&Workspace>) -> Isolation {
+    match ws {
+        Some(ws) => match repo_config(config, ws).and_then(|settings| settings.isolation) {
+            Some(isolation) => isolation,
+            None if uses_devcontainer(config, ws) => Isolation::Devcontainer,
+            None => Isolation::Host,
+        },
+        None => Isolation::Host,
+    }
+}
+
+/// The isolation `engine_launch` would use for a run in `cwd`
+pub fn launch_isolation(conn: &Connection, home: &Path, isolation: Option<Isolation>, cwd: &Path) -> Result<Isolation> {
+    match isolation {
+        Some(isolation) => Ok(isolation),
+        None => Ok(default_isolation(&config_load(home)?, workspace_for_path(conn, cwd)?.as_ref())),
+    }
+}
+
+/// What the daemon spawns for an agent run
+#[derive(Debug, Clone)]
+pub struct EngineLaunch {
+    pub isolation: Isolation,
+    pub command: Vec<String>,
+    /// Docker runs only: the container to remove if the run is stopped
+    pub container: Option<String>,
+}
+
+/// Wrap an engine command line for `isolation`. `None` picks the repo default:
+/// `repos.<name>.isolation`, else its dev container when `repos.<name>.devcontainer`
+/// is on, else the host.
+pub fn engine_launch(
+    conn: &Connection,
+    home: &Path,
+    isolation: Option<Isolation>,
+    session_id: &str,
+    cwd: &Path,
+    command: Vec<String>,
+) -> Result<EngineLaunch> {
+    let config = config_load(home)?;
+    let ws = workspace_for_path(conn, cwd)?;
+    let isolation = isolation.unwrap_or_else(|| default_isolation(&config, ws.as_ref()));
+    let workspace = || {
+        ws.as_ref()
+            .ok_or_else(|| anyhow!("{isolation} isolation needs a workspace; {} is not in one", cwd.display()))
+    };
+    match isolation {
+        Isolation::Host => Ok(EngineLaunch {
+            isolation,
+            command,
+            container: None,
+        }),
+        Isolation::Docker => {
+            let ws = workspace()?;
+            let container = container_name(session_id);
+            let docker = docker_config(&config, ws)?;
+            let (program, args) = command.split_first().ok_or_else(|| anyhow!("empty command"))?;
+            Ok(EngineLaunch {
+                isolation,
+                command: docker_command(&docker, &container, Path::new(&ws.path), cwd, program, args)?,
+                container: Some(container),
+            })
+        }
+        Isolation::Devcontainer => {
+            let ws = workspace()?;
+            if devcontainer_config(Path::new(&ws.path)).is_none() {
+                bail!("{} has no .devcontainer/devcontainer.json", ws.repo);
+            }
+            Ok(EngineLaunch {
+                isolation,
+                command: devcontainer_exec(Path::new(&ws.path), command),
+                container: None,
+            })
+        }
+        Isolation::Sandbox => {
+            let ws = workspace()?;
+            // Not the conductor home: config.json (isolation, command_policy, webhooks) and
+            // conductor.db live there. The workspace covers its .conductor-app/
+            let git = git_paths(Path::new(&ws.path));
+            let mut writable = vec![PathBuf::from(&ws.path), std::env::temp_dir()];
+            writable.extend(git.writable);
+            writable.extend(engine_state_dirs());
+            writable.extend(repo_config(&config, ws).into_iter().flat_map(|settings| settings.sandbox.writable.clone()));
+            Ok(EngineLaunch {
+                isolation,
+                command: sandbox_command(&writable, &git.read_only, cwd, command)?,
+                container: None,
+            })
+        }
+    }
+}
+
+// Where the engine CLIs keep sessions and credentials; they fail without them. Only
+// these: all of ~/.config would let an agent drop systemd units or autostart entries
+fn engine_state_dirs() -> Vec<PathBuf> {
+    let Some(user_home) = std::env::var_os("HOME").map(PathBuf::from) else {
+        return Vec::new();
+    };
+    [".claude", ".claude.json", ".codex", ".gemini"]
+        .iter()
+        .map(|name| user_home.join(name))
+        .collect()
+}
+
+/// `command` under the platform sandbox, with only `writable` paths (and /dev)
+/// writable, less any `read_only` paths inside them; reads and network are left alone
+pub fn sandbox_command(
+    writable: &[PathBuf],
+    read_only: &[PathBuf],
+    cwd: &Path,
+    command: Vec<String>,
+) -> Result<Vec<String>> {
+    let mut wrapped = if cfg!(target_os = "macos") {
+        vec!["sandbox-exec".to_string(), "-p".to_string(), seatbelt_profile(writable, read_only)]
+    } else {
+        if find_in_path("bwrap").is_none() {
+            bail!("sandbox isolation needs bubblewrap (bwrap) on PATH");
+        }
+        let mut bwrap: Vec<String> = ["bwrap", "--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        for path in writable {
+            let path = path.to_string_lossy().to_string();
+            // -try: paths that don't exist (no ~/.codex, say) are skipped
+            bwrap.extend(["--bind-try".to_string(), path.clone(), path]);
+        }
+        // Later mounts win, so these go over the writable ones
+        for path in read_only {
+            let path = path.to_string_lossy().to_string();
+            bwrap.extend(["--ro-bind-try".to_string(), path.clone(), path]);
+        }
+        bwrap.extend([
+            "--die-with-parent".to_string(),
+            "--chdir".to_string(),
+            cwd.to_string_lossy().to_string(),
+            "--".to_string(),
+        ]);
+        bwrap
+    };
+    wrapped.extend(command);
+    Ok(wrapped)
+}
+
+// Seatbelt profile denying writes outside `writable` and inside `read_only`. Paths are
+// resolved first: the kernel sees /private/tmp, not /tmp.
+fn seatbelt_profile(writable: &[PathBuf], read_only: &[PathBuf]) -> String {
+    let mut profile = String::from("(version 1)\n(allow default)\n(deny file-write*)\n(allow file-write*\n  (subpath \"/dev\")");
+    for path in writable {
+        profile.push_str(&format!("\n  (subpath \"{}\")", seatbelt_path(path)));
+    }
+    profile.push_str(")\n");
+    // The last matching rule wins
+    if !read_only.is_empty() {
+        profile.push_str("(deny file-write*");
+        for path in read_only {
+            profile.push_str(&format!("\n  (subpath \"{}\")", seatbelt_path(path)));
+        }
+        profile.push_str(")\n");
+    }
+    profile
+}
+
+// `path` resolved and quoted for a profile string. A path that doesn't exist yet keeps
+// its resolved parent, so a file the agent could create is still matched
+fn seatbelt_path(path: &Path) -> String {
+    let resolved = path.canonicalize().unwrap_or_else(|_| {
+        match (path.parent().and_then(|parent| parent.canonicalize().ok()), path.file_name()) {
+            (Some(parent), Some(name)) => parent.join(name),
+            _ => path.to_path_buf(),
+        }
+    });
+    resolved.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    // A fresh empty dir under the system temp dir
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("conductor-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn worktree_writes_only_what_committing_needs() {
+        let common = Path::new("/repos/acme/.git");
+        let git_dir = common.join("worktrees/victoria");
+        let workspace = Path::new("/ws/acme/victoria");
+        let paths = GitPaths::new(workspace, common, &git_dir);
+        assert_eq!(paths.common.as_deref(), Some(common));
+        assert_eq!(
+            paths.writable,
+            [common.join("objects"), common.join("refs"), common.join("logs"), git_dir.clone()]
+        );
+        assert!(!paths.writable.iter().any(|path| path == common));
+        assert_eq!(
+            paths.read_only,
+            [
+                common.join("config"),
+                common.join("hooks"),
+                workspace.join(".git"),
+                git_dir.join("config.worktree"),
+                git_dir.join("commondir"),
+                git_dir.join("gitdir"),
+            ]
+        );
+    }
+
+    #[test]
+    fn main_checkout_needs_no_extra_writes() {
+        let workspace = Path::new("/repos/acme");
+        let common = workspace.join(".git");
+        let paths = GitPaths::new(workspace, &common, &common);
+        assert_eq!(paths.common, None);
+        assert!(paths.writable.is_empty());
+        assert_eq!(paths.read_only, [common.join("config"), common.join("hooks")]);
+    }
+
+    #[test]
+    fn seatbelt_denies_read_only_paths_after_allowing_writable_ones() {
+        let profile = seatbelt_profile(&[PathBuf::from("/nonexistent/ws")], &[PathBuf::from("/nonexistent/ws/.git")]);
+        let allow = profile.find("(allow file-write*").unwrap();
+        let deny = profile.rfind("(deny file-write*").unwrap();
+        assert!(allow < deny, "{profile}");
+        assert!(profile[allow..deny].contains("(subpath \"/nonexistent/ws\")"));
+        assert!(profile[deny..].contains("(subpath \"/nonexistent/ws/.git\")"));
+        assert!(!seatbelt_profile(&[], &[]).contains("(deny file-write*\n"));
+    }
+
+    #[test]
+    fn seatbelt_paths_are_quoted() {
+        assert_eq!(seatbelt_path(Path::new("/nonexistent/a\"b\\c")), "/nonexistent/a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn bwrap_binds_read_only_paths_over_writable_ones() {
+        if cfg!(target_os = "macos") || find_in_path("bwrap").is_none() {
+            return;
+        }
+        let writable = [PathBuf::from("/ws")];
+        let read_only = [PathBuf::from("/ws/.git")];
+        let argv = sandbox_command(&writable, &read_only, Path::new("/ws"), vec!["claude".into()]).unwrap();
+        let position = |flag: &str, path: &str| argv.windows(3).position(|w| w == [flag, path, path]).unwrap();
+        assert!(position("--bind-try", "/ws") < position("--ro-bind-try", "/ws/.git"));
+        assert_eq!(argv[argv.len() - 2..], ["--".to_string(), "claude".to_string()]);
+    }
+
+    #[test]
+    fn docker_mounts_shared_git_read_only() {
+        let root = scratch_dir("docker");
+        let repo = root.join("repo");
+        std::fs::create_dir(&repo).unwrap();
+        git(&repo, &["init", "--quiet"]);
+        git(&repo, &["commit", "--quiet", "--allow-empty", "-m", "init"]);
+        let workspace = root.join("victoria");
+        git(&repo, &["worktree", "add", "--quiet", "-b", "victoria", workspace.to_str().unwrap()]);
+
+        let docker = DockerConfig { image: "agent:latest".to_string(), ..Default::default() };
+        let args = ["-p".to_string(), "hi".to_string()];
+        let argv = docker_command(&docker, "conductor-agent-x", &workspace, &workspace, "claude", &args).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let volumes: Vec<&str> =
+            argv.windows(2).filter(|w| w[0] == "--volume").map(|w| w[1].as_str()).collect();
+        let common = repo.join(".git").to_string_lossy().to_string();
+        let ws = workspace.to_string_lossy().to_string();
+        assert!(volumes.contains(&format!("{ws}:{ws}").as_str()), "{volumes:?}");
+        assert!(volumes.contains(&format!("{common}:{common}:ro").as_str()), "{volumes:?}");
+        assert!(volumes.contains(&format!("{common}/objects:{common}/objects").as_str()), "{volumes:?}");
+        assert!(volumes.contains(&format!("{common}/hooks:{common}/hooks:ro").as_str()), "{volumes:?}");
+        assert!(volumes.contains(&format!("{common}/config:{common}/config:ro").as_str()), "{volumes:?}");
+        assert!(!volumes.contains(&format!("{common}:{common}").as_str()), "{volumes:?}");
+        let network = argv.iter().position(|arg| arg == "--network").unwrap();
+        assert_eq!(argv[network + 1], "bridge");
+        assert_eq!(argv[argv.len() - 4..], ["agent:latest", "claude", "-p", "hi"]);
+    }
+}
@@ -0,0 +1,289 @@
+//! Issue tracker items a workspace can be created from (`workspace create --from-issue`).
+//! Each tracker is an `IssueTracker`: GitHub goes through the `gh` CLI (which owns auth),
+//! Linear through its GraphQL API with `LINEAR_API_KEY`. The issue is kept with the
+//! workspace, whose chat and first agent run open with it.
+
+use crate::{chat_append, db, get_workspace, Workspace};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+
+/// Longest workspace name `Issue::workspace_name` makes, key included
+const NAME_CHARS: usize = 48;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    /// `IssueTracker::name` of the tracker it came from
+    pub tracker: String,
+    /// The tracker's own key, e.g. "123" or "ENG-123"
+    pub key: String,
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// A workspace's issue as listings show it, without the body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueLink {
+    pub tracker: String,
+    pub key: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+pub trait IssueTracker {
+    /// Stored with linked workspaces, e.g. "github"
+    fn name(&self) -> &'static str;
+    /// Whether `reference` is one of this tracker's issues
+    fn recognizes(&self, reference: &str) -> bool;
+    /// The issue `reference` names; `repo_root` is the repo the workspace is for
+    fn fetch(&self, repo_root: &Path, reference: &str) -> Result<Issue>;
+}
+
+/// `gh issue view`: "123", "#123", "owner/repo#123" or an issue URL
+pub struct GitHub;
+
+/// Linear's GraphQL API: "ENG-123" or an issue URL; needs `LINEAR_API_KEY`
+pub struct Linear;
+
+static GITHUB_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:(\w[\w.-]*/[\w.-]+)#|#)?(\d+)$").expect("valid regex"));
+static GITHUB_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^https://github\.com/[\w.-]+/[\w.-]+/issues/\d+/?$").expect("valid regex"));
+static LINEAR_KEY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9]*-\d+$").expect("valid regex"));
+static LINEAR_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^https://linear\.app/[\w-]+/issue/([A-Za-z][A-Za-z0-9]*-\d+)").expect("valid regex"));
+
+impl IssueTracker for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn recognizes(&self, reference: &str) -> bool {
+        GITHUB_REF.is_match(reference) || GITHUB_URL.is_match(reference)
+    }
+
+    fn fetch(&self, repo_root: &Path, reference: &str) -> Result<Issue> {
+        let mut command = Command::new("gh");
+        command.args(["issue", "view"]).current_dir(repo_root);
+        match GITHUB_REF.captures(reference) {
+            Some(caps) => {
+                command.arg(&caps[2]);
+                if let Some(repo) = caps.get(1) {
+                    command.args(["--repo", repo.as_str()]);
+                }
+            }
+            // Only matching URLs get here, so this can't be read as an option
+            None => {
+                command.arg(reference);
+            }
+        }
+        let output = command
+            .args(["--json", "number,title,body,url"])
+            .output()
+            .context("failed to run gh (install the GitHub CLI and run `gh auth login`)")?;
+        if !output.status.success() {
+            bail!("gh issue view {reference}: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let issue: Value = serde_json::from_slice(&output.stdout).context("unexpected gh output")?;
+        let field = |key: &str| issue.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        Ok(Issue {
+            tracker: self.name().to_string(),
+            key: issue.get("number").and_then(Value::as_i64).map(|number| number.to_string()).unwrap_or_default(),
+            title: field("title"),
+            body: field("body"),
+            url: Some(field("url")).filter(|url| !url.is_empty()),
+        })
+    }
+}
+
+impl IssueTracker for Linear {
+    fn name(&self) -> &'static str {
+        "linear"
+    }
+
+    fn recognizes(&self, reference: &str) -> bool {
+        LINEAR_KEY.is_match(reference) || LINEAR_URL.is_match(reference)
+    }
+
+    fn fetch(&self, _repo_root: &Path, reference: &str) -> Result<Issue> {
+        let key = match LINEAR_URL.captures(reference) {
+            Some(caps) => caps[1].to_string(),
+            None => reference.to_string(),
+        };
+        let token = std::env::var("LINEAR_API_KEY")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| anyhow!("set LINEAR_API_KEY to fetch Linear issues"))?;
+        let query = json!({
+            "query": "query($id: String!) { issue(id: $id) { identifier title description url } }",
+            "variables": { "id": key },
+        });
+        // The key goes in on stdin, never on a command line other users can list
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail-with-body", "--header", "@-"])
+            .args(["--header", "Content-Type: application/json", "--data"])
+            .arg(query.to_string())
+            .arg("https://api.linear.app/graphql")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to run curl")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "Authorization: {token}").context("failed to pass the Linear key to curl")?;
+        }
+        let output = child.wait_with_output().context("failed to run curl")?;
+        let response: Value = serde_json::from_slice(&output.stdout).unwrap_or(Value::Null);
+        if let Some(message) = response.pointer("/errors/0/message").and_then(Value::as_str) {
+            bail!("Linear issue {key}: {message}");
+        }
+        if !output.status.success() {
+            bail!("Linear issue {key}: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let issue = response
+            .pointer("/data/issue")
+            .filter(|issue| !issue.is_null())
+            .ok_or_else(|| anyhow!("Linear issue {key} not found"))?;
+        let field = |key: &str| issue.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        Ok(Issue {
+            tracker: self.name().to_string(),
+            key: field("identifier"),
+            title: field("title"),
+            body: field("description"),
+            url: Some(field("url")).filter(|url| !url.is_empty()),
+        })
+    }
+}
+
+/// The tracker `reference` belongs to
+pub fn tracker_for(reference: &str) -> Result<Box<dyn IssueTracker>> {
+    let reference = reference.trim();
+    let trackers: [Box<dyn IssueTracker>; 2] = [Box::new(GitHub), Box::new(Linear)];
+    trackers.into_iter().find(|tracker| tracker.recognizes(reference)).ok_or_else(|| {
+        anyhow!("not an issue reference: {reference:?} (expected 123, owner/repo#123, ENG-123 or an issue URL)")
+    })
+}
+
+/// Look up the issue `reference` names, for a workspace of the repo at `repo_root`
+pub fn issue_fetch(repo_root: &Path, reference: &str) -> Result<Issue> {
+    let issue = tracker_for(reference)?.fetch(repo_root, reference.trim())?;
+    if issue.title.trim().is_empty() {
+        bail!("issue {reference} has no title");
+    }
+    Ok(issue)
+}
+
+impl Issue {
+    /// The issue's key and the start of its title, e.g. "eng-123-fix-flaky-auth-test"
+    pub fn workspace_name(&self) -> String {
+        let mut name = slug(&self.key);
+        for word in slug(&self.title).split('-').filter(|word| !word.is_empty()) {
+            if name.len() + 1 + word.len() > NAME_CHARS {
+                break;
+            }
+            if !name.is_empty() {
+                name.push('-');
+            }
+            name.push_str(word);
+        }
+        if name.is_empty() {
+            "issue".to_string()
+        } else {
+            name
+        }
+    }
+
+    /// Title, link and body, as the workspace's chat and first agent run get them
+    pub fn text(&self) -> String {
+        let mut text = format!("# {}", self.title.trim());
+        if let Some(url) = &self.url {
+            text.push_str(&format!("\n\n{url}"));
+        }
+        // chat.md separates entries with "---" lines; "***" is the same rule in Markdown
+        let body: Vec<&str> =
+            self.body.trim().lines().map(|line| if line.trim() == "---" { "***" } else { line }).collect();
+        if !body.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&body.join("\n"));
+        }
+        text
+    }
+
+    /// `prompt` with the issue ahead of it, separated by a rule
+    pub fn prompt_with(&self, prompt: &str) -> String {
+        format!("{}\n\n---\n\n{prompt}", self.text())
+    }
+
+    pub fn link(&self) -> IssueLink {
+        IssueLink {
+            tracker: self.tracker.clone(),
+            key: self.key.clone(),
+            title: self.title.clone(),
+            url: self.url.clone(),
+        }
+    }
+}
+
+// Lowercase ASCII words joined by single dashes
+fn slug(text: &str) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        if ch.is_ascii_alphanumeric() {
+            out.push(ch.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+/// Keep `issue` with the workspace, replacing any issue it had
+pub fn workspace_issue_set(conn: &Connection, ws_ref: &str, issue: &Issue) -> Result<()> {
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute(
+        "INSERT INTO workspace_issues (workspace_id, tracker, issue_key, title, body, url, linked_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(workspace_id) DO UPDATE SET
+            tracker = excluded.tracker, issue_key = excluded.issue_key, title = excluded.title,
+            body = excluded.body, url = excluded.url, linked_at = excluded.linked_at",
+        params![ws.id, issue.tracker, issue.key, issue.title, issue.body, issue.url, Utc::now().to_rfc3339()],
+    ))?;
+    Ok(())
+}
+
+/// Link a just-created workspace to its issue and open its chat with it
+pub fn workspace_issue_attach(conn: &Connection, ws: &Workspace, issue: &Issue) -> Result<()> {
+    workspace_issue_set(conn, &ws.id, issue)?;
+    chat_append(Path::new(&ws.path), "Issue", &issue.text())
+}
+
+/// The issue a workspace was created from, body included
+pub fn workspace_issue(conn: &Connection, ws_ref: &str) -> Result<Option<Issue>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn
+        .query_row(
+            "SELECT tracker, issue_key, title, body, url FROM workspace_issues WHERE workspace_id = ?",
+            [&ws.id],
+            |row| {
+                Ok(Issue {
+                    tracker: row.get(0)?,
+                    key: row.get(1)?,
+                    title: row.get(2)?,
+                    body: row.get(3)?,
+                    url: row.get(4)?,
+                })
+            },
+        )
+        .optional())
+}
@@ -1,19 +1,28 @@
 use anyhow::{anyhow, bail, Context, Result};
 use rand::seq::SliceRandom;
-use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use rusqlite::{params, Connection, OptionalExtension, Row};
 use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ValueRef};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fmt;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Component, Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::time::Duration;
 use uuid::Uuid;
 use chrono::Utc;
 
-pub const SCHEMA_VERSION: i64 = 3;
+pub mod github;
+pub mod isolation;
+pub mod issues;
+pub mod migrate;
+pub mod policy;
+pub mod webhooks;
+
+pub const SCHEMA_VERSION: i64 = 17;
 
 const CITIES: &[&str] = &[
     "almaty",
@@ -126,6 +135,25 @@ impl fmt::Display for UserError {
 
 impl std::error::Error for UserError {}
 
+/// A save whose `expected_hash` no longer matches the file on disk
+#[derive(Debug)]
+pub struct FileConflict {
+    pub path: String,
+    /// None when the file doesn't exist
+    pub current_hash: Option<String>,
+}
+
+impl fmt::Display for FileConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.current_hash {
+            Some(_) => write!(f, "{} is not the expected version", self.path),
+            None => write!(f, "{} does not exist", self.path),
+        }
+    }
+}
+
+impl std::error::Error for FileConflict {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Repo {
     pub id: String,
@@ -135,6 +163,18 @@ pub struct Repo {
     pub remote_url: Option<String>,
 }
 
+/// A git remote of a registered repo, as last synced from its git config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoRemote {
+    pub name: String,
+    pub fetch_url: String,
+    /// Only when it differs from `fetch_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_url: Option<String>,
+    /// Wins when a base branch exists on several remotes
+    pub preferred: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workspace {
     pub id: String,
@@ -145,24 +185,105 @@ pub struct Workspace {
     pub base_branch: String,
     pub state: WorkspaceState,
     pub path: String,
+    /// Listed ahead of unpinned workspaces; see `workspace_set_pinned`
+    #[serde(default)]
+    pub pinned: bool,
+    /// Set only where a caller asked for git state (daemon `ListWorkspaces(enriched)`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrichment: Option<WorkspaceEnrichment>,
+    /// Why git can't use the worktree, typically after a move; see `workspace_repair`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_error: Option<String>,
+    /// What went wrong, for a workspace in the error state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub activity: WorkspaceActivity,
+    /// What the workspace's conversation is about, e.g. "Fix flaky auth test"; see
+    /// `workspace_title_update`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Where the conversation got to, refreshed after each agent run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// How the repo's `verify` commands went after the last agent run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<WorkspaceVerification>,
+    /// The tracker issue it was created from; see `issues::workspace_issue`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue: Option<issues::IssueLink>,
+    /// The repo's remote chosen with `repo_remote_prefer`, which a base branch that is
+    /// only on remotes resolves against; None means origin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_remote: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// When a workspace was last used, as RFC 3339 timestamps; unset until it happens
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceActivity {
+    pub last_agent_run_at: Option<String>,
+    /// Committer date of the newest commit on the branch since its base
+    pub last_commit_at: Option<String>,
+    pub last_opened_at: Option<String>,
+}
+
+impl WorkspaceActivity {
+    /// The most recent of the three
+    pub fn latest(&self) -> Option<&str> {
+        [&self.last_agent_run_at, &self.last_commit_at, &self.last_opened_at]
+            .into_iter()
+            .filter_map(|at| at.as_deref())
+            .max()
+    }
+}
+
+/// Where a workspace is in its life. Ready, in-review, merged and abandoned are set by
+/// hand (`workspace_set_state`) and all keep a checkout; archive and reconcile set the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WorkspaceState {
     Ready,
+    InReview,
+    Merged,
+    Abandoned,
     Archived,
     Error,
 }
 
 impl WorkspaceState {
-    fn as_str(self) -> &'static str {
+    pub fn as_str(self) -> &'static str {
         match self {
             WorkspaceState::Ready => "ready",
+            WorkspaceState::InReview => "in_review",
+            WorkspaceState::Merged => "merged",
+            WorkspaceState::Abandoned => "abandoned",
             WorkspaceState::Archived => "archived",
             WorkspaceState::Error => "error",
         }
     }
+
+    /// Whether the workspace has a working checkout (every state but archived and error)
+    pub fn is_live(self) -> bool {
+        !matches!(self, WorkspaceState::Archived | WorkspaceState::Error)
+    }
+}
+
+impl std::str::FromStr for WorkspaceState {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "ready" => Ok(WorkspaceState::Ready),
+            "in_review" | "in-review" => Ok(WorkspaceState::InReview),
+            "merged" => Ok(WorkspaceState::Merged),
+            "abandoned" => Ok(WorkspaceState::Abandoned),
+            "archived" => Ok(WorkspaceState::Archived),
+            "error" => Ok(WorkspaceState::Error),
+            _ => bail!(
+                "unknown workspace state: {value} (expected ready, in-review, merged, abandoned, archived or error)"
+            ),
+        }
+    }
 }
 
 impl fmt::Display for WorkspaceState {
@@ -185,12 +306,9 @@ impl std::error::Error for StateParseError {}
 impl FromSql for WorkspaceState {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         let value = value.as_str()?;
-        match value {
-            "ready" => Ok(WorkspaceState::Ready),
-            "archived" => Ok(WorkspaceState::Archived),
-            "error" => Ok(WorkspaceState::Error),
-            _ => Err(FromSqlError::Other(Box::new(StateParseError(value.to_string())))),
-        }
+        value
+            .parse()
+            .map_err(|_| FromSqlError::Other(Box::new(StateParseError(value.to_string()))))
     }
 }
 
@@ -200,6 +318,9 @@ pub struct ArchiveResult {
     pub ok: bool,
     pub removed: bool,
     pub message: String,
+    /// The configured pre-archive checks, including any `--force` overrode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<ArchiveCheckResult>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -210,6 +331,33 @@ pub struct WorkspaceChange {
     pub status: String,
 }
 
+/// Everything the file list and changes panel need, from `workspace_snapshot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceSnapshot {
+    /// Tracked and untracked (not ignored) paths, sorted
+    pub files: Vec<String>,
+    /// Worktree status of each dirty path, coded as in `TreeEntry::status`
+    pub statuses: BTreeMap<String, String>,
+    /// Changes against the base branch followed by uncommitted ones, as in `workspace_changes`
+    pub changes: Vec<WorkspaceChange>,
+}
+
+/// One child of a workspace directory, as listed by `workspace_tree`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub name: String,
+    /// Relative to the workspace root
+    pub path: String,
+    /// "file", "dir" or "symlink"
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Worktree status against HEAD ("M", "A", "D", "R", "U" or "??"); a directory
+    /// reports "M" when anything below it changed, "??" when all of it is untracked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
 pub fn default_home() -> PathBuf {
     let home = env::var_os("HOME")
         .or_else(|| env::var_os("USERPROFILE"))
@@ -222,131 +370,330 @@ pub fn db_path(home: &Path) -> PathBuf {
     home.join("conductor.db")
 }
 
-pub fn ensure_home_dirs(home: &Path) -> Result<()> {
-    fs(std::fs::create_dir_all(home.join("repos")))?;
-    fs(std::fs::create_dir_all(home.join("workspaces")))?;
-    Ok(())
+pub fn config_path(home: &Path) -> PathBuf {
+    home.join("config.json")
 }
 
-pub fn connect(home: &Path) -> Result<Connection> {
-    ensure_home_dirs(home)?;
-    let path = db_path(home);
-    let mut conn = db(Connection::open(path))?;
-    db(conn.execute_batch("PRAGMA foreign_keys = ON"))?;
-    db(conn.execute_batch("PRAGMA journal_mode = WAL"))?;
-    db(conn.busy_timeout(Duration::from_secs(5)))?;
-    migrate(&mut conn)?;
-    Ok(conn)
+/// Settings from `<home>/config.json`; a missing file means all defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket_path: Option<PathBuf>,
+    /// Extra daemon listener, e.g. "tcp://0.0.0.0:7443" (requires `tls`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Loopback address for the daemon's REST gateway, e.g. "127.0.0.1:7444" (off when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_listen: Option<String>,
+    /// Connect clients to a remote daemon instead of the local socket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteConfig>,
+    /// Let clients shut down and respawn a local daemon that is older than they are
+    pub restart_outdated_daemon: bool,
+    /// Command line used to open workspaces, e.g. "code", "cursor", "zed" or "nvim"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+    /// Terminal for "open in terminal": a macOS app name ("Terminal", "iTerm") or,
+    /// elsewhere, a command started in the workspace directory ("kitty", "wezterm start")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<String>,
+    /// Endpoints the daemon notifies when an agent run completes or fails
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<webhooks::WebhookConfig>,
+    /// Which events the desktop app raises native notifications for
+    pub notifications: NotificationSettings,
+    /// Container settings for agent runs with `isolation: "docker"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docker: Option<isolation::DockerConfig>,
+    /// Deny rules for the shell commands agents run (built-in rules when unset). "block"
+    /// refuses a command beforehand only for Claude on the host or in the sandbox; see
+    /// `policy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_policy: Option<policy::CommandPolicy>,
+    /// Branch for new workspaces when none is given, e.g. "{user}/{workspace}"; see
+    /// `branch_from_template`. The workspace name is used when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_template: Option<String>,
+    /// Per-repo overrides, keyed by repo name or id
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub repos: BTreeMap<String, RepoConfig>,
+    /// How long archived workspaces are kept, e.g. "30d"; the daemon purges older ones
+    /// (see `workspace_purge`). Kept forever when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_retention: Option<String>,
+    /// How long a workspace can go without commits or agent runs before it counts as
+    /// stale, e.g. "14d" (see `workspace_is_stale`); the desktop then suggests cleaning
+    /// it up. Off when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_after: Option<String>,
+    /// What must hold before a workspace is archived without `--force`, e.g.
+    /// ["pushed", "merged"]; see `workspace_archive_checks`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub archive_checks: Vec<ArchiveCheck>,
+    /// How workspaces get their list titles and summaries
+    pub titles: TitleSettings,
+    /// What suggests commit messages for pending changes
+    pub commit_messages: CommitMessageSettings,
+    /// What agent runs write to the workspace chat
+    pub chat: ChatSettings,
+    /// Extra agent CLIs the daemon can run, registered when it starts
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub engines: Vec<CustomEngine>,
+    /// Named fallback chains an agent run can give as its engine, e.g.
+    /// {"overnight": ["claude", "codex", "gemini"]}; see `engine_chain`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub engine_chains: BTreeMap<String, Vec<String>>,
+    /// When the daemon checkpoints workspaces during agent runs
+    pub checkpoints: CheckpointMode,
+    /// Other conductor homes the daemon serves besides this one, e.g. a personal one next
+    /// to work; clients name theirs with `--home`. Read when the daemon starts
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub homes: Vec<PathBuf>,
+    /// Export the daemon's RPC, git and agent run spans over OTLP, e.g. to Jaeger. Off
+    /// when unset; read when the daemon starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingConfig>,
 }
 
-pub fn migrate(conn: &mut Connection) -> Result<()> {
-    let version: i64 = db(conn.query_row("PRAGMA user_version", [], |row| row.get(0)))?;
-    if version == SCHEMA_VERSION {
-        return Ok(());
+/// Settings for one repo under `repos` in config.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoConfig {
+    /// Replaces the global `docker` settings for this repo's agent runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docker: Option<isolation::DockerConfig>,
+    /// Bring up the repo's `.devcontainer` for each new workspace and run shells and
+    /// agents inside it
+    pub devcontainer: bool,
+    /// Isolation for agent runs that don't ask for one, e.g. "sandbox"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<isolation::Isolation>,
+    /// Extra writable paths for `sandbox` runs
+    pub sandbox: isolation::SandboxConfig,
+    /// Replaces the global `branch_template` for this repo's workspaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_template: Option<String>,
+    /// Shell commands the daemon runs in the workspace after each agent run, in order
+    /// and stopping at the first failure, e.g. ["cargo build", "cargo test"]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verify: Vec<String>,
+    /// Replaces the global `archive_checks` for this repo's workspaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_checks: Option<Vec<ArchiveCheck>>,
+}
+
+/// Per-event toggles for desktop notifications (all on by default)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub agent_completed: bool,
+    pub agent_failed: bool,
+    pub approval_requested: bool,
+    pub merge_conflict: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            agent_completed: true,
+            agent_failed: true,
+            approval_requested: true,
+            merge_conflict: true,
+        }
     }
+}
 
-    let tx = db(conn.transaction_with_behavior(TransactionBehavior::Immediate))?;
-    let version: i64 = db(tx.query_row("PRAGMA user_version", [], |row| row.get(0)))?;
-    if version == SCHEMA_VERSION {
-        db(tx.commit())?;
-        return Ok(());
+/// Titles and summaries for workspace lists, set after each agent run (see
+/// `workspace_title_update`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TitleSettings {
+    pub enabled: bool,
+    /// Cheap summarizer, e.g. ["claude", "-p", "--model", "haiku"]: gets the conversation
+    /// on stdin and prints a title line, then the summary. Without one the title is the
+    /// first request and the summary the latest answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+}
+
+impl Default for TitleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: None,
+        }
     }
+}
 
-    if version == 0 {
-        db(tx.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS repos (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                root_path TEXT NOT NULL,
-                default_branch TEXT NOT NULL,
-                remote_url TEXT,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
+/// Where `commit_message_suggest` gets its suggestions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitMessageSettings {
+    /// Engine command that gets instructions and the pending diff on stdin and prints the
+    /// message, e.g. ["claude", "-p", "--model", "haiku"]. Falls back to `titles.command`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+}
 
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_name ON repos(name);
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_root_path ON repos(root_path);
-
-            CREATE TABLE IF NOT EXISTS workspaces (
-                id TEXT PRIMARY KEY,
-                repository_id TEXT NOT NULL,
-                directory_name TEXT NOT NULL,
-                path TEXT NOT NULL,
-                branch TEXT NOT NULL,
-                base_branch TEXT NOT NULL,
-                state TEXT NOT NULL DEFAULT 'ready' CHECK(state IN ('ready', 'archived', 'error')),
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-                FOREIGN KEY(repository_id) REFERENCES repos(id)
-            );
+/// The daemon records each run's prompt and final answer in chat.md; these add to that
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    /// Also record a one-line "Action" entry for each tool call the agent finishes
+    pub record_actions: bool,
+}
 
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+/// Server-side mutual TLS material (PEM files) for TCP listeners
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA that client certificates must chain to
+    pub client_ca: PathBuf,
+}
 
-            PRAGMA user_version = 3;
-            ",
-        ))?;
-        db(tx.commit())?;
-        return Ok(());
+/// Remote daemon endpoint plus the client identity presented to it (PEM files)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// e.g. "https://buildbox:7443"
+    pub endpoint: String,
+    pub ca: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Server name to verify, when it differs from the endpoint host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// Bearer token of the remote daemon (its `<home>/daemon.token`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Where the daemon sends its traces (`tracing` in config.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracingConfig {
+    /// OTLP/gRPC collector, e.g. "http://localhost:4317" for Jaeger
+    pub endpoint: String,
+    /// `service.name` on exported spans; "conductor-daemon" when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+}
+
+pub fn config_load(home: &Path) -> Result<Config> {
+    let path = config_path(home);
+    if !path.exists() {
+        return Ok(Config::default());
     }
+    let text = fs(std::fs::read_to_string(&path))?;
+    serde_json::from_str(&text).with_context(|| format!("invalid config: {}", path.display()))
+}
 
-    if version == 1 {
-        db(tx.execute_batch(
-            "
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_name ON repos(name);
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_root_path ON repos(root_path);
-            ",
-        ))?;
+/// Write `<home>/config.json` (via a temp file, so readers never see half a config)
+pub fn config_save(home: &Path, config: &Config) -> Result<()> {
+    let path = config_path(home);
+    let tmp = path.with_extension("json.tmp");
+    let text = serde_json::to_string_pretty(config)?;
+    fs(std::fs::write(&tmp, text + "\n"))?;
+    fs(std::fs::rename(&tmp, &path))
+}
+
+/// Daemon socket path, resolved the same way by the daemon and every client:
+/// `$CONDUCTOR_SOCKET`, then `socket_path` in config.json, then a per-user default
+/// (`$XDG_RUNTIME_DIR/conductor/daemon.sock`, `~/Library/Application Support/Conductor`
+/// on macOS, otherwise `<home>/daemon.sock`).
+pub fn socket_path(home: &Path) -> Result<PathBuf> {
+    if let Some(path) = env::var_os("CONDUCTOR_SOCKET").filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    if let Some(path) = config_load(home)?.socket_path {
+        return Ok(path);
+    }
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR").filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(runtime).join("conductor").join("daemon.sock"));
     }
+    if cfg!(target_os = "macos") {
+        if let Some(user_home) = env::var_os("HOME") {
+            return Ok(PathBuf::from(user_home)
+                .join("Library/Application Support/Conductor")
+                .join("daemon.sock"));
+        }
+    }
+    Ok(home.join("daemon.sock"))
+}
 
-    if version == 1 || version == 2 {
-        db(tx.execute_batch(
-            "
-            DROP TABLE IF EXISTS workspaces_new;
-            CREATE TABLE workspaces_new (
-                id TEXT PRIMARY KEY,
-                repository_id TEXT NOT NULL,
-                directory_name TEXT NOT NULL,
-                path TEXT NOT NULL,
-                branch TEXT NOT NULL,
-                base_branch TEXT NOT NULL,
-                state TEXT NOT NULL DEFAULT 'ready' CHECK(state IN ('ready', 'archived', 'error')),
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-                FOREIGN KEY(repository_id) REFERENCES repos(id)
-            );
+pub fn token_path(home: &Path) -> PathBuf {
+    home.join("daemon.token")
+}
 
-            INSERT INTO workspaces_new (id, repository_id, directory_name, path, branch, base_branch, state, created_at, updated_at)
-            SELECT
-                id,
-                repository_id,
-                directory_name,
-                path,
-                branch,
-                base_branch,
-                CASE
-                    WHEN state IN ('ready', 'archived', 'error') THEN state
-                    ELSE 'error'
-                END,
-                created_at,
-                updated_at
-            FROM workspaces;
+/// Read the daemon bearer token, creating a random one (mode 0600) if missing
+pub fn auth_token_ensure(home: &Path) -> Result<String> {
+    if let Some(token) = auth_token_load(home)? {
+        return Ok(token);
+    }
+    fs(std::fs::create_dir_all(home))?;
+    let bytes: [u8; 32] = rand::random();
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    match options.open(token_path(home)) {
+        Ok(mut file) => {
+            fs(file.write_all(token.as_bytes()))?;
+            Ok(token)
+        }
+        // Lost a race with another process; use its token
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => auth_token_load(home)?
+            .ok_or_else(|| anyhow!("token file is empty: {}", token_path(home).display())),
+        Err(err) => Err(UserError::Filesystem(err.to_string()).into()),
+    }
+}
 
-            DROP TABLE workspaces;
-            ALTER TABLE workspaces_new RENAME TO workspaces;
+pub fn auth_token_load(home: &Path) -> Result<Option<String>> {
+    let path = token_path(home);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let token = fs(std::fs::read_to_string(&path))?.trim().to_string();
+    Ok(Some(token).filter(|t| !t.is_empty()))
+}
 
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
-            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+pub fn ensure_home_dirs(home: &Path) -> Result<()> {
+    fs(std::fs::create_dir_all(home.join("repos")))?;
+    fs(std::fs::create_dir_all(home.join("workspaces")))?;
+    Ok(())
+}
 
-            PRAGMA user_version = 3;
-            ",
-        ))?;
-        db(tx.commit())?;
+pub fn connect(home: &Path) -> Result<Connection> {
+    let mut conn = open(home)?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Like `connect`, but leaves the schema at whatever version it is
+pub fn open(home: &Path) -> Result<Connection> {
+    ensure_home_dirs(home)?;
+    let path = db_path(home);
+    let conn = db(Connection::open(path))?;
+    db(conn.execute_batch("PRAGMA foreign_keys = ON"))?;
+    db(conn.execute_batch("PRAGMA journal_mode = WAL"))?;
+    db(conn.busy_timeout(Duration::from_secs(5)))?;
+    Ok(conn)
+}
+
+/// Bring the schema up to `SCHEMA_VERSION`; see `migrate` for the steps
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    if migrate::current_version(conn)? == SCHEMA_VERSION {
         return Ok(());
     }
-
-    bail!("unsupported DB schema version: {version}");
+    migrate::migrate_to(conn, SCHEMA_VERSION)?;
+    Ok(())
 }
 
 fn db<T>(result: std::result::Result<T, rusqlite::Error>) -> Result<T> {
@@ -371,15 +718,26 @@ fn format_command(cmd: &str, args: &[&str]) -> String {
 }
 
 fn run(cmd: &str, args: &[&str], cwd: Option<&Path>) -> Result<String> {
+    run_with_env(cmd, args, cwd, &[])
+}
+
+fn run_with_env(cmd: &str, args: &[&str], cwd: Option<&Path>, envs: &[(&str, &str)]) -> Result<String> {
+    Ok(run_with_env_raw(cmd, args, cwd, envs)?.trim().to_string())
+}
+
+// `run_with_env` without the trim, for patches: trailing whitespace (a blank context
+// line, the final newline) is part of them
+fn run_with_env_raw(cmd: &str, args: &[&str], cwd: Option<&Path>, envs: &[(&str, &str)]) -> Result<String> {
     let mut command = Command::new(cmd);
     command.args(args);
+    command.envs(envs.iter().copied());
     if let Some(cwd) = cwd {
         command.current_dir(cwd);
     }
     let display = format_command(cmd, args);
     let output = command.output().with_context(|| format!("failed to run {display}"))?;
     if output.status.success() {
-        return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
+        return Ok(String::from_utf8_lossy(&output.stdout).to_string());
     }
     let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
     let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -392,35 +750,216 @@ fn run(cmd: &str, args: &[&str], cwd: Option<&Path>) -> Result<String> {
     .into())
 }
 
-fn git(repo_root: &Path, args: &[&str]) -> Result<String> {
-    run("git", args, Some(repo_root))
+/// A git command line that keeps options apart from values. Options are `'static`
+/// literals written at the call site; anything that came from a user, the DB or another
+/// command goes in through `value` (refused if git would read it as an option) or
+/// `operand` (placed after `--`), so it can't change what the command does
+struct GitCmd<'a> {
+    dir: &'a Path,
+    args: Vec<String>,
+    operands: Vec<String>,
+    envs: Vec<(String, String)>,
+    dashdash: bool,
+    // The first value that looked like an option; the command fails instead of running
+    rejected: Option<String>,
 }
 
-fn git_try(repo_root: &Path, args: &[&str]) -> Option<String> {
-    git(repo_root, args).ok()
+impl<'a> GitCmd<'a> {
+    fn new(dir: &'a Path) -> Self {
+        GitCmd {
+            dir,
+            args: Vec::new(),
+            operands: Vec::new(),
+            envs: Vec::new(),
+            dashdash: false,
+            rejected: None,
+        }
+    }
+
+    /// Subcommand, flags and anything else spelled out in the source
+    fn args(mut self, args: &[&'static str]) -> Self {
+        self.args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// An option with a computed value, glued on so it can't be taken for another
+    /// argument: `--format=<value>`, or `-U<value>` for a short flag
+    fn opt(mut self, flag: &'static str, value: impl fmt::Display) -> Self {
+        let glue = if flag.starts_with("--") { "=" } else { "" };
+        self.args.push(format!("{flag}{glue}{value}"));
+        self
+    }
+
+    /// A positional value before any `--`: a ref, revision range, remote name, URL...
+    fn value(mut self, value: impl AsRef<str>) -> Self {
+        let value = value.as_ref();
+        if value.starts_with('-') && self.rejected.is_none() {
+            self.rejected = Some(value.to_string());
+        }
+        self.args.push(value.to_string());
+        self
+    }
+
+    /// A positional after `--` (paths, mostly), which git never reads as an option
+    fn operand(mut self, operand: impl AsRef<str>) -> Self {
+        self.operands.push(operand.as_ref().to_string());
+        self
+    }
+
+    fn operands<S: AsRef<str>>(self, operands: impl IntoIterator<Item = S>) -> Self {
+        operands.into_iter().fold(self, GitCmd::operand)
+    }
+
+    /// End with `--` even without operands, so a revision can't be taken for a file
+    /// of the same name
+    fn no_paths(mut self) -> Self {
+        self.dashdash = true;
+        self
+    }
+
+    fn envs(mut self, envs: &[(&str, &str)]) -> Self {
+        self.envs.extend(envs.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+        self
+    }
+
+    fn argv(&self) -> Result<Vec<&str>> {
+        if let Some(value) = &self.rejected {
+            bail!("git: refusing {value:?}: it would be read as an option");
+        }
+        Ok(self.argv_unchecked())
+    }
+
+    fn argv_unchecked(&self) -> Vec<&str> {
+        let mut argv: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        if self.dashdash || !self.operands.is_empty() {
+            argv.push("--");
+            argv.extend(self.operands.iter().map(String::as_str));
+        }
+        argv
+    }
+
+    /// The command line, for messages
+    fn display(&self) -> String {
+        format_command("git", &self.argv_unchecked())
+    }
+
+    /// Stdout, untrimmed: for patches and NUL-separated output, where leading and
+    /// trailing whitespace is data
+    fn run_raw(&self) -> Result<String> {
+        let span = tracing::info_span!(
+            "git",
+            command = %self.display(),
+            dir = %self.dir.display(),
+            error = tracing::field::Empty
+        );
+        let _entered = span.enter();
+        let envs: Vec<(&str, &str)> = self.envs.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+        let result = run_with_env_raw("git", &self.argv()?, Some(self.dir), &envs);
+        if let Err(e) = &result {
+            span.record("error", tracing::field::display(e));
+        }
+        result
+    }
+
+    fn run(&self) -> Result<String> {
+        Ok(self.run_raw()?.trim().to_string())
+    }
+
+    fn try_run(&self) -> Option<String> {
+        self.run().ok()
+    }
+
+    /// For callers that need to stream stdin or stdout themselves
+    fn command(&self) -> Result<Command> {
+        let mut command = Command::new("git");
+        command.args(self.argv()?).current_dir(self.dir);
+        command.envs(self.envs.iter().map(|(key, value)| (key, value)));
+        Ok(command)
+    }
 }
 
 fn git_ref_exists(repo_root: &Path, full_ref: &str) -> bool {
-    git_try(repo_root, &["show-ref", "--verify", "--quiet", full_ref]).is_some()
+    GitCmd::new(repo_root).args(&["show-ref", "--verify", "--quiet"]).value(full_ref).try_run().is_some()
+}
+
+// Bare storage (`CloneOptions::bare`): no checkout of its own, only workspaces
+fn is_bare_repo(repo_root: &Path) -> bool {
+    GitCmd::new(repo_root).args(&["rev-parse", "--is-bare-repository"]).try_run().as_deref() == Some("true")
 }
 
 fn resolve_repo_root(path: &Path) -> Result<PathBuf> {
-    let out = git(path, &["rev-parse", "--show-toplevel"])?;
+    // A bare repo has no top level; its git dir is the root
+    let out = if GitCmd::new(path).args(&["rev-parse", "--is-bare-repository"]).run()? == "true" {
+        GitCmd::new(path).args(&["rev-parse", "--absolute-git-dir"]).run()?
+    } else {
+        GitCmd::new(path).args(&["rev-parse", "--show-toplevel"]).run()?
+    };
     let path = PathBuf::from(&out);
     Ok(path.canonicalize().unwrap_or_else(|_| PathBuf::from(out)))
 }
 
-fn resolve_base_ref(repo_root: &Path, base_branch: &str) -> Result<String> {
-    if git_try(repo_root, &["rev-parse", "--verify", "--quiet", base_branch]).is_some() {
+/// How long a cached base-ref resolution is trusted. Watched workspaces invalidate the
+/// cache when refs move; this bounds staleness from a fetch nobody was watching.
+const BASE_REF_TTL: Duration = Duration::from_secs(300);
+
+type BaseRefCache = HashMap<(PathBuf, String, String), (String, std::time::Instant)>;
+
+/// Base-ref resolutions by (repo or worktree path, base branch, preferred remote). None until a
+/// long-running process opts in with `base_ref_cache_enable`, so one-shot CLI runs
+/// always resolve against the refs as they are.
+static BASE_REFS: Mutex<Option<BaseRefCache>> = Mutex::new(None);
+
+fn base_refs() -> std::sync::MutexGuard<'static, Option<BaseRefCache>> {
+    BASE_REFS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Cache `resolve_base_ref` for the rest of the process, saving its `rev-parse` and
+/// `for-each-ref` on every diff and changes call
+pub fn base_ref_cache_enable() {
+    base_refs().get_or_insert_with(HashMap::new);
+}
+
+/// Forget every cached base ref, after a fetch or a branch being created or deleted.
+/// Each entry costs two git processes to rebuild, so there's no point being selective.
+pub fn base_ref_cache_invalidate() {
+    if let Some(cache) = base_refs().as_mut() {
+        cache.clear();
+    }
+}
+
+// `preferred_remote` is the repo's, from `preferred_remote` or a workspace's own copy; None means origin
+fn resolve_base_ref(repo_root: &Path, base_branch: &str, preferred_remote: Option<&str>) -> Result<String> {
+    let preferred_remote = preferred_remote.unwrap_or("origin");
+    let key = (repo_root.to_path_buf(), base_branch.to_string(), preferred_remote.to_string());
+    if let Some(cache) = base_refs().as_ref() {
+        if let Some((base_ref, _)) = cache.get(&key).filter(|(_, at)| at.elapsed() < BASE_REF_TTL) {
+            return Ok(base_ref.clone());
+        }
+    }
+    // Failures aren't cached: the branch may be created or fetched any moment
+    let base_ref = resolve_base_ref_preferring(repo_root, base_branch, preferred_remote)?;
+    if let Some(cache) = base_refs().as_mut() {
+        cache.insert(key, (base_ref.clone(), std::time::Instant::now()));
+    }
+    Ok(base_ref)
+}
+
+// A branch only on remotes resolves to the remote-tracking ref, `preferred_remote`'s if several have it
+fn resolve_base_ref_preferring(repo_root: &Path, base_branch: &str, preferred_remote: &str) -> Result<String> {
+    let verify = GitCmd::new(repo_root).args(&["rev-parse", "--verify", "--quiet"]).value(base_branch);
+    if verify.try_run().is_some() {
         return Ok(base_branch.to_string());
     }
-    let refs = git(repo_root, &["for-each-ref", "--format=%(refname:short)", &format!("refs/remotes/*/{base_branch}")])?;
+    let refs = GitCmd::new(repo_root)
+        .args(&["for-each-ref", "--format=%(refname:short)"])
+        .value(format!("refs/remotes/*/{base_branch}"))
+        .run()?;
     let remote_refs: Vec<&str> = refs.lines().filter(|line| !line.is_empty()).collect();
     if remote_refs.len() == 1 {
         return Ok(remote_refs[0].to_string());
     }
     if remote_refs.len() > 1 {
-        let preferred = format!("origin/{base_branch}");
+        let preferred = format!("{preferred_remote}/{base_branch}");
         if remote_refs.contains(&preferred.as_str()) {
             return Ok(preferred);
         }
@@ -445,6 +984,40 @@ fn repo_name_from_url(url: &str) -> String {
     }
 }
 
+/// Expand a `branch_template`. Placeholders are {user} (login name), {workspace} (or
+/// {name}), {repo} and {date} (local, YYYY-MM-DD); their values are reduced to
+/// characters safe in a ref, while the template's own text is kept as written
+pub fn branch_from_template(repo_root: &Path, template: &str, repo: &str, workspace: &str) -> Result<String> {
+    let mut branch = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        branch.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            bail!("branch template has an unclosed {{: {template}");
+        };
+        let key = &rest[start + 1..start + len];
+        let value = match key {
+            "user" => env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_default(),
+            "workspace" | "name" => workspace.to_string(),
+            "repo" => repo.to_string(),
+            "date" => chrono::Local::now().format("%Y-%m-%d").to_string(),
+            _ => bail!("branch template has an unknown placeholder {{{key}}} (expected user, workspace, repo or date)"),
+        };
+        // safe_dir_name falls back to "repo" for nothing usable; a branch should fail instead
+        if !value.chars().any(|ch| ch.is_ascii_alphanumeric()) {
+            bail!("branch template placeholder {{{key}}} is empty");
+        }
+        branch.push_str(&safe_dir_name(&value));
+        rest = &rest[start + len + 1..];
+    }
+    branch.push_str(rest);
+    GitCmd::new(repo_root)
+        .args(&["check-ref-format", "--branch"])
+        .value(&branch)
+        .run()
+        .with_context(|| format!("branch template {template:?} gave an invalid branch name: {branch}"))
+}
+
 pub fn safe_dir_name(name: &str) -> String {
     let mut out = String::new();
     for ch in name.trim().chars() {
@@ -479,6 +1052,20 @@ fn safe_workspace_relpath(path: &str) -> Result<PathBuf> {
     Ok(rel)
 }
 
+/// `rel` under the workspace root, refused when a symlink leads it out of the worktree:
+/// any symlinked directory on the way, and with `follow` the path itself. A path that
+/// doesn't exist yet is judged by its deepest existing ancestor, so files can be created.
+fn contained_workspace_path(ws_path: &Path, rel: &Path, follow: bool) -> Result<PathBuf> {
+    let root = fs(ws_path.canonicalize())?;
+    let full = ws_path.join(rel);
+    let checked = if follow { full.as_path() } else { full.parent().unwrap_or(ws_path) };
+    let resolved = checked.ancestors().find_map(|path| path.canonicalize().ok());
+    if !resolved.is_some_and(|resolved| resolved.starts_with(&root)) {
+        bail!("path leads outside the workspace through a symlink: {}", rel.display());
+    }
+    Ok(full)
+}
+
 fn auto_workspace_name(conn: &Connection, repo_id: &str) -> Result<String> {
     let mut stmt = db(conn.prepare("SELECT directory_name FROM workspaces WHERE repository_id = ?"))?;
     let rows = db(stmt.query_map([repo_id], |row| row.get::<_, String>(0)))?;
@@ -497,6 +1084,98 @@ fn auto_workspace_name(conn: &Connection, repo_id: &str) -> Result<String> {
     Ok(format!("ws-{}", &Uuid::new_v4().to_string()[..8]))
 }
 
+// A dangling commit on top of HEAD holding the checkout's uncommitted work, tracked and
+// untracked, or None if it is clean. Built in a scratch index so the real index and
+// working tree are untouched
+fn snapshot_uncommitted(repo_root: &Path) -> Result<Option<String>> {
+    if is_bare_repo(repo_root) {
+        bail!("cannot carry changes: {} is a bare repository with no checkout of its own", repo_root.display());
+    }
+    let head = GitCmd::new(repo_root)
+        .args(&["rev-parse", "--verify", "HEAD"])
+        .run()
+        .context("cannot carry changes from a repository without commits")?;
+    let index = repo_root.join(GitCmd::new(repo_root).args(&["rev-parse", "--git-path", "index"]).run()?);
+    let scratch = repo_root.join(
+        GitCmd::new(repo_root)
+            .args(&["rev-parse", "--git-path"])
+            .value(format!("conductor-carry-{}.index", Uuid::new_v4()))
+            .run()?,
+    );
+    if index.exists() {
+        fs(std::fs::copy(&index, &scratch))?;
+    }
+    let scratch_str = scratch.to_string_lossy().to_string();
+    let env = [("GIT_INDEX_FILE", scratch_str.as_str())];
+    let tree = GitCmd::new(repo_root)
+        .args(&["add", "-A"])
+        .envs(&env)
+        .run()
+        .and_then(|_| GitCmd::new(repo_root).args(&["write-tree"]).envs(&env).run());
+    let _ = std::fs::remove_file(&scratch);
+    let tree = tree?;
+    if tree == GitCmd::new(repo_root).args(&["rev-parse", "HEAD^{tree}"]).run()? {
+        return Ok(None);
+    }
+    let commit = GitCmd::new(repo_root)
+        .args(&["commit-tree"])
+        .value(&tree)
+        .args(&["-p"])
+        .value(&head)
+        .args(&["-m", "conductor: carried changes"])
+        .envs(&CONDUCTOR_IDENTITY);
+    Ok(Some(commit.run()?))
+}
+
+// A fixed identity for the commits Conductor makes for itself, so a repo without
+// user.name/user.email can still snapshot
+const CONDUCTOR_IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "conductor"),
+    ("GIT_AUTHOR_EMAIL", "conductor@localhost"),
+    ("GIT_COMMITTER_NAME", "conductor"),
+    ("GIT_COMMITTER_EMAIL", "conductor@localhost"),
+];
+
+// Replay a `snapshot_uncommitted` commit onto the worktree's HEAD and leave it unstaged.
+// A three-way cherry-pick, so it also lands on a base other than the checkout's HEAD
+fn apply_snapshot(ws_path: &Path, snapshot: &str) -> Result<()> {
+    GitCmd::new(ws_path).args(&["cherry-pick", "--no-commit"]).value(snapshot).run()?;
+    GitCmd::new(ws_path).args(&["reset", "-q"]).run()?;
+    Ok(())
+}
+
+// `name`/`branch`, or the first `-N` pair no workspace of the repo (archived ones
+// included) uses and no directory occupies. Branches without a workspace stay
+// reusable, as they are without suffixing
+fn free_workspace_names(
+    conn: &Connection,
+    repo_id: &str,
+    repo_dir: &Path,
+    name: &str,
+    branch: &str,
+) -> Result<(String, String)> {
+    let taken = |column: &str, value: &str| -> Result<bool> {
+        db(conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM workspaces WHERE repository_id = ? AND {column} = ?)"),
+            params![repo_id, value],
+            |row| row.get(0),
+        ))
+    };
+    for n in 1..=MAX_NAME_SUFFIX {
+        let (candidate_name, candidate_branch) = match n {
+            1 => (name.to_string(), branch.to_string()),
+            n => (format!("{name}-{n}"), format!("{branch}-{n}")),
+        };
+        if !taken("directory_name", &candidate_name)?
+            && !taken("branch", &candidate_branch)?
+            && !repo_dir.join(&candidate_name).exists()
+        {
+            return Ok((candidate_name, candidate_branch));
+        }
+    }
+    bail!("no free workspace name: {name} through {name}-{MAX_NAME_SUFFIX} are all taken");
+}
+
 fn repo_from_row(row: &Row) -> rusqlite::Result<Repo> {
     Ok(Repo {
         id: row.get(0)?,
@@ -539,6 +1218,7 @@ struct WorkspaceRow {
     path: String,
     base_branch: String,
     repo_root: String,
+    preferred_remote: Option<String>,
 }
 
 fn workspace_row_from_row(row: &Row) -> rusqlite::Result<WorkspaceRow> {
@@ -547,6 +1227,7 @@ fn workspace_row_from_row(row: &Row) -> rusqlite::Result<WorkspaceRow> {
         path: row.get(1)?,
         base_branch: row.get(2)?,
         repo_root: row.get(3)?,
+        preferred_remote: row.get(4)?,
     })
 }
 
@@ -556,7 +1237,8 @@ fn get_workspace(conn: &Connection, ws_ref: &str) -> Result<WorkspaceRow> {
             w.id, \
             w.path, \
             w.base_branch, \
-            r.root_path \
+            r.root_path, \
+            (SELECT name FROM repo_remotes WHERE repository_id = w.repository_id AND preferred = 1) \
         FROM workspaces w \
         JOIN repos r ON r.id = w.repository_id \
         WHERE w.id = ?\
@@ -572,7 +1254,8 @@ fn get_workspace(conn: &Connection, ws_ref: &str) -> Result<WorkspaceRow> {
             w.id, \
             w.path, \
             w.base_branch, \
-            r.root_path \
+            r.root_path, \
+            (SELECT name FROM repo_remotes WHERE repository_id = w.repository_id AND preferred = 1) \
         FROM workspaces w \
         JOIN repos r ON r.id = w.repository_id \
         WHERE w.id LIKE ?\
@@ -593,6 +1276,13 @@ struct WorkspaceContext {
     repo_root: PathBuf,
     base_branch: String,
     path: PathBuf,
+    preferred_remote: Option<String>,
+}
+
+impl WorkspaceContext {
+    fn base_ref(&self) -> Result<String> {
+        resolve_base_ref(&self.repo_root, &self.base_branch, self.preferred_remote.as_deref())
+    }
 }
 
 fn workspace_context(conn: &Connection, ws_ref: &str) -> Result<WorkspaceContext> {
@@ -601,6 +1291,7 @@ fn workspace_context(conn: &Connection, ws_ref: &str) -> Result<WorkspaceContext
         repo_root: PathBuf::from(ws.repo_root),
         base_branch: ws.base_branch,
         path: PathBuf::from(ws.path),
+        preferred_remote: ws.preferred_remote,
     })
 }
 
@@ -611,6 +1302,7 @@ pub fn workspace_path(conn: &Connection, ws_ref: &str) -> Result<PathBuf> {
 
 pub fn init(home: &Path) -> Result<PathBuf> {
     ensure_home_dirs(home)?;
+    auth_token_ensure(home)?;
     Ok(db_path(home))
 }
 
@@ -634,11 +1326,14 @@ pub fn repo_add(conn: &Connection, path: &Path, name: Option<&str>, default_bran
         bail!("repo name already registered: {name} ({path})");
     }
 
-    let remote_url = git_try(&repo_root, &["remote", "get-url", "origin"]);
+    let remote_url = GitCmd::new(&repo_root).args(&["remote", "get-url", "origin"]).try_run();
     let default_branch = if let Some(branch) = default_branch {
         branch.to_string()
     } else {
-        git_try(&repo_root, &["symbolic-ref", "--quiet", "--short", "HEAD"]).unwrap_or_else(|| "main".to_string())
+        GitCmd::new(&repo_root)
+            .args(&["symbolic-ref", "--quiet", "--short", "HEAD"])
+            .try_run()
+            .unwrap_or_else(|| "main".to_string())
     };
 
     let repo_id = Uuid::new_v4().to_string();
@@ -646,6 +1341,7 @@ pub fn repo_add(conn: &Connection, path: &Path, name: Option<&str>, default_bran
         "INSERT INTO repos (id, name, root_path, default_branch, remote_url) VALUES (?, ?, ?, ?, ?)",
         params![repo_id, name, root_str, default_branch, remote_url],
     ))?;
+    sync_repo_remotes(conn, &repo_id, &repo_root)?;
 
     Ok(Repo {
         id: repo_id,
@@ -656,44 +1352,266 @@ pub fn repo_add(conn: &Connection, path: &Path, name: Option<&str>, default_bran
     })
 }
 
+/// Extra `git clone` options for `repo_add_url`: partial clones for repos too big to
+/// clone in full, and bare storage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CloneOptions {
+    /// `--depth <n>`: truncate history to the last n commits (implies single-branch in git)
+    pub depth: Option<u32>,
+    /// `--filter=<spec>`, e.g. "blob:none" to fetch file contents on demand
+    pub filter: Option<String>,
+    /// `--single-branch`: only the default branch (or `default_branch` when given)
+    pub single_branch: bool,
+    /// `--bare` into `repos/<name>.git`: workspaces are its only checkouts, so there is
+    /// no main checkout for anyone to edit by accident
+    pub bare: bool,
+}
+
+impl CloneOptions {
+    fn apply<'a>(&self, mut clone: GitCmd<'a>, default_branch: Option<&str>) -> Result<GitCmd<'a>> {
+        if let Some(depth) = self.depth {
+            if depth == 0 {
+                bail!("clone depth must be at least 1");
+            }
+            clone = clone.opt("--depth", depth);
+        }
+        if let Some(filter) = &self.filter {
+            if filter.is_empty() {
+                bail!("clone filter must not be empty");
+            }
+            clone = clone.opt("--filter", filter);
+        }
+        if self.single_branch {
+            clone = clone.args(&["--single-branch"]);
+            if let Some(branch) = default_branch {
+                clone = clone.opt("--branch", branch);
+            }
+        }
+        if self.bare {
+            clone = clone.args(&["--bare"]);
+        }
+        Ok(clone)
+    }
+}
+
 pub fn repo_add_url(
     conn: &Connection,
     home: &Path,
     url: &str,
     name: Option<&str>,
     default_branch: Option<&str>,
+    clone: &CloneOptions,
 ) -> Result<Repo> {
-    if url.starts_with('-') {
-        bail!("repo url must not start with '-'");
-    }
+    let clone_cmd = clone.apply(GitCmd::new(home).args(&["clone"]), default_branch)?;
     ensure_home_dirs(home)?;
     let display_name = match name {
         Some(name) if !name.trim().is_empty() => name.trim().to_string(),
         _ => repo_name_from_url(url),
     };
     let dir_name = safe_dir_name(&display_name);
-    let repo_dir = home.join("repos").join(&dir_name);
+    let repo_dir = if clone.bare {
+        home.join("repos").join(format!("{dir_name}.git"))
+    } else {
+        home.join("repos").join(&dir_name)
+    };
     if repo_dir.exists() {
-        if repo_dir.join(".git").exists() {
+        let existing_repo = if clone.bare {
+            repo_dir.join("HEAD").is_file()
+        } else {
+            repo_dir.join(".git").exists()
+        };
+        if existing_repo {
             return repo_add(conn, &repo_dir, Some(&display_name), default_branch);
         }
         bail!("repo path already exists: {}", repo_dir.display());
     }
     let repo_dir_str = repo_dir.to_string_lossy().to_string();
-    let args = ["clone", url, repo_dir_str.as_str()];
-    if let Err(err) = run("git", &args, Some(home)) {
+    if let Err(err) = clone_cmd.operand(url).operand(&repo_dir_str).run() {
         let _ = std::fs::remove_dir_all(&repo_dir);
         return Err(err);
     }
+    if clone.bare {
+        // A bare clone copies branches as-is and fetches nothing later; track origin like a normal clone
+        let refspec = "+refs/heads/*:refs/remotes/origin/*";
+        GitCmd::new(&repo_dir).args(&["config", "remote.origin.fetch"]).value(refspec).run()?;
+    }
     repo_add(conn, &repo_dir, Some(&display_name), default_branch)
 }
 
+/// Repos in the order set by `repo_reorder`, then the rest newest first
 pub fn repo_list(conn: &Connection) -> Result<Vec<Repo>> {
-    let mut stmt = db(conn.prepare("SELECT id, name, root_path, default_branch, remote_url FROM repos ORDER BY created_at DESC"))?;
+    let mut stmt = db(conn.prepare(
+        "SELECT id, name, root_path, default_branch, remote_url FROM repos
+         ORDER BY sort_order IS NULL, sort_order, created_at DESC",
+    ))?;
     let rows = db(stmt.query_map([], repo_from_row))?;
     collect_rows(rows)
 }
 
+/// Put `repo_refs` at the top of `repo_list`, in that order; repos left out fall back to
+/// newest first below them
+/// A repo by id, name or unique id prefix
+pub fn repo_get(conn: &Connection, repo_ref: &str) -> Result<Repo> {
+    get_repo(conn, repo_ref)
+}
+
+pub fn repo_reorder(conn: &Connection, repo_refs: &[String]) -> Result<Vec<Repo>> {
+    let mut ids: Vec<String> = Vec::new();
+    for repo_ref in repo_refs {
+        let repo = get_repo(conn, repo_ref)?;
+        if ids.contains(&repo.id) {
+            bail!("repo listed twice: {repo_ref}");
+        }
+        ids.push(repo.id);
+    }
+    let tx = db(conn.unchecked_transaction())?;
+    db(tx.execute("UPDATE repos SET sort_order = NULL", []))?;
+    for (position, id) in ids.iter().enumerate() {
+        db(tx.execute("UPDATE repos SET sort_order = ?1 WHERE id = ?2", params![position as i64, id]))?;
+    }
+    db(tx.commit())?;
+    repo_list(conn)
+}
+
+// Mirror the repo's git remotes into repo_remotes (keeping the preferred flag) and
+// repos.remote_url (origin's)
+fn sync_repo_remotes(conn: &Connection, repo_id: &str, repo_root: &Path) -> Result<()> {
+    // `git remote -v` decorates partial clones' URLs, so read the config itself (-z: "key\nvalue\0");
+    // it exits 1 when nothing matches
+    let config = GitCmd::new(repo_root)
+        .args(&["config", "-z", "--get-regexp", r"^remote\..*\.(url|pushurl)$"])
+        .try_run()
+        .unwrap_or_default();
+    let mut remotes: Vec<(String, String, Option<String>)> = Vec::new();
+    let mut push_urls: Vec<(String, String)> = Vec::new();
+    for entry in config.split('\0') {
+        let Some((key, value)) = entry.split_once('\n') else {
+            continue;
+        };
+        let Some((name, field)) = key.strip_prefix("remote.").and_then(|rest| rest.rsplit_once('.')) else {
+            continue;
+        };
+        // First URL wins, as for git itself
+        match field {
+            "url" if !remotes.iter().any(|(n, _, _)| n == name) => {
+                remotes.push((name.to_string(), value.to_string(), None))
+            }
+            "pushurl" => push_urls.push((name.to_string(), value.to_string())),
+            _ => {}
+        }
+    }
+    for (name, push_url) in push_urls {
+        if let Some(remote) = remotes.iter_mut().find(|(n, url, p)| *n == name && *url != push_url && p.is_none()) {
+            remote.2 = Some(push_url);
+        }
+    }
+
+    let names: Vec<&str> = remotes.iter().map(|(name, _, _)| name.as_str()).collect();
+    let existing: Vec<String> = {
+        let mut stmt = db(conn.prepare("SELECT name FROM repo_remotes WHERE repository_id = ?"))?;
+        let rows = db(stmt.query_map([repo_id], |row| row.get(0)))?;
+        collect_rows(rows)?
+    };
+    for name in existing.iter().filter(|name| !names.contains(&name.as_str())) {
+        db(conn.execute("DELETE FROM repo_remotes WHERE repository_id = ? AND name = ?", params![repo_id, name]))?;
+    }
+    for (name, fetch_url, push_url) in &remotes {
+        db(conn.execute(
+            "INSERT INTO repo_remotes (repository_id, name, fetch_url, push_url) VALUES (?, ?, ?, ?)
+             ON CONFLICT(repository_id, name) DO UPDATE SET fetch_url = excluded.fetch_url, push_url = excluded.push_url",
+            params![repo_id, name, fetch_url, push_url],
+        ))?;
+    }
+    let origin = remotes.iter().find(|(name, _, _)| name == "origin").map(|(_, url, _)| url);
+    db(conn.execute(
+        "UPDATE repos SET remote_url = ?, updated_at = datetime('now') WHERE id = ?",
+        params![origin, repo_id],
+    ))?;
+    Ok(())
+}
+
+/// The repo's remotes, re-read from git first so edits made outside Conductor show up
+pub fn repo_remotes(conn: &Connection, repo_ref: &str) -> Result<Vec<RepoRemote>> {
+    let repo = get_repo(conn, repo_ref)?;
+    sync_repo_remotes(conn, &repo.id, Path::new(&repo.root_path))?;
+    let mut stmt = db(conn.prepare(
+        "SELECT name, fetch_url, push_url, preferred FROM repo_remotes WHERE repository_id = ? ORDER BY name",
+    ))?;
+    let rows = db(stmt.query_map([&repo.id], |row| {
+        Ok(RepoRemote {
+            name: row.get(0)?,
+            fetch_url: row.get(1)?,
+            push_url: row.get(2)?,
+            preferred: row.get(3)?,
+        })
+    }))?;
+    collect_rows(rows)
+}
+
+// GitCmd keeps option-like values out; git's own message for an empty one is unhelpful
+fn check_remote_arg(kind: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        bail!("invalid remote {kind}: {value:?}");
+    }
+    Ok(())
+}
+
+/// `git remote add` in the repo, then sync
+pub fn repo_remote_add(conn: &Connection, repo_ref: &str, name: &str, url: &str) -> Result<Vec<RepoRemote>> {
+    check_remote_arg("name", name)?;
+    check_remote_arg("url", url)?;
+    let repo = get_repo(conn, repo_ref)?;
+    GitCmd::new(Path::new(&repo.root_path)).args(&["remote", "add"]).value(name).value(url).run()?;
+    repo_remotes(conn, &repo.id)
+}
+
+/// `git remote remove` in the repo (dropping its remote-tracking refs), then sync
+pub fn repo_remote_remove(conn: &Connection, repo_ref: &str, name: &str) -> Result<Vec<RepoRemote>> {
+    check_remote_arg("name", name)?;
+    let repo = get_repo(conn, repo_ref)?;
+    GitCmd::new(Path::new(&repo.root_path)).args(&["remote", "remove"]).value(name).run()?;
+    repo_remotes(conn, &repo.id)
+}
+
+/// Make `name` the remote whose branches win when a base branch exists on several
+pub fn repo_remote_prefer(conn: &Connection, repo_ref: &str, name: &str) -> Result<Vec<RepoRemote>> {
+    let repo = get_repo(conn, repo_ref)?;
+    let remotes = repo_remotes(conn, &repo.id)?;
+    if !remotes.iter().any(|remote| remote.name == name) {
+        bail!("remote not found: {name}");
+    }
+    db(conn.execute(
+        "UPDATE repo_remotes SET preferred = (name = ?) WHERE repository_id = ?",
+        params![name, repo.id],
+    ))?;
+    repo_remotes(conn, &repo.id)
+}
+
+// The remote chosen with `repo_remote_prefer`; None means origin
+fn preferred_remote(conn: &Connection, repo_id: &str) -> Result<Option<String>> {
+    db(conn
+        .query_row(
+            "SELECT name FROM repo_remotes WHERE repository_id = ? AND preferred = 1",
+            [repo_id],
+            |row| row.get(0),
+        )
+        .optional())
+}
+
+/// Behavior switches for `workspace_create`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateOptions {
+    /// When the directory name or branch is taken, try `-2`, `-3`, ... on both
+    /// instead of failing, so scripted fan-out can reuse one name
+    pub auto_suffix: bool,
+    /// Copy the main checkout's uncommitted changes (untracked files included) into
+    /// the new worktree, unstaged; the main checkout is left as it is
+    pub carry_changes: bool,
+}
+
+// Most `-N` suffixes `auto_suffix` tries before giving up
+const MAX_NAME_SUFFIX: u32 = 100;
+
 pub fn workspace_create(
     conn: &Connection,
     home: &Path,
@@ -701,11 +1619,14 @@ pub fn workspace_create(
     name: Option<&str>,
     base: Option<&str>,
     branch: Option<&str>,
+    options: &CreateOptions,
 ) -> Result<Workspace> {
     let repo = get_repo(conn, repo_ref)?;
     let repo_root = PathBuf::from(&repo.root_path);
     let base_branch = base.unwrap_or(&repo.default_branch);
-    let base_ref = resolve_base_ref(&repo_root, base_branch)?;
+    let preferred_remote = preferred_remote(conn, &repo.id)?;
+    let remote = preferred_remote.as_deref().unwrap_or("origin");
+    let base_ref = resolve_base_ref_preferring(&repo_root, base_branch, remote)?;
 
     let name = if let Some(name) = name {
         name.to_string()
@@ -714,67 +1635,205 @@ pub fn workspace_create(
     } else {
         auto_workspace_name(conn, &repo.id)?
     };
-    let branch = branch.map(|b| b.to_string()).unwrap_or_else(|| name.clone());
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => {
+            let config = config_load(home)?;
+            let template = config
+                .repos
+                .get(&repo.name)
+                .or_else(|| config.repos.get(&repo.id))
+                .and_then(|repo_config| repo_config.branch_template.as_ref())
+                .or(config.branch_template.as_ref());
+            match template {
+                Some(template) => branch_from_template(&repo_root, template, &repo.name, &name)?,
+                None => name.clone(),
+            }
+        }
+    };
+
+    let repo_dir = home.join("workspaces").join(format!("{}-{}", safe_dir_name(&repo.name), &repo.id[..8]));
+    let (name, branch) = if options.auto_suffix {
+        free_workspace_names(conn, &repo.id, &repo_dir, &name, &branch)?
+    } else {
+        (name, branch)
+    };
+    let workspace_path = repo_dir.join(&name);
+    let carried = if options.carry_changes {
+        snapshot_uncommitted(&repo_root)?
+    } else {
+        None
+    };
+
+    let ws = Workspace {
+        id: Uuid::new_v4().to_string(),
+        repo_id: repo.id.clone(),
+        repo: repo.name.clone(),
+        name,
+        branch,
+        base_branch: base_ref,
+        state: WorkspaceState::Ready,
+        path: workspace_path.to_string_lossy().to_string(),
+        pinned: false,
+        enrichment: None,
+        link_error: None,
+        error: None,
+        activity: WorkspaceActivity::default(),
+        title: None,
+        summary: None,
+        verification: None,
+        issue: None,
+        preferred_remote,
+    };
+    let mut undo = CreateUndo::default();
+    match workspace_create_steps(conn, home, &repo, &ws, carried.as_deref(), &mut undo) {
+        Ok(()) => Ok(ws),
+        Err(err) => match undo.run(conn, &repo_root) {
+            Ok(()) => Err(err),
+            Err(left) => Err(err.context(format!("cleanup after the failed create left {left}"))),
+        },
+    }
+}
+
+// What `workspace_create` has done so far, so a failed create can be undone step by step
+#[derive(Default)]
+struct CreateUndo {
+    /// Directories it made, outermost first
+    dirs: Vec<PathBuf>,
+    worktree: Option<String>,
+    branch: Option<String>,
+    row: Option<String>,
+}
+
+impl CreateUndo {
+    // Undo in reverse. A checkout git won't remove keeps its branch, and its row (marked
+    // error) so it can be archived later; the error says what was left behind
+    fn run(self, conn: &Connection, repo_root: &Path) -> Result<()> {
+        let mut left = Vec::new();
+        let mut removed = true;
+        if let Some(path) = &self.worktree {
+            if let Err(err) = GitCmd::new(repo_root).args(&["worktree", "remove", "--force"]).operand(path).run() {
+                removed = false;
+                left.push(format!("the worktree at {path} ({err})"));
+            }
+        }
+        if let Some(ws_id) = &self.row {
+            let result = if removed {
+                db(conn.execute("DELETE FROM workspaces WHERE id = ?", [ws_id])).map(|_| ())
+            } else {
+                let error = format!("create failed and the worktree could not be removed: {}", left.join("; "));
+                set_workspace_error(conn, ws_id, &error)
+            };
+            if let Err(err) = result {
+                left.push(format!("workspace {ws_id} ({err})"));
+            }
+        }
+        if let Some(branch) = self.branch.as_deref().filter(|_| removed) {
+            if let Err(err) = GitCmd::new(repo_root).args(&["branch", "-D"]).operand(branch).run() {
+                left.push(format!("branch {branch} ({err})"));
+            }
+        }
+        for dir in self.dirs.iter().rev() {
+            // Only if empty: something else may have been put there since
+            let _ = std::fs::remove_dir(dir);
+        }
+        if left.is_empty() {
+            Ok(())
+        } else {
+            bail!("{}", left.join(", "))
+        }
+    }
+}
 
-    let repo_dir = format!("{}-{}", safe_dir_name(&repo.name), &repo.id[..8]);
-    let workspace_path = home.join("workspaces").join(repo_dir).join(&name);
-    if workspace_path.exists() {
+// `workspace_create` once names are settled; records each step in `undo`
+fn workspace_create_steps(
+    conn: &Connection,
+    home: &Path,
+    repo: &Repo,
+    ws: &Workspace,
+    carried: Option<&str>,
+    undo: &mut CreateUndo,
+) -> Result<()> {
+    let repo_root = Path::new(&repo.root_path);
+    let workspace_path = Path::new(&ws.path);
+    // An empty directory is what an interrupted create leaves; git checks out into it
+    let empty_dir = std::fs::read_dir(workspace_path).is_ok_and(|mut entries| entries.next().is_none());
+    if workspace_path.exists() && !empty_dir {
         bail!("workspace path already exists: {}", workspace_path.display());
     }
-    fs(std::fs::create_dir_all(
-        workspace_path
-            .parent()
-            .ok_or_else(|| anyhow!("invalid workspace path"))?,
-    ))?;
-    let workspace_path_str = workspace_path.to_string_lossy().to_string();
+    // So does a registration for a checkout that's gone; clear it unless a workspace owns it
+    let stale = registered_worktrees(repo_root)?
+        .into_iter()
+        .any(|worktree| worktree.path == workspace_path && !worktree.path.join(".git").exists());
+    let owned = |other: &Workspace| other.state.is_live() && other.path == ws.path;
+    if stale && !workspace_list(conn, Some(&repo.id))?.iter().any(owned) {
+        GitCmd::new(repo_root).args(&["worktree", "remove", "--force"]).operand(&ws.path).run()?;
+    }
 
-    if git_ref_exists(&repo_root, &format!("refs/heads/{branch}")) {
-        let args = ["worktree", "add", "--", workspace_path_str.as_str(), branch.as_str()];
-        run("git", &args, Some(&repo_root))?;
+    let parent = workspace_path.parent().ok_or_else(|| anyhow!("invalid workspace path"))?;
+    undo.dirs = parent.ancestors().take_while(|dir| !dir.exists()).map(Path::to_path_buf).collect();
+    undo.dirs.reverse();
+    fs(std::fs::create_dir_all(parent))?;
+
+    let created_branch = !git_ref_exists(repo_root, &format!("refs/heads/{}", ws.branch));
+    if !created_branch {
+        let add = GitCmd::new(repo_root).args(&["worktree", "add"]);
+        add.operand(&ws.path).operand(&ws.branch).run()?;
     } else {
-        let args = [
-            "worktree",
-            "add",
-            "-b",
-            branch.as_str(),
-            "--",
-            workspace_path_str.as_str(),
-            base_ref.as_str(),
-        ];
-        run("git", &args, Some(&repo_root))?;
-    }
-
-    let ws_id = Uuid::new_v4().to_string();
-    let insert = db(conn.execute(
+        let add = GitCmd::new(repo_root).args(&["worktree", "add", "-b"]).value(&ws.branch);
+        add.operand(&ws.path).operand(&ws.base_branch).run()?;
+        undo.branch = Some(ws.branch.clone());
+    }
+    undo.worktree = Some(ws.path.clone());
+    if let Some(snapshot) = carried {
+        apply_snapshot(workspace_path, snapshot)
+            .map_err(|err| err.context("could not carry uncommitted changes into the new workspace"))?;
+    }
+
+    db(conn.execute(
         "
         INSERT INTO workspaces (id, repository_id, directory_name, path, branch, base_branch, state)
         VALUES (?, ?, ?, ?, ?, ?, 'ready')
         ",
-        params![ws_id, repo.id, name, workspace_path_str.clone(), branch, base_ref.clone()],
-    ));
-
-    if let Err(err) = insert {
-        let args = ["worktree", "remove", "--force", "--", workspace_path_str.as_str()];
-        let _ = run("git", &args, Some(&repo_root));
-        return Err(err.into());
-    }
+        params![ws.id, repo.id, ws.name, ws.path, ws.branch, ws.base_branch],
+    ))?;
+    undo.row = Some(ws.id.clone());
 
     // Initialize .conductor-app/ folder
-    let _ = ensure_conductor_app(&workspace_path);
+    let _ = ensure_conductor_app(workspace_path);
 
-    Ok(Workspace {
-        id: ws_id,
-        repo_id: repo.id,
-        repo: repo.name,
-        name,
-        branch,
-        base_branch: base_ref,
-        state: WorkspaceState::Ready,
-        path: workspace_path_str,
-    })
+    // A workspace whose container won't come up is no use; undo it like a failed insert
+    isolation::provision(home, ws).map_err(|err| err.context("devcontainer up failed"))?;
+    Ok(())
+}
+
+/// Order for `workspace_list_sorted`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorkspaceSort {
+    /// Newest workspace first
+    #[default]
+    Created,
+    /// Most recent activity first (see `WorkspaceActivity`), then newest
+    Recent,
+}
+
+impl std::str::FromStr for WorkspaceSort {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "" | "created" => Ok(WorkspaceSort::Created),
+            "recent" => Ok(WorkspaceSort::Recent),
+            _ => bail!("unknown workspace sort: {value} (expected created or recent)"),
+        }
+    }
 }
 
 pub fn workspace_list(conn: &Connection, repo_filter: Option<&str>) -> Result<Vec<Workspace>> {
+    workspace_list_sorted(conn, repo_filter, WorkspaceSort::Created)
+}
+
+pub fn workspace_list_sorted(conn: &Connection, repo_filter: Option<&str>, sort: WorkspaceSort) -> Result<Vec<Workspace>> {
     let mut sql = String::from(
         "
         SELECT
@@ -785,9 +1844,29 @@ pub fn workspace_list(conn: &Connection, repo_filter: Option<&str>) -> Result<Ve
             w.branch,
             w.base_branch,
             w.state,
-            w.path
+            w.path,
+            a.last_agent_run_at,
+            a.last_commit_at,
+            a.last_opened_at,
+            t.title,
+            t.summary,
+            v.ok,
+            v.failed_command,
+            v.session_id,
+            v.finished_at,
+            w.pinned,
+            w.error,
+            i.tracker,
+            i.issue_key,
+            i.title,
+            i.url,
+            (SELECT name FROM repo_remotes WHERE repository_id = w.repository_id AND preferred = 1)
         FROM workspaces w
         JOIN repos r ON r.id = w.repository_id
+        LEFT JOIN workspace_activity a ON a.workspace_id = w.id
+        LEFT JOIN workspace_titles t ON t.workspace_id = w.id
+        LEFT JOIN workspace_verifications v ON v.workspace_id = w.id
+        LEFT JOIN workspace_issues i ON i.workspace_id = w.id
         ",
     );
 
@@ -801,6 +1880,24 @@ pub fn workspace_list(conn: &Connection, repo_filter: Option<&str>) -> Result<Ve
 
     let mut stmt = db(conn.prepare(&sql))?;
     let rows = db(stmt.query_map(rusqlite::params_from_iter(params_vec.iter()), |row| {
+        let verification = match row.get::<_, Option<String>>(16)? {
+            Some(finished_at) => Some(WorkspaceVerification {
+                ok: row.get(13)?,
+                failed_command: row.get(14)?,
+                session_id: row.get(15)?,
+                finished_at,
+            }),
+            None => None,
+        };
+        let issue = match row.get::<_, Option<String>>(19)? {
+            Some(tracker) => Some(issues::IssueLink {
+                tracker,
+                key: row.get(20)?,
+                title: row.get(21)?,
+                url: row.get(22)?,
+            }),
+            None => None,
+        };
         Ok(Workspace {
             id: row.get(0)?,
             repo_id: row.get(1)?,
@@ -810,233 +1907,3347 @@ pub fn workspace_list(conn: &Connection, repo_filter: Option<&str>) -> Result<Ve
             base_branch: row.get(5)?,
             state: row.get(6)?,
             path: row.get(7)?,
+            pinned: row.get(17)?,
+            enrichment: None,
+            link_error: None,
+            error: row.get(18)?,
+            activity: WorkspaceActivity {
+                last_agent_run_at: row.get(8)?,
+                last_commit_at: row.get(9)?,
+                last_opened_at: row.get(10)?,
+            },
+            title: row.get(11)?,
+            summary: row.get(12)?,
+            verification,
+            issue,
+            preferred_remote: row.get(23)?,
         })
     }))?;
-    collect_rows(rows)
+    let mut workspaces = collect_rows(rows)?;
+    for ws in workspaces.iter_mut().filter(|ws| ws.state.is_live()) {
+        ws.link_error = worktree_link_problem(Path::new(&ws.path));
+    }
+    if sort == WorkspaceSort::Recent {
+        // Stable, so ties (and never-used workspaces) stay newest first
+        workspaces.sort_by(|a, b| b.activity.latest().cmp(&a.activity.latest()));
+    }
+    // Pinned workspaces lead whatever the order; stable, so each group keeps it
+    workspaces.sort_by_key(|ws| !ws.pinned);
+    Ok(workspaces)
 }
 
-pub fn workspace_files(conn: &Connection, ws_ref: &str) -> Result<Vec<String>> {
-    let context = workspace_context(conn, ws_ref)?;
-    // Get tracked files
-    let tracked = git(&context.path, &["ls-files", "-z"])?;
-    let mut files: Vec<String> = tracked
-        .split('\0')
-        .filter(|entry| !entry.is_empty())
-        .map(|entry| entry.to_string())
-        .collect();
-    // Also get untracked files (excluding .gitignore patterns)
-    if let Ok(untracked) = git(&context.path, &["ls-files", "--others", "--exclude-standard", "-z"]) {
-        files.extend(
-            untracked
-                .split('\0')
-                .filter(|entry| !entry.is_empty())
-                .map(|entry| entry.to_string())
-        );
-    }
-    files.sort();
-    files.dedup();
-    Ok(files)
+/// Pin a workspace to the top of listings, or unpin it
+pub fn workspace_set_pinned(conn: &Connection, ws_ref: &str, pinned: bool) -> Result<Workspace> {
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute("UPDATE workspaces SET pinned = ?1 WHERE id = ?2", params![pinned, ws.id]))?;
+    workspace_get(conn, &ws.id)
 }
 
-pub fn workspace_changes(conn: &Connection, ws_ref: &str) -> Result<Vec<WorkspaceChange>> {
-    let context = workspace_context(conn, ws_ref)?;
-    let base_ref = resolve_base_ref(&context.repo_root, &context.base_branch)?;
-    let diff = git(
-        &context.path,
-        &[
-            "diff",
-            "--name-status",
-            "--no-color",
-            "-z",
-            &format!("{base_ref}...HEAD"),
-        ],
-    )?;
-    let mut changes = Vec::new();
-    let mut seen_paths = std::collections::HashSet::new();
-    let mut parts = diff.split('\0').filter(|part| !part.is_empty());
-    while let Some(status) = parts.next() {
-        if status.starts_with('R') || status.starts_with('C') {
-            let old_path = match parts.next() {
-                Some(path) => path,
-                None => break,
-            };
-            let new_path = match parts.next() {
-                Some(path) => path,
-                None => break,
-            };
-            seen_paths.insert(new_path.to_string());
-            changes.push(WorkspaceChange {
-                old_path: Some(old_path.to_string()),
-                path: new_path.to_string(),
-                status: status.to_string(),
-            });
-        } else {
-            let path = match parts.next() {
-                Some(path) => path,
-                None => break,
-            };
-            seen_paths.insert(path.to_string());
-            changes.push(WorkspaceChange {
-                old_path: None,
-                path: path.to_string(),
-                status: status.to_string(),
-            });
-        }
-    }
-    // Also include untracked files as new additions
-    if let Ok(untracked) = git(&context.path, &["ls-files", "--others", "--exclude-standard", "-z"]) {
-        for path in untracked.split('\0').filter(|p| !p.is_empty()) {
-            if !seen_paths.contains(path) {
-                changes.push(WorkspaceChange {
-                    old_path: None,
-                    path: path.to_string(),
-                    status: "?".to_string(), // Untracked
-                });
-            }
-        }
-    }
-    // Also include modified but unstaged files
-    if let Ok(modified) = git(&context.path, &["diff", "--name-status", "-z"]) {
-        let mut mod_parts = modified.split('\0').filter(|p| !p.is_empty());
-        while let Some(status) = mod_parts.next() {
-            if let Some(path) = mod_parts.next() {
-                if !seen_paths.contains(path) {
-                    seen_paths.insert(path.to_string());
-                    changes.push(WorkspaceChange {
-                        old_path: None,
-                        path: path.to_string(),
-                        status: status.to_string(),
-                    });
-                }
-            }
+/// When work last happened in a workspace: its newest agent run or commit since its base
+/// (read from git too, since commits happen outside Conductor), or its creation
+pub fn workspace_last_worked(conn: &Connection, ws: &Workspace) -> Result<chrono::DateTime<Utc>> {
+    let created: String = db(conn.query_row("SELECT created_at FROM workspaces WHERE id = ?", [&ws.id], |row| {
+        row.get(0)
+    }))?;
+    let mut latest = chrono::NaiveDateTime::parse_from_str(&created, "%Y-%m-%d %H:%M:%S")
+        .map(|at| at.and_utc())
+        .map_err(|e| anyhow!("unexpected created_at {created}: {e}"))?;
+    let ws_path = Path::new(&ws.path);
+    let committed = resolve_base_ref(ws_path, &ws.base_branch, ws.preferred_remote.as_deref())
+        .ok()
+        .and_then(|base_ref| {
+            let log = GitCmd::new(ws_path).args(&["log", "-1", "--format=%cI"]);
+            log.value(format!("{base_ref}..HEAD")).try_run()
+        });
+    let recorded = [&ws.activity.last_agent_run_at, &ws.activity.last_commit_at];
+    for at in recorded.into_iter().flatten().chain(committed.as_ref()) {
+        if let Ok(at) = chrono::DateTime::parse_from_rfc3339(at) {
+            latest = latest.max(at.with_timezone(&Utc));
         }
     }
-    Ok(changes)
+    Ok(latest)
 }
 
-pub fn workspace_file_content(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<String> {
-    let context = workspace_context(conn, ws_ref)?;
-    let rel = safe_workspace_relpath(file_path)?;
-    let full_path = context.path.join(rel);
-    let bytes = fs(std::fs::read(&full_path))?;
-    String::from_utf8(bytes).map_err(|_| anyhow!("file is not valid utf-8"))
+/// Whether a ready or in-review workspace has gone `idle` without commits or agent runs.
+/// Merged and abandoned ones are finished rather than stale (see `workspaces_merged`)
+pub fn workspace_is_stale(conn: &Connection, ws: &Workspace, idle: Duration) -> Result<bool> {
+    if !matches!(ws.state, WorkspaceState::Ready | WorkspaceState::InReview) {
+        return Ok(false);
+    }
+    let idle = chrono::Duration::from_std(idle).map_err(|_| anyhow!("age is too large"))?;
+    Ok(workspace_last_worked(conn, ws)? < Utc::now() - idle)
 }
 
-pub fn workspace_file_diff(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<String> {
-    let context = workspace_context(conn, ws_ref)?;
-    let rel = safe_workspace_relpath(file_path)?;
-    let base_ref = resolve_base_ref(&context.repo_root, &context.base_branch)?;
-    let rel_str = rel.to_string_lossy().to_string();
-    git(
-        &context.path,
-        &[
-            "diff",
-            "--no-color",
-            &format!("{base_ref}...HEAD"),
-            "--",
-            &rel_str,
-        ],
-    )
+/// Something done in a workspace, for `workspace_activity_record`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    AgentRun,
+    Opened,
 }
 
-// =============================================================================
-// .conductor-app/ Folder Structure
-// =============================================================================
-
-/// Session state stored in .conductor-app/session.json
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SessionState {
-    pub agent_id: String,
-    pub resume_id: Option<String>,
-    pub started_at: String,
-    pub updated_at: String,
+/// Stamp `kind` on the workspace with the current time (and count agent runs)
+pub fn workspace_activity_record(conn: &Connection, ws_ref: &str, kind: ActivityKind) -> Result<()> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let sql = match kind {
+        ActivityKind::AgentRun => {
+            "INSERT INTO workspace_activity (workspace_id, last_agent_run_at, agent_runs) VALUES (?1, ?2, 1)
+             ON CONFLICT(workspace_id) DO UPDATE SET
+                 last_agent_run_at = excluded.last_agent_run_at, agent_runs = agent_runs + 1"
+        }
+        ActivityKind::Opened => {
+            "INSERT INTO workspace_activity (workspace_id, last_opened_at) VALUES (?1, ?2)
+             ON CONFLICT(workspace_id) DO UPDATE SET last_opened_at = excluded.last_opened_at"
+        }
+    };
+    db(conn.execute(sql, params![ws.id, Utc::now().to_rfc3339()]))?;
+    Ok(())
 }
 
-/// Chat message for persistence in .conductor-app/chat.md
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatEntry {
-    pub role: String,
-    pub content: String,
-    pub timestamp: String,
+/// Tokens an agent run used, as its engine reported them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Prompt tokens, cached ones included
+    pub input_tokens: u64,
+    pub output_tokens: u64,
 }
 
-/// Get the path to .conductor-app/ folder within a workspace
-pub fn conductor_app_path(ws_path: &Path) -> PathBuf {
-    ws_path.join(".conductor-app")
-}
+impl TokenUsage {
+    /// From the `usage` of an `agent.completed` event. Claude counts cache reads and
+    /// writes apart from `input_tokens`; Codex's `input_tokens` already includes them
+    pub fn from_engine(usage: &serde_json::Value) -> Self {
+        let count = |key: &str| usage.get(key).and_then(serde_json::Value::as_u64).unwrap_or(0);
+        Self {
+            input_tokens: count("input_tokens") + count("cache_creation_input_tokens") + count("cache_read_input_tokens"),
+            output_tokens: count("output_tokens"),
+        }
+    }
 
-/// Ensure .conductor-app/ folder exists with initial structure
-pub fn ensure_conductor_app(ws_path: &Path) -> Result<PathBuf> {
-    let app_dir = conductor_app_path(ws_path);
-    fs(std::fs::create_dir_all(&app_dir))?;
-    Ok(app_dir)
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
 }
 
-/// Read session state from .conductor-app/session.json
-pub fn session_read(ws_path: &Path) -> Result<Option<SessionState>> {
-    let session_path = conductor_app_path(ws_path).join("session.json");
-    if !session_path.exists() {
-        return Ok(None);
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
     }
-    let content = fs(std::fs::read_to_string(&session_path))?;
-    let session: SessionState = serde_json::from_str(&content)
-        .map_err(|e| anyhow!("failed to parse session.json: {}", e))?;
-    Ok(Some(session))
 }
 
-/// Write session state to .conductor-app/session.json
-pub fn session_write(ws_path: &Path, session: &SessionState) -> Result<()> {
-    let app_dir = ensure_conductor_app(ws_path)?;
-    let session_path = app_dir.join("session.json");
-    let content = serde_json::to_string_pretty(session)
-        .map_err(|e| anyhow!("failed to serialize session: {}", e))?;
-    let mut file = fs(std::fs::File::create(&session_path))?;
-    fs(file.write_all(content.as_bytes()))?;
+/// Add a run's token usage to the workspace's running totals
+pub fn workspace_usage_record(conn: &Connection, ws_ref: &str, usage: TokenUsage) -> Result<()> {
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute(
+        "INSERT INTO workspace_activity (workspace_id, input_tokens, output_tokens) VALUES (?1, ?2, ?3)
+         ON CONFLICT(workspace_id) DO UPDATE SET
+             input_tokens = input_tokens + excluded.input_tokens,
+             output_tokens = output_tokens + excluded.output_tokens",
+        params![ws.id, usage.input_tokens as i64, usage.output_tokens as i64],
+    ))?;
     Ok(())
 }
 
-/// Create a new session with the given agent ID
-pub fn session_create(ws_path: &Path, agent_id: &str) -> Result<SessionState> {
-    let now = Utc::now().to_rfc3339();
-    let session = SessionState {
-        agent_id: agent_id.to_string(),
-        resume_id: None,
-        started_at: now.clone(),
-        updated_at: now,
-    };
-    session_write(ws_path, &session)?;
-    Ok(session)
+/// Totals for a workspace header or report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceStats {
+    pub workspace_id: String,
+    /// Commits on the branch since its base
+    pub commits: u32,
+    /// Everything changed relative to the base, uncommitted work included (see `workspace_diffstat`)
+    pub diff: DiffStat,
+    /// Agent runs started in the workspace (counted since schema 14)
+    pub agent_runs: u64,
+    pub usage: TokenUsage,
+    /// UTC, SQLite `datetime` format
+    pub created_at: String,
+    pub age_secs: u64,
 }
 
-/// Update session with a resume ID (for CLI --resume flag)
-pub fn session_set_resume_id(ws_path: &Path, resume_id: &str) -> Result<SessionState> {
-    let mut session = session_read(ws_path)?
-        .ok_or_else(|| anyhow!("no session found"))?;
-    session.resume_id = Some(resume_id.to_string());
-    session.updated_at = Utc::now().to_rfc3339();
-    session_write(ws_path, &session)?;
-    Ok(session)
+pub fn workspace_stats(conn: &Connection, ws_ref: &str) -> Result<WorkspaceStats> {
+    let ws = workspace_get(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    let base_ref = resolve_base_ref(ws_path, &ws.base_branch, ws.preferred_remote.as_deref())?;
+    let commits = GitCmd::new(ws_path)
+        .args(&["rev-list", "--count"])
+        .value(format!("{base_ref}..HEAD"))
+        .run()?
+        .parse()
+        .unwrap_or(0);
+    let diff = workspace_diffstat(conn, &ws.id)?;
+    let (created_at, agent_runs, input_tokens, output_tokens): (String, i64, i64, i64) = db(conn.query_row(
+        "SELECT w.created_at, COALESCE(a.agent_runs, 0), COALESCE(a.input_tokens, 0), COALESCE(a.output_tokens, 0)
+         FROM workspaces w LEFT JOIN workspace_activity a ON a.workspace_id = w.id
+         WHERE w.id = ?",
+        [&ws.id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    ))?;
+    let age_secs = chrono::NaiveDateTime::parse_from_str(&created_at, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|at| (Utc::now() - at.and_utc()).to_std().ok())
+        .map_or(0, |age| age.as_secs());
+    Ok(WorkspaceStats {
+        workspace_id: ws.id,
+        commits,
+        diff,
+        agent_runs: agent_runs as u64,
+        usage: TokenUsage {
+            input_tokens: input_tokens as u64,
+            output_tokens: output_tokens as u64,
+        },
+        created_at,
+        age_secs,
+    })
 }
 
-/// Read chat history from .conductor-app/chat.md
+/// Refresh `last_commit_at` from the branch's newest commit since its base; commits
+/// happen outside Conductor, so callers run this after anything that may have made one
+pub fn workspace_activity_commit(conn: &Connection, ws_ref: &str) -> Result<Option<String>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    let base_ref = resolve_base_ref(ws_path, &ws.base_branch, ws.preferred_remote.as_deref())?;
+    let date = GitCmd::new(ws_path).args(&["log", "-1", "--format=%cI"]).value(format!("{base_ref}..HEAD")).run()?;
+    if date.is_empty() {
+        return Ok(None);
+    }
+    let at = chrono::DateTime::parse_from_rfc3339(&date)
+        .map(|at| at.with_timezone(&Utc).to_rfc3339())
+        .map_err(|e| anyhow!("unexpected commit date {date}: {e}"))?;
+    db(conn.execute(
+        "INSERT INTO workspace_activity (workspace_id, last_commit_at) VALUES (?1, ?2)
+         ON CONFLICT(workspace_id) DO UPDATE SET last_commit_at = excluded.last_commit_at",
+        params![ws.id, at],
+    ))?;
+    Ok(Some(at))
+}
+
+/// Longest title `workspace_title_update` stores, in characters
+pub const TITLE_CHARS: usize = 60;
+/// Longest summary `workspace_title_update` stores, in characters
+pub const SUMMARY_CHARS: usize = 280;
+
+// How much of the end of the conversation a title command gets
+const TITLE_TRANSCRIPT_CHARS: usize = 12_000;
+
+const TITLE_INSTRUCTIONS: &str = "Below is a conversation between a developer and a coding agent. \
+Reply with a title of at most eight words on the first line, then a summary of at most two \
+sentences of what was asked and where the work stands. No other text.";
+
+// The first line of `text` (or all of it, for summaries) cut to `max` characters
+fn clip(text: &str, max: usize, first_line: bool) -> Option<String> {
+    let text = text.trim();
+    let text = if first_line { text.lines().next().unwrap_or("").trim() } else { text };
+    let text = text.trim_matches(|c: char| c == '#' || c == '"' || c.is_whitespace());
+    if text.is_empty() {
+        return None;
+    }
+    Some(match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    })
+}
+
+/// Refresh the workspace's title and summary after an agent run, per `titles` in
+/// config.json. With a `command` both come from it; otherwise the title is the first
+/// user message of the chat (or `prompt`), kept once set, and the summary is `answer`
+/// (or the latest reply). Returns the stored pair, None when titles are off
+pub fn workspace_title_update(
+    conn: &Connection,
+    home: &Path,
+    ws_ref: &str,
+    prompt: Option<&str>,
+    answer: Option<&str>,
+) -> Result<Option<(Option<String>, Option<String>)>> {
+    let settings = config_load(home)?.titles;
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let ws = get_workspace(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    let chat = parse_chat(&chat_read(ws_path)?);
+    let is_user = |entry: &&ChatEntry| entry.role.eq_ignore_ascii_case("user");
+
+    let (title, summary, keep_title) = match settings.command.as_deref() {
+        Some([program, args @ ..]) => {
+            let mut transcript = String::new();
+            for entry in &chat {
+                transcript.push_str(&format!("{}: {}\n\n", entry.role, entry.content.trim()));
+            }
+            if chat.is_empty() {
+                for (role, text) in [("User", prompt), ("Assistant", answer)] {
+                    if let Some(text) = text {
+                        transcript.push_str(&format!("{role}: {}\n\n", text.trim()));
+                    }
+                }
+            }
+            let skip = transcript.chars().count().saturating_sub(TITLE_TRANSCRIPT_CHARS);
+            let transcript: String = transcript.chars().skip(skip).collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let input = format!("{TITLE_INSTRUCTIONS}\n\n{transcript}");
+            let output = run_with_input(program, &args, ws_path, &input)?;
+            let (title, summary) = output.trim().split_once('\n').unwrap_or((output.trim(), ""));
+            (clip(title, TITLE_CHARS, true), clip(summary, SUMMARY_CHARS, false), false)
+        }
+        _ => {
+            let first = chat.iter().find(is_user).map(|entry| entry.content.as_str()).or(prompt);
+            let latest = answer.or_else(|| chat.iter().rev().find(|e| !is_user(e)).map(|e| e.content.as_str()));
+            (
+                first.and_then(|text| clip(text, TITLE_CHARS, true)),
+                latest.and_then(|text| clip(text, SUMMARY_CHARS, false)),
+                true,
+            )
+        }
+    };
+
+    // Derived titles stay put once set; a summarizer's replace the old ones
+    let title_sql = if keep_title {
+        "COALESCE(workspace_titles.title, excluded.title)"
+    } else {
+        "COALESCE(excluded.title, workspace_titles.title)"
+    };
+    db(conn.execute(
+        &format!(
+            "INSERT INTO workspace_titles (workspace_id, title, summary, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(workspace_id) DO UPDATE SET
+                title = {title_sql},
+                summary = COALESCE(excluded.summary, workspace_titles.summary),
+                updated_at = excluded.updated_at"
+        ),
+        params![ws.id, title, summary, Utc::now().to_rfc3339()],
+    ))?;
+    let stored = db(conn.query_row(
+        "SELECT title, summary FROM workspace_titles WHERE workspace_id = ?",
+        [&ws.id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ))?;
+    Ok(Some(stored))
+}
+
+/// Outcome of the `verify` commands run after an agent run (see `RepoConfig::verify`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceVerification {
+    pub ok: bool,
+    /// The command that failed; the ones after it didn't run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_command: Option<String>,
+    /// The agent session whose run was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub finished_at: String,
+}
+
+/// The `verify` commands of the workspace's repo (`repos` entry by name or id)
+pub fn verify_commands(config: &Config, ws: &Workspace) -> Vec<String> {
+    config
+        .repos
+        .get(&ws.repo)
+        .or_else(|| config.repos.get(&ws.repo_id))
+        .map(|repo_config| repo_config.verify.clone())
+        .unwrap_or_default()
+}
+
+/// Store the latest verification of a workspace, replacing the previous one
+pub fn workspace_verification_record(conn: &Connection, ws_ref: &str, verification: &WorkspaceVerification) -> Result<()> {
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute(
+        "INSERT INTO workspace_verifications (workspace_id, ok, failed_command, session_id, finished_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(workspace_id) DO UPDATE SET
+            ok = excluded.ok,
+            failed_command = excluded.failed_command,
+            session_id = excluded.session_id,
+            finished_at = excluded.finished_at",
+        params![
+            ws.id,
+            verification.ok,
+            verification.failed_command,
+            verification.session_id,
+            verification.finished_at
+        ],
+    ))?;
+    Ok(())
+}
+
+// Run `cmd` with `input` on stdin; stdout, trimmed
+fn run_with_input(cmd: &str, args: &[&str], cwd: &Path, input: &str) -> Result<String> {
+    let display = format_command(cmd, args);
+    let mut child = Command::new(cmd)
+        .args(args)
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {display}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        fs(stdin.write_all(input.as_bytes()))?;
+    }
+    let output = fs(child.wait_with_output())?;
+    if !output.status.success() {
+        bail!("{display} failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Checks both links git keeps for a linked worktree: its `.git` file naming a gitdir in
+// the repository, and that gitdir's `gitdir` file naming the worktree back
+fn worktree_link_problem(ws_path: &Path) -> Option<String> {
+    let dot_git = ws_path.join(".git");
+    let content = match std::fs::read_to_string(&dot_git) {
+        Ok(content) => content,
+        Err(_) if !ws_path.exists() => return Some("workspace directory is missing".to_string()),
+        Err(e) => return Some(format!("cannot read {}: {e}", dot_git.display())),
+    };
+    let Some(gitdir) = content.trim().strip_prefix("gitdir: ") else {
+        return Some(format!("{} is not a worktree link", dot_git.display()));
+    };
+    // Relative links (worktree.useRelativePaths) are relative to the file holding them
+    let gitdir = ws_path.join(gitdir);
+    let Ok(back) = std::fs::read_to_string(gitdir.join("gitdir")) else {
+        return Some(format!("repository has no worktree entry at {}", gitdir.display()));
+    };
+    let back = gitdir.join(back.trim());
+    match (back.canonicalize(), dot_git.canonicalize()) {
+        (Ok(back), Ok(dot_git)) if back == dot_git => None,
+        _ => Some(format!("repository links the worktree to {}", back.display())),
+    }
+}
+
+/// One thing `workspace_repair` changed, or couldn't fix
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairAction {
+    /// "repo" or "workspace"
+    pub target: String,
+    pub id: String,
+    pub name: String,
+    /// "relocated" (DB path updated), "relinked" (git links fixed) or "broken"
+    pub action: String,
+    pub detail: String,
+}
+
+/// Fix up repos and workspaces after a conductor home is moved or restored from backup:
+/// recorded paths that no longer exist are pointed at the same place under `home`, then
+/// `git worktree repair` relinks each repo with its workspaces
+pub fn workspace_repair(conn: &Connection, home: &Path) -> Result<Vec<RepairAction>> {
+    let mut actions = Vec::new();
+    // The same trailing components, under this home
+    let relocate = |old: &str, dir: &str, depth: usize| -> Option<PathBuf> {
+        let old = Path::new(old);
+        let tail: Vec<_> = old.components().rev().take(depth).collect();
+        if tail.len() < depth {
+            return None;
+        }
+        let candidate = tail.into_iter().rev().fold(home.join(dir), |path, part| path.join(part));
+        (candidate != old && candidate.exists()).then_some(candidate)
+    };
+
+    for repo in repo_list(conn)? {
+        if Path::new(&repo.root_path).exists() {
+            continue;
+        }
+        let action = match relocate(&repo.root_path, "repos", 1) {
+            Some(new_root) => {
+                let new_root = new_root.to_string_lossy().to_string();
+                db(conn.execute(
+                    "UPDATE repos SET root_path = ?, updated_at = datetime('now') WHERE id = ?",
+                    params![new_root, repo.id],
+                ))?;
+                ("relocated", format!("{} -> {new_root}", repo.root_path))
+            }
+            None => ("broken", format!("repository not found: {}", repo.root_path)),
+        };
+        actions.push(RepairAction {
+            target: "repo".to_string(),
+            id: repo.id,
+            name: repo.name,
+            action: action.0.to_string(),
+            detail: action.1,
+        });
+    }
+
+    let mut workspaces = workspace_list(conn, None)?;
+    workspaces.retain(|ws| !matches!(ws.state, WorkspaceState::Archived));
+    for ws in workspaces.iter_mut().filter(|ws| !Path::new(&ws.path).exists()) {
+        if let Some(new_path) = relocate(&ws.path, "workspaces", 2) {
+            let new_path = new_path.to_string_lossy().to_string();
+            db(conn.execute(
+                "UPDATE workspaces SET path = ?, updated_at = datetime('now') WHERE id = ?",
+                params![new_path, ws.id],
+            ))?;
+            actions.push(RepairAction {
+                target: "workspace".to_string(),
+                id: ws.id.clone(),
+                name: ws.name.clone(),
+                action: "relocated".to_string(),
+                detail: format!("{} -> {new_path}", ws.path),
+            });
+            ws.path = new_path;
+        }
+    }
+
+    for repo in repo_list(conn)? {
+        let repo_root = Path::new(&repo.root_path);
+        let members: Vec<&Workspace> = workspaces.iter().filter(|ws| ws.repo_id == repo.id).collect();
+        let paths: Vec<&str> = members
+            .iter()
+            .filter(|ws| Path::new(&ws.path).exists())
+            .map(|ws| ws.path.as_str())
+            .collect();
+        let repaired = if repo_root.exists() && !paths.is_empty() {
+            GitCmd::new(repo_root).args(&["worktree", "repair"]).operands(&paths).run().map(|_| ())
+        } else {
+            Ok(())
+        };
+        for ws in members {
+            let problem = match (&repaired, worktree_link_problem(Path::new(&ws.path))) {
+                (_, None) if ws.link_error.is_some() || ws.state == WorkspaceState::Error => None,
+                (_, None) => continue,
+                (Err(e), Some(problem)) => Some(format!("{problem} (git worktree repair: {e})")),
+                (Ok(()), Some(problem)) => Some(problem),
+            };
+            match &problem {
+                Some(problem) => set_workspace_error(conn, &ws.id, problem)?,
+                // Repaired: it's usable again
+                None if ws.state == WorkspaceState::Error => set_workspace_state(conn, &ws.id, WorkspaceState::Ready)?,
+                None => {}
+            }
+            actions.push(RepairAction {
+                target: "workspace".to_string(),
+                id: ws.id.clone(),
+                name: ws.name.clone(),
+                action: if problem.is_some() { "broken" } else { "relinked" }.to_string(),
+                detail: problem.unwrap_or_else(|| ws.path.clone()),
+            });
+        }
+    }
+    Ok(actions)
+}
+
+/// Which fixes `workspace_reconcile` applies; with none set it only reports
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReconcileOptions {
+    /// Track unregistered worktrees under the workspaces directory as workspaces
+    pub adopt: bool,
+    /// Archive missing workspaces, drop stale git registrations, remove untracked
+    /// directories and clean unregistered worktrees (git refuses dirty ones). Worktrees
+    /// outside the workspaces directory are left alone
+    pub prune: bool,
+    /// Put missing workspaces in the error state (prune wins when both are set)
+    pub mark_error: bool,
+}
+
+/// A disagreement between the DB, the disk and git's worktree registrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileIssue {
+    /// "missing": a workspace whose directory is gone; "untracked": a directory under
+    /// the home's workspaces/ that neither the DB nor git knows; "unregistered": a git
+    /// worktree of a tracked repo that isn't a workspace; "stale": a worktree git still
+    /// lists whose directory is gone
+    pub kind: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// What was done about it, if anything ("adopted", "archived", "failed: ..." etc.)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+// One entry of `git worktree list --porcelain`
+struct RegisteredWorktree {
+    path: PathBuf,
+    branch: Option<String>,
+    bare: bool,
+}
+
+fn registered_worktrees(repo_root: &Path) -> Result<Vec<RegisteredWorktree>> {
+    let out = GitCmd::new(repo_root).args(&["worktree", "list", "--porcelain", "-z"]).run()?;
+    let mut worktrees = Vec::new();
+    for line in out.split('\0') {
+        if let Some(path) = line.strip_prefix("worktree ") {
+            worktrees.push(RegisteredWorktree {
+                path: PathBuf::from(path),
+                branch: None,
+                bare: false,
+            });
+        } else if let Some(worktree) = worktrees.last_mut() {
+            if let Some(branch) = line.strip_prefix("branch refs/heads/") {
+                worktree.branch = Some(branch.to_string());
+            } else if line == "bare" {
+                worktree.bare = true;
+            }
+        }
+    }
+    Ok(worktrees)
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Find (and optionally fix) workspaces whose directory is gone, directories and git
+/// worktrees that Conductor doesn't track, and stale git worktree registrations
+pub fn workspace_reconcile(conn: &Connection, home: &Path, options: ReconcileOptions) -> Result<Vec<ReconcileIssue>> {
+    let repos = repo_list(conn)?;
+    let workspaces = workspace_list(conn, None)?;
+    let live: Vec<&Workspace> = workspaces
+        .iter()
+        .filter(|ws| !matches!(ws.state, WorkspaceState::Archived))
+        .collect();
+    let tracked = |path: &Path| live.iter().any(|ws| same_path(Path::new(&ws.path), path));
+    let mut issues = Vec::new();
+    let outcome = |result: Result<&str>| match result {
+        Ok(action) => action.to_string(),
+        Err(e) => format!("failed: {e}"),
+    };
+
+    let mut prune_repos: Vec<&str> = Vec::new();
+    for ws in live.iter().filter(|ws| !Path::new(&ws.path).exists()) {
+        let action = if options.prune {
+            prune_repos.push(&ws.repo_id);
+            Some(outcome(set_workspace_state(conn, &ws.id, WorkspaceState::Archived).map(|_| "archived")))
+        } else if options.mark_error && !matches!(ws.state, WorkspaceState::Error) {
+            let error = format!("workspace directory is missing: {}", ws.path);
+            Some(outcome(set_workspace_error(conn, &ws.id, &error).map(|_| "marked error")))
+        } else {
+            None
+        };
+        issues.push(ReconcileIssue {
+            kind: "missing".to_string(),
+            path: ws.path.clone(),
+            repo_id: Some(ws.repo_id.clone()),
+            workspace_id: Some(ws.id.clone()),
+            branch: Some(ws.branch.clone()),
+            action,
+        });
+    }
+
+    // Only worktrees under workspaces/ are ours to adopt or remove; others are the
+    // user's and are just reported
+    let root = home.join("workspaces");
+    let canonical_root = root.canonicalize().unwrap_or_else(|_| root.clone());
+    let managed = |path: &Path| path.starts_with(&root) || path.starts_with(&canonical_root);
+
+    let mut registered: Vec<PathBuf> = Vec::new();
+    for repo in &repos {
+        let repo_root = Path::new(&repo.root_path);
+        let Ok(worktrees) = registered_worktrees(repo_root) else {
+            continue;
+        };
+        // The first entry is the main worktree (or the bare repo itself)
+        for worktree in worktrees.into_iter().skip(1).filter(|w| !w.bare) {
+            registered.push(worktree.path.clone());
+            if tracked(&worktree.path) {
+                continue;
+            }
+            let path = worktree.path.to_string_lossy().to_string();
+            let ours = managed(&worktree.path);
+            let (kind, action) = if !worktree.path.exists() {
+                if options.prune && ours {
+                    prune_repos.push(&repo.id);
+                }
+                ("stale", (options.prune && ours).then(|| "pruned".to_string()))
+            } else if !ours {
+                ("unregistered", None)
+            } else if options.adopt {
+                ("unregistered", Some(outcome(adopt_worktree(conn, repo, &worktree).map(|_| "adopted"))))
+            } else if options.prune {
+                let remove = GitCmd::new(repo_root).args(&["worktree", "remove"]).operand(&path);
+                ("unregistered", Some(outcome(remove.run().map(|_| "removed"))))
+            } else {
+                ("unregistered", None)
+            };
+            issues.push(ReconcileIssue {
+                kind: kind.to_string(),
+                path,
+                repo_id: Some(repo.id.clone()),
+                workspace_id: None,
+                branch: worktree.branch,
+                action,
+            });
+        }
+    }
+
+    // workspaces/<repo dir>/<workspace dir>
+    for repo_dir in std::fs::read_dir(&root).into_iter().flatten().flatten() {
+        for entry in std::fs::read_dir(repo_dir.path()).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if !path.is_dir() || tracked(&path) || registered.iter().any(|r| same_path(r, &path)) {
+                continue;
+            }
+            let action = options.prune.then(|| outcome(fs(std::fs::remove_dir_all(&path)).map(|_| "removed")));
+            issues.push(ReconcileIssue {
+                kind: "untracked".to_string(),
+                path: path.to_string_lossy().to_string(),
+                repo_id: None,
+                workspace_id: None,
+                branch: None,
+                action,
+            });
+        }
+    }
+
+    prune_repos.sort_unstable();
+    prune_repos.dedup();
+    for repo in repos.iter().filter(|repo| prune_repos.contains(&repo.id.as_str())) {
+        let _ = GitCmd::new(Path::new(&repo.root_path)).args(&["worktree", "prune"]).run();
+    }
+    Ok(issues)
+}
+
+// Clears any stored error; the error state goes through `set_workspace_error`
+fn set_workspace_state(conn: &Connection, ws_id: &str, state: WorkspaceState) -> Result<()> {
+    db(conn.execute(
+        "UPDATE workspaces SET state = ?, error = NULL, updated_at = datetime('now') WHERE id = ?",
+        [state.as_str(), ws_id],
+    ))?;
+    Ok(())
+}
+
+// Put a workspace in the error state, saying why
+fn set_workspace_error(conn: &Connection, ws_id: &str, error: &str) -> Result<()> {
+    db(conn.execute(
+        "UPDATE workspaces SET state = 'error', error = ?, updated_at = datetime('now') WHERE id = ?",
+        [error, ws_id],
+    ))?;
+    Ok(())
+}
+
+/// Move a workspace along its pipeline: ready, in-review, merged or abandoned. Archived
+/// workspaces stay archived; archiving goes through `workspace_archive`, which also
+/// removes the checkout. A workspace in the error state needs its checkout back first
+pub fn workspace_set_state(conn: &Connection, ws_ref: &str, state: WorkspaceState) -> Result<Workspace> {
+    if !state.is_live() {
+        bail!("cannot set a workspace to {state}: archive it, or let reconcile mark it");
+    }
+    let ws = workspace_get(conn, ws_ref)?;
+    match ws.state {
+        WorkspaceState::Archived => bail!("workspace {} is archived", ws.id),
+        WorkspaceState::Error => {
+            if let Some(problem) = worktree_link_problem(Path::new(&ws.path)).or_else(|| {
+                (!Path::new(&ws.path).is_dir()).then(|| format!("{} is missing", ws.path))
+            }) {
+                bail!("workspace {} is in the error state: {problem}", ws.id);
+            }
+        }
+        _ => {}
+    }
+    set_workspace_state(conn, &ws.id, state)?;
+    workspace_get(conn, &ws.id)
+}
+
+// Track an existing worktree as a workspace based on the repo's default branch
+fn adopt_worktree(conn: &Connection, repo: &Repo, worktree: &RegisteredWorktree) -> Result<()> {
+    let Some(branch) = &worktree.branch else {
+        bail!("detached HEAD; check out a branch first");
+    };
+    let name = worktree
+        .path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid worktree path"))?
+        .to_string_lossy()
+        .to_string();
+    let preferred_remote = preferred_remote(conn, &repo.id)?;
+    let base_ref = resolve_base_ref(Path::new(&repo.root_path), &repo.default_branch, preferred_remote.as_deref())
+        .unwrap_or_else(|_| repo.default_branch.clone());
+    db(conn.execute(
+        "
+        INSERT INTO workspaces (id, repository_id, directory_name, path, branch, base_branch, state)
+        VALUES (?, ?, ?, ?, ?, ?, 'ready')
+        ",
+        params![
+            Uuid::new_v4().to_string(),
+            repo.id,
+            name,
+            worktree.path.to_string_lossy(),
+            branch,
+            base_ref
+        ],
+    ))?;
+    Ok(())
+}
+
+pub fn workspace_get(conn: &Connection, ws_ref: &str) -> Result<Workspace> {
+    let id = get_workspace(conn, ws_ref)?.id;
+    workspace_list(conn, None)?
+        .into_iter()
+        .find(|ws| ws.id == id)
+        .ok_or_else(|| anyhow!("workspace not found: {ws_ref}"))
+}
+
+/// Filters and paging for `workspace_files`
+#[derive(Debug, Clone, Default)]
+pub struct FileQuery {
+    /// Only paths starting with this string
+    pub prefix: Option<String>,
+    /// Git glob pathspec: `*` stays within a directory, `**/` crosses them
+    pub glob: Option<String>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// One page of `workspace_files`, with the number of paths matching overall
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePage {
+    pub files: Vec<String>,
+    pub total: usize,
+}
+
+/// Tracked and untracked (not ignored) paths, sorted
+pub fn workspace_files(conn: &Connection, ws_ref: &str, query: &FileQuery) -> Result<FilePage> {
+    let context = workspace_context(conn, ws_ref)?;
+    let prefix = query.prefix.as_deref().unwrap_or("");
+    // Narrow git's output where possible; pathspecs OR together, so only one is passed
+    let pathspec = match (&query.glob, prefix.rsplit_once('/')) {
+        (Some(glob), _) if glob.is_empty() => bail!("glob must not be empty"),
+        (Some(glob), _) => Some(format!(":(glob){glob}")),
+        (None, Some((dir, _))) if !dir.is_empty() => Some(format!(":(literal){dir}/")),
+        _ => None,
+    };
+    let ls_files = |args: &[&'static str]| GitCmd::new(&context.path).args(args).operands(&pathspec).run();
+    let tracked = ls_files(&["ls-files", "-z"])?;
+    // Untracked files too (excluding .gitignore patterns)
+    let untracked = ls_files(&["ls-files", "--others", "--exclude-standard", "-z"]).unwrap_or_default();
+    let mut files: Vec<&str> = tracked
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|entry| !entry.is_empty() && entry.starts_with(prefix))
+        .collect();
+    files.sort_unstable();
+    files.dedup();
+    Ok(FilePage {
+        total: files.len(),
+        files: files
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Immediate children of `dir` ("" for the root), without ignored paths, directories
+/// first. Lets a file tree load one level at a time instead of every path up front.
+pub fn workspace_tree(conn: &Connection, ws_ref: &str, dir: &str) -> Result<Vec<TreeEntry>> {
+    let context = workspace_context(conn, ws_ref)?;
+    let dir = dir.trim().trim_end_matches('/');
+    let rel = if dir.is_empty() || dir == "." {
+        PathBuf::new()
+    } else {
+        safe_workspace_relpath(dir)?
+    };
+    let prefix = match rel.to_string_lossy() {
+        p if p.is_empty() => String::new(),
+        p => format!("{p}/"),
+    };
+    let full = contained_workspace_path(&context.path, &rel, true)?;
+    if !full.is_dir() {
+        bail!("not a directory: {dir}");
+    }
+
+    let mut entries: BTreeMap<String, TreeEntry> = BTreeMap::new();
+    for entry in fs(std::fs::read_dir(&full))? {
+        let entry = fs(entry)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" {
+            continue;
+        }
+        let file_type = fs(entry.file_type())?;
+        let (kind, size) = if file_type.is_symlink() {
+            ("symlink", None)
+        } else if file_type.is_dir() {
+            ("dir", None)
+        } else {
+            ("file", entry.metadata().ok().map(|meta| meta.len()))
+        };
+        entries.insert(
+            name.clone(),
+            TreeEntry {
+                path: format!("{prefix}{name}"),
+                name,
+                kind: kind.to_string(),
+                size,
+                status: None,
+            },
+        );
+    }
+    let paths: Vec<String> = entries.values().map(|entry| entry.path.clone()).collect();
+    let visible: HashSet<String> = workspace_filter_ignored(&context.path, &paths)?.into_iter().collect();
+    entries.retain(|_, entry| visible.contains(&entry.path));
+
+    // v2 records never start with a space, which the trimming in GitCmd::run would eat
+    let pathspec = if prefix.is_empty() { ".".to_string() } else { prefix.clone() };
+    let status = GitCmd::new(&context.path)
+        .args(&["status", "--porcelain=v2", "-z", "--untracked-files=all"])
+        .operand(&pathspec)
+        .run()?;
+    for (xy, path) in parse_porcelain_v2(&status) {
+        let code = porcelain_status(xy);
+        let Some(rest) = path.strip_prefix(prefix.as_str()) else {
+            continue;
+        };
+        let (name, nested) = match rest.split_once('/') {
+            Some((name, _)) => (name, true),
+            None => (rest, false),
+        };
+        match entries.get_mut(name) {
+            Some(entry) if nested => {
+                entry.status = Some(match entry.status.as_deref() {
+                    None | Some("??") if code == "??" => "??".to_string(),
+                    _ => "M".to_string(),
+                });
+            }
+            Some(entry) => entry.status = Some(code.to_string()),
+            // Deleted from the worktree but still tracked
+            None if code == "D" => {
+                entries.insert(
+                    name.to_string(),
+                    TreeEntry {
+                        name: name.to_string(),
+                        path: format!("{prefix}{name}"),
+                        kind: if nested { "dir" } else { "file" }.to_string(),
+                        size: None,
+                        status: Some(code.to_string()),
+                    },
+                );
+            }
+            None => {}
+        }
+    }
+
+    let mut entries: Vec<TreeEntry> = entries.into_values().collect();
+    entries.sort_by(|a, b| (a.kind != "dir", &a.name).cmp(&(b.kind != "dir", &b.name)));
+    Ok(entries)
+}
+
+/// `(XY, path)` for each record of `git status --porcelain=v2 -z`. Renames report the
+/// new path; ignored-file records are skipped.
+fn parse_porcelain_v2(status: &str) -> Vec<(&str, &str)> {
+    let mut entries = Vec::new();
+    let mut records = status.split('\0').filter(|record| !record.is_empty());
+    while let Some(record) = records.next() {
+        let fields = match record.as_bytes()[0] {
+            b'1' => 9,
+            b'2' => {
+                // The original path follows as its own record
+                records.next();
+                10
+            }
+            b'u' => 11,
+            b'?' => {
+                entries.push(("??", &record[2..]));
+                continue;
+            }
+            _ => continue,
+        };
+        if let [_, xy, .., path] = record.splitn(fields, ' ').collect::<Vec<_>>()[..] {
+            entries.push((xy, path));
+        }
+    }
+    entries
+}
+
+/// Collapses a porcelain v2 XY code to one status: "??", "U" for conflicts, or
+/// whichever side changed ("M", "A", "D", "R", ...)
+fn porcelain_status(xy: &str) -> &str {
+    match xy {
+        "??" => "??",
+        xy if xy.contains('U') || xy == "AA" || xy == "DD" => "U",
+        xy => xy.trim_matches('.').get(..1).unwrap_or("M"),
+    }
+}
+
+/// Parses `git diff --name-status -z`; renames and copies carry their old path
+fn parse_name_status(diff: &str) -> Vec<WorkspaceChange> {
+    let mut changes = Vec::new();
+    let mut parts = diff.split('\0').filter(|part| !part.is_empty());
+    while let Some(status) = parts.next() {
+        let old_path = if status.starts_with('R') || status.starts_with('C') {
+            match parts.next() {
+                Some(path) => Some(path.to_string()),
+                None => break,
+            }
+        } else {
+            None
+        };
+        let Some(path) = parts.next() else { break };
+        changes.push(WorkspaceChange {
+            old_path,
+            path: path.to_string(),
+            status: status.to_string(),
+        });
+    }
+    changes
+}
+
+pub fn workspace_changes(conn: &Connection, ws_ref: &str) -> Result<Vec<WorkspaceChange>> {
+    let context = workspace_context(conn, ws_ref)?;
+    let base_ref = context.base_ref()?;
+    let diff = GitCmd::new(&context.path)
+        .args(&["diff", "--name-status", "--no-color", "-z"])
+        .value(format!("{base_ref}...HEAD"))
+        .run()?;
+    let mut changes = parse_name_status(&diff);
+    let mut seen_paths: HashSet<String> = changes.iter().map(|change| change.path.clone()).collect();
+    // Also include untracked files as new additions
+    let untracked = GitCmd::new(&context.path).args(&["ls-files", "--others", "--exclude-standard", "-z"]);
+    if let Ok(untracked) = untracked.run() {
+        for path in untracked.split('\0').filter(|p| !p.is_empty()) {
+            if !seen_paths.contains(path) {
+                changes.push(WorkspaceChange {
+                    old_path: None,
+                    path: path.to_string(),
+                    status: "?".to_string(), // Untracked
+                });
+            }
+        }
+    }
+    // Also include modified but unstaged files
+    if let Ok(modified) = GitCmd::new(&context.path).args(&["diff", "--name-status", "-z"]).run() {
+        let mut mod_parts = modified.split('\0').filter(|p| !p.is_empty());
+        while let Some(status) = mod_parts.next() {
+            if let Some(path) = mod_parts.next() {
+                if !seen_paths.contains(path) {
+                    seen_paths.insert(path.to_string());
+                    changes.push(WorkspaceChange {
+                        old_path: None,
+                        path: path.to_string(),
+                        status: status.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Files, worktree statuses and branch changes of a workspace in one pass: `ls-files`
+/// for tracked paths, a single porcelain v2 status for untracked and dirty ones, and
+/// one diff against the base. Replaces calling `workspace_files` and
+/// `workspace_changes` back to back, which spawns twice as many git processes.
+pub fn workspace_snapshot(conn: &Connection, ws_ref: &str) -> Result<WorkspaceSnapshot> {
+    let context = workspace_context(conn, ws_ref)?;
+    let base_ref = context.base_ref()?;
+    let tracked = GitCmd::new(&context.path).args(&["ls-files", "-z"]).run()?;
+    let status = GitCmd::new(&context.path)
+        .args(&["status", "--porcelain=v2", "-z", "--untracked-files=all"])
+        .run()?;
+    let diff = GitCmd::new(&context.path)
+        .args(&["diff", "--name-status", "--no-color", "-z"])
+        .value(format!("{base_ref}...HEAD"))
+        .run()?;
+
+    let statuses: BTreeMap<String, String> = parse_porcelain_v2(&status)
+        .into_iter()
+        .map(|(xy, path)| (path.to_string(), porcelain_status(xy).to_string()))
+        .collect();
+    let mut files: Vec<String> = tracked
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .chain(statuses.iter().filter(|(_, code)| *code == "??").map(|(path, _)| path.clone()))
+        .collect();
+    files.sort_unstable();
+    files.dedup();
+
+    let mut changes = parse_name_status(&diff);
+    let seen: HashSet<String> = changes.iter().map(|change| change.path.clone()).collect();
+    // Uncommitted work the branch diff can't see, in the same form workspace_changes uses
+    changes.extend(statuses.iter().filter(|(path, _)| !seen.contains(*path)).map(|(path, code)| {
+        WorkspaceChange {
+            old_path: None,
+            path: path.clone(),
+            status: if code == "??" { "?".to_string() } else { code.clone() },
+        }
+    }));
+    Ok(WorkspaceSnapshot { files, statuses, changes })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceHead {
+    pub branch: Option<String>,
+    pub head: String,
+}
+
+/// Absolute git dir of a workspace worktree (where its HEAD lives)
+pub fn workspace_git_dir(conn: &Connection, ws_ref: &str) -> Result<PathBuf> {
+    let context = workspace_context(conn, ws_ref)?;
+    worktree_git_dir(&context.path)
+}
+
+/// Absolute git dir of the worktree at `ws_path`
+pub fn worktree_git_dir(ws_path: &Path) -> Result<PathBuf> {
+    let out = GitCmd::new(ws_path).args(&["rev-parse", "--absolute-git-dir"]).run()?;
+    Ok(PathBuf::from(out))
+}
+
+/// Current branch (None when detached) and HEAD commit of a worktree
+pub fn workspace_head(ws_path: &Path) -> Result<WorkspaceHead> {
+    let head = GitCmd::new(ws_path).args(&["rev-parse", "HEAD"]).run()?;
+    let branch = GitCmd::new(ws_path)
+        .args(&["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .try_run()
+        .filter(|b| !b.is_empty());
+    Ok(WorkspaceHead { branch, head })
+}
+
+/// Git state shown next to a workspace in lists
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceEnrichment {
+    pub head: String,
+    /// Uncommitted changes, untracked files included
+    pub dirty: bool,
+    pub changed_files: u32,
+    /// Commits on the workspace branch that its base lacks, and the reverse
+    pub ahead: u32,
+    pub behind: u32,
+}
+
+/// Dirty state and ahead/behind counts against the base branch (a few git calls)
+pub fn workspace_enrichment(ws: &Workspace) -> Result<WorkspaceEnrichment> {
+    let ws_path = Path::new(&ws.path);
+    let head = GitCmd::new(ws_path).args(&["rev-parse", "HEAD"]).run()?;
+    let status = GitCmd::new(ws_path)
+        .args(&["status", "--porcelain=v2", "-z"])
+        .operands([".", ":(exclude).conductor-app"])
+        .run()?;
+    let mut changed_files = 0;
+    let mut records = status.split('\0').filter(|record| !record.is_empty());
+    while let Some(record) = records.next() {
+        if record.starts_with('2') {
+            // Renames carry the original path as an extra record
+            records.next();
+        }
+        changed_files += 1;
+    }
+    let base_ref = resolve_base_ref(ws_path, &ws.base_branch, ws.preferred_remote.as_deref())?;
+    let counts = GitCmd::new(ws_path)
+        .args(&["rev-list", "--left-right", "--count"])
+        .value(format!("{base_ref}...HEAD"))
+        .run()?;
+    let mut counts = counts.split_whitespace().map(|n| n.parse::<u32>().unwrap_or(0));
+    let behind = counts.next().unwrap_or(0);
+    let ahead = counts.next().unwrap_or(0);
+    Ok(WorkspaceEnrichment {
+        head,
+        dirty: changed_files > 0,
+        changed_files,
+        ahead,
+        behind,
+    })
+}
+
+/// A fingerprint of a worktree's HEAD and index read straight from its git dir (no git
+/// process): changes whenever HEAD, the branch tip (via the reflog) or the index does.
+/// Unstaged edits don't show up here.
+pub fn workspace_state_key(git_dir: &Path) -> String {
+    let stamp = |name: &str| {
+        std::fs::metadata(git_dir.join(name))
+            .map(|meta| {
+                let modified = meta
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_nanos());
+                format!("{modified}:{}", meta.len())
+            })
+            .unwrap_or_default()
+    };
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).unwrap_or_default();
+    format!("{}|{}|{}", head.trim(), stamp("index"), stamp("logs/HEAD"))
+}
+
+/// Paths with unresolved merge conflicts (unmerged index entries)
+pub fn workspace_conflicts(ws_path: &Path) -> Result<Vec<String>> {
+    let unmerged = GitCmd::new(ws_path).args(&["diff", "--name-only", "--diff-filter=U"]).run()?;
+    Ok(unmerged.lines().map(str::to_string).collect())
+}
+
+/// Drop paths (relative to the worktree) that .gitignore rules exclude
+pub fn workspace_filter_ignored(ws_path: &Path, paths: &[String]) -> Result<Vec<String>> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let check_ignore = GitCmd::new(ws_path).args(&["check-ignore", "--stdin", "-z"]);
+    let mut child = check_ignore
+        .command()?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to run {}", check_ignore.display()))?;
+    let mut input = Vec::new();
+    for path in paths {
+        input.extend_from_slice(path.as_bytes());
+        input.push(0);
+    }
+    if let Some(mut stdin) = child.stdin.take() {
+        fs(stdin.write_all(&input))?;
+    }
+    let output = fs(child.wait_with_output())?;
+    // Exit code 1 means nothing matched; anything else non-zero is a real failure
+    if !output.status.success() && output.status.code() != Some(1) {
+        return Err(UserError::Command {
+            area: "git",
+            command: check_ignore.display(),
+            message: "check-ignore failed".to_string(),
+        }
+        .into());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let ignored: HashSet<&str> = stdout.split('\0').filter(|p| !p.is_empty()).collect();
+    Ok(paths.iter().filter(|p| !ignored.contains(p.as_str())).cloned().collect())
+}
+
+pub fn workspace_file_content(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<String> {
+    let bytes = workspace_file_bytes(conn, ws_ref, file_path)?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("file is not valid utf-8"))
+}
+
+/// A workspace file as stored, for callers that decode it themselves
+pub fn workspace_file_bytes(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<Vec<u8>> {
+    let full_path = workspace_file_path(conn, ws_ref, file_path)?;
+    fs(std::fs::read(&full_path))
+}
+
+/// Absolute path of a file in a workspace, after the same relpath checks as reads
+pub fn workspace_file_path(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<PathBuf> {
+    let context = workspace_context(conn, ws_ref)?;
+    let rel = safe_workspace_relpath(file_path)?;
+    contained_workspace_path(&context.path, &rel, true)
+}
+
+/// Hex sha256 of file content, the version token for `workspace_file_save`
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Write a workspace file, creating parent directories. With `expected_hash` the write
+/// only happens if the file still has that content hash ("" meaning it must not
+/// exist yet), else it fails with `FileConflict`. Returns the new content hash.
+pub fn workspace_file_save(
+    conn: &Connection,
+    ws_ref: &str,
+    file_path: &str,
+    content: &[u8],
+    expected_hash: Option<&str>,
+) -> Result<String> {
+    // Not workspace_file_path: a save must not replace the .git pointer file either
+    let context = workspace_context(conn, ws_ref)?;
+    let full_path = contained_workspace_path(&context.path, &mutable_workspace_relpath(file_path)?, true)?;
+    if full_path.is_dir() {
+        bail!("is a directory: {file_path}");
+    }
+    if let Some(expected) = expected_hash {
+        let current_hash = match std::fs::read(&full_path) {
+            Ok(bytes) => Some(content_hash(&bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return fs(Err(e)),
+        };
+        if current_hash.as_deref().unwrap_or("") != expected {
+            return Err(FileConflict {
+                path: file_path.to_string(),
+                current_hash,
+            }
+            .into());
+        }
+    }
+    if let Some(parent) = full_path.parent() {
+        fs(std::fs::create_dir_all(parent))?;
+    }
+    // Via a temp file so the agent and watchers never see a half-written file
+    let name = full_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let tmp = full_path.with_file_name(format!(".{name}.conductor-save"));
+    fs(std::fs::write(&tmp, content))?;
+    if let Ok(meta) = std::fs::metadata(&full_path) {
+        fs(std::fs::set_permissions(&tmp, meta.permissions()))?;
+    }
+    if let Err(e) = std::fs::rename(&tmp, &full_path) {
+        let _ = std::fs::remove_file(&tmp);
+        return fs(Err(e));
+    }
+    Ok(content_hash(content))
+}
+
+// A relpath that file management may change: never the root itself or git's own files
+fn mutable_workspace_relpath(path: &str) -> Result<PathBuf> {
+    let rel = safe_workspace_relpath(path)?;
+    let mut names = rel.components().filter(|c| !matches!(c, Component::CurDir)).peekable();
+    match names.peek() {
+        None => bail!("not a path inside the workspace: {path}"),
+        Some(first) if first.as_os_str() == ".git" => bail!("refusing to change .git"),
+        Some(_) => Ok(rel),
+    }
+}
+
+// Whether git tracks `rel` (a file, or anything under a directory). Paths in this
+// section are literal: a file named `*.rs` is not a glob
+fn workspace_tracks(ws_path: &Path, rel: &Path) -> Result<bool> {
+    let rel = rel.to_string_lossy();
+    Ok(!GitCmd::new(ws_path).args(&["--literal-pathspecs", "ls-files", "-z"]).operand(rel).run()?.is_empty())
+}
+
+/// Move a file or directory within a workspace. Tracked paths go through `git mv`, so
+/// the rename is staged; returns whether it was.
+pub fn workspace_file_rename(conn: &Connection, ws_ref: &str, from: &str, to: &str) -> Result<bool> {
+    let context = workspace_context(conn, ws_ref)?;
+    let from_rel = mutable_workspace_relpath(from)?;
+    let to_rel = mutable_workspace_relpath(to)?;
+    // A symlink itself may move; just not through a symlinked directory
+    let from_path = contained_workspace_path(&context.path, &from_rel, false)?;
+    let to_path = contained_workspace_path(&context.path, &to_rel, false)?;
+    if std::fs::symlink_metadata(&from_path).is_err() {
+        bail!("no such file: {from}");
+    }
+    if std::fs::symlink_metadata(&to_path).is_ok() {
+        bail!("already exists: {to}");
+    }
+    if let Some(parent) = to_path.parent() {
+        fs(std::fs::create_dir_all(parent))?;
+    }
+    if workspace_tracks(&context.path, &from_rel)? {
+        let (from, to) = (from_rel.to_string_lossy(), to_rel.to_string_lossy());
+        GitCmd::new(&context.path).args(&["mv"]).operands([from, to]).run()?;
+        return Ok(true);
+    }
+    fs(std::fs::rename(&from_path, &to_path))?;
+    Ok(false)
+}
+
+/// Delete a file or directory (recursively) from a workspace. Tracked paths are
+/// removed with `git rm`, staging the deletion; returns whether it was.
+pub fn workspace_file_delete(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<bool> {
+    let context = workspace_context(conn, ws_ref)?;
+    let rel = mutable_workspace_relpath(file_path)?;
+    // Deleting a symlink removes the link, never what it points at
+    let full_path = contained_workspace_path(&context.path, &rel, false)?;
+    let meta = match std::fs::symlink_metadata(&full_path) {
+        Ok(meta) => meta,
+        Err(_) => bail!("no such file: {file_path}"),
+    };
+    let staged = workspace_tracks(&context.path, &rel)?;
+    if staged {
+        let rel = rel.to_string_lossy();
+        GitCmd::new(&context.path)
+            .args(&["--literal-pathspecs", "rm", "-r", "-f", "--quiet"])
+            .operand(rel)
+            .run()?;
+    }
+    // Whatever git rm left behind (untracked files in a directory) goes too
+    if std::fs::symlink_metadata(&full_path).is_ok() {
+        if meta.is_dir() {
+            fs(std::fs::remove_dir_all(&full_path))?;
+        } else {
+            fs(std::fs::remove_file(&full_path))?;
+        }
+    }
+    Ok(staged)
+}
+
+// Validated pathspecs for the index operations below
+fn workspace_pathspecs(paths: &[String], check: fn(&str) -> Result<PathBuf>) -> Result<Vec<String>> {
+    if paths.is_empty() {
+        bail!("no paths given");
+    }
+    paths
+        .iter()
+        .map(|path| Ok(check(path)?.to_string_lossy().to_string()))
+        .collect()
+}
+
+// `git <args> -- <paths>` with the paths taken literally, never as globs
+fn git_with_paths(ws_path: &Path, args: &[&'static str], paths: &[String]) -> Result<String> {
+    GitCmd::new(ws_path).args(&["--literal-pathspecs"]).args(args).operands(paths).run()
+}
+
+/// Stage files or directories for the next commit, deletions included
+pub fn workspace_stage(conn: &Connection, ws_ref: &str, paths: &[String]) -> Result<()> {
+    let context = workspace_context(conn, ws_ref)?;
+    let paths = workspace_pathspecs(paths, safe_workspace_relpath)?;
+    git_with_paths(&context.path, &["add", "--all"], &paths)?;
+    Ok(())
+}
+
+/// Take paths back out of the index, keeping the worktree as it is
+pub fn workspace_unstage(conn: &Connection, ws_ref: &str, paths: &[String]) -> Result<()> {
+    let context = workspace_context(conn, ws_ref)?;
+    let paths = workspace_pathspecs(paths, safe_workspace_relpath)?;
+    git_with_paths(&context.path, &["restore", "--staged"], &paths)?;
+    Ok(())
+}
+
+/// Throw away uncommitted changes to paths, staged or not, back to HEAD. Paths git
+/// doesn't know are only deleted with `include_untracked`; otherwise they are an error.
+pub fn workspace_discard(conn: &Connection, ws_ref: &str, paths: &[String], include_untracked: bool) -> Result<()> {
+    let context = workspace_context(conn, ws_ref)?;
+    let paths = workspace_pathspecs(paths, mutable_workspace_relpath)?;
+    let mut known = Vec::new();
+    let mut untracked = Vec::new();
+    for path in paths {
+        // In the index, or in HEAD (a staged deletion)
+        let in_git = workspace_tracks(&context.path, Path::new(&path))?
+            || !git_with_paths(&context.path, &["ls-tree", "-r", "--name-only", "HEAD"], std::slice::from_ref(&path))?.is_empty();
+        if in_git {
+            known.push(path);
+        } else {
+            untracked.push(path);
+        }
+    }
+    if !include_untracked && !untracked.is_empty() {
+        bail!("not tracked by git (include untracked files to delete them): {}", untracked.join(", "));
+    }
+    if !known.is_empty() {
+        git_with_paths(&context.path, &["restore", "--source=HEAD", "--staged", "--worktree"], &known)?;
+    }
+    if include_untracked {
+        // Untracked files inside tracked directories go too; ignored files stay
+        let all: Vec<String> = known.into_iter().chain(untracked).collect();
+        git_with_paths(&context.path, &["clean", "--force", "-d", "--quiet"], &all)?;
+    }
+    Ok(())
+}
+
+/// Create a directory (and any missing parents) in a workspace. Git doesn't track
+/// empty directories, so there is nothing to stage.
+pub fn workspace_dir_create(conn: &Connection, ws_ref: &str, dir_path: &str) -> Result<()> {
+    let context = workspace_context(conn, ws_ref)?;
+    let full_path = contained_workspace_path(&context.path, &mutable_workspace_relpath(dir_path)?, true)?;
+    if full_path.exists() && !full_path.is_dir() {
+        bail!("a file is in the way: {dir_path}");
+    }
+    fs(std::fs::create_dir_all(&full_path))
+}
+
+/// How much of a file git looks at when deciding whether it is binary
+pub const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Git's heuristic: binary if the first `BINARY_SNIFF_BYTES` contain a NUL
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0)
+}
+
+/// How file content should be decoded: "binary" when `is_binary` says so, "utf-8", or
+/// "text" for text in some other encoding (Latin-1, Shift JIS, ...)
+pub fn content_encoding(bytes: &[u8]) -> &'static str {
+    if is_binary(bytes) {
+        "binary"
+    } else if std::str::from_utf8(bytes).is_ok() {
+        "utf-8"
+    } else {
+        "text"
+    }
+}
+
+pub fn workspace_file_diff(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<String> {
+    workspace_file_diff_unified(conn, ws_ref, file_path, None)
+}
+
+fn workspace_file_diff_unified(conn: &Connection, ws_ref: &str, file_path: &str, context_lines: Option<u32>) -> Result<String> {
+    let context = workspace_context(conn, ws_ref)?;
+    let rel = safe_workspace_relpath(file_path)?;
+    // git shows a symlink as its target path, so only the directories on the way matter
+    contained_workspace_path(&context.path, &rel, false)?;
+    let base_ref = context.base_ref()?;
+    let rel_str = rel.to_string_lossy().to_string();
+    let mut diff = GitCmd::new(&context.path).args(&["diff", "--no-color"]);
+    if let Some(lines) = context_lines {
+        diff = diff.opt("--unified", lines);
+    }
+    diff.value(format!("{base_ref}...HEAD")).operand(&rel_str).run_raw()
+}
+
+/// How `workspace_file_diff_with` shapes a diff
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffOptions {
+    /// Lines of context around each change; git's default (3) when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<u32>,
+    /// Hunk lines to return before cutting off, so a lockfile or generated file can't
+    /// swamp a viewer. The hunk at the cut is shortened, the ones after it left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_lines: Option<usize>,
+    /// Only this hunk (0-based), whole: the follow-up for one cut or left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hunk: Option<usize>,
+}
+
+/// A file's patch, possibly cut short by `DiffOptions::max_lines`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileDiff {
+    /// Still parses as a patch when cut: shortened hunks have their headers recounted
+    pub diff: String,
+    /// Hunk lines in the whole diff
+    pub total_lines: usize,
+    /// Hunks shortened or left out, by index; fetch each with `DiffOptions::hunk`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub omitted_hunks: Vec<u32>,
+}
+
+impl FileDiff {
+    pub fn truncated(&self) -> bool {
+        !self.omitted_hunks.is_empty()
+    }
+}
+
+/// `workspace_file_diff` with context, a size cap and single-hunk fetches
+pub fn workspace_file_diff_with(conn: &Connection, ws_ref: &str, file_path: &str, options: &DiffOptions) -> Result<FileDiff> {
+    let diff = workspace_file_diff_unified(conn, ws_ref, file_path, options.context)?;
+    limit_diff(&diff, options)
+}
+
+/// Cut a single-file patch down to its file header plus one hunk, or plus
+/// `max_lines` lines of hunks
+fn limit_diff(diff: &str, options: &DiffOptions) -> Result<FileDiff> {
+    // Lines that count against the cap: everything but "\ No newline at end of file"
+    fn body_lines(hunk: &[&str]) -> usize {
+        hunk[1..].iter().filter(|line| !line.starts_with('\\')).count()
+    }
+
+    let lines: Vec<&str> = diff.split_inclusive('\n').collect();
+    let first_hunk = lines.iter().position(|line| line.starts_with("@@")).unwrap_or(lines.len());
+    let mut hunks: Vec<&[&str]> = Vec::new();
+    let mut start = first_hunk;
+    for index in first_hunk + 1..=lines.len() {
+        if index == lines.len() || lines[index].starts_with("@@") {
+            hunks.push(&lines[start..index]);
+            start = index;
+        }
+    }
+    let total_lines = hunks.iter().map(|hunk| body_lines(hunk)).sum();
+    let mut limited = FileDiff {
+        diff: lines[..first_hunk].concat(),
+        total_lines,
+        omitted_hunks: Vec::new(),
+    };
+
+    if let Some(index) = options.hunk {
+        let Some(hunk) = hunks.get(index) else {
+            bail!("hunk {index} not found; the diff has {} hunks now", hunks.len());
+        };
+        limited.diff.push_str(&hunk.concat());
+        return Ok(limited);
+    }
+    let mut budget = options.max_lines.unwrap_or(usize::MAX);
+    for (index, hunk) in hunks.iter().enumerate() {
+        if budget == 0 {
+            limited.omitted_hunks.push(index as u32);
+            continue;
+        }
+        let count = body_lines(hunk);
+        if count <= budget {
+            limited.diff.push_str(&hunk.concat());
+            budget -= count;
+            continue;
+        }
+        // Keep `budget` lines (and the no-newline marker of the last one), then recount
+        let mut kept = Vec::new();
+        let (mut taken, mut old_lines, mut new_lines, mut changed) = (0, 0, 0, false);
+        for line in &hunk[1..] {
+            match line.as_bytes().first() {
+                Some(b'\\') => {}
+                _ if taken == budget => break,
+                Some(b'-') => (old_lines, changed) = (old_lines + 1, true),
+                Some(b'+') => (new_lines, changed) = (new_lines + 1, true),
+                _ => {
+                    old_lines += 1;
+                    new_lines += 1;
+                }
+            }
+            if !line.starts_with('\\') {
+                taken += 1;
+            }
+            kept.push(*line);
+        }
+        // Context alone isn't a hunk git (or a viewer) will take
+        if changed {
+            limited.diff.push_str(&recount_hunk_header(hunk[0], old_lines, new_lines));
+            limited.diff.push_str(&kept.concat());
+        }
+        limited.omitted_hunks.push(index as u32);
+        budget = 0;
+    }
+    Ok(limited)
+}
+
+// "@@ -12,30 +12,31 @@ fn main" with new line counts; starts and function context kept
+fn recount_hunk_header(header: &str, old_lines: u32, new_lines: u32) -> String {
+    let mut parts = header.splitn(4, ' ');
+    let (Some("@@"), Some(old), Some(new), Some(rest)) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return header.to_string();
+    };
+    let start = |spec: &str| spec.split(',').next().unwrap_or(spec).to_string();
+    format!("@@ {},{old_lines} {},{new_lines} {rest}", start(old), start(new))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    /// "context", "add" or "delete"
+    pub kind: String,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<u32>,
+    /// Git's "\ No newline at end of file" applies to this line
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_newline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    /// Function context git prints after the second @@, if any
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StructuredDiff {
+    pub binary: bool,
+    pub hunks: Vec<DiffHunk>,
+    /// As in `FileDiff`: indexes into the whole diff, not `hunks`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub omitted_hunks: Vec<u32>,
+}
+
+/// Hunks of a single-file unified diff; file headers are skipped
+pub fn parse_unified_diff(diff: &str) -> StructuredDiff {
+    // "-12,3" or "+7" (a count of 1 is left out)
+    fn range(spec: &str) -> (u32, u32) {
+        let spec = &spec[1..];
+        match spec.split_once(',') {
+            Some((start, count)) => (start.parse().unwrap_or(0), count.parse().unwrap_or(0)),
+            None => (spec.parse().unwrap_or(0), 1),
+        }
+    }
+
+    let mut parsed = StructuredDiff::default();
+    let (mut old_line, mut new_line) = (0, 0);
+    for line in diff.lines() {
+        if let Some(rest) = line.strip_prefix("@@ ") {
+            let Some((ranges, header)) = rest.split_once(" @@") else {
+                continue;
+            };
+            let (old, new) = ranges.split_once(' ').unwrap_or((ranges, "+0,0"));
+            let ((old_start, old_lines), (new_start, new_lines)) = (range(old), range(new));
+            (old_line, new_line) = (old_start, new_start);
+            parsed.hunks.push(DiffHunk {
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                header: header.trim().to_string(),
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        let Some(hunk) = parsed.hunks.last_mut() else {
+            if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                parsed.binary = true;
+            }
+            continue;
+        };
+        let (kind, content) = match line.as_bytes().first() {
+            Some(b'+') => ("add", &line[1..]),
+            Some(b'-') => ("delete", &line[1..]),
+            Some(b' ') => ("context", &line[1..]),
+            // Trimmed output can lose the space of a final blank context line
+            None => ("context", ""),
+            Some(b'\\') => {
+                if let Some(last) = hunk.lines.last_mut() {
+                    last.no_newline = true;
+                }
+                continue;
+            }
+            // The next file's header; this parser handles one file
+            _ => break,
+        };
+        let (old, new) = match kind {
+            "add" => (None, Some(new_line)),
+            "delete" => (Some(old_line), None),
+            _ => (Some(old_line), Some(new_line)),
+        };
+        old_line += u32::from(old.is_some());
+        new_line += u32::from(new.is_some());
+        hunk.lines.push(DiffLine {
+            kind: kind.to_string(),
+            content: content.to_string(),
+            old_line: old,
+            new_line: new,
+            no_newline: false,
+        });
+    }
+    parsed
+}
+
+/// `workspace_file_diff_with` as parsed hunks with line numbers on both sides
+pub fn workspace_file_diff_structured(
+    conn: &Connection,
+    ws_ref: &str,
+    file_path: &str,
+    options: &DiffOptions,
+) -> Result<StructuredDiff> {
+    let limited = workspace_file_diff_with(conn, ws_ref, file_path, options)?;
+    Ok(StructuredDiff {
+        omitted_hunks: limited.omitted_hunks,
+        ..parse_unified_diff(&limited.diff)
+    })
+}
+
+/// Who last touched one line of a file, from `git blame`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// 1-based line number in the worktree file
+    pub line: u32,
+    /// All zeros for lines not committed yet
+    pub commit: String,
+    pub author: String,
+    pub author_email: String,
+    /// Author time, RFC 3339
+    pub time: String,
+    pub summary: String,
+    pub content: String,
+}
+
+/// Per-line blame of a workspace file as it is on disk, uncommitted edits included
+pub fn workspace_file_blame(conn: &Connection, ws_ref: &str, file_path: &str) -> Result<Vec<BlameLine>> {
+    let context = workspace_context(conn, ws_ref)?;
+    let rel = safe_workspace_relpath(file_path)?;
+    contained_workspace_path(&context.path, &rel, false)?;
+    let rel_str = rel.to_string_lossy().to_string();
+    let out = GitCmd::new(&context.path).args(&["blame", "--line-porcelain"]).operand(&rel_str).run()?;
+
+    // Each line: "<sha> <orig> <final> [<count>]", "key value" headers, then "\t<content>"
+    let mut lines = Vec::new();
+    let mut current: Option<BlameLine> = None;
+    for row in out.lines() {
+        if let Some(content) = row.strip_prefix('\t') {
+            if let Some(mut line) = current.take() {
+                line.content = content.to_string();
+                lines.push(line);
+            }
+            continue;
+        }
+        let (key, value) = row.split_once(' ').unwrap_or((row, ""));
+        match current.as_mut() {
+            None => {
+                let line = value.split(' ').nth(1).and_then(|n| n.parse().ok()).unwrap_or(0);
+                current = Some(BlameLine {
+                    line,
+                    commit: key.to_string(),
+                    author: String::new(),
+                    author_email: String::new(),
+                    time: String::new(),
+                    summary: String::new(),
+                    content: String::new(),
+                });
+            }
+            Some(line) => match key {
+                "author" => line.author = value.to_string(),
+                "author-mail" => line.author_email = value.trim_matches(['<', '>']).to_string(),
+                "author-time" => {
+                    line.time = value
+                        .parse()
+                        .ok()
+                        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+                        .map(|time| time.to_rfc3339())
+                        .unwrap_or_default();
+                }
+                "summary" => line.summary = value.to_string(),
+                _ => {}
+            },
+        }
+    }
+    Ok(lines)
+}
+
+/// Everything a workspace changed relative to its base as one patch: the committed
+/// `base...HEAD` work plus uncommitted edits and untracked files (except .conductor-app/)
+pub fn workspace_diff(conn: &Connection, ws_ref: &str) -> Result<String> {
+    diff_against_base(conn, ws_ref, &[])
+}
+
+/// Line counts for the same change set as `workspace_diff`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffStat {
+    pub files: u32,
+    pub insertions: u32,
+    pub deletions: u32,
+}
+
+impl DiffStat {
+    // One `--numstat` line; binary files report "-" for both counts
+    fn add_numstat(&mut self, line: &str) {
+        let mut fields = line.split('\t');
+        let added = fields.next().and_then(|n| n.parse::<u32>().ok()).unwrap_or(0);
+        let removed = fields.next().and_then(|n| n.parse::<u32>().ok()).unwrap_or(0);
+        self.files += 1;
+        self.insertions += added;
+        self.deletions += removed;
+    }
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.files == 1 { "" } else { "s" };
+        write!(f, "{} file{plural} changed, +{} -{}", self.files, self.insertions, self.deletions)
+    }
+}
+
+pub fn workspace_diffstat(conn: &Connection, ws_ref: &str) -> Result<DiffStat> {
+    let numstat = diff_against_base(conn, ws_ref, &["--numstat"])?;
+    let mut stat = DiffStat::default();
+    for line in numstat.lines() {
+        stat.add_numstat(line);
+    }
+    Ok(stat)
+}
+
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub limit: Option<usize>,
+    /// Only the workspace's own commits (base..HEAD) rather than all of HEAD's history
+    pub since_base: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self { limit: None, since_base: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogCommit {
+    pub sha: String,
+    pub author: String,
+    pub author_email: String,
+    /// Author date, strict ISO 8601
+    pub date: String,
+    pub subject: String,
+    pub stat: DiffStat,
+}
+
+/// Commits on a workspace's branch, newest first
+pub fn workspace_log(conn: &Connection, ws_ref: &str, options: &LogOptions) -> Result<Vec<LogCommit>> {
+    let context = workspace_context(conn, ws_ref)?;
+    let range = if options.since_base {
+        let base_ref = context.base_ref()?;
+        format!("{base_ref}..HEAD")
+    } else {
+        "HEAD".to_string()
+    };
+    // Records start with 0x1e and fields split on 0x1f; numstat lines follow the header
+    let mut log = GitCmd::new(&context.path).args(&[
+        "log",
+        "--no-color",
+        "--numstat",
+        "--format=%x1e%H%x1f%an%x1f%ae%x1f%aI%x1f%s",
+    ]);
+    if let Some(limit) = options.limit {
+        log = log.opt("--max-count", limit);
+    }
+    let out = log.value(range).no_paths().run()?;
+
+    let mut commits = Vec::new();
+    for record in out.split('\x1e').filter(|record| !record.trim().is_empty()) {
+        let mut lines = record.lines();
+        let header = lines.next().unwrap_or("");
+        let fields: Vec<&str> = header.splitn(5, '\x1f').collect();
+        let [sha, author, author_email, date, subject] = fields[..] else {
+            continue;
+        };
+        let mut stat = DiffStat::default();
+        for line in lines.filter(|line| !line.is_empty()) {
+            stat.add_numstat(line);
+        }
+        commits.push(LogCommit {
+            sha: sha.to_string(),
+            author: author.to_string(),
+            author_email: author_email.to_string(),
+            date: date.to_string(),
+            subject: subject.to_string(),
+            stat,
+        });
+    }
+    Ok(commits)
+}
+
+fn diff_against_base(conn: &Connection, ws_ref: &str, extra_args: &[&'static str]) -> Result<String> {
+    let context = workspace_context(conn, ws_ref)?;
+    let base_ref = context.base_ref()?;
+    let merge_base = GitCmd::new(&context.path).args(&["merge-base"]).value(&base_ref).args(&["HEAD"]).run()?;
+    diff_worktree_against(&context, &merge_base, extra_args)
+}
+
+// Everything in the worktree (untracked files included, .conductor-app/ not) against `target`
+fn diff_worktree_against(context: &WorkspaceContext, target: &str, extra_args: &[&'static str]) -> Result<String> {
+    // Stage the worktree into a scratch copy of the index so the user's staging is untouched;
+    // starting from the real index keeps its stat cache, so only changed files get hashed
+    let scratch = env::temp_dir().join(format!("conductor-diff-{}.index", Uuid::new_v4()));
+    let real_index = PathBuf::from(GitCmd::new(&context.path).args(&["rev-parse", "--git-path", "index"]).run()?);
+    let real_index = context.path.join(real_index);
+    let scratch_str = scratch.to_string_lossy().to_string();
+    let envs = [("GIT_INDEX_FILE", scratch_str.as_str())];
+    let result = (|| {
+        let git = || GitCmd::new(&context.path).envs(&envs);
+        if std::fs::copy(&real_index, &scratch).is_err() {
+            git().args(&["read-tree", "HEAD"]).run()?;
+        }
+        git().args(&["add", "--all"]).operands([".", ":(exclude).conductor-app"]).run()?;
+        git().args(&["diff", "--cached", "--no-color"]).args(extra_args).value(target).run_raw()
+    })();
+    let _ = std::fs::remove_file(&scratch);
+    result
+}
+
+// The change a commit would take right now: the index when anything is staged, otherwise
+// all of the worktree (see `workspace_commit`). The flag says it was the index
+fn pending_diff(context: &WorkspaceContext) -> Result<(String, bool)> {
+    let staged = GitCmd::new(&context.path).args(&["diff", "--cached", "--no-color", "HEAD"]).run_raw()?;
+    if !staged.trim().is_empty() {
+        return Ok((staged, true));
+    }
+    Ok((diff_worktree_against(context, "HEAD", &[])?, false))
+}
+
+/// Commit the workspace's pending work: what's staged when anything is, otherwise every
+/// change outside .conductor-app/. Returns the new commit's sha
+pub fn workspace_commit(conn: &Connection, ws_ref: &str, message: &str) -> Result<String> {
+    let message = message.trim();
+    if message.is_empty() {
+        bail!("commit message is empty");
+    }
+    let context = workspace_context(conn, ws_ref)?;
+    let (diff, staged) = pending_diff(&context)?;
+    if diff.trim().is_empty() {
+        bail!("nothing to commit");
+    }
+    let git = || GitCmd::new(&context.path);
+    if !staged {
+        git().args(&["add", "--all"]).operands([".", ":(exclude).conductor-app"]).run()?;
+    }
+    git().args(&["commit", "--quiet"]).opt("--message", message).run()?;
+    git().args(&["rev-parse", "HEAD"]).run()
+}
+
+// How much of the pending diff the commit message engine gets
+const COMMIT_DIFF_CHARS: usize = 24_000;
+
+const COMMIT_INSTRUCTIONS: &str = "Below is a diff about to be committed. Reply with its commit message in the \
+Conventional Commits format: a subject line `type(scope): summary`, where type is one of feat, fix, docs, style, \
+refactor, perf, test, build, ci, chore or revert, the scope is optional, and the summary is imperative, lowercase \
+and under 72 characters. Add a blank line and a short body wrapped at 72 columns only if the change needs \
+explaining. No other text and no code fences.";
+
+/// A Conventional Commits message for the change `workspace_commit` would make, from the
+/// `commit_messages` engine in config.json
+pub fn commit_message_suggest(conn: &Connection, home: &Path, ws_ref: &str) -> Result<String> {
+    let config = config_load(home)?;
+    let command = config.commit_messages.command.as_deref().or(config.titles.command.as_deref());
+    let Some([program, args @ ..]) = command else {
+        bail!("no engine to suggest commit messages: set commit_messages.command in config.json");
+    };
+    let context = workspace_context(conn, ws_ref)?;
+    let (diff, _) = pending_diff(&context)?;
+    if diff.trim().is_empty() {
+        bail!("nothing to commit");
+    }
+    let diff: String = diff.chars().take(COMMIT_DIFF_CHARS).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let reply = run_with_input(program, &args, &context.path, &format!("{COMMIT_INSTRUCTIONS}\n\n{diff}"))?;
+    conventional_commit(&reply)
+}
+
+// The engine's reply without code fences or wrapping quotes; refused unless its subject
+// line is a conventional commit
+fn conventional_commit(reply: &str) -> Result<String> {
+    let lines: Vec<&str> = reply.lines().filter(|line| !line.trim_start().starts_with("```")).collect();
+    let message = lines.join("\n");
+    let message = message.trim().trim_matches(|c: char| c == '"' || c == '`').trim();
+    let subject = message.lines().next().unwrap_or("");
+    let types = "feat|fix|docs|style|refactor|perf|test|build|ci|chore|revert";
+    let conventional = regex::Regex::new(&format!(r"^({types})(\([^()\s]+\))?!?: \S")).expect("valid regex");
+    if !conventional.is_match(subject) {
+        bail!("the suggestion is not a conventional commit: {subject:?}");
+    }
+    Ok(message.to_string())
+}
+
+/// The workspace whose directory contains `path`, if any
+pub fn workspace_for_path(conn: &Connection, path: &Path) -> Result<Option<Workspace>> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    Ok(workspace_list(conn, None)?.into_iter().find(|ws| {
+        let ws_path = Path::new(&ws.path);
+        let ws_path = ws_path.canonicalize().unwrap_or_else(|_| ws_path.to_path_buf());
+        path.starts_with(ws_path)
+    }))
+}
+
+/// Files a repo can keep its agent guidelines in, relative to the checkout root; the
+/// first one found wins
+pub const CONTEXT_FILES: &[&str] = &[".conductor/context.md", "CONDUCTOR.md"];
+
+/// A repo's context file, which the daemon puts ahead of the prompt of new agent runs
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoContext {
+    pub path: String,
+    pub content: String,
+}
+
+/// The context file for agent runs in `dir`. Looked up at the top of `dir`'s checkout,
+/// then, inside a workspace, in the repo's main checkout (bare repos have none), so a
+/// file that isn't committed (or is on another branch) still applies. Blank files
+/// count as absent
+pub fn repo_context(conn: &Connection, dir: &Path) -> Result<Option<RepoContext>> {
+    let top = GitCmd::new(dir).args(&["rev-parse", "--show-toplevel"]).try_run();
+    let mut roots = vec![top.map_or_else(|| dir.to_path_buf(), PathBuf::from)];
+    if let Some(ws) = workspace_for_path(conn, dir)? {
+        let repo_root = PathBuf::from(get_repo(conn, &ws.repo_id)?.root_path);
+        if !is_bare_repo(&repo_root) {
+            roots.push(repo_root);
+        }
+    }
+    for root in &roots {
+        for name in CONTEXT_FILES {
+            let path = root.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let content = fs(std::fs::read_to_string(&path))?;
+            if content.trim().is_empty() {
+                continue;
+            }
+            return Ok(Some(RepoContext {
+                path: path.to_string_lossy().to_string(),
+                content,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// `prompt` with the repo context ahead of it, separated by a rule
+pub fn prompt_with_context(context: &RepoContext, prompt: &str) -> String {
+    format!("{}\n\n---\n\n{prompt}", context.content.trim_end())
+}
+
+/// Media type of an attachment engines can take as an image, by extension
+pub fn attachment_media_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Resolve attachment paths (absolute, or relative to the workspace) to files inside
+/// `ws_path`; anything outside it, symlinked or not, is refused
+pub fn resolve_attachments(ws_path: &Path, paths: &[String]) -> Result<Vec<PathBuf>> {
+    let root = fs(ws_path.canonicalize())?;
+    paths
+        .iter()
+        .map(|path| {
+            let resolved = root
+                .join(path)
+                .canonicalize()
+                .map_err(|e| anyhow!("attachment {path}: {e}"))?;
+            if !resolved.starts_with(&root) {
+                bail!("attachment {path} is outside the workspace {}", root.display());
+            }
+            if !resolved.is_file() {
+                bail!("attachment {path} is not a file");
+            }
+            Ok(resolved)
+        })
+        .collect()
+}
+
+/// `prompt` followed by a list of attached files, for engines that only see text
+pub fn prompt_with_attachments(prompt: &str, files: &[PathBuf]) -> String {
+    if files.is_empty() {
+        return prompt.to_string();
+    }
+    let list: Vec<String> = files.iter().map(|file| format!("- {}", file.display())).collect();
+    format!("{prompt}\n\nAttached files:\n{}", list.join("\n"))
+}
+
+/// Store `data` under .conductor-app/attachments/ so it can be attached to a run there
+pub fn attachment_save(ws_path: &Path, file_name: &str, data: &[u8]) -> Result<PathBuf> {
+    let dir = ensure_conductor_app(ws_path)?.join("attachments");
+    fs(std::fs::create_dir_all(&dir))?;
+    let path = dir.join(safe_dir_name(file_name));
+    fs(std::fs::write(&path, data))?;
+    Ok(path)
+}
+
+/// How `workspace_export` packages a workspace for someone without Conductor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// `git bundle` of the commits since the base (fetchable with `git pull <file>`)
+    Bundle,
+    /// `git format-patch` series of the commits since the base (apply with `git am`)
+    Patches,
+    /// .tar.gz of the changed files as they are on disk, committed or not
+    Tarball,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "bundle" => Ok(ExportFormat::Bundle),
+            "patches" | "format-patch" => Ok(ExportFormat::Patches),
+            "tarball" | "tar" => Ok(ExportFormat::Tarball),
+            _ => bail!("unknown export format: {value} (expected bundle, patches or tarball)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportResult {
+    pub format: ExportFormat,
+    /// The bundle/tarball file, or the directory holding the patches
+    pub path: String,
+    /// Patch files written, or workspace paths packed into the tarball
+    pub files: Vec<String>,
+}
+
+/// Write a workspace's work to `target`. Bundles and patches carry commits only;
+/// the tarball also picks up uncommitted and untracked files.
+pub fn workspace_export(conn: &Connection, ws_ref: &str, format: ExportFormat, target: &Path) -> Result<ExportResult> {
+    // git runs inside the worktree, so pin relative paths to our cwd first
+    let target = if target.is_absolute() {
+        target.to_path_buf()
+    } else {
+        fs(env::current_dir())?.join(target)
+    };
+    let target = target.as_path();
+    let target_str = target.to_string_lossy().to_string();
+    let reusable_dir = format == ExportFormat::Patches
+        && target.is_dir()
+        && fs(std::fs::read_dir(target))?.next().is_none();
+    if target.exists() && !reusable_dir {
+        bail!("export path already exists: {}", target.display());
+    }
+
+    let context = workspace_context(conn, ws_ref)?;
+    let base_ref = context.base_ref()?;
+    let merge_base = GitCmd::new(&context.path).args(&["merge-base"]).value(&base_ref).args(&["HEAD"]).run()?;
+    let head = workspace_head(&context.path)?;
+    let commits = GitCmd::new(&context.path)
+        .args(&["rev-list", "--count"])
+        .value(format!("{merge_base}..HEAD"))
+        .run()?;
+    if format != ExportFormat::Tarball && commits == "0" {
+        bail!("nothing to export: no commits since {base_ref} (commit first, or export a tarball)");
+    }
+
+    let files = match format {
+        ExportFormat::Bundle => {
+            // Name the branch so the recipient gets a ref, not just a detached tip
+            let tip = head.branch.unwrap_or_else(|| "HEAD".to_string());
+            let range = format!("{merge_base}..{tip}");
+            GitCmd::new(&context.path).args(&["bundle", "create"]).value(&target_str).value(range).run()?;
+            Vec::new()
+        }
+        ExportFormat::Patches => {
+            let range = format!("{merge_base}..HEAD");
+            // Prints the path of each patch it writes
+            let out = GitCmd::new(&context.path)
+                .args(&["format-patch"])
+                .opt("--output-directory", &target_str)
+                .value(range)
+                .run()?;
+            out.lines().map(str::to_string).collect()
+        }
+        ExportFormat::Tarball => {
+            let app_dir = ".conductor-app/";
+            let mut paths: Vec<String> = workspace_changes(conn, ws_ref)?
+                .into_iter()
+                .filter(|change| !change.status.starts_with('D'))
+                .map(|change| change.path)
+                .filter(|path| !path.starts_with(app_dir) && path != ".conductor-app")
+                .filter(|path| context.path.join(path).exists())
+                .collect();
+            paths.sort();
+            paths.dedup();
+            if paths.is_empty() {
+                bail!("nothing to export: no changed files");
+            }
+            // NUL-separated list file keeps odd names and long lists out of argv
+            let list = env::temp_dir().join(format!("conductor-export-{}.list", Uuid::new_v4()));
+            let mut listing = Vec::new();
+            for path in &paths {
+                listing.extend_from_slice(path.as_bytes());
+                listing.push(0);
+            }
+            fs(std::fs::write(&list, listing))?;
+            let list_str = list.to_string_lossy().to_string();
+            let ws_str = context.path.to_string_lossy().to_string();
+            let result = run(
+                "tar",
+                &["-czf", &target_str, "-C", &ws_str, "--null", "-T", &list_str],
+                None,
+            );
+            let _ = std::fs::remove_file(&list);
+            result?;
+            paths
+        }
+    };
+
+    Ok(ExportResult {
+        format,
+        path: target_str,
+        files,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyResult {
+    /// The patch landed in the worktree (possibly with conflict markers, see `conflicts`)
+    pub applied: bool,
+    /// Paths the patch touches
+    pub files: Vec<String>,
+    /// Paths that failed to apply, or (3-way) were left with conflict markers
+    pub conflicts: Vec<String>,
+    /// git's own report
+    pub message: String,
+}
+
+/// Apply a unified diff to a workspace's worktree, leaving the result uncommitted.
+/// Without `three_way` the patch applies entirely or not at all; with it, git falls back
+/// to a 3-way merge and leaves conflict markers (and unmerged index entries) to resolve.
+pub fn workspace_apply_patch(conn: &Connection, ws_ref: &str, patch: &str, three_way: bool) -> Result<ApplyResult> {
+    if patch.trim().is_empty() {
+        bail!("patch is empty");
+    }
+    let context = workspace_context(conn, ws_ref)?;
+
+    // --numstat only parses, which both validates the patch and lists its paths
+    let numstat = git_apply(&context.path, &["--numstat"], patch)?;
+    if !numstat.status.success() {
+        bail!("not a valid patch: {}", String::from_utf8_lossy(&numstat.stderr).trim());
+    }
+    let files: Vec<String> = String::from_utf8_lossy(&numstat.stdout)
+        .lines()
+        .filter_map(|line| line.splitn(3, '\t').nth(2))
+        .map(str::to_string)
+        .collect();
+
+    let args: &[&'static str] = if three_way { &["--3way"] } else { &[] };
+    let output = git_apply(&context.path, args, patch)?;
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if output.status.success() {
+        return Ok(ApplyResult {
+            applied: true,
+            files,
+            conflicts: Vec::new(),
+            message,
+        });
+    }
+
+    let unmerged = if three_way {
+        GitCmd::new(&context.path).args(&["diff", "--name-only", "--diff-filter=U"]).run()?
+    } else {
+        String::new()
+    };
+    let unmerged: Vec<String> = unmerged.lines().map(str::to_string).collect();
+    let applied = !unmerged.is_empty();
+    let conflicts = if applied { unmerged } else { apply_error_paths(&message) };
+    Ok(ApplyResult {
+        applied,
+        files,
+        conflicts,
+        message,
+    })
+}
+
+/// Apply the commits in a `git bundle` (e.g. from `workspace_export`) as uncommitted
+/// changes: fetch it, diff HEAD against its tip, and apply that like a pasted patch.
+pub fn workspace_apply_bundle(conn: &Connection, ws_ref: &str, bundle: &Path, three_way: bool) -> Result<ApplyResult> {
+    let bundle_str = bundle.to_string_lossy().to_string();
+    let context = workspace_context(conn, ws_ref)?;
+    // Bundles from workspace_export name a branch rather than HEAD; take the first ref
+    let heads = GitCmd::new(&context.path).args(&["bundle", "list-heads"]).value(&bundle_str).run()?;
+    let Some(head) = heads.lines().find_map(|line| line.split_whitespace().nth(1)) else {
+        bail!("bundle has no refs: {}", bundle.display());
+    };
+    GitCmd::new(&context.path).args(&["fetch", "--no-tags", "--quiet"]).operands([bundle_str.as_str(), head]).run()?;
+    let diff = GitCmd::new(&context.path).args(&["diff", "--binary", "--no-color", "HEAD...FETCH_HEAD"]);
+    let patch = diff.run_raw()?;
+    if patch.is_empty() {
+        bail!("bundle has no changes relative to this workspace");
+    }
+    workspace_apply_patch(conn, ws_ref, &patch, three_way)
+}
+
+fn git_apply(ws_path: &Path, args: &[&'static str], patch: &str) -> Result<std::process::Output> {
+    // "-": the patch comes on stdin
+    let apply = GitCmd::new(ws_path).args(&["apply"]).args(args).args(&["-"]);
+    let mut child = apply
+        .command()?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", apply.display()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        fs(stdin.write_all(patch.as_bytes()))?;
+    }
+    fs(child.wait_with_output())
+}
+
+// Paths named by `git apply` errors, e.g. "error: patch failed: src/a.rs:12"
+// or "error: src/b.rs: does not exist in index"
+fn apply_error_paths(stderr: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for line in stderr.lines() {
+        let Some(rest) = line.strip_prefix("error: ") else {
+            continue;
+        };
+        let path = match rest.strip_prefix("patch failed: ") {
+            Some(location) => location.rsplit_once(':').map_or(location, |(path, _)| path),
+            None => match rest.split_once(": ") {
+                Some((path, _)) => path,
+                None => continue,
+            },
+        };
+        if !paths.iter().any(|p| p == path) {
+            paths.push(path.to_string());
+        }
+    }
+    paths
+}
+
+// =============================================================================
+// Checkpoints
+// =============================================================================
+
+/// When the daemon checkpoints a workspace during agent runs (`checkpoints` in config.json)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckpointMode {
+    /// Only when asked (`conductor workspace checkpoint`)
+    #[default]
+    Off,
+    /// Before and after each agent run
+    Turn,
+    /// Also after each file change the agent reports
+    FileChange,
+}
+
+/// A snapshot of a workspace's files, tracked and untracked, taken without touching
+/// its branch, index or working tree; kept as refs/conductor/checkpoints/<workspace id>/<id>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Sequence number within the workspace, from 1
+    pub id: u64,
+    pub commit: String,
+    /// The branch's commit when it was taken; rolling back returns the branch there
+    pub head: String,
+    pub label: String,
+    pub created_at: String,
+}
+
+/// Where `workspace_rollback` takes a workspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackTarget {
+    /// The commit the branch left its base at, dropping all of its commits
+    Base,
+    /// A checkpoint: the branch's commit then, and its files as they were
+    Checkpoint(u64),
+    /// Drop the branch's last N commits
+    Commits(usize),
+}
+
+impl std::str::FromStr for RollbackTarget {
+    type Err = anyhow::Error;
+
+    /// "base", a checkpoint id such as "3", or "~N" for the last N commits
+    fn from_str(value: &str) -> Result<Self> {
+        let parsed = match value {
+            "base" => Some(RollbackTarget::Base),
+            _ => match value.strip_prefix('~') {
+                Some(count) => count.parse().ok().map(RollbackTarget::Commits),
+                None => value.parse().ok().map(RollbackTarget::Checkpoint),
+            },
+        };
+        parsed.ok_or_else(|| anyhow!("unknown rollback target: {value} (expected base, a checkpoint id or ~N commits)"))
+    }
+}
+
+impl fmt::Display for RollbackTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollbackTarget::Base => write!(f, "base"),
+            RollbackTarget::Checkpoint(id) => write!(f, "{id}"),
+            RollbackTarget::Commits(count) => write!(f, "~{count}"),
+        }
+    }
+}
+
+/// What `workspace_rollback` did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackResult {
+    /// The target, as `RollbackTarget` parses it
+    pub target: String,
+    /// The branch's commit now, and before (`git reset --hard <previous_head>` to
+    /// get the dropped commits back)
+    pub head: String,
+    pub previous_head: String,
+    /// Commits taken off the branch
+    pub dropped_commits: usize,
+    /// The stash holding the uncommitted work found, if there was any
+    pub stash: Option<String>,
+    /// The checkpoint returned to
+    pub checkpoint: Option<Checkpoint>,
+}
+
+const CHECKPOINT_SUBJECT: &str = "conductor checkpoint: ";
+
+fn checkpoint_ref_prefix(ws_id: &str) -> String {
+    format!("refs/conductor/checkpoints/{ws_id}/")
+}
+
+// The workspace's checkpoints, oldest first
+fn checkpoints(ws_path: &Path, ws_id: &str) -> Result<Vec<Checkpoint>> {
+    let prefix = checkpoint_ref_prefix(ws_id);
+    let format = "--format=%(refname)%09%(objectname)%09%(parent)%09%(creatordate:iso-strict)%09%(contents:subject)";
+    let output = GitCmd::new(ws_path).args(&["for-each-ref", format]).value(&prefix).run()?;
+    let mut list: Vec<Checkpoint> = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, '\t');
+            let id = fields.next()?.strip_prefix(&prefix)?.parse().ok()?;
+            let commit = fields.next()?.to_string();
+            let head = fields.next()?.to_string();
+            let created_at = fields.next()?.to_string();
+            let subject = fields.next().unwrap_or_default();
+            Some(Checkpoint {
+                id,
+                commit,
+                head,
+                label: subject.strip_prefix(CHECKPOINT_SUBJECT).unwrap_or(subject).to_string(),
+                created_at,
+            })
+        })
+        .collect();
+    list.sort_by_key(|checkpoint| checkpoint.id);
+    Ok(list)
+}
+
+pub fn checkpoint_list(conn: &Connection, ws_ref: &str) -> Result<Vec<Checkpoint>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    checkpoints(Path::new(&ws.path), &ws.id)
+}
+
+/// Checkpoint the workspace as it is now. None when nothing changed since the latest
+/// checkpoint
+pub fn checkpoint_create(conn: &Connection, ws_ref: &str, label: &str) -> Result<Option<Checkpoint>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    let head = GitCmd::new(ws_path)
+        .args(&["rev-parse", "--verify", "HEAD"])
+        .run()
+        .context("cannot checkpoint a workspace without commits")?;
+
+    // Stage everything into a scratch copy of the index, as `diff_against_base` does
+    let scratch = env::temp_dir().join(format!("conductor-checkpoint-{}.index", Uuid::new_v4()));
+    let real_index = ws_path.join(GitCmd::new(ws_path).args(&["rev-parse", "--git-path", "index"]).run()?);
+    let scratch_str = scratch.to_string_lossy().to_string();
+    let envs = [("GIT_INDEX_FILE", scratch_str.as_str())];
+    let tree = (|| {
+        let git = || GitCmd::new(ws_path).envs(&envs);
+        if std::fs::copy(&real_index, &scratch).is_err() {
+            git().args(&["read-tree", "HEAD"]).run()?;
+        }
+        git().args(&["add", "--all"]).operands([".", ":(exclude).conductor-app"]).run()?;
+        git().args(&["write-tree"]).run()
+    })();
+    let _ = std::fs::remove_file(&scratch);
+    let tree = tree?;
+
+    let existing = checkpoints(ws_path, &ws.id)?;
+    if let Some(last) = existing.last() {
+        let last_tree = GitCmd::new(ws_path).args(&["rev-parse"]).value(format!("{}^{{tree}}", last.commit)).run()?;
+        if last.head == head && last_tree == tree {
+            return Ok(None);
+        }
+    }
+    let id = existing.last().map_or(1, |last| last.id + 1);
+    let message = format!("{CHECKPOINT_SUBJECT}{label}");
+    let commit = GitCmd::new(ws_path)
+        .args(&["commit-tree"])
+        .value(&tree)
+        .args(&["-p"])
+        .value(&head)
+        .opt("-m", &message)
+        .envs(&CONDUCTOR_IDENTITY)
+        .run()?;
+    GitCmd::new(ws_path)
+        .args(&["update-ref"])
+        .value(format!("{}{id}", checkpoint_ref_prefix(&ws.id)))
+        .value(&commit)
+        .run()?;
+    Ok(Some(Checkpoint {
+        id,
+        commit,
+        head,
+        label: label.to_string(),
+        created_at: Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Undo agent work: move the branch back to `target` and reset the files to match,
+/// removing files created since (ignored ones are left alone). Uncommitted work is
+/// stashed first. Refuses mid-merge or mid-rebase, and won't drop commits that are
+/// already on the branch's upstream or that came from its base
+pub fn workspace_rollback(conn: &Connection, ws_ref: &str, target: RollbackTarget) -> Result<RollbackResult> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let ws_path = Path::new(&ws.path);
+    if !ws_path.is_dir() {
+        bail!("workspace directory is missing: {}", ws.path);
+    }
+    for (marker, operation) in [("MERGE_HEAD", "merge"), ("rebase-merge", "rebase"), ("rebase-apply", "rebase")] {
+        if ws_path.join(GitCmd::new(ws_path).args(&["rev-parse", "--git-path", marker]).run()?).exists() {
+            bail!("a {operation} is in progress in {}; finish or abort it first", ws.path);
+        }
+    }
+    let previous_head = GitCmd::new(ws_path).args(&["rev-parse", "--verify", "HEAD"]).run()?;
+    let base_ref = resolve_base_ref(Path::new(&ws.repo_root), &ws.base_branch, ws.preferred_remote.as_deref())?;
+    let fork_point = GitCmd::new(ws_path).args(&["merge-base"]).value(&base_ref).args(&["HEAD"]).run()?;
+    let own_commits: usize = GitCmd::new(ws_path)
+        .args(&["rev-list", "--count"])
+        .value(format!("{fork_point}..HEAD"))
+        .run()?
+        .parse()
+        .unwrap_or(0);
+
+    let checkpoint = match target {
+        RollbackTarget::Checkpoint(id) => match checkpoints(ws_path, &ws.id)?.into_iter().find(|c| c.id == id) {
+            Some(checkpoint) => Some(checkpoint),
+            None => bail!("no checkpoint {id} in workspace {}", ws.id),
+        },
+        _ => None,
+    };
+    let head = match (target, &checkpoint) {
+        (_, Some(checkpoint)) => checkpoint.head.clone(),
+        (RollbackTarget::Commits(0), _) => bail!("nothing to roll back: ~0 drops no commits"),
+        (RollbackTarget::Commits(count), _) if count > own_commits => {
+            bail!("the branch has {own_commits} commit(s) since {base_ref}; cannot drop {count}")
+        }
+        (RollbackTarget::Commits(count), _) => {
+            GitCmd::new(ws_path).args(&["rev-parse"]).value(format!("HEAD~{count}")).run()?
+        }
+        _ => fork_point,
+    };
+    let dropped_commits: usize = GitCmd::new(ws_path)
+        .args(&["rev-list", "--count"])
+        .value(format!("{head}..HEAD"))
+        .run()?
+        .parse()
+        .unwrap_or(0);
+    // Published commits would come back on the next pull, or be force-pushed away
+    if dropped_commits > 0 {
+        if let Some(upstream) = GitCmd::new(ws_path).args(&["rev-parse", "--abbrev-ref", "@{upstream}"]).try_run() {
+            let unpushed: usize = GitCmd::new(ws_path)
+                .args(&["rev-list", "--count"])
+                .value(format!("{head}..HEAD"))
+                .args(&["--not"])
+                .value(&upstream)
+                .run()?
+                .parse()
+                .unwrap_or(0);
+            if unpushed < dropped_commits {
+                bail!("rolling back to {target} would drop commits already pushed to {upstream}");
+            }
+        }
+    }
+
+    let outside_app_dir = [".", ":(exclude).conductor-app"];
+    let status = GitCmd::new(ws_path).args(&["status", "--porcelain"]).operands(outside_app_dir).run()?;
+    let stash = if status.is_empty() {
+        None
+    } else {
+        GitCmd::new(ws_path)
+            .args(&["stash", "push", "--include-untracked"])
+            .opt("--message", format!("conductor: before rollback to {target}"))
+            .operands(outside_app_dir)
+            .envs(&CONDUCTOR_IDENTITY)
+            .run()?;
+        Some(GitCmd::new(ws_path).args(&["rev-parse", "stash@{0}"]).run()?)
+    };
+
+    match &checkpoint {
+        // Check the snapshot out whole, drop what it doesn't have, then put the branch
+        // back where it was, leaving the snapshot's changes uncommitted
+        Some(checkpoint) => {
+            GitCmd::new(ws_path).args(&["reset", "-q", "--hard"]).value(&checkpoint.commit).run()?;
+            GitCmd::new(ws_path).args(&["clean", "-fdq", "-e", "/.conductor-app"]).run()?;
+            GitCmd::new(ws_path).args(&["reset", "-q"]).value(&checkpoint.head).run()?;
+        }
+        None => {
+            GitCmd::new(ws_path).args(&["reset", "-q", "--hard"]).value(&head).run()?;
+        }
+    }
+    Ok(RollbackResult {
+        target: target.to_string(),
+        head,
+        previous_head,
+        dropped_commits,
+        stash,
+        checkpoint,
+    })
+}
+
+// Drop a workspace's checkpoint refs, letting git collect the snapshots
+fn checkpoint_refs_delete(repo_root: &Path, ws_id: &str) -> Result<()> {
+    let refs = GitCmd::new(repo_root)
+        .args(&["for-each-ref", "--format=%(refname)"])
+        .value(checkpoint_ref_prefix(ws_id))
+        .run()?;
+    for name in refs.lines() {
+        GitCmd::new(repo_root).args(&["update-ref", "-d"]).value(name).run()?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Review Comments
+// =============================================================================
+
+/// A reviewer's note on a line range of a workspace file, kept until resolved. Comments
+/// synced from the workspace's PR may be on a whole file or on the PR itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub id: String,
+    pub workspace_id: String,
+    /// Relative to the workspace root; empty for a comment on the whole PR
+    pub file_path: String,
+    /// 1-based and inclusive; 0 for a comment on the whole file or PR
+    pub start_line: u32,
+    pub end_line: u32,
+    pub author: String,
+    pub body: String,
+    pub resolved: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    /// The comment on GitHub, for synced comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// What `review_comments_sync` brought in from the workspace's PR
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewSync {
+    pub pr_number: i64,
+    /// Comments not seen before, now in the workspace's review comments
+    pub added: Vec<ReviewComment>,
+    /// Earlier synced comments whose text has since been edited on GitHub
+    pub updated: u32,
+}
+
+// Lines of the file quoted with each comment in a review prompt
+const REVIEW_EXCERPT_LINES: usize = 20;
+
+const REVIEW_COMMENT_COLUMNS: &str =
+    "id, workspace_id, file_path, start_line, end_line, author, body, resolved, created_at, updated_at, url";
+
+fn review_comment_row(row: &rusqlite::Row) -> rusqlite::Result<ReviewComment> {
+    Ok(ReviewComment {
+        id: row.get(0)?,
+        workspace_id: row.get(1)?,
+        file_path: row.get(2)?,
+        start_line: row.get(3)?,
+        end_line: row.get(4)?,
+        author: row.get(5)?,
+        body: row.get(6)?,
+        resolved: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        url: row.get(10)?,
+    })
+}
+
+/// Comment on lines `start..=end` of `file_path`. Without an author, the workspace's
+/// git user.name is used
+pub fn review_comment_add(
+    conn: &Connection,
+    ws_ref: &str,
+    file_path: &str,
+    (start_line, end_line): (u32, u32),
+    author: Option<&str>,
+    body: &str,
+) -> Result<ReviewComment> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let rel = safe_workspace_relpath(file_path)?;
+    if start_line == 0 || end_line < start_line {
+        bail!("invalid line range {start_line}-{end_line}: lines start at 1 and the end can't come before the start");
+    }
+    let body = body.trim();
+    if body.is_empty() {
+        bail!("comment text is required");
+    }
+    let author = match author.map(str::trim).filter(|a| !a.is_empty()) {
+        Some(author) => author.to_string(),
+        None => GitCmd::new(Path::new(&ws.path)).args(&["config", "user.name"]).try_run()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "reviewer".to_string()),
+    };
+    let now = Utc::now().to_rfc3339();
+    let comment = ReviewComment {
+        id: Uuid::new_v4().to_string(),
+        workspace_id: ws.id,
+        file_path: rel.to_string_lossy().into_owned(),
+        start_line,
+        end_line,
+        author,
+        body: body.to_string(),
+        resolved: false,
+        created_at: now.clone(),
+        updated_at: now,
+        url: None,
+    };
+    review_comment_insert(conn, &comment, None)?;
+    Ok(comment)
+}
+
+fn review_comment_insert(conn: &Connection, comment: &ReviewComment, github_id: Option<&str>) -> Result<()> {
+    db(conn.execute(
+        &format!(
+            "INSERT INTO review_comments ({REVIEW_COMMENT_COLUMNS}, github_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+        ),
+        params![
+            comment.id,
+            comment.workspace_id,
+            comment.file_path,
+            comment.start_line,
+            comment.end_line,
+            comment.author,
+            comment.body,
+            comment.resolved,
+            comment.created_at,
+            comment.updated_at,
+            comment.url,
+            github_id
+        ],
+    ))?;
+    Ok(())
+}
+
+/// Pull reviewers' comments from the workspace branch's GitHub PR into its review
+/// comments, so `review_prompt` covers them. Comments already synced keep their
+/// resolved state and only take up edits; new ones are also added to the chat
+pub fn review_comments_sync(conn: &Connection, ws_ref: &str) -> Result<ReviewSync> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let pr = github::pr_status_refresh(conn, &ws.id)?;
+    let Some(pr_number) = pr.number else {
+        bail!("branch {} has no pull request", pr.branch);
+    };
+    let mut sync = ReviewSync { pr_number, added: Vec::new(), updated: 0 };
+    let now = Utc::now().to_rfc3339();
+    for remote in github::pr_comments(Path::new(&ws.path), pr_number)? {
+        let file_path = match remote.path.as_deref().map(safe_workspace_relpath).transpose() {
+            Ok(path) => path.map(|path| path.to_string_lossy().into_owned()).unwrap_or_default(),
+            // A path that would leave the workspace; not a comment worth trusting
+            Err(_) => continue,
+        };
+        let existing: Option<(String, String)> = db(conn
+            .query_row(
+                "SELECT id, body FROM review_comments WHERE workspace_id = ?1 AND github_id = ?2",
+                params![ws.id, remote.github_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional())?;
+        match existing {
+            Some((_, body)) if body == remote.body => {}
+            Some((id, _)) => {
+                db(conn.execute(
+                    "UPDATE review_comments SET body = ?2, updated_at = ?3 WHERE id = ?1",
+                    params![id, remote.body, now],
+                ))?;
+                sync.updated += 1;
+            }
+            None => {
+                let comment = ReviewComment {
+                    id: Uuid::new_v4().to_string(),
+                    workspace_id: ws.id.clone(),
+                    file_path,
+                    start_line: remote.start_line,
+                    end_line: remote.end_line,
+                    author: remote.author,
+                    body: remote.body,
+                    resolved: false,
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                    url: remote.url,
+                };
+                review_comment_insert(conn, &comment, Some(&remote.github_id))?;
+                sync.added.push(comment);
+            }
+        }
+    }
+    if !sync.added.is_empty() {
+        let mut entry = format!("New review comments on PR #{pr_number}:\n");
+        for comment in &sync.added {
+            entry.push_str(&format!("\n- {} ({}): {}", review_comment_anchor(comment), comment.author, comment.body));
+        }
+        chat_append(Path::new(&ws.path), "Review", &entry)?;
+    }
+    Ok(sync)
+}
+
+// Where a comment points, e.g. "src/main.rs lines 3-5", "src/main.rs" or "the pull request"
+fn review_comment_anchor(comment: &ReviewComment) -> String {
+    match (comment.file_path.as_str(), comment.start_line, comment.end_line) {
+        ("", _, _) => "the pull request".to_string(),
+        (path, 0, _) => path.to_string(),
+        (path, start, end) if start == end => format!("{path} line {start}"),
+        (path, start, end) => format!("{path} lines {start}-{end}"),
+    }
+}
+
+/// The workspace's comments in file and line order; resolved ones only when asked for
+pub fn review_comment_list(conn: &Connection, ws_ref: &str, include_resolved: bool) -> Result<Vec<ReviewComment>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let mut stmt = db(conn.prepare(&format!(
+        "SELECT {REVIEW_COMMENT_COLUMNS} FROM review_comments
+         WHERE workspace_id = ?1 AND (?2 OR resolved = 0)
+         ORDER BY file_path, start_line, created_at"
+    )))?;
+    let rows = db(stmt.query_map(params![ws.id, include_resolved], review_comment_row))?;
+    collect_rows(rows)
+}
+
+/// A comment by id or unique id prefix
+pub fn review_comment_get(conn: &Connection, id: &str) -> Result<ReviewComment> {
+    let mut stmt = db(conn.prepare(&format!(
+        "SELECT {REVIEW_COMMENT_COLUMNS} FROM review_comments WHERE id = ?1 OR id LIKE ?1 || '%' LIMIT 2"
+    )))?;
+    let mut matches = collect_rows(db(stmt.query_map([id], review_comment_row))?)?;
+    if id.is_empty() || matches.is_empty() {
+        bail!("review comment not found: {id}");
+    }
+    if matches.len() > 1 && matches[0].id != id {
+        bail!("review comment id is ambiguous: {id}");
+    }
+    Ok(matches.swap_remove(0))
+}
+
+/// Edit a comment's text and/or mark it resolved or open again
+pub fn review_comment_update(
+    conn: &Connection,
+    id: &str,
+    body: Option<&str>,
+    resolved: Option<bool>,
+) -> Result<ReviewComment> {
+    let mut comment = review_comment_get(conn, id)?;
+    if let Some(body) = body {
+        let body = body.trim();
+        if body.is_empty() {
+            bail!("comment text is required");
+        }
+        comment.body = body.to_string();
+    }
+    if let Some(resolved) = resolved {
+        comment.resolved = resolved;
+    }
+    comment.updated_at = Utc::now().to_rfc3339();
+    db(conn.execute(
+        "UPDATE review_comments SET body = ?2, resolved = ?3, updated_at = ?4 WHERE id = ?1",
+        params![comment.id, comment.body, comment.resolved, comment.updated_at],
+    ))?;
+    Ok(comment)
+}
+
+/// Remove a comment; returns what was removed
+pub fn review_comment_delete(conn: &Connection, id: &str) -> Result<ReviewComment> {
+    let comment = review_comment_get(conn, id)?;
+    db(conn.execute("DELETE FROM review_comments WHERE id = ?1", [&comment.id]))?;
+    Ok(comment)
+}
+
+/// A follow-up prompt asking the agent to address the workspace's unresolved comments,
+/// each quoting the lines it is about. None when there is nothing to address
+pub fn review_prompt(conn: &Connection, ws_ref: &str) -> Result<Option<String>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let comments = review_comment_list(conn, &ws.id, false)?;
+    if comments.is_empty() {
+        return Ok(None);
+    }
+    let mut prompt = String::from(
+        "Address these review comments on your changes. Line numbers refer to the files as they are now.\n",
+    );
+    for (n, comment) in comments.iter().enumerate() {
+        prompt.push_str(&format!("\n{}. {} ({}):\n", n + 1, review_comment_anchor(comment), comment.author));
+        // The file may be gone since; the comment still stands without the quote
+        let content = match comment.start_line {
+            0 => None,
+            _ => std::fs::read_to_string(Path::new(&ws.path).join(&comment.file_path)).ok(),
+        };
+        if let Some(content) = content {
+            let excerpt: Vec<&str> = content
+                .lines()
+                .skip(comment.start_line as usize - 1)
+                .take((comment.end_line - comment.start_line + 1) as usize)
+                .take(REVIEW_EXCERPT_LINES)
+                .collect();
+            if !excerpt.is_empty() {
+                prompt.push_str(&format!("```\n{}\n```\n", excerpt.join("\n")));
+            }
+        }
+        prompt.push_str(&comment.body);
+        prompt.push('\n');
+    }
+    prompt.push_str("\nWhen you're done, say briefly how you handled each comment.");
+    Ok(Some(prompt))
+}
+
+// =============================================================================
+// Tasks
+// =============================================================================
+
+/// Where a task stands. Linking a run moves an open task to in-progress; choosing a
+/// candidate workspace makes it done
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Open,
+    InProgress,
+    Done,
+    Abandoned,
+}
+
+impl TaskStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskStatus::Open => "open",
+            TaskStatus::InProgress => "in_progress",
+            TaskStatus::Done => "done",
+            TaskStatus::Abandoned => "abandoned",
+        }
+    }
+
+    fn is_closed(self) -> bool {
+        matches!(self, TaskStatus::Done | TaskStatus::Abandoned)
+    }
+}
+
+impl std::str::FromStr for TaskStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "open" => Ok(TaskStatus::Open),
+            "in_progress" | "in-progress" => Ok(TaskStatus::InProgress),
+            "done" => Ok(TaskStatus::Done),
+            "abandoned" => Ok(TaskStatus::Abandoned),
+            _ => bail!("unknown task status: {value} (expected open, in-progress, done or abandoned)"),
+        }
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A candidate workspace for a task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskWorkspace {
+    pub workspace_id: String,
+    pub name: String,
+    pub branch: String,
+    pub linked_at: String,
+}
+
+/// An agent run started in one of a task's workspaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRun {
+    pub session_id: String,
+    pub workspace_id: String,
+    pub started_at: String,
+}
+
+/// A unit of work fanned out over candidate workspaces, one of which is chosen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub status: TaskStatus,
+    pub workspaces: Vec<TaskWorkspace>,
+    pub runs: Vec<TaskRun>,
+    /// The candidate whose work is kept; it may since have been purged
+    pub chosen_workspace_id: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+fn task_load(conn: &Connection, id: &str) -> Result<Task> {
+    let mut stmt = db(conn.prepare(
+        "SELECT id, title, description, status, chosen_workspace_id, created_at, updated_at
+         FROM tasks WHERE id = ?1 OR id LIKE ?1 || '%' LIMIT 2",
+    ))?;
+    let rows = db(stmt.query_map([id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    }))?;
+    let mut matches = collect_rows(rows)?;
+    if id.is_empty() || matches.is_empty() {
+        bail!("task not found: {id}");
+    }
+    if matches.len() > 1 && matches[0].0 != id {
+        bail!("task id is ambiguous: {id}");
+    }
+    let (id, title, description, status, chosen_workspace_id, created_at, updated_at) = matches.swap_remove(0);
+
+    let mut stmt = db(conn.prepare(
+        "SELECT t.workspace_id, w.directory_name, w.branch, t.linked_at
+         FROM task_workspaces t JOIN workspaces w ON w.id = t.workspace_id
+         WHERE t.task_id = ? ORDER BY t.linked_at",
+    ))?;
+    let workspaces = collect_rows(db(stmt.query_map([&id], |row| {
+        Ok(TaskWorkspace {
+            workspace_id: row.get(0)?,
+            name: row.get(1)?,
+            branch: row.get(2)?,
+            linked_at: row.get(3)?,
+        })
+    }))?)?;
+    let mut stmt = db(conn.prepare(
+        "SELECT session_id, workspace_id, started_at FROM task_runs WHERE task_id = ? ORDER BY started_at",
+    ))?;
+    let runs = collect_rows(db(stmt.query_map([&id], |row| {
+        Ok(TaskRun {
+            session_id: row.get(0)?,
+            workspace_id: row.get(1)?,
+            started_at: row.get(2)?,
+        })
+    }))?)?;
+
+    Ok(Task {
+        id,
+        title,
+        description,
+        status: status.parse()?,
+        workspaces,
+        runs,
+        chosen_workspace_id,
+        created_at,
+        updated_at,
+    })
+}
+
+fn task_touch(conn: &Connection, id: &str) -> Result<()> {
+    db(conn.execute("UPDATE tasks SET updated_at = ?2 WHERE id = ?1", params![id, Utc::now().to_rfc3339()]))?;
+    Ok(())
+}
+
+/// New open task, with any candidate workspaces already made for it
+pub fn task_create(conn: &Connection, title: &str, description: &str, workspaces: &[String]) -> Result<Task> {
+    let title = title.trim();
+    if title.is_empty() {
+        bail!("task title is required");
+    }
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let tx = db(conn.unchecked_transaction())?;
+    db(tx.execute(
+        "INSERT INTO tasks (id, title, description, status, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+        params![id, title, description.trim(), TaskStatus::Open.as_str(), now],
+    ))?;
+    for ws_ref in workspaces {
+        task_link(&tx, &id, ws_ref)?;
+    }
+    db(tx.commit())?;
+    task_load(conn, &id)
+}
+
+/// Tasks, most recently updated first; only those in `status` when given
+pub fn task_list(conn: &Connection, status: Option<TaskStatus>) -> Result<Vec<Task>> {
+    let mut stmt = db(conn.prepare(
+        "SELECT id FROM tasks WHERE ?1 IS NULL OR status = ?1 ORDER BY updated_at DESC",
+    ))?;
+    let ids = collect_rows(db(stmt.query_map([status.map(TaskStatus::as_str)], |row| row.get::<_, String>(0)))?)?;
+    ids.iter().map(|id| task_load(conn, id)).collect()
+}
+
+/// A task by id or unique id prefix
+pub fn task_get(conn: &Connection, id: &str) -> Result<Task> {
+    task_load(conn, id)
+}
+
+/// Change a task's title, description or status; `None` keeps the current value
+pub fn task_update(
+    conn: &Connection,
+    id: &str,
+    title: Option<&str>,
+    description: Option<&str>,
+    status: Option<TaskStatus>,
+) -> Result<Task> {
+    let task = task_load(conn, id)?;
+    let title = title.map(str::trim).unwrap_or(&task.title);
+    if title.is_empty() {
+        bail!("task title is required");
+    }
+    db(conn.execute(
+        "UPDATE tasks SET title = ?2, description = ?3, status = ?4, updated_at = ?5 WHERE id = ?1",
+        params![
+            task.id,
+            title,
+            description.map(str::trim).unwrap_or(&task.description),
+            status.unwrap_or(task.status).as_str(),
+            Utc::now().to_rfc3339()
+        ],
+    ))?;
+    task_load(conn, &task.id)
+}
+
+/// Add a candidate workspace to a task; linking one twice is a no-op
+pub fn task_link(conn: &Connection, id: &str, ws_ref: &str) -> Result<Task> {
+    let task = task_load(conn, id)?;
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute(
+        "INSERT OR IGNORE INTO task_workspaces (task_id, workspace_id, linked_at) VALUES (?1, ?2, ?3)",
+        params![task.id, ws.id, Utc::now().to_rfc3339()],
+    ))?;
+    task_touch(conn, &task.id)?;
+    task_load(conn, &task.id)
+}
+
+/// Drop a candidate workspace from a task, and the choice if it was the chosen one.
+/// Its runs stay on record
+pub fn task_unlink(conn: &Connection, id: &str, ws_ref: &str) -> Result<Task> {
+    let task = task_load(conn, id)?;
+    let ws = get_workspace(conn, ws_ref)?;
+    let removed = db(conn.execute(
+        "DELETE FROM task_workspaces WHERE task_id = ?1 AND workspace_id = ?2",
+        params![task.id, ws.id],
+    ))?;
+    if removed == 0 {
+        bail!("workspace {} is not linked to task {}", ws.id, task.id);
+    }
+    db(conn.execute(
+        "UPDATE tasks SET chosen_workspace_id = NULL WHERE id = ?1 AND chosen_workspace_id = ?2",
+        params![task.id, ws.id],
+    ))?;
+    task_touch(conn, &task.id)?;
+    task_load(conn, &task.id)
+}
+
+/// Pick the candidate whose work is kept (linking it if needed); the task is done
+pub fn task_choose(conn: &Connection, id: &str, ws_ref: &str) -> Result<Task> {
+    let task = task_link(conn, id, ws_ref)?;
+    let ws = get_workspace(conn, ws_ref)?;
+    db(conn.execute(
+        "UPDATE tasks SET chosen_workspace_id = ?2, status = ?3, updated_at = ?4 WHERE id = ?1",
+        params![task.id, ws.id, TaskStatus::Done.as_str(), Utc::now().to_rfc3339()],
+    ))?;
+    task_load(conn, &task.id)
+}
+
+/// Remove a task with its links; the workspaces stay. Returns what was removed
+pub fn task_delete(conn: &Connection, id: &str) -> Result<Task> {
+    let task = task_load(conn, id)?;
+    let tx = db(conn.unchecked_transaction())?;
+    for table in ["task_runs", "task_workspaces"] {
+        db(tx.execute(&format!("DELETE FROM {table} WHERE task_id = ?"), [&task.id]))?;
+    }
+    db(tx.execute("DELETE FROM tasks WHERE id = ?", [&task.id]))?;
+    db(tx.commit())?;
+    Ok(task)
+}
+
+/// Record an agent run against every unfinished task the workspace is a candidate
+/// for, moving open ones to in-progress. Returns the ids of those tasks
+pub fn task_record_run(conn: &Connection, ws_ref: &str, session_id: &str) -> Result<Vec<String>> {
+    let ws = get_workspace(conn, ws_ref)?;
+    let mut stmt = db(conn.prepare(
+        "SELECT t.id, t.status FROM tasks t JOIN task_workspaces l ON l.task_id = t.id WHERE l.workspace_id = ?",
+    ))?;
+    let linked = collect_rows(db(stmt.query_map([&ws.id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    }))?)?;
+    let now = Utc::now().to_rfc3339();
+    let mut recorded = Vec::new();
+    for (task_id, status) in linked {
+        let status: TaskStatus = status.parse()?;
+        if status.is_closed() {
+            continue;
+        }
+        db(conn.execute(
+            "INSERT OR IGNORE INTO task_runs (task_id, session_id, workspace_id, started_at) VALUES (?1, ?2, ?3, ?4)",
+            params![task_id, session_id, ws.id, now],
+        ))?;
+        db(conn.execute(
+            "UPDATE tasks SET status = ?2, updated_at = ?3 WHERE id = ?1",
+            params![task_id, TaskStatus::InProgress.as_str(), now],
+        ))?;
+        recorded.push(task_id);
+    }
+    Ok(recorded)
+}
+
+// =============================================================================
+// .conductor-app/ Folder Structure
+// =============================================================================
+
+/// Session state stored in .conductor-app/session.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    pub agent_id: String,
+    pub resume_id: Option<String>,
+    pub started_at: String,
+    pub updated_at: String,
+    /// Where the latest run's results came from; filled in by the daemon as runs start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_version: Option<String>,
+    /// As the engine reported it when it started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Workspace HEAD when the run started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conductor_version: Option<String>,
+}
+
+/// What a run started from, for `session_record_run`
+#[derive(Debug, Clone, Default)]
+pub struct RunProvenance {
+    pub engine_version: Option<String>,
+    pub model: Option<String>,
+    pub base_sha: Option<String>,
+}
+
+/// Chat message for persistence in .conductor-app/chat.md
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatEntry {
+    pub role: String,
+    pub content: String,
+    pub timestamp: String,
+}
+
+/// Get the path to .conductor-app/ folder within a workspace
+pub fn conductor_app_path(ws_path: &Path) -> PathBuf {
+    ws_path.join(".conductor-app")
+}
+
+/// Ensure .conductor-app/ folder exists with initial structure
+pub fn ensure_conductor_app(ws_path: &Path) -> Result<PathBuf> {
+    let app_dir = conductor_app_path(ws_path);
+    fs(std::fs::create_dir_all(&app_dir))?;
+    Ok(app_dir)
+}
+
+/// Read session state from .conductor-app/session.json
+pub fn session_read(ws_path: &Path) -> Result<Option<SessionState>> {
+    let session_path = conductor_app_path(ws_path).join("session.json");
+    if !session_path.exists() {
+        return Ok(None);
+    }
+    let content = fs(std::fs::read_to_string(&session_path))?;
+    let session: SessionState = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("failed to parse session.json: {}", e))?;
+    Ok(Some(session))
+}
+
+/// Write session state to .conductor-app/session.json. Via a temp file renamed over it,
+/// so a crash mid-write leaves the old state rather than a truncated file
+pub fn session_write(ws_path: &Path, session: &SessionState) -> Result<()> {
+    let app_dir = ensure_conductor_app(ws_path)?;
+    let session_path = app_dir.join("session.json");
+    let content = serde_json::to_string_pretty(session)
+        .map_err(|e| anyhow!("failed to serialize session: {}", e))?;
+    // Named per writer, so concurrent writes can't rename each other's half-written file
+    let tmp = app_dir.join(format!(".session.json.{}.tmp", Uuid::new_v4()));
+    let written = std::fs::File::create(&tmp)
+        .and_then(|mut file| file.write_all(content.as_bytes()).and_then(|_| file.sync_all()))
+        .and_then(|_| std::fs::rename(&tmp, &session_path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    fs(written)
+}
+
+/// Create a new session with the given agent ID
+pub fn session_create(ws_path: &Path, agent_id: &str) -> Result<SessionState> {
+    let now = Utc::now().to_rfc3339();
+    let session = SessionState {
+        agent_id: agent_id.to_string(),
+        resume_id: None,
+        started_at: now.clone(),
+        updated_at: now,
+        engine_version: None,
+        model: None,
+        base_sha: None,
+        conductor_version: None,
+    };
+    session_write(ws_path, &session)?;
+    Ok(session)
+}
+
+/// Update session with a resume ID (for CLI --resume flag)
+pub fn session_set_resume_id(ws_path: &Path, resume_id: &str) -> Result<SessionState> {
+    let mut session = session_read(ws_path)?
+        .ok_or_else(|| anyhow!("no session found"))?;
+    session.resume_id = Some(resume_id.to_string());
+    session.updated_at = Utc::now().to_rfc3339();
+    session_write(ws_path, &session)?;
+    Ok(session)
+}
+
+/// Read chat history from .conductor-app/chat.md
 pub fn chat_read(ws_path: &Path) -> Result<String> {
     let chat_path = conductor_app_path(ws_path).join("chat.md");
     if !chat_path.exists() {
         return Ok(String::new());
     }
-    fs(std::fs::read_to_string(&chat_path))
+    // Shared lock: never read half of an entry `chat_append_at` is writing
+    let mut file = fs(std::fs::File::open(&chat_path))?;
+    fs(file.lock_shared())?;
+    let mut content = String::new();
+    fs(file.read_to_string(&mut content))?;
+    Ok(content)
 }
 
 /// Append a message to .conductor-app/chat.md
 pub fn chat_append(ws_path: &Path, role: &str, content: &str) -> Result<()> {
+    chat_append_at(ws_path, role, content, &Utc::now().to_rfc3339())
+}
+
+/// Append a message stamped with `timestamp` rather than now (e.g. when importing)
+pub fn chat_append_at(ws_path: &Path, role: &str, content: &str, timestamp: &str) -> Result<()> {
     let app_dir = ensure_conductor_app(ws_path)?;
     let chat_path = app_dir.join("chat.md");
-    let timestamp = Utc::now().to_rfc3339();
 
     let mut file = fs(std::fs::OpenOptions::new()
         .create(true)
         .append(true)
         .open(&chat_path))?;
+    // Advisory, held until the file closes: concurrent appends (CLI and daemon, or two
+    // runs) land one whole entry after another instead of interleaving
+    fs(file.lock())?;
 
     // Format: ## Role (timestamp)\n\ncontent\n\n---\n\n
     let entry = format!("## {} ({})\n\n{}\n\n---\n\n", role, timestamp, content);
@@ -1044,6 +5255,32 @@ pub fn chat_append(ws_path: &Path, role: &str, content: &str) -> Result<()> {
     Ok(())
 }
 
+// One "## Role (timestamp)" block of chat.md; None for empty or malformed blocks
+fn parse_chat_block(block: &str) -> Option<ChatEntry> {
+    let (header, body) = block.trim().split_once('\n')?;
+    let (role, timestamp) = header.strip_prefix("## ")?.split_once(" (")?;
+    let body = body.trim();
+    if body.is_empty() {
+        return None;
+    }
+    Some(ChatEntry {
+        role: role.trim().to_string(),
+        content: body.to_string(),
+        timestamp: timestamp.strip_suffix(')')?.to_string(),
+    })
+}
+
+/// Every entry of a chat.md, oldest first
+pub fn parse_chat(content: &str) -> Vec<ChatEntry> {
+    content.split("\n---\n").filter_map(parse_chat_block).collect()
+}
+
+/// The most recent entry in .conductor-app/chat.md, if any
+pub fn chat_last_entry(ws_path: &Path) -> Result<Option<ChatEntry>> {
+    let content = chat_read(ws_path)?;
+    Ok(content.rsplit("\n---\n").find_map(parse_chat_block))
+}
+
 /// Clear chat history
 pub fn chat_clear(ws_path: &Path) -> Result<()> {
     let chat_path = conductor_app_path(ws_path).join("chat.md");
@@ -1053,6 +5290,238 @@ pub fn chat_clear(ws_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// A transcript past this size is rotated to `transcript-<session>.1.jsonl`, replacing
+/// any earlier rotation, so one run keeps at most twice this on disk
+pub const TRANSCRIPT_MAX_BYTES: u64 = 8 * 1024 * 1024;
+/// Transcripts kept per workspace; older sessions' are deleted when a new one starts
+pub const TRANSCRIPTS_KEPT: usize = 20;
+
+/// Where the raw engine output of `session_id` is recorded
+pub fn transcript_path(ws_path: &Path, session_id: &str) -> PathBuf {
+    conductor_app_path(ws_path).join(format!("transcript-{}.jsonl", safe_dir_name(session_id)))
+}
+
+/// Transcript files in a workspace (rotated parts excluded), newest first
+pub fn transcript_list(ws_path: &Path) -> Result<Vec<PathBuf>> {
+    let app_dir = conductor_app_path(ws_path);
+    if !app_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut found = Vec::new();
+    for entry in fs(std::fs::read_dir(&app_dir))?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("transcript-") && name.ends_with(".jsonl") && !name.ends_with(".1.jsonl") {
+            let modified = entry.metadata().and_then(|meta| meta.modified()).ok();
+            found.push((modified, entry.path()));
+        }
+    }
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(found.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Appends every line an engine prints to its session's transcript, for debugging
+/// the parser and replaying runs through newer versions of it
+pub struct Transcript {
+    path: PathBuf,
+    file: std::fs::File,
+    bytes: u64,
+}
+
+impl Transcript {
+    /// Start (or continue) the transcript for `session_id`, pruning the oldest ones
+    /// beyond `TRANSCRIPTS_KEPT`
+    pub fn create(ws_path: &Path, session_id: &str) -> Result<Self> {
+        ensure_conductor_app(ws_path)?;
+        let path = transcript_path(ws_path, session_id);
+        for old in transcript_list(ws_path)?.into_iter().filter(|old| *old != path).skip(TRANSCRIPTS_KEPT - 1) {
+            let _ = std::fs::remove_file(old.with_extension("1.jsonl"));
+            fs(std::fs::remove_file(&old))?;
+        }
+        let file = fs(std::fs::OpenOptions::new().create(true).append(true).open(&path))?;
+        let bytes = fs(file.metadata())?.len();
+        Ok(Self { path, file, bytes })
+    }
+
+    pub fn append(&mut self, line: &str) -> Result<()> {
+        if self.bytes > 0 && self.bytes + line.len() as u64 + 1 > TRANSCRIPT_MAX_BYTES {
+            fs(std::fs::rename(&self.path, self.path.with_extension("1.jsonl")))?;
+            self.file = fs(std::fs::File::create(&self.path))?;
+            self.bytes = 0;
+        }
+        fs(self.file.write_all(line.as_bytes()))?;
+        fs(self.file.write_all(b"\n"))?;
+        self.bytes += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Formats `chat_export` renders a workspace chat to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatExportFormat {
+    /// Markdown with `<details>` blocks, for pasting into PRs and tickets
+    Markdown,
+    /// Standalone HTML page with inline styles
+    Html,
+}
+
+impl std::str::FromStr for ChatExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "markdown" | "md" => Ok(ChatExportFormat::Markdown),
+            "html" => Ok(ChatExportFormat::Html),
+            _ => bail!("unknown chat export format: {value} (expected markdown or html)"),
+        }
+    }
+}
+
+/// Audit events for the workspace at `ws_path`, oldest first: operations on the
+/// workspace itself plus the agent runs started inside it
+pub fn chat_events(conn: &Connection, ws_path: &Path) -> Result<Vec<AuditEvent>> {
+    let ws_id = workspace_for_path(conn, ws_path)?.map(|ws| ws.id);
+    let path = ws_path.to_string_lossy().to_string();
+    let mut stmt = db(conn.prepare(
+        "SELECT id, created_at, source, action, target, ok, metadata FROM audit_events
+         WHERE target = ? OR target = ? OR action = 'agent.run' ORDER BY id",
+    ))?;
+    let rows = db(stmt.query_map(params![ws_id, path], audit_event_row))?;
+    Ok(collect_rows(rows)?
+        .into_iter()
+        .filter(|event| {
+            // Agent runs are keyed by session; match them on where they ran
+            event.action != "agent.run"
+                || event.metadata["cwd"].as_str().is_some_and(|cwd| Path::new(cwd).starts_with(ws_path))
+        })
+        .collect())
+}
+
+// A chat message or an audit event, in the order they happened
+enum ChatExportItem<'a> {
+    Message(&'a ChatEntry),
+    Event(&'a AuditEvent),
+}
+
+impl ChatExportItem<'_> {
+    fn timestamp(&self) -> &str {
+        match self {
+            ChatExportItem::Message(entry) => &entry.timestamp,
+            ChatExportItem::Event(event) => &event.created_at,
+        }
+    }
+}
+
+// "agent.run (ok)", plus the metadata worth showing; the caller's details are noise here
+fn chat_event_detail(event: &AuditEvent) -> (String, String) {
+    let outcome = if event.ok { "ok" } else { "failed" };
+    let mut metadata = event.metadata.clone();
+    if let Some(map) = metadata.as_object_mut() {
+        map.remove("client");
+    }
+    let detail = serde_json::to_string_pretty(&metadata).unwrap_or_default();
+    (format!("{} ({outcome})", event.action), detail)
+}
+
+// A code fence longer than any backtick run in `text`
+fn markdown_fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+const CHAT_EXPORT_STYLE: &str = "body{font-family:-apple-system,system-ui,sans-serif;max-width:860px;margin:2em auto;padding:0 1em;color:#1f2328}\
+h1{font-size:1.5em}h2{font-size:1.05em;margin:0 0 .5em}time,.meta{color:#656d76;font-weight:normal;font-size:.85em}\
+section{border:1px solid #d0d7de;border-radius:6px;padding:.75em 1em;margin:1em 0}section.user{background:#f6f8fa}\
+.content{white-space:pre-wrap}details{margin:.5em 0;color:#656d76}pre{background:#f6f8fa;padding:.5em;overflow-x:auto}";
+
+/// Render the chat of the workspace at `ws_path` as a standalone document, with the
+/// workspace's audit events folded in as collapsed action details
+pub fn chat_export(conn: &Connection, ws_path: &Path, format: ChatExportFormat) -> Result<String> {
+    let entries = parse_chat(&chat_read(ws_path)?);
+    let events = chat_events(conn, ws_path)?;
+    let title = match workspace_for_path(conn, ws_path)? {
+        Some(ws) => ws.title.unwrap_or(ws.name),
+        None => ws_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| ws_path.display().to_string()),
+    };
+
+    let mut items: Vec<ChatExportItem> = entries.iter().map(ChatExportItem::Message).collect();
+    items.extend(events.iter().map(ChatExportItem::Event));
+    // Stable, so same-instant items keep messages ahead of events
+    items.sort_by_cached_key(|item| chrono::DateTime::parse_from_rfc3339(item.timestamp()).ok());
+    let exported_at = Utc::now().to_rfc3339();
+
+    let mut out = String::new();
+    match format {
+        ChatExportFormat::Markdown => {
+            out.push_str(&format!("# {title}\n\nExported from Conductor at {exported_at}\n"));
+            for item in &items {
+                match item {
+                    ChatExportItem::Message(entry) => {
+                        out.push_str(&format!("\n### {} ({})\n\n{}\n", entry.role, entry.timestamp, entry.content));
+                    }
+                    ChatExportItem::Event(event) => {
+                        let (summary, detail) = chat_event_detail(event);
+                        let fence = markdown_fence(&detail);
+                        out.push_str(&format!(
+                            "\n<details>\n<summary>{} {}</summary>\n\n{fence}json\n{detail}\n{fence}\n\n</details>\n",
+                            html_escape(&summary),
+                            event.created_at,
+                        ));
+                    }
+                }
+            }
+        }
+        ChatExportFormat::Html => {
+            let title = html_escape(&title);
+            out.push_str(&format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{CHAT_EXPORT_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"meta\">Exported from Conductor at {exported_at}</p>\n"
+            ));
+            for item in &items {
+                match item {
+                    ChatExportItem::Message(entry) => {
+                        let role = html_escape(&entry.role);
+                        out.push_str(&format!(
+                            "<section class=\"{}\">\n<h2>{role} <time>{}</time></h2>\n<div class=\"content\">{}</div>\n</section>\n",
+                            role.to_lowercase(),
+                            html_escape(&entry.timestamp),
+                            html_escape(&entry.content),
+                        ));
+                    }
+                    ChatExportItem::Event(event) => {
+                        let (summary, detail) = chat_event_detail(event);
+                        out.push_str(&format!(
+                            "<details>\n<summary>{} <time>{}</time></summary>\n<pre>{}</pre>\n</details>\n",
+                            html_escape(&summary),
+                            html_escape(&event.created_at),
+                            html_escape(&detail),
+                        ));
+                    }
+                }
+            }
+            out.push_str("</body>\n</html>\n");
+        }
+    }
+    Ok(out)
+}
+
 /// Archive session data before workspace archive (to global archive location)
 pub fn conductor_app_archive(home: &Path, ws_id: &str, ws_path: &Path) -> Result<()> {
     let app_dir = conductor_app_path(ws_path);
@@ -1063,7 +5532,7 @@ pub fn conductor_app_archive(home: &Path, ws_id: &str, ws_path: &Path) -> Result
     // Create archive in global location (survives worktree removal)
     // Uses .conductor-app/archive/ at the home level for consistency
     let timestamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
-    let archive_dir = home.join(".conductor-app").join("archive").join(ws_id).join(&timestamp);
+    let archive_dir = archive_root(home).join(ws_id).join(&timestamp);
     fs(std::fs::create_dir_all(&archive_dir))?;
 
     // Copy (not move) session.json and chat.md to archive
@@ -1079,29 +5548,624 @@ pub fn conductor_app_archive(home: &Path, ws_id: &str, ws_path: &Path) -> Result
     Ok(())
 }
 
-/// Update session with a resume ID, creating session if it doesn't exist
+/// One snapshot `conductor_app_archive` saved when a workspace was archived
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub workspace_id: String,
+    /// Directory name under the workspace's archive, `%Y%m%d-%H%M%S` in UTC
+    pub timestamp: String,
+    pub has_session: bool,
+    pub has_chat: bool,
+    pub bytes: u64,
+}
+
+/// A snapshot's contents, as `archive_read` returns them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub workspace_id: String,
+    pub timestamp: String,
+    pub session: Option<SessionState>,
+    pub chat: Vec<ChatEntry>,
+}
+
+fn archive_root(home: &Path) -> PathBuf {
+    home.join(".conductor-app").join("archive")
+}
+
+fn dir_names(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs(std::fs::read_dir(path))? {
+        let entry = fs(entry)?;
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Saved session snapshots, newest first; all workspaces unless `ws_id` is given
+pub fn archive_list(home: &Path, ws_id: Option<&str>) -> Result<Vec<ArchiveEntry>> {
+    let root = archive_root(home);
+    let workspace_ids = match ws_id {
+        Some(ws_id) => vec![ws_id.to_string()],
+        None => dir_names(&root)?,
+    };
+    let mut entries = Vec::new();
+    for workspace_id in workspace_ids {
+        if !is_single_component(&workspace_id) {
+            bail!("invalid workspace id: {workspace_id}");
+        }
+        for timestamp in dir_names(&root.join(&workspace_id))? {
+            let dir = root.join(&workspace_id).join(&timestamp);
+            entries.push(ArchiveEntry {
+                has_session: dir.join("session.json").exists(),
+                has_chat: dir.join("chat.md").exists(),
+                bytes: dir_size(&dir),
+                workspace_id: workspace_id.clone(),
+                timestamp,
+            });
+        }
+    }
+    entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.workspace_id.cmp(&b.workspace_id)));
+    Ok(entries)
+}
+
+/// The session and chat saved in one snapshot from `archive_list`
+pub fn archive_read(home: &Path, ws_id: &str, timestamp: &str) -> Result<ArchivedSession> {
+    if !is_single_component(ws_id) || !is_single_component(timestamp) {
+        bail!("invalid archive: {ws_id}/{timestamp}");
+    }
+    let dir = archive_root(home).join(ws_id).join(timestamp);
+    if !dir.is_dir() {
+        bail!("archive not found: {ws_id}/{timestamp}");
+    }
+    let session_path = dir.join("session.json");
+    let session = if session_path.exists() {
+        let content = fs(std::fs::read_to_string(&session_path))?;
+        Some(serde_json::from_str(&content).with_context(|| format!("invalid {}", session_path.display()))?)
+    } else {
+        None
+    };
+    let chat_path = dir.join("chat.md");
+    let chat = if chat_path.exists() {
+        parse_chat(&fs(std::fs::read_to_string(&chat_path))?)
+    } else {
+        Vec::new()
+    };
+    Ok(ArchivedSession {
+        workspace_id: ws_id.to_string(),
+        timestamp: timestamp.to_string(),
+        session,
+        chat,
+    })
+}
+
+// A plain file name: no separators, and not "." or ".."
+fn is_single_component(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// Update session with a resume ID, creating session if it doesn't exist. A token
+/// from a different engine starts a new session: one engine can't resume another's.
 pub fn session_upsert_resume_id(ws_path: &Path, agent_id: &str, resume_id: &str) -> Result<SessionState> {
     let now = Utc::now().to_rfc3339();
     let session = match session_read(ws_path)? {
-        Some(mut s) => {
+        Some(mut s) if s.agent_id == agent_id => {
             s.resume_id = Some(resume_id.to_string());
             s.updated_at = now;
             s
         }
-        None => SessionState {
+        _ => SessionState {
             agent_id: agent_id.to_string(),
             resume_id: Some(resume_id.to_string()),
             started_at: now.clone(),
             updated_at: now,
+            engine_version: None,
+            model: None,
+            base_sha: None,
+            conductor_version: None,
         }
     };
     session_write(ws_path, &session)?;
     Ok(session)
 }
 
-// =============================================================================
-// Workspace Archive
-// =============================================================================
+/// Record what a run of `agent_id` started from, with this build's version. The
+/// conversation carries on when the engine is the session's; another engine starts a
+/// new session.
+pub fn session_record_run(ws_path: &Path, agent_id: &str, run: &RunProvenance) -> Result<SessionState> {
+    let now = Utc::now().to_rfc3339();
+    let mut session = match session_read(ws_path)? {
+        Some(s) if s.agent_id == agent_id => s,
+        _ => SessionState {
+            agent_id: agent_id.to_string(),
+            resume_id: None,
+            started_at: now.clone(),
+            updated_at: now.clone(),
+            engine_version: None,
+            model: None,
+            base_sha: None,
+            conductor_version: None,
+        },
+    };
+    session.engine_version = run.engine_version.clone();
+    session.model = run.model.clone();
+    session.base_sha = run.base_sha.clone();
+    session.conductor_version = Some(env!("CARGO_PKG_VERSION").to_string());
+    session.updated_at = now;
+    session_write(ws_path, &session)?;
+    Ok(session)
+}
+
+// =============================================================================
+// Session Import
+// =============================================================================
+
+/// A conversation found in an engine's own session store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineSession {
+    /// "claude" or "codex"
+    pub engine: String,
+    /// Session/thread id the engine resumes from
+    pub resume_id: String,
+    /// The engine's log file
+    pub source: String,
+    pub started_at: String,
+    pub updated_at: String,
+    #[serde(skip)]
+    pub messages: Vec<ChatEntry>,
+    pub message_count: usize,
+}
+
+/// What `session_import` brought into a workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionImport {
+    pub engine: String,
+    pub resume_id: String,
+    pub source: String,
+    /// Chat entries appended; ones already in chat.md are skipped
+    pub imported: usize,
+}
+
+// `$name`, else `~/<fallback>`
+fn engine_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+}
+
+fn read_jsonl(path: &Path) -> Vec<serde_json::Value> {
+    std::fs::read_to_string(path)
+        .map(|text| text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+// Text blocks of a message's content, which is either a string or a list of blocks
+fn content_text(content: &serde_json::Value, block_types: &[&str]) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .filter(|block| block["type"].as_str().is_some_and(|kind| block_types.contains(&kind)))
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+// Add a message, folding it into the previous one when the same role keeps talking
+// (engines log each streamed block on its own line)
+fn push_imported(messages: &mut Vec<ChatEntry>, role: &str, text: &str, timestamp: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    match messages.last_mut() {
+        Some(last) if last.role == role => {
+            last.content.push_str("\n\n");
+            last.content.push_str(text);
+        }
+        _ => messages.push(ChatEntry {
+            role: role.to_string(),
+            content: text.to_string(),
+            timestamp: timestamp.to_string(),
+        }),
+    }
+}
+
+fn engine_session(engine: &str, resume_id: String, source: &Path, started_at: String, updated_at: String, messages: Vec<ChatEntry>) -> EngineSession {
+    EngineSession {
+        engine: engine.to_string(),
+        resume_id,
+        source: source.display().to_string(),
+        started_at,
+        updated_at,
+        message_count: messages.len(),
+        messages,
+    }
+}
+
+// Claude Code keeps one JSONL per session under projects/<cwd with every
+// non-alphanumeric character replaced by '-'>
+fn claude_sessions(ws_path: &Path) -> Vec<EngineSession> {
+    let Some(dir) = engine_dir("CLAUDE_CONFIG_DIR", ".claude") else {
+        return Vec::new();
+    };
+    let encoded: String = ws_path
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let Ok(entries) = std::fs::read_dir(dir.join("projects").join(encoded)) else {
+        return Vec::new();
+    };
+    let mut sessions = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|ext| ext != "jsonl") {
+            continue;
+        }
+        let lines = read_jsonl(&path);
+        let mut resume_id = None;
+        let mut messages = Vec::new();
+        let mut timestamps = Vec::new();
+        for line in &lines {
+            let kind = line["type"].as_str().unwrap_or_default();
+            if kind != "user" && kind != "assistant" {
+                continue;
+            }
+            // The encoding is lossy, so confirm the session really ran here
+            if line["cwd"].as_str().is_some_and(|cwd| Path::new(cwd) != ws_path) {
+                continue;
+            }
+            if line["isSidechain"].as_bool() == Some(true) || line["isMeta"].as_bool() == Some(true) {
+                continue;
+            }
+            let timestamp = line["timestamp"].as_str().unwrap_or_default();
+            resume_id = resume_id.or_else(|| line["sessionId"].as_str().map(str::to_string));
+            timestamps.push(timestamp.to_string());
+            let role = if kind == "user" { "User" } else { "Assistant" };
+            // User lines also carry tool results, which have no text blocks
+            push_imported(&mut messages, role, &content_text(&line["message"]["content"], &["text"]), timestamp);
+        }
+        let Some(resume_id) = resume_id else { continue };
+        let started_at = timestamps.first().cloned().unwrap_or_default();
+        let updated_at = timestamps.last().cloned().unwrap_or_default();
+        sessions.push(engine_session("claude", resume_id, &path, started_at, updated_at, messages));
+    }
+    sessions
+}
+
+fn rollout_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            rollout_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "jsonl") {
+            files.push(path);
+        }
+    }
+}
+
+// Codex writes sessions/YYYY/MM/DD/rollout-*.jsonl, opening with a session_meta
+// line that names the thread and its cwd
+fn codex_sessions(ws_path: &Path) -> Vec<EngineSession> {
+    let Some(dir) = engine_dir("CODEX_HOME", ".codex") else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    rollout_files(&dir.join("sessions"), &mut files);
+    let mut sessions = Vec::new();
+    for path in files {
+        let lines = read_jsonl(&path);
+        let Some(meta) = lines.iter().find(|line| line["type"] == "session_meta") else {
+            continue;
+        };
+        let (Some(resume_id), Some(cwd)) = (meta["payload"]["id"].as_str(), meta["payload"]["cwd"].as_str()) else {
+            continue;
+        };
+        if !Path::new(cwd).starts_with(ws_path) {
+            continue;
+        }
+        let mut messages = Vec::new();
+        let mut timestamps = Vec::new();
+        for line in &lines {
+            let payload = &line["payload"];
+            if line["type"] != "response_item" || payload["type"] != "message" {
+                continue;
+            }
+            let timestamp = line["timestamp"].as_str().unwrap_or_default();
+            timestamps.push(timestamp.to_string());
+            let (role, text) = match payload["role"].as_str() {
+                Some("user") => ("User", content_text(&payload["content"], &["input_text"])),
+                Some("assistant") => ("Assistant", content_text(&payload["content"], &["output_text"])),
+                _ => continue,
+            };
+            // Codex injects <environment_context>/<user_instructions> as user turns
+            if role == "User" && text.trim_start().starts_with('<') {
+                continue;
+            }
+            push_imported(&mut messages, role, &text, timestamp);
+        }
+        let started_at = meta["payload"]["timestamp"]
+            .as_str()
+            .or(meta["timestamp"].as_str())
+            .unwrap_or_default()
+            .to_string();
+        let updated_at = timestamps.last().cloned().unwrap_or_else(|| started_at.clone());
+        sessions.push(engine_session("codex", resume_id.to_string(), &path, started_at, updated_at, messages));
+    }
+    sessions
+}
+
+/// Sessions Claude Code and Codex recorded for `ws_path` outside Conductor, most
+/// recently active first
+pub fn engine_sessions(ws_path: &Path) -> Vec<EngineSession> {
+    // Engines log the cwd they saw, with symlinks resolved
+    let ws_path = ws_path.canonicalize().unwrap_or_else(|_| ws_path.to_path_buf());
+    let mut sessions = claude_sessions(&ws_path);
+    sessions.extend(codex_sessions(&ws_path));
+    sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    sessions
+}
+
+/// Backfill chat.md and session.json from an engine session (the most recent one
+/// when `resume_id` is None), so it can be resumed from Conductor
+pub fn session_import(ws_path: &Path, engine: Option<&str>, resume_id: Option<&str>) -> Result<SessionImport> {
+    let Some(session) = engine_sessions(ws_path).into_iter().find(|session| {
+        engine.is_none_or(|engine| session.engine == engine)
+            && resume_id.is_none_or(|id| session.resume_id == id)
+    }) else {
+        bail!("no {} session found for {}", engine.unwrap_or("Claude Code or Codex"), ws_path.display());
+    };
+
+    // Re-importing must not duplicate entries; the engines' timestamps are stable
+    let existing: HashSet<(String, String)> = parse_chat(&chat_read(ws_path)?)
+        .into_iter()
+        .map(|entry| (entry.role, entry.timestamp))
+        .collect();
+    let mut imported = 0;
+    for entry in &session.messages {
+        if existing.contains(&(entry.role.clone(), entry.timestamp.clone())) {
+            continue;
+        }
+        chat_append_at(ws_path, &entry.role, &entry.content, &entry.timestamp)?;
+        imported += 1;
+    }
+
+    let started_at = session_read(ws_path)?.map_or_else(|| session.started_at.clone(), |s| s.started_at);
+    session_write(
+        ws_path,
+        &SessionState {
+            agent_id: session.engine.clone(),
+            resume_id: Some(session.resume_id.clone()),
+            started_at,
+            updated_at: Utc::now().to_rfc3339(),
+            engine_version: None,
+            model: None,
+            base_sha: None,
+            conductor_version: None,
+        },
+    )?;
+    Ok(SessionImport {
+        engine: session.engine,
+        resume_id: session.resume_id,
+        source: session.source,
+        imported,
+    })
+}
+
+// =============================================================================
+// Workspace Archive
+// =============================================================================
+
+// Why the workspace's work is already in its base, or None while some of it isn't (or
+// there is none yet). Merge commits and fast-forwards leave HEAD in the base; rebases
+// and cherry-picks leave patch-equivalent commits (`git cherry` marks them "-"); squash
+// merges leave neither, so a merged PR counts too
+fn merged_reason(conn: &Connection, ws: &Workspace, base_ref: &str) -> Result<Option<String>> {
+    if let Some(pr) = github::pr_status_cached(conn, &ws.id)?.filter(|pr| pr.state == "merged") {
+        return Ok(Some(format!("PR #{} merged", pr.number.unwrap_or_default())));
+    }
+    let ws_path = Path::new(&ws.path);
+    let cherry = GitCmd::new(ws_path).args(&["cherry"]).value(base_ref).args(&["HEAD"]).run()?;
+    if cherry.lines().any(|line| line.starts_with('+')) {
+        return Ok(None);
+    }
+    let applied = cherry.lines().filter(|line| line.starts_with('-')).count();
+    if applied > 0 {
+        let plural = if applied == 1 { "" } else { "s" };
+        return Ok(Some(format!("{applied} commit{plural} applied to {base_ref}")));
+    }
+    // HEAD is in the base. Unless it was committed after the workspace was made, that's
+    // just where the workspace started
+    let committed: i64 = GitCmd::new(ws_path).args(&["log", "-1", "--format=%ct", "HEAD"]).run()?.parse().unwrap_or(0);
+    let created: i64 = db(conn.query_row(
+        "SELECT CAST(strftime('%s', created_at) AS INTEGER) FROM workspaces WHERE id = ?",
+        [&ws.id],
+        |row| row.get(0),
+    ))?;
+    Ok((committed >= created).then(|| format!("merged into {base_ref}")))
+}
+
+/// Whether everything the workspace's branch committed is in its base branch (merged,
+/// fast-forwarded, rebased or squashed in via a merged PR). False for a branch with no
+/// commits of its own yet
+pub fn workspace_is_merged(conn: &Connection, ws_ref: &str) -> Result<bool> {
+    let ws = workspace_get(conn, ws_ref)?;
+    let base_ref = resolve_base_ref(Path::new(&ws.path), &ws.base_branch, ws.preferred_remote.as_deref())?;
+    Ok(merged_reason(conn, &ws, &base_ref)?.is_some())
+}
+
+/// A live workspace whose branch is merged into its base (see `workspace_is_merged`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedWorkspace {
+    pub id: String,
+    pub repo: String,
+    pub name: String,
+    pub branch: String,
+    pub base_branch: String,
+    pub pinned: bool,
+    /// How it got there, e.g. "PR #12 merged" or "3 commits applied to master"
+    pub reason: String,
+    /// What cleanup did about it ("archived", "kept: pinned", "failed: ...")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+/// Live workspaces whose branches are fully merged into their base, for cleanup. Ones
+/// whose checkout is missing or whose git state can't be read are left out
+pub fn workspaces_merged(conn: &Connection) -> Result<Vec<MergedWorkspace>> {
+    let mut merged = Vec::new();
+    for ws in workspace_list(conn, None)? {
+        if !ws.state.is_live() || !Path::new(&ws.path).is_dir() {
+            continue;
+        }
+        let reason = resolve_base_ref(Path::new(&ws.path), &ws.base_branch, ws.preferred_remote.as_deref())
+            .and_then(|base_ref| merged_reason(conn, &ws, &base_ref));
+        if let Ok(Some(reason)) = reason {
+            merged.push(MergedWorkspace {
+                id: ws.id,
+                repo: ws.repo,
+                name: ws.name,
+                branch: ws.branch,
+                base_branch: ws.base_branch,
+                pinned: ws.pinned,
+                reason,
+                action: None,
+            });
+        }
+    }
+    Ok(merged)
+}
+
+/// Something that must hold before a workspace is archived (`archive_checks` in config.json)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArchiveCheck {
+    /// The branch's commits are all on its upstream (or the same branch on origin)
+    Pushed,
+    /// Its PR was merged, or its commits are already in the base branch
+    Merged,
+    /// The repo's `verify` commands passed after the last agent run
+    Verified,
+}
+
+impl ArchiveCheck {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArchiveCheck::Pushed => "pushed",
+            ArchiveCheck::Merged => "merged",
+            ArchiveCheck::Verified => "verified",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveCheckResult {
+    pub check: ArchiveCheck,
+    pub ok: bool,
+    /// Why it passed or failed, e.g. "3 commits not on origin/feature"
+    pub detail: String,
+}
+
+impl fmt::Display for ArchiveCheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = if self.ok { "ok" } else { "FAILED" };
+        write!(f, "{} {mark}: {}", self.check.as_str(), self.detail)
+    }
+}
+
+/// The `archive_checks` for the workspace's repo (`repos` entry by name or id, else global)
+pub fn archive_checks(config: &Config, ws: &Workspace) -> Vec<ArchiveCheck> {
+    config
+        .repos
+        .get(&ws.repo)
+        .or_else(|| config.repos.get(&ws.repo_id))
+        .and_then(|repo_config| repo_config.archive_checks.clone())
+        .unwrap_or_else(|| config.archive_checks.clone())
+}
+
+/// Evaluate the configured pre-archive checks without archiving. Work with nothing beyond
+/// its base, or a workspace marked merged or abandoned, has nothing left to lose, so
+/// `pushed` and `merged` pass for it
+pub fn workspace_archive_checks(conn: &Connection, home: &Path, ws_ref: &str) -> Result<Vec<ArchiveCheckResult>> {
+    let ws = workspace_get(conn, ws_ref)?;
+    let checks = archive_checks(&config_load(home)?, &ws);
+    if checks.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ws_path = Path::new(&ws.path);
+    let base_ref = resolve_base_ref(ws_path, &ws.base_branch, ws.preferred_remote.as_deref())?;
+    let ahead: u32 = GitCmd::new(ws_path)
+        .args(&["rev-list", "--count"])
+        .value(format!("{base_ref}..HEAD"))
+        .run()?
+        .parse()
+        .unwrap_or(0);
+    let settled = match ws.state {
+        _ if ahead == 0 => Some(format!("no commits beyond {base_ref}")),
+        WorkspaceState::Merged | WorkspaceState::Abandoned => Some(format!("marked {}", ws.state)),
+        _ => None,
+    };
+    let plural = |n: u32| if n == 1 { "" } else { "s" };
+
+    let mut results = Vec::new();
+    for check in checks {
+        let (ok, detail) = match (check, &settled) {
+            (ArchiveCheck::Pushed | ArchiveCheck::Merged, Some(why)) => (true, why.clone()),
+            (ArchiveCheck::Pushed, None) => {
+                let remote_branch = format!("origin/{}", ws.branch);
+                let upstream = GitCmd::new(ws_path)
+                    .args(&["rev-parse", "--abbrev-ref", "@{upstream}"])
+                    .try_run()
+                    .or_else(|| {
+                        git_ref_exists(ws_path, &format!("refs/remotes/{remote_branch}")).then_some(remote_branch)
+                    });
+                match upstream {
+                    Some(upstream) => {
+                        let unpushed: u32 = GitCmd::new(ws_path)
+                            .args(&["rev-list", "--count"])
+                            .value(format!("{upstream}..HEAD"))
+                            .run()?
+                            .parse()
+                            .unwrap_or(0);
+                        if unpushed == 0 {
+                            (true, format!("up to date with {upstream}"))
+                        } else {
+                            (false, format!("{unpushed} commit{} not on {upstream}", plural(unpushed)))
+                        }
+                    }
+                    None => (false, format!("{} was never pushed", ws.branch)),
+                }
+            }
+            (ArchiveCheck::Merged, None) => match merged_reason(conn, &ws, &base_ref)? {
+                Some(reason) => (true, reason),
+                None => {
+                    let pr = github::pr_status_cached(conn, &ws.id)?;
+                    let mut detail = format!("{ahead} commit{} not in {base_ref}", plural(ahead));
+                    if let Some(number) = pr.and_then(|pr| pr.number.filter(|_| pr.state == "open")) {
+                        detail.push_str(&format!("; PR #{number} is open"));
+                    }
+                    (false, detail)
+                }
+            },
+            (ArchiveCheck::Verified, _) => match &ws.verification {
+                Some(verification) if verification.ok => (true, format!("checks passed at {}", verification.finished_at)),
+                Some(verification) => (
+                    false,
+                    format!("`{}` failed", verification.failed_command.as_deref().unwrap_or("verify")),
+                ),
+                None => (false, "no passing checks recorded".to_string()),
+            },
+        };
+        results.push(ArchiveCheckResult { check, ok, detail });
+    }
+    Ok(results)
+}
 
 pub fn workspace_archive(conn: &Connection, home: &Path, workspace_ref: &str, force: bool) -> Result<ArchiveResult> {
     let ws = get_workspace(conn, workspace_ref)?;
@@ -1110,14 +6174,24 @@ pub fn workspace_archive(conn: &Connection, home: &Path, workspace_ref: &str, fo
     let ws_path = PathBuf::from(ws.path);
     let mut removed = false;
     let mut message = "archived".to_string();
+    let mut checks = Vec::new();
     if ws_path.exists() {
+        checks = workspace_archive_checks(conn, home, &ws_id)?;
+        let failed: Vec<String> = checks.iter().filter(|c| !c.ok).map(ToString::to_string).collect();
+        if !force && !failed.is_empty() {
+            bail!(
+                "workspace failed its pre-archive checks; pass --force to archive anyway: {}",
+                failed.join("; ")
+            );
+        }
+
         // Archive .conductor-app/ data before removing worktree (to global archive)
         if let Err(err) = conductor_app_archive(home, &ws_id, &ws_path) {
             message = format!("warning: failed to archive session data: {err}");
         }
 
         if !force {
-            let status = git(&ws_path, &["status", "--porcelain", "--untracked-files=all"])?;
+            let status = GitCmd::new(&ws_path).args(&["status", "--porcelain", "--untracked-files=all"]).run()?;
             if !status.trim().is_empty() {
                 bail!(
                     "workspace has uncommitted changes; commit or stash before archiving, or pass --force: {}",
@@ -1125,31 +6199,915 @@ pub fn workspace_archive(conn: &Connection, home: &Path, workspace_ref: &str, fo
                 );
             }
         }
-        let mut args = vec!["worktree", "remove"];
+        let mut remove = GitCmd::new(&repo_root).args(&["worktree", "remove"]);
         if force {
-            args.push("--force");
+            remove = remove.args(&["--force"]);
+        }
+        if let Err(err) = remove.operand(ws_path.to_string_lossy()).run() {
+            // A remove that got partway leaves a checkout git can no longer use
+            if let Some(problem) = worktree_link_problem(&ws_path) {
+                set_workspace_error(conn, &ws_id, &format!("archive failed: {problem}"))?;
+            }
+            return Err(err);
         }
-        let ws_path_str = ws_path.to_string_lossy().to_string();
-        args.push("--");
-        args.push(ws_path_str.as_str());
-        run("git", &args, Some(&repo_root))?;
         removed = true;
     } else {
         message = "workspace path already removed".to_string();
     }
-    if let Err(err) = run("git", &["worktree", "prune"], Some(&repo_root)) {
+    if let Err(err) = GitCmd::new(&repo_root).args(&["worktree", "prune"]).run() {
         message = format!("{message} (prune failed: {err})");
     }
 
-    db(conn.execute(
-        "UPDATE workspaces SET state = ?, updated_at = datetime('now') WHERE id = ?",
-        [WorkspaceState::Archived.as_str(), ws_id.as_str()],
-    ))?;
+    set_workspace_state(conn, &ws_id, WorkspaceState::Archived)?;
 
     Ok(ArchiveResult {
         id: ws_id,
         ok: true,
         removed,
         message,
+        checks,
+    })
+}
+
+/// An archived workspace removed (or, in a dry run, due for removal) by `workspace_purge`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgedWorkspace {
+    pub id: String,
+    pub repo: String,
+    pub name: String,
+    pub branch: String,
+    /// When it was archived (UTC, SQLite `datetime` format)
+    pub archived_at: String,
+    /// Size of its saved session data under `<home>/.conductor-app/archive`
+    pub archive_bytes: u64,
+}
+
+/// Parse an age like "30d", "12h", "2w", "90m" or "45s"
+pub fn parse_age(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let Ok(number) = number.parse::<u64>() else {
+        bail!("invalid age: {text:?} (expected e.g. 30d, 12h or 2w)");
+    };
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("invalid age: {text:?} (units are s, m, h, d and w)"),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(unit_secs)))
+}
+
+/// Forget workspaces archived more than `older_than` ago: their rows (with cached PR
+/// state and activity) and their saved session data. Branches stay in the repo, since
+/// they may hold work nobody merged
+pub fn workspace_purge(
+    conn: &Connection,
+    home: &Path,
+    older_than: Duration,
+    dry_run: bool,
+) -> Result<Vec<PurgedWorkspace>> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(older_than).map_err(|_| anyhow!("age is too large"))?;
+    let cutoff = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut stmt = db(conn.prepare(
+        "SELECT w.id, r.name, w.directory_name, w.branch, w.updated_at
+         FROM workspaces w JOIN repos r ON r.id = w.repository_id
+         WHERE w.state = 'archived' AND w.updated_at < ?
+         ORDER BY w.updated_at",
+    ))?;
+    let rows = db(stmt.query_map([&cutoff], |row| {
+        Ok(PurgedWorkspace {
+            id: row.get(0)?,
+            repo: row.get(1)?,
+            name: row.get(2)?,
+            branch: row.get(3)?,
+            archived_at: row.get(4)?,
+            archive_bytes: 0,
+        })
+    }))?;
+    let mut purged = collect_rows(rows)?;
+    for ws in &mut purged {
+        let archive_dir = archive_root(home).join(&ws.id);
+        ws.archive_bytes = dir_size(&archive_dir);
+        if dry_run {
+            continue;
+        }
+        // The repo may be gone too; its refs went with it
+        if let Ok(row) = get_workspace(conn, &ws.id) {
+            let _ = checkpoint_refs_delete(Path::new(&row.repo_root), &ws.id);
+        }
+        let tx = db(conn.unchecked_transaction())?;
+        for table in [
+            "workspace_prs",
+            "workspace_activity",
+            "workspace_titles",
+            "workspace_verifications",
+            "workspace_issues",
+            "review_comments",
+            "task_workspaces",
+            "task_runs",
+        ] {
+            db(tx.execute(&format!("DELETE FROM {table} WHERE workspace_id = ?"), [&ws.id]))?;
+        }
+        db(tx.execute("DELETE FROM workspaces WHERE id = ?", [&ws.id]))?;
+        db(tx.commit())?;
+        // The row is gone either way; leftover files only cost space
+        if archive_dir.exists() {
+            fs(std::fs::remove_dir_all(&archive_dir))?;
+        }
+    }
+    Ok(purged)
+}
+
+// =============================================================================
+// Audit Log
+// =============================================================================
+
+/// One mutating operation recorded in the append-only audit_events table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub created_at: String,
+    pub source: String,
+    pub action: String,
+    pub target: Option<String>,
+    pub ok: bool,
+    pub metadata: serde_json::Value,
+}
+
+/// Filters for audit_list; all fields are optional
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Append an audit event. `source` identifies the caller ("cli", "daemon").
+pub fn audit_record(
+    conn: &Connection,
+    source: &str,
+    action: &str,
+    target: Option<&str>,
+    ok: bool,
+    metadata: &serde_json::Value,
+) -> Result<()> {
+    let created_at = Utc::now().to_rfc3339();
+    db(conn.execute(
+        "INSERT INTO audit_events (created_at, source, action, target, ok, metadata) VALUES (?, ?, ?, ?, ?, ?)",
+        params![created_at, source, action, target, ok, metadata.to_string()],
+    ))?;
+    Ok(())
+}
+
+/// List audit events, newest first
+pub fn audit_list(conn: &Connection, filter: &AuditFilter) -> Result<Vec<AuditEvent>> {
+    let mut sql = String::from("SELECT id, created_at, source, action, target, ok, metadata FROM audit_events");
+    let mut clauses = Vec::new();
+    let mut params_vec: Vec<String> = Vec::new();
+    if let Some(action) = &filter.action {
+        // "workspace" matches "workspace.create", "workspace.archive", ...
+        clauses.push("(action = ? OR action LIKE ?)");
+        params_vec.push(action.clone());
+        params_vec.push(format!("{action}.%"));
+    }
+    if let Some(target) = &filter.target {
+        clauses.push("target = ?");
+        params_vec.push(target.clone());
+    }
+    if let Some(since) = &filter.since {
+        clauses.push("created_at >= ?");
+        params_vec.push(since.clone());
+    }
+    if !clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&clauses.join(" AND "));
+    }
+    sql.push_str(" ORDER BY id DESC");
+    if let Some(limit) = filter.limit {
+        sql.push_str(&format!(" LIMIT {limit}"));
+    }
+
+    let mut stmt = db(conn.prepare(&sql))?;
+    let rows = db(stmt.query_map(rusqlite::params_from_iter(params_vec.iter()), audit_event_row))?;
+    collect_rows(rows)
+}
+
+// Columns: id, created_at, source, action, target, ok, metadata
+fn audit_event_row(row: &Row) -> rusqlite::Result<AuditEvent> {
+    let metadata: String = row.get(6)?;
+    Ok(AuditEvent {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        source: row.get(2)?,
+        action: row.get(3)?,
+        target: row.get(4)?,
+        ok: row.get(5)?,
+        metadata: serde_json::from_str(&metadata).unwrap_or(serde_json::Value::Null),
+    })
+}
+
+// =============================================================================
+// Editor
+// =============================================================================
+
+/// GUI editors tried, in order, when none is configured
+const KNOWN_EDITORS: &[&str] = &["code", "cursor", "zed"];
+
+/// Command line (program plus args) to open a workspace with: config.json `editor`,
+/// then `$VISUAL`/`$EDITOR` when a terminal is attached, then the first of VS Code,
+/// Cursor or Zed on PATH. The workspace path is appended by the caller.
+pub fn editor_command(config: &Config, terminal: bool) -> Result<Vec<String>> {
+    let mut candidates: Vec<String> = config.editor.iter().cloned().collect();
+    if terminal {
+        candidates.extend(["VISUAL", "EDITOR"].iter().filter_map(|var| env::var(var).ok()));
+    }
+    for line in candidates {
+        let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        if !words.is_empty() {
+            return Ok(words);
+        }
+    }
+    KNOWN_EDITORS
+        .iter()
+        .find(|program| find_in_path(program).is_some())
+        .map(|program| vec![program.to_string()])
+        .ok_or_else(|| {
+            anyhow!("no editor found; install code, cursor or zed, or set \"editor\" in config.json")
+        })
+}
+
+const LINUX_TERMINALS: &[&str] = &[
+    "x-terminal-emulator",
+    "gnome-terminal",
+    "konsole",
+    "kitty",
+    "alacritty",
+    "wezterm",
+    "xfce4-terminal",
+    "xterm",
+];
+
+/// Command line that opens a terminal at `path`; run it with `path` as the working
+/// directory. macOS uses `open -a` with config.json `terminal` (default Terminal);
+/// elsewhere `terminal`, then `$TERMINAL`, then the first known emulator on PATH.
+pub fn terminal_command(config: &Config, path: &Path) -> Result<Vec<String>> {
+    if cfg!(target_os = "macos") {
+        let app = config.terminal.clone().unwrap_or_else(|| "Terminal".to_string());
+        return Ok(vec!["open".to_string(), "-a".to_string(), app, path.to_string_lossy().to_string()]);
+    }
+    let configured = config.terminal.clone().or_else(|| env::var("TERMINAL").ok());
+    if let Some(line) = configured {
+        let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        if !words.is_empty() {
+            return Ok(words);
+        }
+    }
+    LINUX_TERMINALS
+        .iter()
+        .find(|program| find_in_path(program).is_some())
+        .map(|program| vec![program.to_string()])
+        .ok_or_else(|| anyhow!("no terminal found; set \"terminal\" in config.json or $TERMINAL"))
+}
+
+/// Command line that shows `path` in the platform file manager (Finder, or xdg-open)
+pub fn reveal_command(path: &Path) -> Vec<String> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    vec![opener.to_string(), path.to_string_lossy().to_string()]
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+// =============================================================================
+// Engines
+// =============================================================================
+
+/// An agent CLI the daemon knows how to launch
+pub struct EngineSpec {
+    pub name: &'static str,
+    /// Other names clients may use for it
+    pub aliases: &'static [&'static str],
+    pub program: &'static str,
+    pub supports_resume: bool,
+    /// Emits JSON events the parser turns into live actions and messages
+    pub supports_streaming: bool,
+}
+
+pub const ENGINES: &[EngineSpec] = &[
+    EngineSpec {
+        name: "claude",
+        aliases: &["claude-code"],
+        program: "claude",
+        supports_resume: true,
+        supports_streaming: true,
+    },
+    EngineSpec {
+        name: "codex",
+        aliases: &[],
+        program: "codex",
+        supports_resume: true,
+        supports_streaming: true,
+    },
+    EngineSpec {
+        name: "gemini",
+        aliases: &[],
+        program: "gemini",
+        supports_resume: false,
+        supports_streaming: false,
+    },
+];
+
+/// How the daemon reads a custom engine's output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EngineParser {
+    /// Prints Claude Code's stream-json
+    ClaudeCompatible,
+    /// Prints `codex exec --json` events
+    CodexCompatible,
+    /// Plain text, shown as the agent's message as it arrives
+    #[default]
+    Passthrough,
+}
+
+/// An agent CLI declared under `engines` in config.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomEngine {
+    pub name: String,
+    /// Program and arguments; `{prompt}` and `{resume}` are substituted in each one.
+    /// Arguments mentioning `{resume}` are dropped from fresh runs, so write the flag
+    /// and its value as one argument, e.g. "--resume={resume}"
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub parser: EngineParser,
+}
+
+impl CustomEngine {
+    pub fn supports_resume(&self) -> bool {
+        self.command.iter().any(|arg| arg.contains("{resume}"))
+    }
+
+    /// The command line for one run
+    pub fn argv(&self, prompt: &str, resume: Option<&str>) -> Vec<String> {
+        self.command
+            .iter()
+            .filter_map(|arg| match resume {
+                _ if !arg.contains("{resume}") => Some(arg.replace("{prompt}", prompt)),
+                Some(resume) => Some(arg.replace("{resume}", resume).replace("{prompt}", prompt)),
+                None => None,
+            })
+            .collect()
+    }
+}
+
+/// The usable engines from config.json: a command is required, and built-in names
+/// can't be taken over. Problems are returned alongside for the caller to report
+pub fn custom_engines(config: &Config) -> (Vec<CustomEngine>, Vec<String>) {
+    let mut engines: Vec<CustomEngine> = Vec::new();
+    let mut problems = Vec::new();
+    for engine in &config.engines {
+        let builtin = ENGINES.iter().any(|spec| spec.name == engine.name || spec.aliases.contains(&engine.name.as_str()));
+        if engine.name.is_empty() || engine.command.is_empty() {
+            problems.push(format!("engine {:?} needs a name and a command", engine.name));
+        } else if builtin || engines.iter().any(|other| other.name == engine.name) {
+            problems.push(format!("engine {:?} is already defined", engine.name));
+        } else {
+            engines.push(engine.clone());
+        }
+    }
+    (engines, problems)
+}
+
+/// The engines a run asked for, most preferred first: a chain from `engine_chains`, a
+/// comma-separated list such as "claude,codex", or just the one engine
+pub fn engine_chain(config: &Config, engine: &str) -> Vec<String> {
+    if let Some(chain) = config.engine_chains.get(engine).filter(|chain| !chain.is_empty()) {
+        return chain.clone();
+    }
+    let mut chain: Vec<String> = Vec::new();
+    for name in engine.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if !chain.iter().any(|seen| seen == name) {
+            chain.push(name.to_string());
+        }
+    }
+    if chain.is_empty() {
+        chain.push(engine.to_string());
+    }
+    chain
+}
+
+/// Whether this machine can run an engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineInfo {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub program: String,
+    /// The program was found on PATH
+    pub available: bool,
+    pub path: Option<String>,
+    /// First line of `<program> --version`
+    pub version: Option<String>,
+    /// Credentials were found (true) or are known to be missing (false); None when
+    /// they can't be seen from here, e.g. kept in the macOS keychain
+    pub authenticated: Option<bool>,
+    pub supports_resume: bool,
+    pub supports_streaming: bool,
+}
+
+// Signs of a login: API keys in the environment, or the files each CLI writes on login
+fn engine_authenticated(name: &str) -> Option<bool> {
+    let has_env = |vars: &[&str]| vars.iter().any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()));
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let has_file = |dir: Option<PathBuf>, file: &str| dir.is_some_and(|dir| dir.join(file).is_file());
+    match name {
+        "claude" => {
+            let config_dir = engine_dir("CLAUDE_CONFIG_DIR", ".claude");
+            let logged_in = has_env(&["ANTHROPIC_API_KEY", "CLAUDE_CODE_OAUTH_TOKEN"])
+                || has_file(config_dir, ".credentials.json");
+            // Elsewhere the token may sit in the keychain, which we don't read
+            logged_in.then_some(true)
+        }
+        "codex" => Some(has_env(&["OPENAI_API_KEY"]) || has_file(engine_dir("CODEX_HOME", ".codex"), "auth.json")),
+        "gemini" => {
+            let logged_in = has_env(&["GEMINI_API_KEY", "GOOGLE_API_KEY"])
+                || has_file(home.map(|home| home.join(".gemini")), "oauth_creds.json");
+            logged_in.then_some(true)
+        }
+        _ => None,
+    }
+}
+
+// First line of `<program> --version`
+fn program_version(path: &Path) -> Option<String> {
+    let out = run(&path.to_string_lossy(), &["--version"], None).ok()?;
+    out.lines().next().map(str::to_string)
+}
+
+/// Version of the built-in engine `engine` (name or alias) on PATH; None for custom
+/// engines, which have no agreed version flag
+pub fn engine_version(engine: &str) -> Option<String> {
+    let spec = ENGINES.iter().find(|spec| spec.name == engine || spec.aliases.contains(&engine))?;
+    program_version(&find_in_path(spec.program)?)
+}
+
+/// Probe every known engine, built in or from `custom`: PATH lookup, version and
+/// login state
+pub fn engine_list(custom: &[CustomEngine]) -> Vec<EngineInfo> {
+    let mut engines: Vec<EngineInfo> = ENGINES
+        .iter()
+        .map(|spec| {
+            let path = find_in_path(spec.program);
+            let version = path.as_deref().and_then(program_version);
+            EngineInfo {
+                name: spec.name.to_string(),
+                aliases: spec.aliases.iter().map(|alias| alias.to_string()).collect(),
+                program: spec.program.to_string(),
+                available: path.is_some(),
+                path: path.map(|path| path.display().to_string()),
+                version,
+                authenticated: engine_authenticated(spec.name),
+                supports_resume: spec.supports_resume,
+                supports_streaming: spec.supports_streaming,
+            }
+        })
+        .collect();
+    engines.extend(custom.iter().map(|engine| {
+        let program = &engine.command[0];
+        // Custom CLIs have no agreed version flag, so don't run them
+        let path = if program.contains('/') {
+            Some(PathBuf::from(program)).filter(|path| path.is_file())
+        } else {
+            find_in_path(program)
+        };
+        EngineInfo {
+            name: engine.name.clone(),
+            aliases: Vec::new(),
+            program: program.clone(),
+            available: path.is_some(),
+            path: path.map(|path| path.display().to_string()),
+            version: None,
+            authenticated: None,
+            supports_resume: engine.supports_resume(),
+            supports_streaming: engine.parser != EngineParser::Passthrough,
+        }
+    }));
+    engines
+}
+
+// =============================================================================
+// Home Status
+// =============================================================================
+
+/// Summary of a conductor home for status displays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HomeStatus {
+    pub home: String,
+    pub db_path: String,
+    pub schema_version: i64,
+    /// Total size of everything under the home (repos, workspaces, db)
+    pub disk_bytes: u64,
+    pub repo_count: u64,
+    pub workspace_count: u64,
+    pub archived_workspace_count: u64,
+}
+
+pub fn home_status(conn: &Connection, home: &Path) -> Result<HomeStatus> {
+    let schema_version: i64 = db(conn.query_row("PRAGMA user_version", [], |row| row.get(0)))?;
+    let repo_count: u64 = db(conn.query_row("SELECT COUNT(*) FROM repos", [], |row| row.get(0)))?;
+    let (workspace_count, archived_workspace_count): (u64, u64) = db(conn.query_row(
+        "SELECT COUNT(*) FILTER (WHERE state != 'archived'), COUNT(*) FILTER (WHERE state = 'archived') FROM workspaces",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ))?;
+    Ok(HomeStatus {
+        home: home.to_string_lossy().to_string(),
+        db_path: db_path(home).to_string_lossy().to_string(),
+        schema_version,
+        disk_bytes: dir_size(home),
+        repo_count,
+        workspace_count,
+        archived_workspace_count,
+    })
+}
+
+// Best-effort recursive size; symlinks are not followed and unreadable entries count as 0
+fn dir_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries.flatten().map(|entry| dir_size(&entry.path())).sum()
+}
+
+/// What `home_migrate` did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HomeMigration {
+    pub from: String,
+    pub to: String,
+    /// The homes are on different filesystems, so it was copied rather than renamed
+    pub copied: bool,
+    /// A copied home is removed once the new one checks out; kept when it didn't
+    pub old_home_kept: bool,
+    pub repos_rewritten: usize,
+    pub workspaces_rewritten: usize,
+    /// `workspace_repair` on the new home; "broken" entries need a look
+    pub repairs: Vec<RepairAction>,
+}
+
+/// Move a conductor home to `to` (which must not exist, or be an empty directory) and
+/// point everything at it: repo and workspace paths under the old home are rewritten,
+/// git's worktree links repaired, and the result checked. The daemon must be stopped
+/// first; it holds the DB open and serves the old paths.
+pub fn home_migrate(from: &Path, to: &Path) -> Result<HomeMigration> {
+    let from = from.canonicalize().map_err(|e| anyhow!("conductor home not found: {}: {e}", from.display()))?;
+    if !db_path(&from).exists() {
+        bail!("not a conductor home (no conductor.db): {}", from.display());
+    }
+    // Absolute, without requiring `to` itself to exist yet
+    let to = match (to.parent().filter(|parent| !parent.as_os_str().is_empty()), to.file_name()) {
+        (Some(parent), Some(name)) => fs(parent.canonicalize())?.join(name),
+        (None, Some(name)) => fs(env::current_dir())?.join(name),
+        _ => bail!("invalid destination: {}", to.display()),
+    };
+    if to.starts_with(&from) {
+        bail!("cannot move the home into itself: {}", to.display());
+    }
+    if to.exists() {
+        if !to.is_dir() || fs(std::fs::read_dir(&to))?.next().is_some() {
+            bail!("destination exists and is not an empty directory: {}", to.display());
+        }
+        fs(std::fs::remove_dir(&to))?;
+    }
+    let (from_str, to_str) = (from.to_string_lossy().to_string(), to.to_string_lossy().to_string());
+
+    // Fold the WAL into the DB so the file is complete on its own
+    {
+        let conn = open(&from)?;
+        db(conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)"))?;
+    }
+    let copied = match std::fs::rename(&from, &to) {
+        Ok(()) => false,
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(err) = copy_tree(&from, &to) {
+                let _ = std::fs::remove_dir_all(&to);
+                return Err(err.context(format!("could not copy {from_str} to {to_str}")));
+            }
+            true
+        }
+        Err(err) => return Err(UserError::Filesystem(format!("could not move {from_str} to {to_str}: {err}")).into()),
+    };
+
+    let conn = connect(&to)?;
+    let integrity: String = db(conn.query_row("PRAGMA quick_check", [], |row| row.get(0)))?;
+    if integrity != "ok" {
+        bail!("the moved database failed its integrity check: {integrity}");
+    }
+    // Paths equal to the old home or below it; LIKE would treat `_` and `%` in them as wildcards
+    let tx = db(conn.unchecked_transaction())?;
+    let rewrite = |table: &str, column: &str| {
+        tx.execute(
+            &format!(
+                "UPDATE {table} SET {column} = ?2 || substr({column}, length(?1) + 1), updated_at = datetime('now')
+                 WHERE {column} = ?1 OR substr({column}, 1, length(?1) + 1) = ?1 || '/'"
+            ),
+            params![from_str, to_str],
+        )
+    };
+    let repos_rewritten = db(rewrite("repos", "root_path"))?;
+    let workspaces_rewritten = db(rewrite("workspaces", "path"))?;
+    db(tx.commit())?;
+
+    let mut config = config_load(&to)?;
+    if let Some(socket) = config.socket_path.as_ref().and_then(|socket| socket.strip_prefix(&from).ok()) {
+        config.socket_path = Some(to.join(socket));
+        config_save(&to, &config)?;
+    }
+
+    // A worktree's `.git` file still naming a repo under the old home would keep using it
+    // (and `git worktree repair` follows it there), so point those at the new one first
+    for ws in workspace_list(&conn, None)? {
+        let dot_git = Path::new(&ws.path).join(".git");
+        let Ok(content) = std::fs::read_to_string(&dot_git) else {
+            continue;
+        };
+        let gitdir = content.trim().strip_prefix("gitdir: ").map(Path::new);
+        if let Some(rest) = gitdir.and_then(|gitdir| gitdir.strip_prefix(&from).ok()) {
+            fs(std::fs::write(&dot_git, format!("gitdir: {}\n", to.join(rest).display())))?;
+        }
+    }
+
+    let repairs = workspace_repair(&conn, &to)?;
+    let healthy = repairs.iter().all(|repair| repair.action != "broken");
+    if copied && healthy {
+        fs(std::fs::remove_dir_all(&from))?;
+    }
+    Ok(HomeMigration {
+        from: from_str,
+        to: to_str,
+        copied,
+        old_home_kept: copied && !healthy,
+        repos_rewritten,
+        workspaces_rewritten,
+        repairs,
     })
 }
+
+// Recursive copy for moves across filesystems; symlinks are recreated, not followed
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    fs(std::fs::create_dir(to))?;
+    fs(std::fs::set_permissions(to, fs(std::fs::metadata(from))?.permissions()))?;
+    for entry in fs(std::fs::read_dir(from))? {
+        let entry = fs(entry)?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        let kind = fs(entry.file_type())?;
+        if kind.is_dir() {
+            copy_tree(&source, &target)?;
+        } else if kind.is_symlink() {
+            let link = fs(std::fs::read_link(&source))?;
+            #[cfg(unix)]
+            fs(std::os::unix::fs::symlink(&link, &target))?;
+            #[cfg(not(unix))]
+            bail!("cannot copy symlink {} -> {}", source.display(), link.display());
+        } else {
+            fs(std::fs::copy(&source, &target))?;
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Search
+// =============================================================================
+
+/// What a `search` hit points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Repo,
+    Workspace,
+    File,
+    Chat,
+}
+
+impl SearchHitKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchHitKind::Repo => "repo",
+            SearchHitKind::Workspace => "workspace",
+            SearchHitKind::File => "file",
+            SearchHitKind::Chat => "chat",
+        }
+    }
+}
+
+impl std::fmt::Display for SearchHitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SearchHitKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "repo" => Ok(SearchHitKind::Repo),
+            "workspace" => Ok(SearchHitKind::Workspace),
+            "file" => Ok(SearchHitKind::File),
+            "chat" => Ok(SearchHitKind::Chat),
+            _ => bail!("unknown search hit kind: {value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    /// Higher is better: exact over prefix over word over substring matches, names over
+    /// file paths over chat
+    pub score: u32,
+    pub repo_id: String,
+    pub repo: String,
+    /// Unset for repo hits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Repo or workspace name, file path relative to the workspace, or the chat entry's
+    /// "role (timestamp)"
+    pub label: String,
+    /// The matching text around the query: root path, branch or title, chat excerpt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+/// Repos, workspaces (name, branch, title), the files of live workspaces and their chat
+/// history matching `query` (case-insensitive), best first, at most `limit`
+pub fn search(conn: &Connection, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+    let query = query.trim().to_ascii_lowercase();
+    if query.is_empty() {
+        bail!("search query is required");
+    }
+    let mut hits = Vec::new();
+    let repos = repo_list(conn)?;
+    for repo in &repos {
+        if let Some(score) = match_score(&repo.name, &query) {
+            hits.push(SearchHit {
+                kind: SearchHitKind::Repo,
+                score: score + 3,
+                repo_id: repo.id.clone(),
+                repo: repo.name.clone(),
+                workspace_id: None,
+                workspace: None,
+                label: repo.name.clone(),
+                snippet: Some(repo.root_path.clone()),
+            });
+        }
+    }
+
+    for ws in workspace_list(conn, None)? {
+        let workspace_hit = |score: u32, snippet: Option<String>| SearchHit {
+            kind: SearchHitKind::Workspace,
+            score,
+            repo_id: ws.repo_id.clone(),
+            repo: ws.repo.clone(),
+            workspace_id: Some(ws.id.clone()),
+            workspace: Some(ws.name.clone()),
+            label: ws.name.clone(),
+            snippet,
+        };
+        let candidates = [Some(&ws.name), Some(&ws.branch), ws.title.as_ref()];
+        if let Some((score, text)) = candidates
+            .into_iter()
+            .flatten()
+            .filter_map(|text| Some((match_score(text, &query)?, text)))
+            .max_by_key(|(score, _)| *score)
+        {
+            // Archived ones are still worth finding, just after the live ones
+            let bonus = if ws.state.is_live() { 2 } else { 0 };
+            let snippet = if *text == ws.name { ws.title.clone().or(Some(ws.branch.clone())) } else { Some(text.clone()) };
+            hits.push(workspace_hit(score + bonus, snippet));
+        }
+        if !ws.state.is_live() || ws.link_error.is_some() {
+            continue;
+        }
+
+        let files = workspace_files(conn, &ws.id, &FileQuery::default()).map(|page| page.files).unwrap_or_default();
+        for file in files {
+            let name = file.rsplit('/').next().unwrap_or(&file);
+            // A match in the directories alone ranks below any match in the file name
+            let score = match match_score(name, &query) {
+                Some(score) => score + 1,
+                None if file.to_ascii_lowercase().contains(&query) => 10,
+                None => continue,
+            };
+            hits.push(SearchHit {
+                kind: SearchHitKind::File,
+                score,
+                label: file,
+                snippet: None,
+                ..workspace_hit(0, None)
+            });
+        }
+
+        let chat = chat_read(Path::new(&ws.path)).unwrap_or_default();
+        for entry in parse_chat(&chat).into_iter().rev() {
+            let Some(snippet) = entry.content.lines().find_map(|line| excerpt(line, &query)) else {
+                continue;
+            };
+            hits.push(SearchHit {
+                kind: SearchHitKind::Chat,
+                score: 5,
+                label: format!("{} ({})", entry.role, entry.timestamp),
+                snippet: Some(snippet),
+                ..workspace_hit(0, None)
+            });
+        }
+    }
+
+    // Stable, so chat hits stay newest first within a workspace
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.kind.cmp(&b.kind)));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+// How well `text` matches the (lowercased) query: 100 exact, 75 prefix, 50 at a word
+// start, 25 anywhere else
+fn match_score(text: &str, query: &str) -> Option<u32> {
+    let text = text.to_ascii_lowercase();
+    if text == query {
+        return Some(100);
+    }
+    if text.starts_with(query) {
+        return Some(75);
+    }
+    let mut score = None;
+    for (at, _) in text.match_indices(query) {
+        let word_start = text[..at].chars().next_back().is_some_and(|c| !c.is_alphanumeric());
+        if word_start {
+            return Some(50);
+        }
+        score = Some(25);
+    }
+    score
+}
+
+// Up to ~120 bytes of `line` around the first match of the (lowercased) query. ASCII
+// lowercasing keeps byte offsets, so they index the original line.
+fn excerpt(line: &str, query: &str) -> Option<String> {
+    let at = line.to_ascii_lowercase().find(query)?;
+    let mut start = at.saturating_sub(40);
+    while !line.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (at + query.len() + 80).min(line.len());
+    while !line.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut snippet = line[start..end].trim().to_string();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < line.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_chains_resolve() {
+        let mut config = Config::default();
+        config.engine_chains.insert("fast".to_string(), vec!["codex".to_string(), "claude".to_string()]);
+        config.engine_chains.insert("empty".to_string(), Vec::new());
+        assert_eq!(engine_chain(&config, "fast"), ["codex", "claude"]);
+        assert_eq!(engine_chain(&config, "claude, codex,,claude"), ["claude", "codex"]);
+        assert_eq!(engine_chain(&config, "claude"), ["claude"]);
+        assert_eq!(engine_chain(&config, "empty"), ["empty"]);
+    }
+
+    #[test]
+    fn diffstat_counts_numstat_lines() {
+        let mut stat = DiffStat::default();
+        stat.add_numstat("3\t1\tsrc/main.rs");
+        stat.add_numstat("-\t-\tlogo.png");
+        assert_eq!(stat.to_string(), "2 files changed, +3 -1");
+    }
+}
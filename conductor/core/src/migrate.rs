@@ -0,0 +1,590 @@
+//! Versioned schema migrations. Each step moves `PRAGMA user_version` one version up or
+//! down; `connect` runs every pending up step, and `conductor db migrate --to` can roll
+//! a home back for an older conductor. Up steps are idempotent so homes created by the
+//! old single-shot setup (tables at their latest shape, any version) migrate cleanly.
+
+use crate::{db, SCHEMA_VERSION};
+use anyhow::{bail, Result};
+use rusqlite::{Connection, TransactionBehavior};
+use serde::Serialize;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    /// Takes the schema from `version - 1` to `version`
+    pub up: &'static str,
+    /// Takes the schema from `version` back to `version - 1`
+    pub down: &'static str,
+}
+
+/// Every step, in version order; the last one is `SCHEMA_VERSION`
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "repos and workspaces",
+        up: "
+            CREATE TABLE IF NOT EXISTS repos (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                root_path TEXT NOT NULL,
+                default_branch TEXT NOT NULL,
+                remote_url TEXT,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS workspaces (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                directory_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base_branch TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'ready',
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspaces;
+            DROP TABLE IF EXISTS repos;
+        ",
+    },
+    Migration {
+        version: 2,
+        name: "unique repo names and roots",
+        up: "
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_name ON repos(name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_repos_root_path ON repos(root_path);
+        ",
+        down: "
+            DROP INDEX IF EXISTS idx_repos_name;
+            DROP INDEX IF EXISTS idx_repos_root_path;
+        ",
+    },
+    Migration {
+        version: 3,
+        name: "workspace state check",
+        up: "
+            DROP TABLE IF EXISTS workspaces_new;
+            CREATE TABLE workspaces_new (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                directory_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base_branch TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'ready' CHECK(state IN ('ready', 'archived', 'error')),
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+
+            INSERT INTO workspaces_new (id, repository_id, directory_name, path, branch, base_branch, state, created_at, updated_at)
+            SELECT
+                id,
+                repository_id,
+                directory_name,
+                path,
+                branch,
+                base_branch,
+                CASE
+                    WHEN state IN ('ready', 'archived', 'error') THEN state
+                    ELSE 'error'
+                END,
+                created_at,
+                updated_at
+            FROM workspaces;
+
+            DROP TABLE workspaces;
+            ALTER TABLE workspaces_new RENAME TO workspaces;
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspaces_old;
+            CREATE TABLE workspaces_old (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                directory_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base_branch TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'ready',
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+
+            INSERT INTO workspaces_old SELECT * FROM workspaces;
+            DROP TABLE workspaces;
+            ALTER TABLE workspaces_old RENAME TO workspaces;
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+        ",
+    },
+    Migration {
+        version: 4,
+        name: "audit events",
+        up: "
+            CREATE TABLE IF NOT EXISTS audit_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at TEXT NOT NULL,
+                source TEXT NOT NULL,
+                action TEXT NOT NULL,
+                target TEXT,
+                ok INTEGER NOT NULL,
+                metadata TEXT NOT NULL DEFAULT '{}'
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_events_created_at ON audit_events(created_at);
+            CREATE INDEX IF NOT EXISTS idx_audit_events_action ON audit_events(action);
+
+            CREATE TRIGGER IF NOT EXISTS audit_events_no_update BEFORE UPDATE ON audit_events
+            BEGIN SELECT RAISE(ABORT, 'audit_events is append-only'); END;
+            CREATE TRIGGER IF NOT EXISTS audit_events_no_delete BEFORE DELETE ON audit_events
+            BEGIN SELECT RAISE(ABORT, 'audit_events is append-only'); END;
+        ",
+        // Loses the audit log; the triggers would refuse a row-by-row delete anyway
+        down: "
+            DROP TRIGGER IF EXISTS audit_events_no_update;
+            DROP TRIGGER IF EXISTS audit_events_no_delete;
+            DROP TABLE IF EXISTS audit_events;
+        ",
+    },
+    Migration {
+        version: 5,
+        name: "pull request cache",
+        up: "
+            CREATE TABLE IF NOT EXISTS workspace_prs (
+                workspace_id TEXT PRIMARY KEY,
+                branch TEXT NOT NULL,
+                state TEXT NOT NULL,
+                number INTEGER,
+                url TEXT,
+                review_decision TEXT,
+                checks TEXT NOT NULL DEFAULT '{}',
+                fetched_at TEXT NOT NULL,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspace_prs;
+        ",
+    },
+    Migration {
+        version: 6,
+        name: "repo remotes",
+        up: "
+            CREATE TABLE IF NOT EXISTS repo_remotes (
+                repository_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                fetch_url TEXT NOT NULL,
+                push_url TEXT,
+                preferred INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY(repository_id, name),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS repo_remotes;
+        ",
+    },
+    Migration {
+        version: 7,
+        name: "workspace activity",
+        up: "
+            CREATE TABLE IF NOT EXISTS workspace_activity (
+                workspace_id TEXT PRIMARY KEY,
+                last_agent_run_at TEXT,
+                last_commit_at TEXT,
+                last_opened_at TEXT,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspace_activity;
+        ",
+    },
+    Migration {
+        version: 8,
+        name: "workspace titles",
+        up: "
+            CREATE TABLE IF NOT EXISTS workspace_titles (
+                workspace_id TEXT PRIMARY KEY,
+                title TEXT,
+                summary TEXT,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspace_titles;
+        ",
+    },
+    Migration {
+        version: 9,
+        name: "workspace verifications",
+        up: "
+            CREATE TABLE IF NOT EXISTS workspace_verifications (
+                workspace_id TEXT PRIMARY KEY,
+                ok INTEGER NOT NULL,
+                failed_command TEXT,
+                session_id TEXT,
+                finished_at TEXT NOT NULL,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspace_verifications;
+        ",
+    },
+    Migration {
+        version: 10,
+        name: "review comments",
+        up: "
+            CREATE TABLE IF NOT EXISTS review_comments (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL,
+                file_path TEXT NOT NULL,
+                start_line INTEGER NOT NULL,
+                end_line INTEGER NOT NULL,
+                author TEXT NOT NULL,
+                body TEXT NOT NULL,
+                resolved INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_review_comments_workspace ON review_comments(workspace_id);
+        ",
+        down: "
+            DROP INDEX IF EXISTS idx_review_comments_workspace;
+            DROP TABLE IF EXISTS review_comments;
+        ",
+    },
+    Migration {
+        version: 11,
+        name: "tasks",
+        up: "
+            CREATE TABLE IF NOT EXISTS tasks (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL DEFAULT '',
+                status TEXT NOT NULL DEFAULT 'open',
+                chosen_workspace_id TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS task_workspaces (
+                task_id TEXT NOT NULL,
+                workspace_id TEXT NOT NULL,
+                linked_at TEXT NOT NULL,
+                PRIMARY KEY(task_id, workspace_id),
+                FOREIGN KEY(task_id) REFERENCES tasks(id),
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_task_workspaces_workspace ON task_workspaces(workspace_id);
+            CREATE TABLE IF NOT EXISTS task_runs (
+                task_id TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                workspace_id TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                PRIMARY KEY(task_id, session_id),
+                FOREIGN KEY(task_id) REFERENCES tasks(id),
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS task_runs;
+            DROP INDEX IF EXISTS idx_task_workspaces_workspace;
+            DROP TABLE IF EXISTS task_workspaces;
+            DROP TABLE IF EXISTS tasks;
+        ",
+    },
+    Migration {
+        version: 12,
+        name: "workspace lifecycle states",
+        up: "
+            DROP TABLE IF EXISTS workspaces_new;
+            CREATE TABLE workspaces_new (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                directory_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base_branch TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'ready'
+                    CHECK(state IN ('ready', 'in_review', 'merged', 'abandoned', 'archived', 'error')),
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+
+            INSERT INTO workspaces_new SELECT * FROM workspaces;
+            DROP TABLE workspaces;
+            ALTER TABLE workspaces_new RENAME TO workspaces;
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspaces_old;
+            CREATE TABLE workspaces_old (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                directory_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base_branch TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'ready' CHECK(state IN ('ready', 'archived', 'error')),
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(repository_id) REFERENCES repos(id)
+            );
+
+            -- The pipeline states all still have a checkout
+            INSERT INTO workspaces_old (id, repository_id, directory_name, path, branch, base_branch, state, created_at, updated_at)
+            SELECT
+                id,
+                repository_id,
+                directory_name,
+                path,
+                branch,
+                base_branch,
+                CASE
+                    WHEN state IN ('ready', 'archived', 'error') THEN state
+                    ELSE 'ready'
+                END,
+                created_at,
+                updated_at
+            FROM workspaces;
+
+            DROP TABLE workspaces;
+            ALTER TABLE workspaces_old RENAME TO workspaces;
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_dir ON workspaces(repository_id, directory_name);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_repo_branch ON workspaces(repository_id, branch);
+        ",
+    },
+    Migration {
+        version: 13,
+        name: "pinned workspaces and repo order",
+        up: "
+            ALTER TABLE workspaces ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+            -- NULL: not placed, listed after the placed repos
+            ALTER TABLE repos ADD COLUMN sort_order INTEGER;
+        ",
+        down: "
+            ALTER TABLE repos DROP COLUMN sort_order;
+            ALTER TABLE workspaces DROP COLUMN pinned;
+        ",
+    },
+    Migration {
+        version: 14,
+        name: "agent run counts and token usage",
+        up: "
+            ALTER TABLE workspace_activity ADD COLUMN agent_runs INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE workspace_activity ADD COLUMN input_tokens INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE workspace_activity ADD COLUMN output_tokens INTEGER NOT NULL DEFAULT 0;
+            -- Runs weren't counted before; a workspace that had one had at least one
+            UPDATE workspace_activity SET agent_runs = 1 WHERE last_agent_run_at IS NOT NULL;
+        ",
+        down: "
+            ALTER TABLE workspace_activity DROP COLUMN output_tokens;
+            ALTER TABLE workspace_activity DROP COLUMN input_tokens;
+            ALTER TABLE workspace_activity DROP COLUMN agent_runs;
+        ",
+    },
+    Migration {
+        version: 15,
+        name: "workspace error messages",
+        up: "
+            -- Why a workspace is in the error state; cleared when it leaves it
+            ALTER TABLE workspaces ADD COLUMN error TEXT;
+        ",
+        down: "
+            ALTER TABLE workspaces DROP COLUMN error;
+        ",
+    },
+    Migration {
+        version: 16,
+        name: "workspace issues",
+        up: "
+            -- The tracker issue a workspace was created from (`issues::workspace_issue_set`)
+            CREATE TABLE IF NOT EXISTS workspace_issues (
+                workspace_id TEXT PRIMARY KEY,
+                tracker TEXT NOT NULL,
+                issue_key TEXT NOT NULL,
+                title TEXT NOT NULL,
+                body TEXT NOT NULL DEFAULT '',
+                url TEXT,
+                linked_at TEXT NOT NULL,
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+            );
+        ",
+        down: "
+            DROP TABLE IF EXISTS workspace_issues;
+        ",
+    },
+    Migration {
+        version: 17,
+        name: "github review comments",
+        up: "
+            -- Set on comments fetched from the workspace's PR, so syncing again updates them
+            ALTER TABLE review_comments ADD COLUMN github_id TEXT;
+            ALTER TABLE review_comments ADD COLUMN url TEXT;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_review_comments_github ON review_comments(workspace_id, github_id);
+        ",
+        down: "
+            DROP INDEX IF EXISTS idx_review_comments_github;
+            ALTER TABLE review_comments DROP COLUMN url;
+            ALTER TABLE review_comments DROP COLUMN github_id;
+        ",
+    },
+];
+
+/// One step of a plan, with the SQL it runs
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    /// "up" or "down"
+    pub direction: &'static str,
+    /// The version the schema is at after this step
+    pub to_version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    db(conn.query_row("PRAGMA user_version", [], |row| row.get(0)))
+}
+
+/// The steps from `from` to `to`, in the order they run
+pub fn plan(from: i64, to: i64) -> Result<Vec<Step>> {
+    for version in [from, to] {
+        if !(0..=SCHEMA_VERSION).contains(&version) {
+            bail!("unsupported DB schema version: {version} (this conductor knows 0 to {SCHEMA_VERSION})");
+        }
+    }
+    let steps = if to >= from {
+        MIGRATIONS
+            .iter()
+            .filter(|m| m.version > from && m.version <= to)
+            .map(|m| Step {
+                direction: "up",
+                to_version: m.version,
+                name: m.name,
+                sql: m.up,
+            })
+            .collect()
+    } else {
+        MIGRATIONS
+            .iter()
+            .rev()
+            .filter(|m| m.version <= from && m.version > to)
+            .map(|m| Step {
+                direction: "down",
+                to_version: m.version - 1,
+                name: m.name,
+                sql: m.down,
+            })
+            .collect()
+    };
+    Ok(steps)
+}
+
+/// What `migrate_to(conn, to)` would run, without running it
+pub fn pending(conn: &Connection, to: i64) -> Result<Vec<Step>> {
+    plan(current_version(conn)?, to)
+}
+
+/// Run every step to `to` in one transaction; returns the steps run
+pub fn migrate_to(conn: &mut Connection, to: i64) -> Result<Vec<Step>> {
+    // Steps that rebuild a table (SQLite can't change a CHECK in place) drop it while
+    // other tables still reference it, so foreign keys are enforced once, at the end.
+    // The pragma is a no-op inside a transaction, hence out here
+    let foreign_keys: bool = db(conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0)))?;
+    db(conn.execute_batch("PRAGMA foreign_keys = OFF"))?;
+    let result = run_steps(conn, to);
+    if foreign_keys {
+        db(conn.execute_batch("PRAGMA foreign_keys = ON"))?;
+    }
+    result
+}
+
+fn run_steps(conn: &mut Connection, to: i64) -> Result<Vec<Step>> {
+    let tx = db(conn.transaction_with_behavior(TransactionBehavior::Immediate))?;
+    // Re-read under the write lock: another process may have just migrated
+    let steps = plan(current_version(&tx)?, to)?;
+    for step in &steps {
+        db(tx.execute_batch(step.sql))?;
+    }
+    let violations: i64 = db(tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0)))?;
+    if violations > 0 {
+        bail!("migrating to schema {to} would leave {violations} broken foreign key reference(s)");
+    }
+    if !steps.is_empty() {
+        db(tx.execute_batch(&format!("PRAGMA user_version = {to}")))?;
+    }
+    db(tx.commit())?;
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every table, index and trigger with its columns. Column changes are compared by
+    // table_info since ALTER TABLE rewrites the stored SQL text
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT m.type || ' ' || m.name || ' on ' || m.tbl_name || ': ' || \
+                 COALESCE((SELECT group_concat(c.name || ' ' || c.type || ' ' || c.\"notnull\" || ' ' || \
+                 COALESCE(c.dflt_value, '') || ' ' || c.pk, ', ') FROM pragma_table_info(m.name) c), '') \
+                 FROM sqlite_master m WHERE m.name NOT LIKE 'sqlite_%' ORDER BY m.type, m.name",
+            )
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn versions_are_contiguous() {
+        let versions: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(versions, (1..=SCHEMA_VERSION).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn every_step_round_trips() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, SCHEMA_VERSION).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), SCHEMA_VERSION);
+        for version in (1..=SCHEMA_VERSION).rev() {
+            let before = schema(&conn);
+            migrate_to(&mut conn, version - 1).unwrap();
+            assert_eq!(current_version(&conn).unwrap(), version - 1);
+            migrate_to(&mut conn, version).unwrap();
+            assert_eq!(schema(&conn), before, "migration {version} down then up");
+            migrate_to(&mut conn, version - 1).unwrap();
+        }
+        assert!(schema(&conn).is_empty(), "{:?}", schema(&conn));
+    }
+
+    #[test]
+    fn plans_run_in_order() {
+        let up: Vec<i64> = plan(0, 3).unwrap().iter().map(|step| step.to_version).collect();
+        assert_eq!(up, [1, 2, 3]);
+        let down: Vec<i64> = plan(3, 1).unwrap().iter().map(|step| step.to_version).collect();
+        assert_eq!(down, [2, 1]);
+        assert!(plan(2, 2).unwrap().is_empty());
+        assert!(plan(0, SCHEMA_VERSION + 1).is_err());
+        assert!(plan(-1, 0).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Webhooks the daemon calls when an agent run finishes (Slack, Discord, ntfy, anything
+//! that accepts a POST). Delivery lives in the daemon; this module owns the config and
+//! the request body.
+
+use crate::DiffStat;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Longest answer excerpt included in a notification
+pub const ANSWER_SUMMARY_CHARS: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Request body with `{{placeholder}}` substitution, e.g.
+    /// `{"text": "{{workspace}}: {{engine}} {{status}} ({{diffstat}})\n{{answer}}"}`.
+    /// Values are JSON-string escaped so a JSON template stays valid. Without a
+    /// template the body is the full event as JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Which outcomes fire this hook: "completed", "failed" (default both)
+    #[serde(default = "default_events")]
+    pub events: Vec<String>,
+    /// Extra request headers, e.g. an Authorization token
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_events() -> Vec<String> {
+    vec!["completed".to_string(), "failed".to_string()]
+}
+
+impl WebhookConfig {
+    pub fn wants(&self, status: &str) -> bool {
+        self.events.iter().any(|event| event == status)
+    }
+}
+
+/// What happened in one agent run, as sent to webhooks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentRunEvent {
+    /// "completed" or "failed"
+    pub status: String,
+    pub session_id: String,
+    pub engine: String,
+    pub cwd: String,
+    pub workspace_id: Option<String>,
+    pub workspace: Option<String>,
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_secs: u64,
+    /// The agent's final answer, cut to `ANSWER_SUMMARY_CHARS`
+    pub answer: Option<String>,
+    pub diffstat: Option<DiffStat>,
+}
+
+/// Cut an answer down for a notification, on a char boundary
+pub fn summarize_answer(answer: &str) -> String {
+    let answer = answer.trim();
+    match answer.char_indices().nth(ANSWER_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", answer[..end].trim_end()),
+        None => answer.to_string(),
+    }
+}
+
+/// Request body for one hook
+pub fn webhook_body(hook: &WebhookConfig, event: &AgentRunEvent) -> Result<String> {
+    let Some(template) = &hook.template else {
+        return Ok(serde_json::to_string(event)?);
+    };
+    let fields = serde_json::to_value(event)?;
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find("}}") else {
+            bail!("webhook template for {}: unclosed '{{{{'", hook.url);
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let value = match (name, fields.get(name)) {
+            ("diffstat", _) => event.diffstat.as_ref().map(ToString::to_string).unwrap_or_default(),
+            (_, Some(Value::String(s))) => s.clone(),
+            (_, Some(Value::Null)) => String::new(),
+            (_, Some(other)) => other.to_string(),
+            (_, None) => bail!("webhook template for {}: unknown placeholder {{{{{name}}}}}", hook.url),
+        };
+        // Escape as a JSON string body (without the quotes)
+        let escaped = Value::String(value).to_string();
+        body.push_str(&escaped[1..escaped.len() - 1]);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    body.push_str(rest);
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(template: Option<&str>) -> WebhookConfig {
+        WebhookConfig {
+            url: "https://hooks.example.com/x".to_string(),
+            template: template.map(str::to_string),
+            events: default_events(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    fn event() -> AgentRunEvent {
+        AgentRunEvent {
+            status: "completed".to_string(),
+            engine: "claude".to_string(),
+            workspace: Some("victoria".to_string()),
+            exit_code: Some(0),
+            answer: Some("Fixed \"flaky\" test\nin auth\\login".to_string()),
+            diffstat: Some(DiffStat { files: 1, insertions: 3, deletions: 2 }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn template_values_are_json_escaped() {
+        let body = webhook_body(&hook(Some(r#"{"text": "{{workspace}}: {{answer}}"}"#)), &event()).unwrap();
+        let parsed: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed["text"], "victoria: Fixed \"flaky\" test\nin auth\\login");
+    }
+
+    #[test]
+    fn template_fills_every_kind_of_field() {
+        let template = "{{ engine }} {{status}} exit={{exit_code}} repo=[{{repo}}] ({{diffstat}})";
+        let body = webhook_body(&hook(Some(template)), &event()).unwrap();
+        assert_eq!(body, "claude completed exit=0 repo=[] (1 file changed, +3 -2)");
+    }
+
+    #[test]
+    fn template_errors_name_the_problem() {
+        let unknown = webhook_body(&hook(Some("{{nope}}")), &event()).unwrap_err().to_string();
+        assert!(unknown.contains("unknown placeholder {{nope}}"), "{unknown}");
+        let unclosed = webhook_body(&hook(Some("{{engine")), &event()).unwrap_err().to_string();
+        assert!(unclosed.contains("unclosed"), "{unclosed}");
+    }
+
+    #[test]
+    fn no_template_sends_the_whole_event() {
+        let body = webhook_body(&hook(None), &event()).unwrap();
+        let parsed: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed["engine"], "claude");
+        assert_eq!(parsed["diffstat"]["insertions"], 3);
+    }
+
+    #[test]
+    fn answers_are_cut_on_char_boundaries() {
+        let long = "é".repeat(ANSWER_SUMMARY_CHARS + 10);
+        let summary = summarize_answer(&long);
+        assert_eq!(summary.chars().count(), ANSWER_SUMMARY_CHARS + 1);
+        assert!(summary.ends_with('…'));
+        assert_eq!(summarize_answer("  short  "), "short");
+    }
+}
@@ -1,7 +1,13 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
     tonic_build::configure()
+        // Served by the reflection service so grpcurl/grpcui work without the .proto
+        .file_descriptor_set_path(out_dir.join("conductor_descriptor.bin"))
         .build_server(true)
         .build_client(true) // Also build client for desktop crate to use
+        // The REST gateway speaks the same messages as JSON
+        .type_attribute(".conductor", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(".conductor", "#[serde(default)]")
         .compile_protos(&["proto/conductor.proto"], &["proto/"])?;
     Ok(())
 }
@@ -0,0 +1,74 @@
+//! Bearer-token authentication for daemon RPCs, and the peer check on the local socket
+
+use crate::homes::HOME_METADATA;
+use crate::proto::conductor_client::ConductorClient;
+use std::path::Path;
+use tonic::metadata::{Ascii, Binary, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+
+/// Daemon client that sends the bearer token with every request
+pub type DaemonClient = ConductorClient<InterceptedService<Channel, BearerToken>>;
+
+/// Wrap a connected channel in a client that authenticates with `token`
+pub fn client(channel: Channel, token: &str) -> anyhow::Result<DaemonClient> {
+    Ok(ConductorClient::with_interceptor(channel, BearerToken::new(token)?))
+}
+
+/// Like `client`, but also names `home` so a daemon serving several homes picks this one
+pub fn client_for_home(channel: Channel, token: &str, home: &Path) -> anyhow::Result<DaemonClient> {
+    Ok(ConductorClient::with_interceptor(channel, BearerToken::new(token)?.for_home(home)))
+}
+
+/// Client-side interceptor: attaches `authorization: Bearer <token>`, and the home
+/// when one is set
+#[derive(Clone)]
+pub struct BearerToken {
+    token: MetadataValue<Ascii>,
+    home: Option<MetadataValue<Binary>>,
+}
+
+impl BearerToken {
+    pub fn new(token: &str) -> anyhow::Result<Self> {
+        Ok(Self { token: format!("Bearer {token}").parse()?, home: None })
+    }
+
+    pub fn for_home(self, home: &Path) -> Self {
+        let home = home.canonicalize().unwrap_or_else(|_| home.to_path_buf());
+        let home = MetadataValue::from_bytes(home.to_string_lossy().as_bytes());
+        Self { home: Some(home), ..self }
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let metadata = request.metadata_mut();
+        metadata.insert("authorization", self.token.clone());
+        if let Some(home) = &self.home {
+            metadata.insert_bin(HOME_METADATA, home.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Refuse a local socket connection unless the process at the other end runs as the
+/// daemon's user (SO_PEERCRED on Linux, LOCAL_PEERCRED on macOS). The socket's 0600
+/// mode already keeps others out; this still holds if the mode is loosened or a
+/// connected descriptor is passed on. Err says who was refused
+pub fn check_peer(stream: &tokio::net::UnixStream) -> Result<(), String> {
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    let cred = stream.peer_cred().map_err(|e| format!("cannot read peer credentials: {e}"))?;
+    if cred.uid() == uid {
+        return Ok(());
+    }
+    let pid = cred.pid().map_or_else(|| "unknown".to_string(), |pid| pid.to_string());
+    Err(format!("peer uid {} (pid {pid}) is not the daemon's uid {uid}", cred.uid()))
+}
+
+/// Compare secrets without leaking where they differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
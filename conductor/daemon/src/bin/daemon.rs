@@ -1,306 +1,2502 @@
+use clap::Parser;
 use conductor_agent::AgentParser;
 use conductor_core::{self as core};
 use conductor_daemon::proto::conductor_server::{Conductor, ConductorServer};
+use conductor_daemon::{auth, compat};
+use conductor_daemon::db::DbPool;
+use conductor_daemon::homes::{self, HomeContext, HomeScoped, Homes};
+use conductor_daemon::instance::{self, InstanceLock};
 use conductor_daemon::proto::*;
-use conductor_daemon::SOCKET_PATH;
+use conductor_daemon::transport::{self, Listen};
+use conductor_daemon::watch::WorkspaceWatcher;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::{broadcast, Mutex};
-use tokio_stream::Stream;
+use tokio_stream::{Stream, StreamExt};
 use tonic::{Request, Response, Status};
-use tracing::{info, warn};
+use tracing::{info, warn, Instrument};
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Events kept per session so late attachers can replay what they missed
+const AGENT_HISTORY_MAX: usize = 5000;
+
+// GetWorkspaceSummary's last chat message is cut to this many characters
+const SUMMARY_EXCERPT_CHARS: usize = 500;
+
+// A post-run `verify` command still going after this long is killed and fails
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+// A run on a fallback chain hands over to the next engine when it fails this soon
+// without having acted (auth errors, usage limits, a provider that's down)
+const FALLBACK_WINDOW: Duration = Duration::from_secs(60);
+
+// StreamFileContent defaults and chunk size bounds
+const STREAM_MAX_BYTES: u64 = 16 * 1024 * 1024;
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+const STREAM_CHUNK_RANGE: (usize, usize) = (8 * 1024, 4 * 1024 * 1024);
+
+// Broadcast channel plus replayable, sequence-numbered history for one agent session
+#[derive(Clone)]
+struct AgentEvents {
+    session_id: String,
+    sender: broadcast::Sender<AgentEvent>,
+    history: Arc<std::sync::Mutex<VecDeque<AgentEvent>>>,
+}
+
+impl AgentEvents {
+    fn new(session_id: String) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            session_id,
+            sender,
+            history: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+        }
+    }
+
+    // Record and broadcast under the history lock so subscribe() never sees a gap or duplicate
+    fn emit(&self, event_type: &str, payload: String) {
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let event = AgentEvent {
+            session_id: self.session_id.clone(),
+            event_type: event_type.to_string(),
+            payload,
+            seq: history.back().map_or(1, |e| e.seq + 1),
+        };
+        if history.len() == AGENT_HISTORY_MAX {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+        let _ = self.sender.send(event);
+    }
+
+    // Live receiver, plus the events already emitted when `replay` is set
+    fn subscribe(&self, replay: bool) -> (Vec<AgentEvent>, broadcast::Receiver<AgentEvent>) {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let backlog = if replay {
+            history.iter().cloned().collect()
+        } else {
+            Vec::new()
+        };
+        (backlog, self.sender.subscribe())
+    }
+}
+
+fn agent_event_stream(
+    backlog: Vec<AgentEvent>,
+    mut rx: broadcast::Receiver<AgentEvent>,
+) -> Pin<Box<dyn Stream<Item = Result<AgentEvent, Status>> + Send>> {
+    Box::pin(async_stream::stream! {
+        for event in backlog {
+            yield Ok(event);
+        }
+        while let Ok(event) = rx.recv().await {
+            yield Ok(event);
+        }
+    })
+}
+
+// The longest-running of `agents` in the workspace at `ws_path`
+fn workspace_lock_in(agents: &HashMap<String, ActiveAgentHandle>, ws_path: &str) -> Option<WorkspaceLock> {
+    let ws_path = Path::new(ws_path).canonicalize().unwrap_or_else(|_| PathBuf::from(ws_path));
+    agents
+        .iter()
+        .filter(|(_, agent)| {
+            let cwd = Path::new(&agent.cwd);
+            cwd.canonicalize().unwrap_or_else(|_| cwd.to_path_buf()).starts_with(&ws_path)
+        })
+        .max_by_key(|(_, agent)| agent.started_at.elapsed())
+        .map(|(session_id, agent)| WorkspaceLock {
+            session_id: session_id.clone(),
+            engine: agent.engine.clone(),
+            cwd: agent.cwd.clone(),
+            held_secs: agent.started_at.elapsed().as_secs(),
+        })
+}
+
+fn lock_refusal(lock: &WorkspaceLock, action: &str) -> Status {
+    Status::failed_precondition(format!(
+        "cannot {action}: agent session {} ({}) has been running in the workspace for {}s; stop it or use force",
+        lock.session_id, lock.engine, lock.held_secs
+    ))
+}
+
+// A run's place in the agents map, taken before it starts so no other run can claim the
+// session id or workspace in the meantime. Dropped before `keep`, it frees them again.
+struct RunReservation {
+    agents: Arc<Mutex<HashMap<String, ActiveAgentHandle>>>,
+    session_id: String,
+    kept: bool,
+}
+
+impl RunReservation {
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for RunReservation {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        let session_id = std::mem::take(&mut self.session_id);
+        // Freed at once when the map is free, so a retry right after the error succeeds
+        match self.agents.try_lock() {
+            Ok(mut agents) => {
+                agents.remove(&session_id);
+            }
+            Err(_) => {
+                let agents = self.agents.clone();
+                tokio::spawn(async move {
+                    agents.lock().await.remove(&session_id);
+                });
+            }
+        }
+    }
+}
+
+// One engine of a run's fallback chain, ready to spawn
+struct AgentAttempt {
+    engine: String,
+    launch: core::isolation::EngineLaunch,
+    // Written to the engine's stdin
+    input: Option<String>,
+    custom: Option<core::CustomEngine>,
+    // The engine asks the command policy before each shell command (see `policy_hook`),
+    // so "block" rules stop the command instead of the run
+    policy_gate: bool,
+}
+
+// Start an attempt's engine in `cwd`, feeding it its input
+fn spawn_attempt(attempt: &AgentAttempt, cwd: &str) -> Result<Child, String> {
+    let command = &attempt.launch.command;
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(cwd)
+        .stdin(if attempt.input.is_some() { Stdio::piped() } else { Stdio::inherit() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to spawn {}: {}", command[0], e))?;
+    if let (Some(input), Some(mut stdin)) = (attempt.input.clone(), child.stdin.take()) {
+        // Dropping stdin afterwards ends the input, so the engine runs the one message
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(input.as_bytes()).await {
+                warn!("Failed to write agent input: {}", e);
+            }
+        });
+    }
+    Ok(child)
+}
+
+fn fallback_event(from: &str, to: &str, error: &str) -> String {
+    serde_json::json!({ "type": "agent.fallback", "from": from, "engine": to, "error": error }).to_string()
+}
+
 // Active agent with its event broadcast channel
 struct ActiveAgentHandle {
     engine: String,
     cwd: String,
     started_at: Instant,
-    sender: broadcast::Sender<AgentEvent>,
+    events: AgentEvents,
     child: Option<Child>, // Mutable for cleanup
+    container: Option<String>,
+}
+
+impl ActiveAgentHandle {
+    // Kill the engine without waiting for it to exit
+    fn start_kill(&mut self) {
+        if let Some(ref mut child) = self.child {
+            let _ = child.start_kill();
+            // Killing the `docker run` client leaves the container running
+            if let Some(container) = &self.container {
+                remove_container(container);
+            }
+        }
+    }
+}
+
+impl Drop for ActiveAgentHandle {
+    fn drop(&mut self) {
+        // Kill child process on drop to prevent zombies
+        self.start_kill();
+    }
+}
+
+fn remove_container(container: &str) {
+    let removed = std::process::Command::new("docker")
+        .args(["rm", "--force", container])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match removed {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Failed to remove container {}: {}", container, e),
+    }
+}
+
+// Bounded history of failures reported by GetStatus
+const RECENT_ERRORS_MAX: usize = 50;
+
+struct ConductorService {
+    homes: Arc<Homes>,
+    agents: Arc<Mutex<HashMap<String, ActiveAgentHandle>>>,
+    start_time: Instant,
+    recent_errors: std::sync::Mutex<VecDeque<DaemonError>>,
+}
+
+impl ConductorService {
+    fn new(homes: Arc<Homes>) -> Self {
+        Self {
+            homes,
+            agents: Arc::new(Mutex::new(HashMap::new())),
+            start_time: Instant::now(),
+            recent_errors: std::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // The home the current request named (see `homes`); the daemon's own otherwise,
+    // e.g. for REST calls
+    fn ctx(&self) -> Arc<HomeContext> {
+        homes::current().unwrap_or_else(|| self.homes.primary().clone())
+    }
+
+    // Remember a failure for GetStatus (oldest entries drop off)
+    fn note_error(&self, context: &str, message: &str) {
+        let mut errors = self.recent_errors.lock().unwrap_or_else(|e| e.into_inner());
+        if errors.len() == RECENT_ERRORS_MAX {
+            errors.pop_back();
+        }
+        errors.push_front(DaemonError {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            context: context.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    // Helper to run blocking DB operations on a pooled connection
+    async fn with_db<F, T>(&self, f: F) -> Result<T, Status>
+    where
+        F: FnOnce(&rusqlite::Connection) -> Result<T, anyhow::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.ctx().db.run(f).await.map_err(|e| Status::internal(e.to_string()))
+    }
+
+    fn custom_engine(&self, name: &str) -> Option<core::CustomEngine> {
+        self.ctx().custom_engines.iter().find(|engine| engine.name == name).cloned()
+    }
+
+    // The soft lock on the workspace at `ws_path`: its longest-running agent session.
+    // Archive, rollback and new runs there are refused unless forced
+    async fn workspace_lock(&self, ws_path: &str) -> Option<WorkspaceLock> {
+        workspace_lock_in(&*self.agents.lock().await, ws_path)
+    }
+
+    // Stop every run holding the workspace at `ws_path`
+    async fn stop_agents_in(&self, ws_path: &str) {
+        while let Some(lock) = self.workspace_lock(ws_path).await {
+            if let Some(mut handle) = self.agents.lock().await.remove(&lock.session_id) {
+                if let Some(ref mut child) = handle.child {
+                    let _ = child.kill().await;
+                }
+                info!("Stopped agent {} holding {}", lock.session_id, ws_path);
+            }
+        }
+    }
+
+    // Refuse `action` on a workspace a run holds, unless forced
+    async fn check_workspace_lock(&self, ws_path: &str, action: &str, force: bool) -> Result<(), Status> {
+        match self.workspace_lock(ws_path).await {
+            Some(lock) if !force => Err(lock_refusal(&lock, action)),
+            _ => Ok(()),
+        }
+    }
+
+    // Everything needed to start `req.engine`, so a fallback can start without the service
+    async fn agent_attempt(&self, req: &RunAgentRequest) -> Result<AgentAttempt, Status> {
+        let custom = self.custom_engine(&req.engine);
+        // Claude takes attachments as a stream-json message on stdin
+        let input = match (&custom, req.engine.as_str()) {
+            (None, "claude" | "claude-code") if !req.attachments.is_empty() => {
+                Some(claude_input(req).map_err(|e| Status::failed_precondition(e.to_string()))?)
+            }
+            _ => None,
+        };
+        let (launch, policy_gate) = self.agent_launch(req).await?;
+        Ok(AgentAttempt { engine: req.engine.clone(), launch, input, custom, policy_gate })
+    }
+
+    // What to spawn for a run, after wrapping it for the requested isolation, and whether
+    // the engine checks the command policy before running commands
+    async fn agent_launch(&self, req: &RunAgentRequest) -> Result<(core::isolation::EngineLaunch, bool), Status> {
+        // Empty means the repo's default
+        let isolation: Option<core::isolation::Isolation> = match req.isolation.as_str() {
+            "" => None,
+            requested => Some(requested.parse().map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?),
+        };
+        let home = self.ctx().home.clone();
+        let cwd = PathBuf::from(&req.cwd);
+        let isolation = {
+            let (home, cwd) = (home.clone(), cwd.clone());
+            self.with_db(move |conn| core::isolation::launch_isolation(conn, &home, isolation, &cwd)).await?
+        };
+        let custom = self.custom_engine(&req.engine);
+        // Claude runs a hook before each shell command; the hook is this binary, so only
+        // an engine on this machine can run it
+        let policy = core::config_load(&home)
+            .map_err(|e| Status::failed_precondition(format!("{:#}", e)))?
+            .command_policy
+            .unwrap_or_default();
+        let policy_gate = custom.is_none()
+            && matches!(req.engine.as_str(), "claude" | "claude-code")
+            && policy.blocks()
+            && isolation.on_host();
+        let settings = if policy_gate {
+            let exe = std::env::current_exe().map_err(|e| Status::internal(e.to_string()))?;
+            let hook = format!("{} --policy-hook {}", shell_quote(&exe), shell_quote(&home));
+            Some(core::policy::claude_hook_settings(&hook))
+        } else {
+            None
+        };
+        let command = match custom {
+            Some(custom) => custom.argv(
+                &core::prompt_with_attachments(&req.prompt, &attachment_paths(&req.attachments)),
+                req.resume_id.as_deref(),
+            ),
+            None => engine_command(req, settings.as_deref())
+                .map(|(program, args)| std::iter::once(program.to_string()).chain(args).collect())
+                .ok_or_else(|| Status::invalid_argument(format!("Unknown engine: {}", req.engine)))?,
+        };
+        let session_id = req.session_id.clone();
+        let launch = self
+            .with_db(move |conn| {
+                core::isolation::engine_launch(conn, &home, Some(isolation), &session_id, &cwd, command)
+            })
+            .await?;
+        Ok((launch, policy_gate))
+    }
+
+    // Kill every running agent (daemon shutdown)
+    async fn stop_all_agents(&self) {
+        let mut agents = self.agents.lock().await;
+        for (id, mut handle) in agents.drain() {
+            if let Some(ref mut child) = handle.child {
+                let _ = child.kill().await;
+            }
+            info!("Killed agent {} during shutdown", id);
+        }
+    }
+
+    // Record a mutating RPC in the audit log; audit failures are logged, never surfaced
+    async fn audit<T>(
+        &self,
+        action: &'static str,
+        target: Option<String>,
+        result: &Result<T, Status>,
+        mut metadata: Value,
+    ) {
+        let ok = result.is_ok();
+        if let Err(e) = result {
+            self.note_error(action, e.message());
+            if let Value::Object(map) = &mut metadata {
+                map.insert("error".to_string(), Value::String(e.message().to_string()));
+            }
+        }
+        let recorded = self
+            .with_db(move |conn| {
+                core::audit_record(conn, "daemon", action, target.as_deref(), ok, &metadata)
+            })
+            .await;
+        if let Err(e) = recorded {
+            warn!("Failed to record audit event {}: {}", action, e.message());
+            self.note_error("audit", e.message());
+        }
+    }
+}
+
+// Caller details from gRPC request metadata, stored alongside audit events
+fn client_meta<T>(request: &Request<T>) -> Value {
+    let user_agent = request
+        .metadata()
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    serde_json::json!({ "user_agent": user_agent })
+}
+
+fn workspace_status_proto(
+    ws: core::Workspace,
+    pr: Option<core::github::PrStatus>,
+    pr_error: Option<String>,
+) -> WorkspaceStatus {
+    WorkspaceStatus {
+        workspace: Some(workspace_proto(ws, None)),
+        pull_request: pr.map(|pr| PullRequestStatus {
+            state: pr.state,
+            number: pr.number,
+            url: pr.url,
+            review_decision: pr.review_decision,
+            checks_state: pr.checks.state,
+            checks_passed: pr.checks.passed,
+            checks_failed: pr.checks.failed,
+            checks_pending: pr.checks.pending,
+            fetched_at: pr.fetched_at,
+        }),
+        pull_request_error: pr_error,
+        lock: None,
+    }
+}
+
+fn repo_proto(repo: core::Repo) -> Repo {
+    Repo {
+        id: repo.id,
+        name: repo.name,
+        root_path: repo.root_path,
+        default_branch: repo.default_branch,
+        remote_url: repo.remote_url,
+    }
+}
+
+fn activity_proto(activity: core::WorkspaceActivity) -> WorkspaceActivity {
+    WorkspaceActivity {
+        last_agent_run_at: activity.last_agent_run_at,
+        last_commit_at: activity.last_commit_at,
+        last_opened_at: activity.last_opened_at,
+    }
+}
+
+fn verification_proto(verification: core::WorkspaceVerification) -> WorkspaceVerification {
+    WorkspaceVerification {
+        ok: verification.ok,
+        failed_command: verification.failed_command,
+        session_id: verification.session_id,
+        finished_at: verification.finished_at,
+    }
+}
+
+fn checkpoint_proto(checkpoint: core::Checkpoint) -> Checkpoint {
+    Checkpoint {
+        id: checkpoint.id,
+        commit: checkpoint.commit,
+        head: checkpoint.head,
+        label: checkpoint.label,
+        created_at: checkpoint.created_at,
+    }
+}
+
+fn workspace_proto(w: core::Workspace, enrichment: Option<core::WorkspaceEnrichment>) -> Workspace {
+    Workspace {
+        id: w.id,
+        repository_id: w.repo_id,
+        directory_name: w.name,
+        path: w.path,
+        branch: w.branch,
+        base_branch: w.base_branch,
+        state: w.state.to_string(),
+        pinned: w.pinned,
+        enrichment: enrichment.map(|e| WorkspaceEnrichment {
+            head: e.head,
+            dirty: e.dirty,
+            changed_files: e.changed_files,
+            ahead: e.ahead,
+            behind: e.behind,
+        }),
+        link_error: w.link_error,
+        error: w.error,
+        activity: Some(activity_proto(w.activity)),
+        title: w.title,
+        summary: w.summary,
+        verification: w.verification.map(verification_proto),
+    }
+}
+
+fn review_comment_proto(comment: core::ReviewComment) -> ReviewComment {
+    ReviewComment {
+        id: comment.id,
+        workspace_id: comment.workspace_id,
+        file_path: comment.file_path,
+        start_line: comment.start_line,
+        end_line: comment.end_line,
+        author: comment.author,
+        body: comment.body,
+        resolved: comment.resolved,
+        created_at: comment.created_at,
+        updated_at: comment.updated_at,
+        url: comment.url,
+    }
+}
+
+fn task_proto(task: core::Task) -> Task {
+    Task {
+        id: task.id,
+        title: task.title,
+        description: task.description,
+        status: task.status.to_string(),
+        workspaces: task
+            .workspaces
+            .into_iter()
+            .map(|ws| TaskWorkspace {
+                workspace_id: ws.workspace_id,
+                name: ws.name,
+                branch: ws.branch,
+                linked_at: ws.linked_at,
+            })
+            .collect(),
+        runs: task
+            .runs
+            .into_iter()
+            .map(|run| TaskRun {
+                session_id: run.session_id,
+                workspace_id: run.workspace_id,
+                started_at: run.started_at,
+            })
+            .collect(),
+        chosen_workspace_id: task.chosen_workspace_id,
+        created_at: task.created_at,
+        updated_at: task.updated_at,
+    }
+}
+
+fn diff_options(req: &GetFileDiffRequest) -> core::DiffOptions {
+    core::DiffOptions {
+        context: req.context,
+        max_lines: req.max_lines.map(|lines| lines as usize),
+        hunk: req.hunk.map(|hunk| hunk as usize),
+    }
+}
+
+fn changed_file_proto(change: core::WorkspaceChange) -> ChangedFile {
+    ChangedFile {
+        path: change.path,
+        status: change.status,
+        insertions: 0, // Not available in core::WorkspaceChange
+        deletions: 0,
+        old_path: change.old_path,
+    }
+}
+
+// No session is reported as an all-empty message
+fn session_proto(session: Option<core::SessionState>) -> SessionState {
+    match session {
+        Some(s) => SessionState {
+            agent_id: Some(s.agent_id),
+            resume_id: s.resume_id,
+            started_at: Some(s.started_at),
+            updated_at: Some(s.updated_at),
+            engine_version: s.engine_version,
+            model: s.model,
+            base_sha: s.base_sha,
+            conductor_version: s.conductor_version,
+        },
+        None => SessionState::default(),
+    }
+}
+
+fn active_agent_proto(session_id: &str, handle: &ActiveAgentHandle) -> ActiveAgent {
+    ActiveAgent {
+        session_id: session_id.to_string(),
+        engine: handle.engine.clone(),
+        cwd: handle.cwd.clone(),
+        started_at: handle.started_at.elapsed().as_secs().to_string(),
+    }
+}
+
+// Record a run handing over from one engine to the next; runs inside the agent's event task
+async fn audit_fallback(db: Arc<DbPool>, session_id: String, from: &str, to: &str, error: &str) {
+    let metadata = serde_json::json!({ "from": from, "engine": to, "error": error });
+    let recorded = db
+        .run(move |conn| core::audit_record(conn, "daemon", "agent.fallback", Some(&session_id), true, &metadata))
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record engine fallback: {}", e);
+    }
+}
+
+// Record a command policy hit; runs inside an agent's event task, away from the service
+async fn audit_policy_violation(db: Arc<DbPool>, session_id: String, violation: core::policy::PolicyViolation) {
+    let recorded = db
+        .run(move |conn| {
+            let metadata = serde_json::json!({
+                "rule": violation.rule,
+                "decision": violation.action.as_str(),
+                "command": violation.command,
+            });
+            // A flagged command went ahead; a blocked one was refused or ended the run
+            let ok = violation.action == core::policy::PolicyAction::Flag;
+            core::audit_record(conn, "daemon", "agent.policy_violation", Some(&session_id), ok, &metadata)
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record policy violation: {}", e);
+    }
+}
+
+// Stamp a run on the workspace holding `cwd`: its start, or at the end the commits it
+// made. Agents can run outside any workspace; failures only cost sort order
+// Add a finished run's token usage to the workspace holding `cwd`
+async fn record_usage(db: Arc<DbPool>, cwd: String, usage: core::TokenUsage) {
+    let recorded = db
+        .run(move |conn| match core::workspace_for_path(conn, Path::new(&cwd))? {
+            Some(ws) => core::workspace_usage_record(conn, &ws.id, usage),
+            None => Ok(()),
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record token usage: {}", e);
+    }
+}
+
+async fn record_agent_activity(db: Arc<DbPool>, cwd: String, finished: bool) {
+    let recorded = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, Path::new(&cwd))? else {
+                return Ok(());
+            };
+            if finished {
+                core::workspace_activity_commit(conn, &ws.id)?;
+            } else {
+                core::workspace_activity_record(conn, &ws.id, core::ActivityKind::AgentRun)?;
+            }
+            Ok(())
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record workspace activity: {}", e);
+    }
+}
+
+// Checkpoint the workspace holding `cwd` (see `CheckpointMode`). None outside a
+// workspace, when nothing changed since the last one, or on failure, which is logged
+async fn checkpoint_workspace(db: Arc<DbPool>, cwd: String, label: String) -> Option<core::Checkpoint> {
+    let created = db
+        .run(move |conn| match core::workspace_for_path(conn, Path::new(&cwd))? {
+            Some(ws) => core::checkpoint_create(conn, &ws.id, &label),
+            None => Ok(None),
+        })
+        .await;
+    created.unwrap_or_else(|e| {
+        warn!("Failed to checkpoint workspace: {}", e);
+        None
+    })
+}
+
+// The `checkpoint` event telling a run's clients what they can roll back to
+fn checkpoint_event(checkpoint: &core::Checkpoint) -> String {
+    serde_json::json!({ "type": "checkpoint", "checkpoint": checkpoint }).to_string()
+}
+
+// The `agent.diffstat` event summing up what the workspace holding `cwd` now changes
+// relative to its base, so clients needn't ask. None outside a workspace or on failure
+async fn diffstat_event(db: Arc<DbPool>, cwd: String) -> Option<String> {
+    let summary = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, Path::new(&cwd))? else {
+                return Ok(None);
+            };
+            let stat = core::workspace_diffstat(conn, &ws.id)?;
+            let untracked: Vec<String> = core::workspace_changes(conn, &ws.id)?
+                .into_iter()
+                // The app's own files aren't the agent's work (workspace_diffstat skips them too)
+                .filter(|change| change.status == "?" && !change.path.starts_with(".conductor-app/"))
+                .map(|change| change.path)
+                .collect();
+            Ok(Some((ws.id, stat, untracked)))
+        })
+        .await;
+    let (workspace_id, stat, untracked) = summary
+        .unwrap_or_else(|e| {
+            warn!("Failed to summarize workspace changes: {}", e);
+            None
+        })?;
+    Some(
+        serde_json::json!({
+            "type": "agent.diffstat",
+            "workspace_id": workspace_id,
+            "files": stat.files,
+            "insertions": stat.insertions,
+            "deletions": stat.deletions,
+            "untracked": untracked,
+        })
+        .to_string(),
+    )
+}
+
+// Run the `verify` commands of the repo whose workspace holds `cwd`, streaming them as
+// `verification` events, and record how they went on the workspace and in the audit
+// log. None when there is nothing to verify
+async fn verify_run(
+    db: Arc<DbPool>,
+    home: &Path,
+    events: &AgentEvents,
+    cwd: String,
+    session_id: &str,
+) -> Option<core::WorkspaceVerification> {
+    let config = match core::config_load(home) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Skipping verification: {:#}", e);
+            return None;
+        }
+    };
+    let ws = db
+        .run(move |conn| core::workspace_for_path(conn, Path::new(&cwd)))
+        .await
+        .ok()
+        .flatten()?;
+    let commands = core::verify_commands(&config, &ws);
+    if commands.is_empty() {
+        return None;
+    }
+
+    let mut failed_command = None;
+    let mut results = Vec::new();
+    for command in commands {
+        let emit = |payload: Value| events.emit("event", payload.to_string());
+        emit(serde_json::json!({ "type": "verification", "phase": "started", "command": command }));
+        let started_at = Instant::now();
+        let outcome = verify_command(&command, Path::new(&ws.path), &emit).await;
+        let ok = matches!(outcome, Ok(Some(0)));
+        let result = serde_json::json!({
+            "command": command,
+            "ok": ok,
+            "exit_code": outcome.clone().ok().flatten(),
+            "error": outcome.as_ref().err(),
+            "duration_ms": started_at.elapsed().as_millis() as u64,
+        });
+        let mut event = result.clone();
+        event["type"] = "verification".into();
+        event["phase"] = "completed".into();
+        emit(event);
+        results.push(result);
+        if !ok {
+            failed_command = Some(command);
+            break;
+        }
+    }
+
+    let verification = core::WorkspaceVerification {
+        ok: failed_command.is_none(),
+        failed_command,
+        session_id: Some(session_id.to_string()),
+        finished_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let stored = verification.clone();
+    let session_id = session_id.to_string();
+    let recorded = db
+        .run(move |conn| {
+            core::workspace_verification_record(conn, &ws.id, &stored)?;
+            let metadata = serde_json::json!({ "workspace": ws.id, "commands": results });
+            core::audit_record(conn, "daemon", "agent.verify", Some(&session_id), stored.ok, &metadata)
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record verification: {}", e);
+    }
+    Some(verification)
+}
+
+// One `verify` command through sh in `dir`, each output line (stdout and stderr
+// together) emitted as it comes; its exit code, None if killed by a signal
+async fn verify_command(command: &str, dir: &Path, emit: &impl Fn(Value)) -> Result<Option<i32>, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("exec 2>&1\n{command}"))
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start sh: {e}"))?;
+    let stdout = child.stdout.take().ok_or("failed to capture output")?;
+    let run = async {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            emit(serde_json::json!({ "type": "verification", "phase": "output", "command": command, "line": line }));
+        }
+        child.wait().await
+    };
+    match tokio::time::timeout(VERIFY_TIMEOUT, run).await {
+        Ok(Ok(status)) => Ok(status.code()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("timed out after {}s", VERIFY_TIMEOUT.as_secs())),
+    }
+}
+
+// Title and summarize the workspace holding `cwd` after a run (`titles` in config.json);
+// failures only cost the sidebar its label
+async fn record_workspace_title(db: Arc<DbPool>, home: PathBuf, cwd: String, prompt: String, answer: Option<String>) {
+    let recorded = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, Path::new(&cwd))? else {
+                return Ok(());
+            };
+            core::workspace_title_update(conn, &home, &ws.id, Some(&prompt), answer.as_deref())?;
+            Ok(())
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to update workspace title: {}", e);
+    }
+}
+
+// Save an engine's resume token to the workspace's session.json so the next run
+// (from any client) continues the conversation
+async fn record_resume_id(db: Arc<DbPool>, cwd: String, engine: String, resume_id: String) {
+    let recorded = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, Path::new(&cwd))? else {
+                return Ok(());
+            };
+            core::session_upsert_resume_id(Path::new(&ws.path), &engine, &resume_id)?;
+            Ok(())
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to save resume id: {}", e);
+    }
+}
+
+// Note what a run started from in the workspace's session.json, so its results can be
+// traced to the engine build, model and commit that produced them
+async fn record_provenance(db: Arc<DbPool>, cwd: String, engine: String, run: core::RunProvenance) {
+    let recorded = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, Path::new(&cwd))? else {
+                return Ok(());
+            };
+            core::session_record_run(Path::new(&ws.path), &engine, &run)?;
+            Ok(())
+        })
+        .await;
+    if let Err(e) = recorded {
+        warn!("Failed to record run provenance: {}", e);
+    }
+}
+
+// Record an agent's raw output in its workspace on a blocking thread, off the event
+// loop; the transcript closes when the sender is dropped
+fn start_transcript(ws_path: PathBuf, session_id: String) -> std::sync::mpsc::Sender<String> {
+    let (tx, rx) = std::sync::mpsc::channel::<String>();
+    tokio::task::spawn_blocking(move || {
+        let written = core::Transcript::create(&ws_path, &session_id)
+            .and_then(|mut transcript| rx.iter().try_for_each(|line| transcript.append(&line)));
+        if let Err(e) = written {
+            warn!("Failed to record transcript for {}: {}", session_id, e);
+        }
+    });
+    tx
+}
+
+// Append to a workspace's chat.md; runs outside any workspace (None) have no chat
+async fn record_chat(ws_path: Option<PathBuf>, role: &'static str, content: String) {
+    let Some(ws_path) = ws_path else { return };
+    match tokio::task::spawn_blocking(move || core::chat_append(&ws_path, role, &content)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to append to chat: {}", e),
+        Err(e) => warn!("Failed to append to chat: {}", e),
+    }
 }
 
-impl Drop for ActiveAgentHandle {
-    fn drop(&mut self) {
-        // Kill child process on drop to prevent zombies
-        if let Some(ref mut child) = self.child {
-            let _ = child.start_kill();
-        }
+// POST a finished run to the configured webhooks; failures are logged, never retried
+async fn notify_webhooks(home: PathBuf, db: Arc<DbPool>, mut run: core::webhooks::AgentRunEvent) {
+    let hooks = match core::config_load(&home) {
+        Ok(config) => config.webhooks,
+        Err(e) => {
+            warn!("Webhooks skipped, config unreadable: {}", e);
+            return;
+        }
+    };
+    let hooks: Vec<_> = hooks.into_iter().filter(|hook| hook.wants(&run.status)).collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    // Workspace details are best effort: agents can run outside any workspace
+    let cwd = run.cwd.clone();
+    let workspace = db
+        .run(move |conn| {
+            let Some(ws) = core::workspace_for_path(conn, std::path::Path::new(&cwd))? else {
+                return Ok(None);
+            };
+            Ok(Some((core::workspace_diffstat(conn, &ws.id).ok(), ws)))
+        })
+        .await;
+    if let Ok(Some((diffstat, ws))) = workspace {
+        run.diffstat = diffstat;
+        run.workspace_id = Some(ws.id);
+        run.workspace = Some(ws.name);
+        run.repo = Some(ws.repo);
+        run.branch = Some(ws.branch);
+    }
+
+    let client = match reqwest::Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Webhooks skipped: {}", e);
+            return;
+        }
+    };
+    for hook in hooks {
+        let body = match core::webhooks::webhook_body(&hook, &run) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Webhook {} skipped: {}", hook.url, e);
+                continue;
+            }
+        };
+        let mut request = client
+            .post(&hook.url)
+            .header("content-type", "application/json")
+            .body(body);
+        for (name, value) in &hook.headers {
+            request = request.header(name, value);
+        }
+        match request.send().await.and_then(|response| response.error_for_status()) {
+            Ok(_) => info!("Webhook {} notified ({} {})", hook.url, run.session_id, run.status),
+            Err(e) => warn!("Webhook {} failed: {}", hook.url, e),
+        }
+    }
+}
+
+// Program and arguments for an engine run; None for unknown engines
+// `claude_settings` goes to claude's --settings; the other engines take none
+fn engine_command(req: &RunAgentRequest, claude_settings: Option<&str>) -> Option<(&'static str, Vec<String>)> {
+    let command = match req.engine.as_str() {
+        "claude" | "claude-code" => {
+            let mut args = vec![
+                "-p".to_string(),
+                "--output-format".to_string(),
+                "stream-json".to_string(),
+                "--verbose".to_string(),
+                "--dangerously-skip-permissions".to_string(),
+            ];
+            if let Some(settings) = claude_settings {
+                args.push("--settings".to_string());
+                args.push(settings.to_string());
+            }
+            if let Some(ref resume) = req.resume_id {
+                args.push("--resume".to_string());
+                args.push(resume.clone());
+            }
+            // With attachments the prompt goes in on stdin as a user message carrying
+            // image blocks (see `claude_input`)
+            if req.attachments.is_empty() {
+                args.push("--".to_string());
+                args.push(req.prompt.clone());
+            } else {
+                args.push("--input-format".to_string());
+                args.push("stream-json".to_string());
+            }
+            ("claude", args)
+        }
+        "codex" => {
+            // `exec --json` streams the thread.* / item.* events the parser reads; a
+            // resumed run continues the thread its thread.started event named
+            let mut args = vec!["exec".to_string(), "--json".to_string(), "--full-auto".to_string()];
+            let (images, files) = split_attachments(&req.attachments);
+            for image in images {
+                args.push("--image".to_string());
+                args.push(image.display().to_string());
+            }
+            if let Some(ref resume) = req.resume_id {
+                args.push("resume".to_string());
+                args.push(resume.clone());
+            }
+            args.push("--".to_string());
+            args.push(core::prompt_with_attachments(&req.prompt, &files));
+            ("codex", args)
+        }
+        "gemini" => (
+            "gemini",
+            vec![
+                "-m".to_string(),
+                "gemini-3-pro-preview".to_string(),
+                "--yolo".to_string(),
+                core::prompt_with_attachments(&req.prompt, &attachment_paths(&req.attachments)),
+            ],
+        ),
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn attachment_paths(attachments: &[String]) -> Vec<PathBuf> {
+    attachments.iter().map(PathBuf::from).collect()
+}
+
+// Attachments the engine takes as images, and the rest
+fn split_attachments(attachments: &[String]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    attachment_paths(attachments)
+        .into_iter()
+        .partition(|path| core::attachment_media_type(path).is_some())
+}
+
+// Claude's stream-json input for a prompt with attachments: one user message with
+// the prompt (and any non-image files listed) followed by base64 image blocks
+fn claude_input(req: &RunAgentRequest) -> anyhow::Result<String> {
+    use base64::Engine as _;
+
+    let (images, files) = split_attachments(&req.attachments);
+    let mut content = vec![serde_json::json!({
+        "type": "text",
+        "text": core::prompt_with_attachments(&req.prompt, &files),
+    })];
+    for image in images {
+        let data = std::fs::read(&image)
+            .map_err(|e| anyhow::anyhow!("attachment {}: {}", image.display(), e))?;
+        content.push(serde_json::json!({
+            "type": "image",
+            "source": {
+                "type": "base64",
+                "media_type": core::attachment_media_type(&image),
+                "data": base64::engine::general_purpose::STANDARD.encode(data),
+            },
+        }));
+    }
+    let message = serde_json::json!({
+        "type": "user",
+        "message": { "role": "user", "content": content },
+    });
+    Ok(format!("{message}\n"))
+}
+
+#[tonic::async_trait]
+impl Conductor for ConductorService {
+    // =========================================================================
+    // Repository Management
+    // =========================================================================
+
+    async fn list_repos(
+        &self,
+        _request: Request<ListReposRequest>,
+    ) -> Result<Response<ListReposResponse>, Status> {
+        let repos: Vec<core::Repo> = self
+            .with_db(core::repo_list)
+            .await?;
+
+        Ok(Response::new(ListReposResponse {
+            repos: repos.into_iter().map(repo_proto).collect(),
+        }))
+    }
+
+    async fn add_repo(&self, request: Request<AddRepoRequest>) -> Result<Response<Repo>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let path = PathBuf::from(&req.path);
+
+        let result = self
+            .with_db(move |conn| core::repo_add(conn, &path, None, None))
+            .await;
+        let target = result.as_ref().ok().map(|r| r.id.clone());
+        self.audit("repo.add", target, &result, serde_json::json!({ "path": req.path, "client": client }))
+            .await;
+        let repo = result?;
+
+        Ok(Response::new(repo_proto(repo)))
+    }
+
+    async fn add_repo_url(
+        &self,
+        request: Request<AddRepoUrlRequest>,
+    ) -> Result<Response<Repo>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let home = self.ctx().home.clone();
+        let url = req.url.clone();
+        let clone = core::CloneOptions {
+            depth: req.depth,
+            filter: req.filter.clone(),
+            single_branch: req.single_branch,
+            bare: req.bare,
+        };
+        let metadata = serde_json::json!({ "url": req.url, "clone": clone, "client": client });
+
+        let result = self
+            .with_db(move |conn| core::repo_add_url(conn, &home, &url, None, None, &clone))
+            .await;
+        let target = result.as_ref().ok().map(|r| r.id.clone());
+        self.audit("repo.add_url", target, &result, metadata).await;
+        let repo = result?;
+
+        Ok(Response::new(repo_proto(repo)))
+    }
+
+    async fn reorder_repos(
+        &self,
+        request: Request<ReorderReposRequest>,
+    ) -> Result<Response<ListReposResponse>, Status> {
+        let client = client_meta(&request);
+        let repo_ids = request.into_inner().repo_ids;
+        let metadata = serde_json::json!({ "repos": repo_ids, "client": client });
+        let result = self
+            .with_db(move |conn| core::repo_reorder(conn, &repo_ids))
+            .await;
+        self.audit("repo.reorder", None, &result, metadata).await;
+        Ok(Response::new(ListReposResponse {
+            repos: result?.into_iter().map(repo_proto).collect(),
+        }))
+    }
+
+    // =========================================================================
+    // Workspace Management
+    // =========================================================================
+
+    async fn list_workspaces(
+        &self,
+        request: Request<ListWorkspacesRequest>,
+    ) -> Result<Response<ListWorkspacesResponse>, Status> {
+        let req = request.into_inner();
+        let repo_id = req.repo_id;
+        let cache = req.enriched.then(|| self.ctx().enrichment.clone());
+        let sort: core::WorkspaceSort =
+            req.sort.parse().map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let states = req
+            .states
+            .iter()
+            .map(|state| state.parse())
+            .collect::<anyhow::Result<Vec<core::WorkspaceState>>>()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let idle = req
+            .stale
+            .as_deref()
+            .map(core::parse_age)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let workspaces: Vec<(core::Workspace, Option<core::WorkspaceEnrichment>)> = self
+            .with_db(move |conn| {
+                let mut workspaces = core::workspace_list_sorted(conn, repo_id.as_deref(), sort)?;
+                if let Some(idle) = idle {
+                    let mut stale = Vec::new();
+                    for ws in workspaces {
+                        if core::workspace_is_stale(conn, &ws, idle)? {
+                            stale.push(ws);
+                        }
+                    }
+                    workspaces = stale;
+                }
+                workspaces.retain(|ws| states.is_empty() || states.contains(&ws.state));
+                // A workspace whose git state can't be read is listed without it
+                let mut enrichments = HashMap::new();
+                if let Some(cache) = &cache {
+                    let enrichable: Vec<&core::Workspace> =
+                        workspaces.iter().filter(|ws| ws.state.is_live() && ws.link_error.is_none()).collect();
+                    for (ws, enrichment) in enrichable.iter().zip(cache.get_many(&enrichable)) {
+                        match enrichment {
+                            Ok(enrichment) => {
+                                enrichments.insert(ws.id.clone(), enrichment);
+                            }
+                            Err(e) => warn!("Failed to enrich workspace {}: {}", ws.id, e),
+                        }
+                    }
+                }
+                Ok(workspaces
+                    .into_iter()
+                    .map(|ws| {
+                        let enrichment = enrichments.remove(&ws.id);
+                        (ws, enrichment)
+                    })
+                    .collect())
+            })
+            .await?;
+
+        Ok(Response::new(ListWorkspacesResponse {
+            workspaces: workspaces
+                .into_iter()
+                .map(|(w, enrichment)| workspace_proto(w, enrichment))
+                .collect(),
+        }))
+    }
+
+    async fn create_workspace(
+        &self,
+        request: Request<CreateWorkspaceRequest>,
+    ) -> Result<Response<Workspace>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let ctx = self.ctx();
+        let home = ctx.home.clone();
+        let repo_id = req.repo_id.clone();
+        let name = req.name.clone();
+        let options = core::CreateOptions {
+            auto_suffix: req.auto_suffix,
+            carry_changes: req.carry_changes,
+        };
+
+        // One create per repo at a time; the request may name it rather than give its id.
+        // A repo that doesn't resolve fails the create below anyway
+        let repo_ref = req.repo_id.clone();
+        let lock_key = match self.with_db(move |conn| core::repo_get(conn, &repo_ref)).await {
+            Ok(repo) => repo.id,
+            Err(_) => req.repo_id.clone(),
+        };
+        let _creating = ctx.repo_lock(&lock_key).await;
+        let result = self
+            .with_db(move |conn| {
+                core::workspace_create(conn, &home, &repo_id, name.as_deref(), None, None, &options)
+            })
+            .await;
+        let target = result.as_ref().ok().map(|w| w.id.clone());
+        let metadata = serde_json::json!({
+            "repo": req.repo_id,
+            "name": req.name,
+            "auto_suffix": req.auto_suffix,
+            "carry_changes": req.carry_changes,
+            "client": client,
+        });
+        self.audit("workspace.create", target, &result, metadata).await;
+        Ok(Response::new(workspace_proto(result?, None)))
+    }
+
+    async fn set_workspace_state(
+        &self,
+        request: Request<SetWorkspaceStateRequest>,
+    ) -> Result<Response<Workspace>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let state: core::WorkspaceState =
+            req.state.parse().map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let workspace_id = req.workspace_id.clone();
+        let result = self
+            .with_db(move |conn| core::workspace_set_state(conn, &workspace_id, state))
+            .await;
+        let metadata = serde_json::json!({ "state": state, "client": client });
+        self.audit("workspace.set_state", Some(req.workspace_id), &result, metadata).await;
+        Ok(Response::new(workspace_proto(result?, None)))
+    }
+
+    async fn set_workspace_pinned(
+        &self,
+        request: Request<SetWorkspacePinnedRequest>,
+    ) -> Result<Response<Workspace>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id.clone();
+        let pinned = req.pinned;
+        let result = self
+            .with_db(move |conn| core::workspace_set_pinned(conn, &workspace_id, pinned))
+            .await;
+        let metadata = serde_json::json!({ "pinned": pinned, "client": client });
+        self.audit("workspace.set_pinned", Some(req.workspace_id), &result, metadata).await;
+        Ok(Response::new(workspace_proto(result?, None)))
+    }
+
+    async fn archive_workspace(
+        &self,
+        request: Request<ArchiveWorkspaceRequest>,
+    ) -> Result<Response<ArchiveWorkspaceResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let home = self.ctx().home.clone();
+        let workspace_id = req.workspace_id.clone();
+        let force = req.force;
+
+        let lookup = workspace_id.clone();
+        let result: Result<core::ArchiveResult, Status> = match self.with_db(move |conn| core::workspace_get(conn, &lookup)).await {
+            Ok(ws) => match self.check_workspace_lock(&ws.path, "archive", force).await {
+                Ok(()) => {
+                    // Forced: the runs would only fail once their directory is gone
+                    self.stop_agents_in(&ws.path).await;
+                    self.with_db(move |conn| core::workspace_archive(conn, &home, &workspace_id, force)).await
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        let overridden = result.as_ref().ok().map(|result| {
+            result.checks.iter().filter(|c| !c.ok).map(|c| c.check.as_str()).collect::<Vec<_>>()
+        });
+        let metadata = serde_json::json!({ "force": force, "overridden_checks": overridden, "client": client });
+        self.audit("workspace.archive", Some(req.workspace_id), &result, metadata).await;
+
+        match result {
+            Ok(result) => Ok(Response::new(ArchiveWorkspaceResponse {
+                success: true,
+                error: None,
+                checks: result
+                    .checks
+                    .into_iter()
+                    .map(|c| ArchiveCheckResult {
+                        check: c.check.as_str().to_string(),
+                        ok: c.ok,
+                        detail: c.detail,
+                    })
+                    .collect(),
+            })),
+            Err(e) => Ok(Response::new(ArchiveWorkspaceResponse {
+                success: false,
+                error: Some(e.to_string()),
+                checks: Vec::new(),
+            })),
+        }
+    }
+
+    async fn get_workspace_status(
+        &self,
+        request: Request<GetWorkspaceStatusRequest>,
+    ) -> Result<Response<WorkspaceStatus>, Status> {
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id;
+        let refresh_pr = req.refresh_pr;
+
+        let (ws, pr, pr_error) = self
+            .with_db(move |conn| {
+                let ws = core::workspace_get(conn, &workspace_id)?;
+                let mut pr_error = None;
+                if refresh_pr {
+                    if let Err(e) = core::github::pr_status_refresh(conn, &ws.id) {
+                        pr_error = Some(format!("{e:#}"));
+                    }
+                }
+                let pr = core::github::pr_status_cached(conn, &ws.id)?;
+                Ok((ws, pr, pr_error))
+            })
+            .await?;
+
+        let lock = self.workspace_lock(&ws.path).await;
+        let mut status = workspace_status_proto(ws, pr, pr_error);
+        status.lock = lock;
+        Ok(Response::new(status))
+    }
+
+    async fn get_repo_context(
+        &self,
+        request: Request<GetRepoContextRequest>,
+    ) -> Result<Response<RepoContext>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let context = self
+            .with_db(move |conn| {
+                let ws = core::workspace_get(conn, &workspace_id)?;
+                core::repo_context(conn, Path::new(&ws.path))
+            })
+            .await?;
+        Ok(Response::new(match context {
+            Some(context) => RepoContext {
+                path: Some(context.path),
+                content: context.content,
+            },
+            None => RepoContext::default(),
+        }))
+    }
+
+    async fn get_workspace_stats(
+        &self,
+        request: Request<GetWorkspaceStatsRequest>,
+    ) -> Result<Response<WorkspaceStats>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let stats = self.with_db(move |conn| core::workspace_stats(conn, &workspace_id)).await?;
+        Ok(Response::new(WorkspaceStats {
+            workspace_id: stats.workspace_id,
+            commits: stats.commits,
+            files_changed: stats.diff.files,
+            insertions: stats.diff.insertions,
+            deletions: stats.diff.deletions,
+            agent_runs: stats.agent_runs,
+            input_tokens: stats.usage.input_tokens,
+            output_tokens: stats.usage.output_tokens,
+            created_at: stats.created_at,
+            age_secs: stats.age_secs,
+        }))
+    }
+
+    async fn get_workspace_summary(
+        &self,
+        request: Request<GetWorkspaceSummaryRequest>,
+    ) -> Result<Response<WorkspaceSummary>, Status> {
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id;
+
+        let (ws, pr, changes, session, last_message) = self
+            .with_db(move |conn| {
+                let ws = core::workspace_get(conn, &workspace_id)?;
+                let pr = core::github::pr_status_cached(conn, &ws.id)?;
+                let changes = core::workspace_changes(conn, &ws.id)?;
+                let path = PathBuf::from(&ws.path);
+                let session = core::session_read(&path)?;
+                let last_message = core::chat_last_entry(&path)?;
+                Ok((ws, pr, changes, session, last_message))
+            })
+            .await?;
+
+        let agents = self
+            .agents
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, handle)| Path::new(&handle.cwd).starts_with(&ws.path))
+            .map(|(id, handle)| active_agent_proto(id, handle))
+            .collect();
+
+        Ok(Response::new(WorkspaceSummary {
+            status: Some(workspace_status_proto(ws, pr, None)),
+            changes: changes.into_iter().map(changed_file_proto).collect(),
+            session: Some(session_proto(session)),
+            last_message: last_message.map(|entry| ChatMessage {
+                role: entry.role,
+                content: entry.content.chars().take(SUMMARY_EXCERPT_CHARS).collect(),
+                timestamp: entry.timestamp,
+            }),
+            agents,
+        }))
+    }
+
+    async fn reconcile_workspaces(
+        &self,
+        request: Request<ReconcileWorkspacesRequest>,
+    ) -> Result<Response<ReconcileWorkspacesResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let options = core::ReconcileOptions {
+            adopt: req.adopt,
+            prune: req.prune,
+            mark_error: req.mark_error,
+        };
+        let home = self.ctx().home.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_reconcile(conn, &home, options))
+            .await;
+        // Only runs that can change something are worth an audit event
+        if req.adopt || req.prune || req.mark_error {
+            let metadata = serde_json::json!({
+                "adopt": req.adopt,
+                "prune": req.prune,
+                "mark_error": req.mark_error,
+                "issues": result.as_ref().ok(),
+                "client": client,
+            });
+            self.audit("workspace.reconcile", None, &result, metadata).await;
+        }
+        let issues = result?;
+
+        Ok(Response::new(ReconcileWorkspacesResponse {
+            issues: issues
+                .into_iter()
+                .map(|issue| ReconcileIssue {
+                    kind: issue.kind,
+                    path: issue.path,
+                    repository_id: issue.repo_id,
+                    workspace_id: issue.workspace_id,
+                    branch: issue.branch,
+                    action: issue.action,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn export_workspace(
+        &self,
+        request: Request<ExportWorkspaceRequest>,
+    ) -> Result<Response<ExportWorkspaceResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let format: core::ExportFormat = req
+            .format
+            .parse()
+            .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let target = PathBuf::from(&req.target_path);
+        if !target.is_absolute() {
+            return Err(Status::invalid_argument("target_path must be absolute"));
+        }
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_export(conn, &workspace_id, format, &target))
+            .await;
+        let metadata = serde_json::json!({ "format": req.format, "path": req.target_path, "client": client });
+        self.audit("workspace.export", Some(req.workspace_id), &result, metadata).await;
+        let export = result?;
+
+        Ok(Response::new(ExportWorkspaceResponse {
+            path: export.path,
+            files: export.files,
+        }))
+    }
+
+    async fn apply_patch(
+        &self,
+        request: Request<ApplyPatchRequest>,
+    ) -> Result<Response<ApplyPatchResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({
+            "three_way": req.three_way,
+            "bundle": req.bundle_path,
+            "patch_bytes": req.patch.len(),
+            "client": client,
+        });
+        let workspace_id = req.workspace_id.clone();
+        let (patch, bundle_path, three_way) = (req.patch, req.bundle_path, req.three_way);
+
+        let result = self
+            .with_db(move |conn| {
+                Ok(match bundle_path {
+                    Some(bundle) => core::workspace_apply_bundle(conn, &workspace_id, bundle.as_ref(), three_way)?,
+                    None => core::workspace_apply_patch(conn, &workspace_id, &patch, three_way)?,
+                })
+            })
+            .await;
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.apply_patch", Some(req.workspace_id), &result, metadata).await;
+        let result = result?;
+
+        Ok(Response::new(ApplyPatchResponse {
+            applied: result.applied,
+            files: result.files,
+            conflicts: result.conflicts,
+            message: result.message,
+        }))
+    }
+
+    async fn commit_workspace(
+        &self,
+        request: Request<CommitWorkspaceRequest>,
+    ) -> Result<Response<CommitWorkspaceResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "message": req.message, "client": client });
+        let (workspace_id, message) = (req.workspace_id.clone(), req.message);
+        let result = self.with_db(move |conn| core::workspace_commit(conn, &workspace_id, &message)).await;
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.commit", Some(req.workspace_id), &result, metadata).await;
+        Ok(Response::new(CommitWorkspaceResponse { sha: result? }))
+    }
+
+    async fn suggest_commit_message(
+        &self,
+        request: Request<SuggestCommitMessageRequest>,
+    ) -> Result<Response<SuggestCommitMessageResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let home = self.ctx().home.clone();
+        let message = self.with_db(move |conn| core::commit_message_suggest(conn, &home, &workspace_id)).await?;
+        Ok(Response::new(SuggestCommitMessageResponse { message }))
+    }
+
+    async fn list_checkpoints(
+        &self,
+        request: Request<ListCheckpointsRequest>,
+    ) -> Result<Response<ListCheckpointsResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let checkpoints = self.with_db(move |conn| core::checkpoint_list(conn, &workspace_id)).await?;
+        Ok(Response::new(ListCheckpointsResponse {
+            checkpoints: checkpoints.into_iter().map(checkpoint_proto).collect(),
+        }))
+    }
+
+    async fn rollback_workspace(
+        &self,
+        request: Request<RollbackWorkspaceRequest>,
+    ) -> Result<Response<RollbackWorkspaceResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let target: core::RollbackTarget = req
+            .target
+            .parse()
+            .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let workspace_id = req.workspace_id.clone();
+        let ws = self.with_db(move |conn| core::workspace_get(conn, &workspace_id)).await?;
+        // Resetting under a running agent would have it carry on from files it never saw
+        let result = match self.check_workspace_lock(&ws.path, "roll back", req.force).await {
+            Ok(()) => {
+                let workspace_id = ws.id.clone();
+                self.with_db(move |conn| core::workspace_rollback(conn, &workspace_id, target)).await
+            }
+            Err(e) => Err(e),
+        };
+        let metadata = serde_json::json!({ "target": req.target, "force": req.force, "client": client });
+        self.audit("workspace.rollback", Some(req.workspace_id), &result, metadata).await;
+        let rollback = result?;
+        Ok(Response::new(RollbackWorkspaceResponse {
+            head: rollback.head,
+            previous_head: rollback.previous_head,
+            dropped_commits: rollback.dropped_commits as u32,
+            stash: rollback.stash,
+            checkpoint: rollback.checkpoint.map(checkpoint_proto),
+        }))
+    }
+
+    async fn open_workspace(
+        &self,
+        request: Request<OpenWorkspaceRequest>,
+    ) -> Result<Response<OpenWorkspaceResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let home = self.ctx().home.clone();
+        let lookup_id = workspace_id.clone();
+        let (path, editor) = self
+            .with_db(move |conn| {
+                let path = core::workspace_path(conn, &lookup_id)?;
+                // No terminal here, so $EDITOR is skipped in favor of GUI editors
+                let editor = core::editor_command(&core::config_load(&home)?, false)?;
+                Ok((path, editor))
+            })
+            .await?;
+
+        let mut child = Command::new(&editor[0])
+            .args(&editor[1..])
+            .arg(&path)
+            .current_dir(&path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| Status::failed_precondition(format!("failed to launch {}: {e}", editor[0])))?;
+        // Reap it; GUI launchers usually hand off and exit right away
+        tokio::spawn(async move {
+            let _ = child.wait().await;
+        });
+        let recorded = self
+            .with_db(move |conn| core::workspace_activity_record(conn, &workspace_id, core::ActivityKind::Opened))
+            .await;
+        if let Err(e) = recorded {
+            warn!("Failed to record workspace activity: {}", e.message());
+        }
+
+        Ok(Response::new(OpenWorkspaceResponse {
+            path: path.to_string_lossy().to_string(),
+            editor: editor[0].clone(),
+        }))
+    }
+
+    // =========================================================================
+    // Workspace Files
+    // =========================================================================
+
+    async fn get_workspace_files(
+        &self,
+        request: Request<GetWorkspaceFilesRequest>,
+    ) -> Result<Response<GetWorkspaceFilesResponse>, Status> {
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id;
+        let query = core::FileQuery {
+            prefix: req.prefix,
+            glob: req.glob,
+            offset: req.offset as usize,
+            limit: req.limit.map(|l| l as usize),
+        };
+
+        let page = self
+            .with_db(move |conn| core::workspace_files(conn, &workspace_id, &query))
+            .await?;
+
+        Ok(Response::new(GetWorkspaceFilesResponse {
+            total: page.total as u32,
+            files: page
+                .files
+                .into_iter()
+                .map(|path| FileEntry {
+                    path,
+                    status: "tracked".to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_workspace_tree(
+        &self,
+        request: Request<GetWorkspaceTreeRequest>,
+    ) -> Result<Response<GetWorkspaceTreeResponse>, Status> {
+        let req = request.into_inner();
+
+        let entries = self
+            .with_db(move |conn| core::workspace_tree(conn, &req.workspace_id, &req.dir))
+            .await?;
+
+        Ok(Response::new(GetWorkspaceTreeResponse {
+            entries: entries
+                .into_iter()
+                .map(|entry| TreeEntry {
+                    name: entry.name,
+                    path: entry.path,
+                    kind: entry.kind,
+                    size: entry.size,
+                    status: entry.status,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_workspace_changes(
+        &self,
+        request: Request<GetWorkspaceChangesRequest>,
+    ) -> Result<Response<GetWorkspaceChangesResponse>, Status> {
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id;
+
+        let changes: Vec<core::WorkspaceChange> = self
+            .with_db(move |conn| core::workspace_changes(conn, &workspace_id))
+            .await?;
+
+        Ok(Response::new(GetWorkspaceChangesResponse {
+            changes: changes.into_iter().map(changed_file_proto).collect(),
+        }))
     }
-}
 
-struct ConductorService {
-    home: PathBuf,
-    agents: Arc<Mutex<HashMap<String, ActiveAgentHandle>>>,
-    start_time: Instant,
-}
+    async fn get_workspace_snapshot(
+        &self,
+        request: Request<GetWorkspaceSnapshotRequest>,
+    ) -> Result<Response<WorkspaceSnapshot>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let snapshot = self
+            .with_db(move |conn| core::workspace_snapshot(conn, &workspace_id))
+            .await?;
 
-impl ConductorService {
-    fn new(home: PathBuf) -> Self {
-        Self {
-            home,
-            agents: Arc::new(Mutex::new(HashMap::new())),
-            start_time: Instant::now(),
+        Ok(Response::new(WorkspaceSnapshot {
+            files: snapshot.files,
+            statuses: snapshot.statuses.into_iter().collect(),
+            changes: snapshot.changes.into_iter().map(changed_file_proto).collect(),
+        }))
+    }
+
+    async fn get_file_content(
+        &self,
+        request: Request<GetFileContentRequest>,
+    ) -> Result<Response<GetFileContentResponse>, Status> {
+        let req = request.into_inner();
+        let workspace_id = req.workspace_id;
+        let file_path = req.file_path;
+
+        let data = self
+            .with_db(move |conn| core::workspace_file_bytes(conn, &workspace_id, &file_path))
+            .await?;
+
+        let hash = core::content_hash(&data);
+        let encoding = core::content_encoding(&data).to_string();
+        if req.raw {
+            return Ok(Response::new(GetFileContentResponse { content: String::new(), hash, data, encoding }));
         }
+        // Moves the buffer rather than copying it
+        let content = String::from_utf8(data)
+            .map_err(|_| Status::invalid_argument(format!("file is not valid utf-8 ({encoding}); request it raw")))?;
+        Ok(Response::new(GetFileContentResponse { content, hash, data: Vec::new(), encoding }))
     }
 
-    // Helper to run blocking DB operations
-    async fn with_db<F, T>(&self, f: F) -> Result<T, Status>
-    where
-        F: FnOnce(rusqlite::Connection) -> Result<T, anyhow::Error> + Send + 'static,
-        T: Send + 'static,
-    {
-        let home = self.home.clone();
-        tokio::task::spawn_blocking(move || {
-            let conn = core::connect(&home)?;
-            f(conn)
-        })
-        .await
-        .map_err(|e| Status::internal(format!("Task join error: {}", e)))?
-        .map_err(|e| Status::internal(e.to_string()))
+    async fn put_file_content(
+        &self,
+        request: Request<PutFileContentRequest>,
+    ) -> Result<Response<PutFileContentResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({
+            "path": req.file_path,
+            "bytes": req.content.len(),
+            "conditional": req.expected_hash.is_some(),
+            "client": client,
+        });
+        let workspace_id = req.workspace_id.clone();
+
+        let saved = self
+            .with_db(move |conn| {
+                Ok(core::workspace_file_save(
+                    conn,
+                    &workspace_id,
+                    &req.file_path,
+                    &req.content,
+                    req.expected_hash.as_deref(),
+                ))
+            })
+            .await;
+        // ABORTED lets clients tell a stale expected_hash from a failed write
+        let result = match saved {
+            Ok(Ok(hash)) => Ok(hash),
+            Ok(Err(e)) if e.is::<core::FileConflict>() => Err(Status::aborted(e.to_string())),
+            Ok(Err(e)) => Err(Status::internal(e.to_string())),
+            Err(status) => Err(status),
+        };
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.save_file", Some(req.workspace_id), &result, metadata).await;
+
+        Ok(Response::new(PutFileContentResponse { hash: result? }))
     }
-}
 
-#[tonic::async_trait]
-impl Conductor for ConductorService {
-    // =========================================================================
-    // Repository Management
-    // =========================================================================
+    async fn rename_file(
+        &self,
+        request: Request<RenameFileRequest>,
+    ) -> Result<Response<RenameFileResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "from": req.from_path, "to": req.to_path, "client": client });
+        let workspace_id = req.workspace_id.clone();
 
-    async fn list_repos(
+        let result = self
+            .with_db(move |conn| core::workspace_file_rename(conn, &workspace_id, &req.from_path, &req.to_path))
+            .await;
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.rename_file", Some(req.workspace_id), &result, metadata).await;
+
+        Ok(Response::new(RenameFileResponse { staged: result? }))
+    }
+
+    async fn delete_file(
         &self,
-        _request: Request<ListReposRequest>,
-    ) -> Result<Response<ListReposResponse>, Status> {
-        let repos: Vec<core::Repo> = self
-            .with_db(|conn| Ok(core::repo_list(&conn)?))
+        request: Request<DeleteFileRequest>,
+    ) -> Result<Response<DeleteFileResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "path": req.file_path, "client": client });
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_file_delete(conn, &workspace_id, &req.file_path))
+            .await;
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.delete_file", Some(req.workspace_id), &result, metadata).await;
+
+        Ok(Response::new(DeleteFileResponse { staged: result? }))
+    }
+
+    async fn create_directory(
+        &self,
+        request: Request<CreateDirectoryRequest>,
+    ) -> Result<Response<CreateDirectoryResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "path": req.dir_path, "client": client });
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_dir_create(conn, &workspace_id, &req.dir_path))
+            .await;
+        self.audit("workspace.create_directory", Some(req.workspace_id), &result, metadata).await;
+        result?;
+
+        Ok(Response::new(CreateDirectoryResponse { success: true }))
+    }
+
+    async fn stage_files(
+        &self,
+        request: Request<StageFilesRequest>,
+    ) -> Result<Response<StageFilesResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "paths": req.paths, "client": client });
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_stage(conn, &workspace_id, &req.paths))
+            .await;
+        self.audit("workspace.stage", Some(req.workspace_id), &result, metadata).await;
+        result?;
+
+        Ok(Response::new(StageFilesResponse { success: true }))
+    }
+
+    async fn unstage_files(
+        &self,
+        request: Request<UnstageFilesRequest>,
+    ) -> Result<Response<UnstageFilesResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "paths": req.paths, "client": client });
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| core::workspace_unstage(conn, &workspace_id, &req.paths))
+            .await;
+        self.audit("workspace.unstage", Some(req.workspace_id), &result, metadata).await;
+        result?;
+
+        Ok(Response::new(UnstageFilesResponse { success: true }))
+    }
+
+    async fn discard_changes(
+        &self,
+        request: Request<DiscardChangesRequest>,
+    ) -> Result<Response<DiscardChangesResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({
+            "paths": req.paths,
+            "include_untracked": req.include_untracked,
+            "client": client,
+        });
+        let workspace_id = req.workspace_id.clone();
+
+        let result = self
+            .with_db(move |conn| {
+                core::workspace_discard(conn, &workspace_id, &req.paths, req.include_untracked)
+            })
+            .await;
+        self.ctx().enrichment.invalidate(&req.workspace_id);
+        self.audit("workspace.discard", Some(req.workspace_id), &result, metadata).await;
+        result?;
+
+        Ok(Response::new(DiscardChangesResponse { success: true }))
+    }
+
+    type StreamFileContentStream = Pin<Box<dyn Stream<Item = Result<FileContentChunk, Status>> + Send>>;
+
+    async fn stream_file_content(
+        &self,
+        request: Request<StreamFileContentRequest>,
+    ) -> Result<Response<Self::StreamFileContentStream>, Status> {
+        let req = request.into_inner();
+        let max_bytes = req.max_bytes.unwrap_or(STREAM_MAX_BYTES);
+        // At least one sniff window per chunk, so the first decides binary
+        let chunk_size = req
+            .chunk_size
+            .map_or(STREAM_CHUNK_SIZE, |size| size as usize)
+            .clamp(STREAM_CHUNK_RANGE.0, STREAM_CHUNK_RANGE.1);
+        let workspace_id = req.workspace_id;
+        let file_path = req.file_path;
+
+        let path = self
+            .with_db(move |conn| core::workspace_file_path(conn, &workspace_id, &file_path))
             .await?;
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| Status::not_found(format!("{}: {}", path.display(), e)))?;
+        let meta = file.metadata().await.map_err(|e| Status::internal(e.to_string()))?;
+        if !meta.is_file() {
+            return Err(Status::invalid_argument(format!("not a file: {}", path.display())));
+        }
+        // The size when opened; a file still being written is cut off there
+        let total_size = meta.len();
 
-        Ok(Response::new(ListReposResponse {
-            repos: repos
-                .into_iter()
-                .map(|r| Repo {
-                    id: r.id,
-                    name: r.name,
-                    root_path: r.root_path,
-                    default_branch: r.default_branch,
-                    remote_url: r.remote_url,
-                })
-                .collect(),
-        }))
+        let stream = async_stream::stream! {
+            let mut offset = 0u64;
+            let mut binary = None;
+            loop {
+                let want = (chunk_size as u64).min(max_bytes.saturating_sub(offset)).min(total_size - offset);
+                let mut data = vec![0u8; want as usize];
+                let mut filled = 0;
+                while filled < data.len() {
+                    match file.read(&mut data[filled..]).await {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(e) => {
+                            yield Err(Status::internal(format!("read failed: {}", e)));
+                            return;
+                        }
+                    }
+                }
+                data.truncate(filled);
+                let binary = *binary.get_or_insert_with(|| core::is_binary(&data));
+                let chunk_offset = offset;
+                offset += filled as u64;
+                let last = filled == 0 || offset >= total_size || offset >= max_bytes;
+                yield Ok(FileContentChunk {
+                    data,
+                    offset: chunk_offset,
+                    total_size,
+                    binary,
+                    last,
+                    truncated: last && offset < total_size,
+                });
+                if last {
+                    break;
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(stream)))
     }
 
-    async fn add_repo(&self, request: Request<AddRepoRequest>) -> Result<Response<Repo>, Status> {
+    async fn get_file_diff(
+        &self,
+        request: Request<GetFileDiffRequest>,
+    ) -> Result<Response<GetFileDiffResponse>, Status> {
         let req = request.into_inner();
-        let path = PathBuf::from(&req.path);
+        let options = diff_options(&req);
 
-        let repo = self
-            .with_db(move |conn| Ok(core::repo_add(&conn, &path, None, None)?))
+        let diff = self
+            .with_db(move |conn| core::workspace_file_diff_with(conn, &req.workspace_id, &req.file_path, &options))
             .await?;
 
-        Ok(Response::new(Repo {
-            id: repo.id,
-            name: repo.name,
-            root_path: repo.root_path,
-            default_branch: repo.default_branch,
-            remote_url: repo.remote_url,
+        Ok(Response::new(GetFileDiffResponse {
+            diff: diff.diff,
+            total_lines: diff.total_lines as u32,
+            omitted_hunks: diff.omitted_hunks,
         }))
     }
 
-    async fn add_repo_url(
+    async fn get_file_diff_structured(
         &self,
-        request: Request<AddRepoUrlRequest>,
-    ) -> Result<Response<Repo>, Status> {
+        request: Request<GetFileDiffRequest>,
+    ) -> Result<Response<GetFileDiffStructuredResponse>, Status> {
         let req = request.into_inner();
-        let home = self.home.clone();
-        let url = req.url;
+        let options = diff_options(&req);
 
-        let repo = self
-            .with_db(move |conn| Ok(core::repo_add_url(&conn, &home, &url, None, None)?))
+        let diff = self
+            .with_db(move |conn| core::workspace_file_diff_structured(conn, &req.workspace_id, &req.file_path, &options))
             .await?;
 
-        Ok(Response::new(Repo {
-            id: repo.id,
-            name: repo.name,
-            root_path: repo.root_path,
-            default_branch: repo.default_branch,
-            remote_url: repo.remote_url,
+        Ok(Response::new(GetFileDiffStructuredResponse {
+            binary: diff.binary,
+            omitted_hunks: diff.omitted_hunks,
+            hunks: diff
+                .hunks
+                .into_iter()
+                .map(|hunk| DiffHunk {
+                    old_start: hunk.old_start,
+                    old_lines: hunk.old_lines,
+                    new_start: hunk.new_start,
+                    new_lines: hunk.new_lines,
+                    header: hunk.header,
+                    lines: hunk
+                        .lines
+                        .into_iter()
+                        .map(|line| DiffLine {
+                            kind: line.kind,
+                            content: line.content,
+                            old_line: line.old_line,
+                            new_line: line.new_line,
+                            no_newline: line.no_newline,
+                        })
+                        .collect(),
+                })
+                .collect(),
         }))
     }
 
-    // =========================================================================
-    // Workspace Management
-    // =========================================================================
-
-    async fn list_workspaces(
+    async fn get_workspace_log(
         &self,
-        request: Request<ListWorkspacesRequest>,
-    ) -> Result<Response<ListWorkspacesResponse>, Status> {
+        request: Request<GetWorkspaceLogRequest>,
+    ) -> Result<Response<GetWorkspaceLogResponse>, Status> {
         let req = request.into_inner();
-        let repo_id = req.repo_id;
+        let options = core::LogOptions {
+            limit: req.limit.map(|limit| limit as usize),
+            since_base: req.since_base.unwrap_or(true),
+        };
 
-        let workspaces: Vec<core::Workspace> = self
-            .with_db(move |conn| Ok(core::workspace_list(&conn, repo_id.as_deref())?))
+        let commits = self
+            .with_db(move |conn| core::workspace_log(conn, &req.workspace_id, &options))
             .await?;
 
-        Ok(Response::new(ListWorkspacesResponse {
-            workspaces: workspaces
+        Ok(Response::new(GetWorkspaceLogResponse {
+            commits: commits
                 .into_iter()
-                .map(|w| Workspace {
-                    id: w.id,
-                    repository_id: w.repo_id,
-                    directory_name: w.name,
-                    path: w.path,
-                    branch: w.branch,
-                    base_branch: w.base_branch,
-                    state: w.state.to_string(),
+                .map(|commit| LogCommit {
+                    sha: commit.sha,
+                    author: commit.author,
+                    author_email: commit.author_email,
+                    date: commit.date,
+                    subject: commit.subject,
+                    files: commit.stat.files,
+                    insertions: commit.stat.insertions,
+                    deletions: commit.stat.deletions,
                 })
                 .collect(),
         }))
     }
 
-    async fn create_workspace(
+    async fn get_file_blame(
         &self,
-        request: Request<CreateWorkspaceRequest>,
-    ) -> Result<Response<Workspace>, Status> {
+        request: Request<GetFileBlameRequest>,
+    ) -> Result<Response<GetFileBlameResponse>, Status> {
         let req = request.into_inner();
-        let home = self.home.clone();
-        let repo_id = req.repo_id;
-        let name = req.name;
 
-        let ws = self
-            .with_db(move |conn| {
-                Ok(core::workspace_create(
-                    &conn,
-                    &home,
-                    &repo_id,
-                    name.as_deref(),
-                    None,
-                    None,
-                )?)
-            })
+        let lines = self
+            .with_db(move |conn| core::workspace_file_blame(conn, &req.workspace_id, &req.file_path))
             .await?;
 
-        Ok(Response::new(Workspace {
-            id: ws.id,
-            repository_id: ws.repo_id,
-            directory_name: ws.name,
-            path: ws.path,
-            branch: ws.branch,
-            base_branch: ws.base_branch,
-            state: ws.state.to_string(),
+        Ok(Response::new(GetFileBlameResponse {
+            lines: lines
+                .into_iter()
+                .map(|line| BlameLine {
+                    line: line.line,
+                    commit: line.commit,
+                    author: line.author,
+                    author_email: line.author_email,
+                    time: line.time,
+                    summary: line.summary,
+                    content: line.content,
+                })
+                .collect(),
         }))
     }
 
-    async fn archive_workspace(
+    async fn get_workspace_diff(
         &self,
-        request: Request<ArchiveWorkspaceRequest>,
-    ) -> Result<Response<ArchiveWorkspaceResponse>, Status> {
+        request: Request<GetWorkspaceDiffRequest>,
+    ) -> Result<Response<GetWorkspaceDiffResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+
+        let diff = self
+            .with_db(move |conn| core::workspace_diff(conn, &workspace_id))
+            .await?;
+
+        Ok(Response::new(GetWorkspaceDiffResponse { diff }))
+    }
+
+    type WatchWorkspaceStream = Pin<Box<dyn Stream<Item = Result<WorkspaceEvent, Status>> + Send>>;
+
+    async fn watch_workspace(
+        &self,
+        request: Request<WatchWorkspaceRequest>,
+    ) -> Result<Response<Self::WatchWorkspaceStream>, Status> {
         let req = request.into_inner();
-        let home = self.home.clone();
         let workspace_id = req.workspace_id;
-        let force = req.force;
+        let debounce = Duration::from_millis(u64::from(req.debounce_ms.unwrap_or(300)));
 
-        let result: Result<core::ArchiveResult, Status> = self
-            .with_db(move |conn| Ok(core::workspace_archive(&conn, &home, &workspace_id, force)?))
-            .await;
+        let lookup_id = workspace_id.clone();
+        let (ws_path, git_dir) = self
+            .with_db(move |conn| {
+                let path = core::workspace_path(conn, &lookup_id)?;
+                let git_dir = core::workspace_git_dir(conn, &lookup_id)?;
+                Ok((path, git_dir))
+            })
+            .await?;
 
-        match result {
-            Ok(_) => Ok(Response::new(ArchiveWorkspaceResponse {
-                success: true,
-                error: None,
-            })),
-            Err(e) => Ok(Response::new(ArchiveWorkspaceResponse {
-                success: false,
-                error: Some(e.to_string()),
-            })),
-        }
+        let mut watcher = WorkspaceWatcher::new(ws_path.clone(), git_dir)
+            .map_err(|e| Status::internal(format!("Failed to watch workspace: {}", e)))?;
+        info!("Watching workspace {}", workspace_id);
+        let enrichment = self.ctx().enrichment.clone();
+
+        let stream = async_stream::stream! {
+            let head_path = ws_path.clone();
+            let mut last_head = tokio::task::spawn_blocking(move || core::workspace_head(&head_path).ok())
+                .await
+                .ok()
+                .flatten();
+            let conflict_path = ws_path.clone();
+            let mut last_conflicts = tokio::task::spawn_blocking(move || core::workspace_conflicts(&conflict_path).ok())
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+
+            while let Some(batch) = watcher.next_batch(debounce).await {
+                // Ignored paths included: a stale entry only costs one recompute
+                enrichment.invalidate(&workspace_id);
+                if batch.head_changed || batch.refs_changed {
+                    core::base_ref_cache_invalidate();
+                }
+                if batch.refs_changed {
+                    // A fetch can move the base, and with it the workspace's changes
+                    yield Ok(WorkspaceEvent {
+                        workspace_id: workspace_id.clone(),
+                        event_type: "refs_changed".to_string(),
+                        paths: Vec::new(),
+                        branch: None,
+                        head: None,
+                    });
+                }
+                if batch.head_changed {
+                    let head_path = ws_path.clone();
+                    let head = tokio::task::spawn_blocking(move || core::workspace_head(&head_path).ok())
+                        .await
+                        .ok()
+                        .flatten();
+                    if let Some(head) = head {
+                        if last_head.as_ref() != Some(&head) {
+                            yield Ok(WorkspaceEvent {
+                                workspace_id: workspace_id.clone(),
+                                event_type: "branch_changed".to_string(),
+                                paths: Vec::new(),
+                                branch: head.branch.clone(),
+                                head: Some(head.head.clone()),
+                            });
+                            last_head = Some(head);
+                        }
+                    }
+
+                    // Merges, rebases and stash pops leave unmerged entries in the index
+                    let conflict_path = ws_path.clone();
+                    let conflicts = tokio::task::spawn_blocking(move || core::workspace_conflicts(&conflict_path).ok())
+                        .await
+                        .ok()
+                        .flatten();
+                    if let Some(conflicts) = conflicts {
+                        if !conflicts.is_empty() && conflicts != last_conflicts {
+                            yield Ok(WorkspaceEvent {
+                                workspace_id: workspace_id.clone(),
+                                event_type: "conflicts_detected".to_string(),
+                                paths: conflicts.clone(),
+                                branch: None,
+                                head: None,
+                            });
+                        }
+                        last_conflicts = conflicts;
+                    }
+                }
+
+                if batch.paths.is_empty() {
+                    continue;
+                }
+                let filter_path = ws_path.clone();
+                let paths = batch.paths;
+                let filtered = tokio::task::spawn_blocking(move || core::workspace_filter_ignored(&filter_path, &paths))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|res| res);
+                match filtered {
+                    Ok(paths) if paths.is_empty() => {}
+                    Ok(paths) => {
+                        yield Ok(WorkspaceEvent {
+                            workspace_id: workspace_id.clone(),
+                            event_type: "files_changed".to_string(),
+                            paths,
+                            branch: None,
+                            head: None,
+                        });
+                    }
+                    Err(e) => warn!("Watch filter failed for {}: {}", workspace_id, e),
+                }
+            }
+            info!("Stopped watching workspace {}", workspace_id);
+        };
+
+        Ok(Response::new(Box::pin(stream)))
     }
 
     // =========================================================================
-    // Workspace Files
+    // Review Comments
     // =========================================================================
 
-    async fn get_workspace_files(
+    async fn list_review_comments(
         &self,
-        request: Request<GetWorkspaceFilesRequest>,
-    ) -> Result<Response<GetWorkspaceFilesResponse>, Status> {
+        request: Request<ListReviewCommentsRequest>,
+    ) -> Result<Response<ListReviewCommentsResponse>, Status> {
         let req = request.into_inner();
-        let workspace_id = req.workspace_id;
-
-        let files: Vec<String> = self
-            .with_db(move |conn| Ok(core::workspace_files(&conn, &workspace_id)?))
+        let comments = self
+            .with_db(move |conn| core::review_comment_list(conn, &req.workspace_id, req.include_resolved))
             .await?;
-
-        Ok(Response::new(GetWorkspaceFilesResponse {
-            files: files
-                .into_iter()
-                .map(|path| FileEntry {
-                    path,
-                    status: "tracked".to_string(),
-                })
-                .collect(),
+        Ok(Response::new(ListReviewCommentsResponse {
+            comments: comments.into_iter().map(review_comment_proto).collect(),
         }))
     }
 
-    async fn get_workspace_changes(
+    async fn add_review_comment(
         &self,
-        request: Request<GetWorkspaceChangesRequest>,
-    ) -> Result<Response<GetWorkspaceChangesResponse>, Status> {
+        request: Request<AddReviewCommentRequest>,
+    ) -> Result<Response<ReviewComment>, Status> {
+        let client = client_meta(&request);
         let req = request.into_inner();
-        let workspace_id = req.workspace_id;
+        let metadata = serde_json::json!({
+            "file_path": req.file_path,
+            "start_line": req.start_line,
+            "end_line": req.end_line,
+            "client": client,
+        });
+        let workspace_id = req.workspace_id.clone();
+        let result = self
+            .with_db(move |conn| {
+                let lines = (req.start_line, req.end_line.max(req.start_line));
+                core::review_comment_add(conn, &req.workspace_id, &req.file_path, lines, req.author.as_deref(), &req.body)
+            })
+            .await;
+        self.audit("review.comment_add", Some(workspace_id), &result, metadata).await;
+        Ok(Response::new(review_comment_proto(result?)))
+    }
 
-        let changes: Vec<core::WorkspaceChange> = self
-            .with_db(move |conn| Ok(core::workspace_changes(&conn, &workspace_id)?))
+    async fn update_review_comment(
+        &self,
+        request: Request<UpdateReviewCommentRequest>,
+    ) -> Result<Response<ReviewComment>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let mut metadata = serde_json::json!({
+            "comment_id": req.id,
+            "edited": req.body.is_some(),
+            "resolved": req.resolved,
+            "client": client,
+        });
+        let result = self
+            .with_db(move |conn| core::review_comment_update(conn, &req.id, req.body.as_deref(), req.resolved))
+            .await;
+        if let Ok(comment) = &result {
+            metadata["comment_id"] = comment.id.clone().into();
+        }
+        let target = result.as_ref().ok().map(|comment| comment.workspace_id.clone());
+        self.audit("review.comment_update", target, &result, metadata).await;
+        Ok(Response::new(review_comment_proto(result?)))
+    }
+
+    async fn delete_review_comment(
+        &self,
+        request: Request<DeleteReviewCommentRequest>,
+    ) -> Result<Response<DeleteReviewCommentResponse>, Status> {
+        let client = client_meta(&request);
+        let id = request.into_inner().id;
+        let mut metadata = serde_json::json!({ "comment_id": id, "client": client });
+        let result = self.with_db(move |conn| core::review_comment_delete(conn, &id)).await;
+        if let Ok(comment) = &result {
+            metadata["comment_id"] = comment.id.clone().into();
+        }
+        let target = result.as_ref().ok().map(|comment| comment.workspace_id.clone());
+        self.audit("review.comment_delete", target, &result, metadata).await;
+        result?;
+        Ok(Response::new(DeleteReviewCommentResponse {}))
+    }
+
+    async fn get_review_prompt(
+        &self,
+        request: Request<GetReviewPromptRequest>,
+    ) -> Result<Response<GetReviewPromptResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let (prompt, comment_count) = self
+            .with_db(move |conn| {
+                let count = core::review_comment_list(conn, &workspace_id, false)?.len();
+                Ok((core::review_prompt(conn, &workspace_id)?, count as u32))
+            })
             .await?;
+        Ok(Response::new(GetReviewPromptResponse { prompt, comment_count }))
+    }
+
+    async fn sync_review_comments(
+        &self,
+        request: Request<SyncReviewCommentsRequest>,
+    ) -> Result<Response<SyncReviewCommentsResponse>, Status> {
+        let client = client_meta(&request);
+        let workspace_id = request.into_inner().workspace_id;
+        let id = workspace_id.clone();
+        let result = self.with_db(move |conn| core::review_comments_sync(conn, &id)).await;
+        let mut metadata = serde_json::json!({ "client": client });
+        if let Ok(sync) = &result {
+            metadata["pr"] = sync.pr_number.into();
+            metadata["added"] = sync.added.len().into();
+            metadata["updated"] = sync.updated.into();
+        }
+        self.audit("review.sync", Some(workspace_id), &result, metadata).await;
+        let sync = result?;
+        Ok(Response::new(SyncReviewCommentsResponse {
+            pr_number: sync.pr_number,
+            added: sync.added.into_iter().map(review_comment_proto).collect(),
+            updated: sync.updated,
+        }))
+    }
+
+    // =========================================================================
+    // Tasks
+    // =========================================================================
 
-        Ok(Response::new(GetWorkspaceChangesResponse {
-            changes: changes
-                .into_iter()
-                .map(|c| ChangedFile {
-                    path: c.path,
-                    status: c.status,
-                    insertions: 0, // Not available in core::WorkspaceChange
-                    deletions: 0,
-                })
-                .collect(),
+    async fn list_tasks(&self, request: Request<ListTasksRequest>) -> Result<Response<ListTasksResponse>, Status> {
+        let status: Option<core::TaskStatus> = request
+            .into_inner()
+            .status
+            .map(|status| status.parse())
+            .transpose()
+            .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let tasks = self.with_db(move |conn| core::task_list(conn, status)).await?;
+        Ok(Response::new(ListTasksResponse {
+            tasks: tasks.into_iter().map(task_proto).collect(),
         }))
     }
 
-    async fn get_file_content(
-        &self,
-        request: Request<GetFileContentRequest>,
-    ) -> Result<Response<GetFileContentResponse>, Status> {
+    async fn get_task(&self, request: Request<GetTaskRequest>) -> Result<Response<Task>, Status> {
+        let id = request.into_inner().id;
+        let task = self.with_db(move |conn| core::task_get(conn, &id)).await?;
+        Ok(Response::new(task_proto(task)))
+    }
+
+    async fn create_task(&self, request: Request<CreateTaskRequest>) -> Result<Response<Task>, Status> {
+        let client = client_meta(&request);
         let req = request.into_inner();
-        let workspace_id = req.workspace_id;
-        let file_path = req.file_path;
+        let metadata = serde_json::json!({ "title": req.title, "workspaces": req.workspace_ids, "client": client });
+        let result = self
+            .with_db(move |conn| core::task_create(conn, &req.title, &req.description, &req.workspace_ids))
+            .await;
+        let target = result.as_ref().ok().map(|task| task.id.clone());
+        self.audit("task.create", target, &result, metadata).await;
+        Ok(Response::new(task_proto(result?)))
+    }
 
-        let content = self
-            .with_db(move |conn| Ok(core::workspace_file_content(&conn, &workspace_id, &file_path)?))
-            .await?;
+    async fn update_task(&self, request: Request<UpdateTaskRequest>) -> Result<Response<Task>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let status: Option<core::TaskStatus> = req
+            .status
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
+        let metadata = serde_json::json!({
+            "title": req.title,
+            "description_changed": req.description.is_some(),
+            "status": req.status,
+            "client": client,
+        });
+        let result = self
+            .with_db(move |conn| core::task_update(conn, &req.id, req.title.as_deref(), req.description.as_deref(), status))
+            .await;
+        let target = result.as_ref().ok().map(|task| task.id.clone());
+        self.audit("task.update", target, &result, metadata).await;
+        Ok(Response::new(task_proto(result?)))
+    }
 
-        Ok(Response::new(GetFileContentResponse { content }))
+    async fn link_task_workspace(
+        &self,
+        request: Request<LinkTaskWorkspaceRequest>,
+    ) -> Result<Response<Task>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let metadata = serde_json::json!({ "workspace_id": req.workspace_id, "unlink": req.unlink, "client": client });
+        let result = self
+            .with_db(move |conn| {
+                if req.unlink {
+                    core::task_unlink(conn, &req.task_id, &req.workspace_id)
+                } else {
+                    core::task_link(conn, &req.task_id, &req.workspace_id)
+                }
+            })
+            .await;
+        let target = result.as_ref().ok().map(|task| task.id.clone());
+        self.audit("task.link", target, &result, metadata).await;
+        Ok(Response::new(task_proto(result?)))
     }
 
-    async fn get_file_diff(
+    async fn choose_task_workspace(
         &self,
-        request: Request<GetFileDiffRequest>,
-    ) -> Result<Response<GetFileDiffResponse>, Status> {
+        request: Request<ChooseTaskWorkspaceRequest>,
+    ) -> Result<Response<Task>, Status> {
+        let client = client_meta(&request);
         let req = request.into_inner();
-        let workspace_id = req.workspace_id;
-        let file_path = req.file_path;
+        let metadata = serde_json::json!({ "workspace_id": req.workspace_id, "client": client });
+        let result = self
+            .with_db(move |conn| core::task_choose(conn, &req.task_id, &req.workspace_id))
+            .await;
+        let target = result.as_ref().ok().map(|task| task.id.clone());
+        self.audit("task.choose", target, &result, metadata).await;
+        Ok(Response::new(task_proto(result?)))
+    }
 
-        let diff = self
-            .with_db(move |conn| Ok(core::workspace_file_diff(&conn, &workspace_id, &file_path)?))
-            .await?;
+    async fn delete_task(&self, request: Request<DeleteTaskRequest>) -> Result<Response<DeleteTaskResponse>, Status> {
+        let client = client_meta(&request);
+        let id = request.into_inner().id;
+        let metadata = serde_json::json!({ "client": client });
+        let result = self.with_db(move |conn| core::task_delete(conn, &id)).await;
+        let target = result.as_ref().ok().map(|task| task.id.clone());
+        self.audit("task.delete", target, &result, metadata).await;
+        result?;
+        Ok(Response::new(DeleteTaskResponse {}))
+    }
 
-        Ok(Response::new(GetFileDiffResponse { diff }))
+    // =========================================================================
+    // Search
+    // =========================================================================
+
+    async fn search(&self, request: Request<SearchRequest>) -> Result<Response<SearchResponse>, Status> {
+        let req = request.into_inner();
+        if req.query.trim().is_empty() {
+            return Err(Status::invalid_argument("query is required"));
+        }
+        let limit = req.limit.unwrap_or(50) as usize;
+        let hits = self.with_db(move |conn| core::search(conn, &req.query, limit)).await?;
+        Ok(Response::new(SearchResponse {
+            hits: hits
+                .into_iter()
+                .map(|hit| SearchHit {
+                    kind: hit.kind.to_string(),
+                    score: hit.score,
+                    repo_id: hit.repo_id,
+                    repo: hit.repo,
+                    workspace_id: hit.workspace_id,
+                    workspace: hit.workspace,
+                    label: hit.label,
+                    snippet: hit.snippet,
+                })
+                .collect(),
+        }))
     }
 
     // =========================================================================
@@ -319,20 +2515,7 @@ impl Conductor for ConductorService {
             .map_err(|e| Status::internal(e.to_string()))?
             .map_err(|e| Status::internal(e.to_string()))?;
 
-        Ok(Response::new(match session {
-            Some(s) => SessionState {
-                agent_id: Some(s.agent_id),
-                resume_id: s.resume_id,
-                started_at: Some(s.started_at),
-                updated_at: Some(s.updated_at),
-            },
-            None => SessionState {
-                agent_id: None,
-                resume_id: None,
-                started_at: None,
-                updated_at: None,
-            },
-        }))
+        Ok(Response::new(session_proto(session)))
     }
 
     async fn create_session(
@@ -348,12 +2531,7 @@ impl Conductor for ConductorService {
             .map_err(|e| Status::internal(e.to_string()))?
             .map_err(|e| Status::internal(e.to_string()))?;
 
-        Ok(Response::new(SessionState {
-            agent_id: Some(session.agent_id),
-            resume_id: session.resume_id,
-            started_at: Some(session.started_at),
-            updated_at: Some(session.updated_at),
-        }))
+        Ok(Response::new(session_proto(Some(session))))
     }
 
     async fn set_resume_id(
@@ -370,12 +2548,7 @@ impl Conductor for ConductorService {
                 .map_err(|e| Status::internal(e.to_string()))?
                 .map_err(|e| Status::internal(e.to_string()))?;
 
-        Ok(Response::new(SessionState {
-            agent_id: Some(session.agent_id),
-            resume_id: session.resume_id,
-            started_at: Some(session.started_at),
-            updated_at: Some(session.updated_at),
-        }))
+        Ok(Response::new(session_proto(Some(session))))
     }
 
     // =========================================================================
@@ -425,15 +2598,87 @@ impl Conductor for ConductorService {
         &self,
         request: Request<ClearChatRequest>,
     ) -> Result<Response<ClearChatResponse>, Status> {
+        let client = client_meta(&request);
+        let req = request.into_inner();
+        let path = PathBuf::from(&req.workspace_path);
+
+        let result = match tokio::task::spawn_blocking(move || core::chat_clear(&path)).await {
+            Ok(res) => res.map_err(|e| Status::internal(e.to_string())),
+            Err(e) => Err(Status::internal(e.to_string())),
+        };
+        self.audit("chat.clear", Some(req.workspace_path), &result, serde_json::json!({ "client": client }))
+            .await;
+        result?;
+
+        Ok(Response::new(ClearChatResponse { success: true }))
+    }
+
+    async fn export_chat(
+        &self,
+        request: Request<ExportChatRequest>,
+    ) -> Result<Response<ExportChatResponse>, Status> {
         let req = request.into_inner();
+        let format: core::ChatExportFormat = req
+            .format
+            .parse()
+            .map_err(|e: anyhow::Error| Status::invalid_argument(e.to_string()))?;
         let path = PathBuf::from(&req.workspace_path);
 
-        tokio::task::spawn_blocking(move || core::chat_clear(&path))
+        let content = self.with_db(move |conn| core::chat_export(conn, &path, format)).await?;
+        Ok(Response::new(ExportChatResponse { content }))
+    }
+
+    async fn list_archives(
+        &self,
+        request: Request<ListArchivesRequest>,
+    ) -> Result<Response<ListArchivesResponse>, Status> {
+        let workspace_id = request.into_inner().workspace_id;
+        let home = self.ctx().home.clone();
+        let archives = tokio::task::spawn_blocking(move || core::archive_list(&home, workspace_id.as_deref()))
             .await
             .map_err(|e| Status::internal(e.to_string()))?
             .map_err(|e| Status::internal(e.to_string()))?;
 
-        Ok(Response::new(ClearChatResponse { success: true }))
+        Ok(Response::new(ListArchivesResponse {
+            archives: archives
+                .into_iter()
+                .map(|a| ArchiveEntry {
+                    workspace_id: a.workspace_id,
+                    timestamp: a.timestamp,
+                    has_session: a.has_session,
+                    has_chat: a.has_chat,
+                    bytes: a.bytes,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_archive(
+        &self,
+        request: Request<GetArchiveRequest>,
+    ) -> Result<Response<ArchivedSession>, Status> {
+        let req = request.into_inner();
+        let home = self.ctx().home.clone();
+        let archived =
+            tokio::task::spawn_blocking(move || core::archive_read(&home, &req.workspace_id, &req.timestamp))
+                .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| Status::not_found(e.to_string()))?;
+
+        Ok(Response::new(ArchivedSession {
+            workspace_id: archived.workspace_id,
+            timestamp: archived.timestamp,
+            session: Some(session_proto(archived.session)),
+            messages: archived
+                .chat
+                .into_iter()
+                .map(|entry| ChatMessage {
+                    role: entry.role,
+                    content: entry.content,
+                    timestamp: entry.timestamp,
+                })
+                .collect(),
+        }))
     }
 
     // =========================================================================
@@ -446,152 +2691,519 @@ impl Conductor for ConductorService {
         &self,
         request: Request<RunAgentRequest>,
     ) -> Result<Response<Self::RunAgentStream>, Status> {
-        let req = request.into_inner();
+        let client = client_meta(&request);
+        let mut req = request.into_inner();
         let session_id = req.session_id.clone();
         let engine = req.engine.clone();
         let cwd = req.cwd.clone();
 
-        // Check if session is already running (prevent double-starts)
-        {
-            let agents = self.agents.lock().await;
+        // One run per workspace unless forced; runs outside any workspace aren't locked
+        let dir = PathBuf::from(&req.cwd);
+        let workspace = self.with_db(move |conn| core::workspace_for_path(conn, &dir)).await.ok().flatten();
+
+        // Check and claim the session id and workspace together, so two runs starting at
+        // once can't both get past the checks; the claim shows as a run without a process
+        let events = AgentEvents::new(session_id.clone());
+        let reservation = {
+            let mut agents = self.agents.lock().await;
             if agents.contains_key(&session_id) {
                 return Err(Status::already_exists(format!(
                     "Agent session {} is already running",
                     session_id
                 )));
             }
-        }
-
-        // Build command based on engine
-        let (cmd, args) = match engine.as_str() {
-            "claude" | "claude-code" => {
-                let mut args = vec![
-                    "-p".to_string(),
-                    "--output-format".to_string(),
-                    "stream-json".to_string(),
-                    "--verbose".to_string(),
-                    "--dangerously-skip-permissions".to_string(),
-                ];
-                if let Some(ref resume) = req.resume_id {
-                    args.push("--resume".to_string());
-                    args.push(resume.clone());
+            if let Some(lock) = workspace.as_ref().and_then(|ws| workspace_lock_in(&agents, &ws.path)) {
+                if !req.force {
+                    return Err(lock_refusal(&lock, "start another run"));
                 }
-                args.push("--".to_string());
-                args.push(req.prompt.clone());
-                ("claude", args)
             }
-            "codex" => (
-                "codex",
-                vec!["--full-auto".to_string(), req.prompt.clone()],
-            ),
-            "gemini" => (
-                "gemini",
-                vec![
-                    "-m".to_string(),
-                    "gemini-3-pro-preview".to_string(),
-                    "--yolo".to_string(),
-                    req.prompt.clone(),
-                ],
-            ),
-            _ => {
-                return Err(Status::invalid_argument(format!(
-                    "Unknown engine: {}",
-                    engine
-                )))
+            agents.insert(
+                session_id.clone(),
+                ActiveAgentHandle {
+                    engine: engine.clone(),
+                    cwd: cwd.clone(),
+                    started_at: Instant::now(),
+                    events: events.clone(),
+                    child: None,
+                    container: None,
+                },
+            );
+            RunReservation { agents: self.agents.clone(), session_id: session_id.clone(), kept: false }
+        };
+
+        // Titles and the chat get what the user asked, not the context put ahead of it
+        let user_prompt = req.prompt.clone();
+        // A resumed conversation already opened with the context
+        let context = if req.resume_id.is_none() && !req.skip_context {
+            let dir = PathBuf::from(&req.cwd);
+            self.with_db(move |conn| core::repo_context(conn, &dir)).await?
+        } else {
+            None
+        };
+        // A workspace created from an issue hands it to its first run, under the context
+        let issue = match &workspace {
+            Some(ws)
+                if ws.issue.is_some()
+                    && ws.activity.last_agent_run_at.is_none()
+                    && req.resume_id.is_none()
+                    && !req.skip_context =>
+            {
+                let ws_id = ws.id.clone();
+                self.with_db(move |conn| core::issues::workspace_issue(conn, &ws_id)).await?
+            }
+            _ => None,
+        };
+        if let Some(issue) = &issue {
+            req.prompt = issue.prompt_with(&req.prompt);
+        }
+        if let Some(context) = &context {
+            req.prompt = core::prompt_with_context(context, &req.prompt);
+        }
+        if !req.attachments.is_empty() {
+            let dir = PathBuf::from(&req.cwd);
+            let paths = req.attachments.clone();
+            let resolved = self
+                .with_db(move |conn| {
+                    let ws = core::workspace_for_path(conn, &dir)?
+                        .ok_or_else(|| anyhow::anyhow!("attachments need a workspace: {} is not in one", dir.display()))?;
+                    core::resolve_attachments(Path::new(&ws.path), &paths)
+                })
+                .await
+                .map_err(|e| Status::invalid_argument(e.message().to_string()))?;
+            req.attachments = resolved.iter().map(|path| path.display().to_string()).collect();
+        }
+        let config = core::config_load(&self.ctx().home).map_err(|e| Status::failed_precondition(format!("{:#}", e)))?;
+        // Engines to try in order; a resumed conversation belongs to the one engine
+        let mut chain = core::engine_chain(&config, &engine);
+        if req.resume_id.is_some() {
+            chain.truncate(1);
+        }
+        let policy = core::policy::PolicyChecker::new(&config.command_policy.unwrap_or_default())
+            .map_err(|e| Status::failed_precondition(format!("{:#}", e)))?;
+        let record_actions = config.chat.record_actions;
+        let checkpoint_mode = config.checkpoints;
+        let mut attempts = VecDeque::new();
+        for name in chain {
+            let attempt = self.agent_attempt(&RunAgentRequest { engine: name.clone(), ..req.clone() }).await;
+            attempts.push_back((name, attempt));
+        }
+        // Taken before the engine starts, so a rollback can undo all of the run
+        let before_run = match checkpoint_mode {
+            core::CheckpointMode::Off => None,
+            _ => checkpoint_workspace(self.ctx().db.clone(), cwd.clone(), "before run".to_string()).await,
+        };
+
+        // Read before the engine can commit anything
+        let base_sha = match &workspace {
+            Some(ws) => {
+                let ws_path = PathBuf::from(&ws.path);
+                tokio::task::spawn_blocking(move || core::workspace_head(&ws_path).ok().map(|head| head.head))
+                    .await
+                    .ok()
+                    .flatten()
             }
+            None => None,
         };
 
-        // Spawn the process
-        let mut child = Command::new(cmd)
-            .args(&args)
-            .current_dir(&cwd)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| Status::internal(format!("Failed to spawn {}: {}", cmd, e)))?;
+        // Start the first engine that will; the rest stay in reserve in case it fails at once
+        let mut skipped: Vec<(String, String)> = Vec::new();
+        let mut spawned = Err(Status::invalid_argument("No engine given"));
+        while let Some((name, attempt)) = attempts.pop_front() {
+            let attempt = match attempt {
+                Ok(attempt) => attempt,
+                Err(e) => {
+                    skipped.push((name, e.message().to_string()));
+                    spawned = Err(e);
+                    continue;
+                }
+            };
+            match spawn_attempt(&attempt, &cwd) {
+                Ok(child) => {
+                    spawned = Ok((attempt, child));
+                    break;
+                }
+                Err(e) => {
+                    skipped.push((name, e.clone()));
+                    spawned = Err(Status::internal(e));
+                }
+            }
+        }
+        let isolation = spawned
+            .as_ref()
+            .map_or_else(|_| req.isolation.clone(), |(attempt, _)| attempt.launch.isolation.to_string());
+        let mut metadata = serde_json::json!({
+            "engine": spawned.as_ref().map_or(&engine, |(attempt, _)| &attempt.engine),
+            "cwd": cwd,
+            "isolation": isolation,
+            "resume": req.resume_id.is_some(),
+            "prompt_chars": req.prompt.chars().count(),
+            "attachments": req.attachments,
+            "context": context.map(|context| context.path),
+            "issue": issue.map(|issue| format!("{}:{}", issue.tracker, issue.key)),
+            "client": client,
+        });
+        if !skipped.is_empty() {
+            metadata["fallbacks"] = skipped
+                .iter()
+                .map(|(engine, error)| serde_json::json!({ "engine": engine, "error": error }))
+                .collect::<Value>();
+        }
+        self.audit("agent.run", Some(session_id.clone()), &spawned, metadata).await;
+        let (mut attempt, mut child) = spawned?;
+        let engine = attempt.engine.clone();
 
         let stdout = child
             .stdout
             .take()
             .ok_or_else(|| Status::internal("Failed to capture stdout"))?;
 
-        // Create broadcast channel for this agent's events
-        let (tx, _) = broadcast::channel::<AgentEvent>(256);
-        let tx_clone = tx.clone();
+        // Subscribe before the reader task starts so the caller sees "started"
+        let (_, rx) = events.subscribe(false);
 
-        // Register agent
+        // Hand the reserved place the running engine
         {
             let mut agents = self.agents.lock().await;
-            agents.insert(
-                session_id.clone(),
-                ActiveAgentHandle {
-                    engine: engine.clone(),
-                    cwd: cwd.clone(),
-                    started_at: Instant::now(),
-                    sender: tx.clone(),
-                    child: Some(child),
-                },
-            );
+            let Some(handle) = agents.get_mut(&session_id) else {
+                // Stopped while it was starting
+                let _ = child.start_kill();
+                if let Some(container) = &attempt.launch.container {
+                    remove_container(container);
+                }
+                return Err(Status::aborted(format!("Agent session {} was stopped before it started", session_id)));
+            };
+            handle.engine = engine.clone();
+            handle.started_at = Instant::now();
+            handle.child = Some(child);
+            handle.container = attempt.launch.container.clone();
         }
+        reservation.keep();
 
         info!("Started agent {} with engine {}", session_id, engine);
+        record_agent_activity(self.ctx().db.clone(), cwd.clone(), false).await;
+        if let Some(ws) = &workspace {
+            let (ws_id, run_id) = (ws.id.clone(), session_id.clone());
+            if let Err(e) = self.with_db(move |conn| core::task_record_run(conn, &ws_id, &run_id)).await {
+                warn!("Failed to link run {} to its tasks: {}", session_id, e.message());
+            }
+        }
+        // Runs launched from the CLI, or a UI that dies mid-run, still leave a transcript
+        let chat_path = workspace.map(|ws| PathBuf::from(ws.path));
+        record_chat(chat_path.clone(), "User", user_prompt.clone()).await;
+        let transcript = chat_path.clone().map(|ws_path| start_transcript(ws_path, session_id.clone()));
 
         // Spawn task to read stdout and broadcast events
         let session_id_clone = session_id.clone();
-        let engine_clone = engine.clone();
         let agents_clone = self.agents.clone();
+        let home = self.ctx().home.clone();
+        let db = self.ctx().db.clone();
+        let started_at = Instant::now();
+        // Covers the run from spawn to exit, checkpoints and verification included
+        let run_span = tracing::info_span!(
+            "agent_run",
+            session_id = %session_id,
+            engine = %engine,
+            cwd = %cwd,
+            isolation = %isolation,
+            outcome = tracing::field::Empty
+        );
 
         tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout).lines();
-            let mut parser = AgentParser::new();
+            let (mut stdout, mut remaining) = (stdout, attempts);
+            let mut attempt_started_at = started_at;
 
             // Send started event
-            let _ = tx_clone.send(AgentEvent {
-                session_id: session_id_clone.clone(),
-                event_type: "started".to_string(),
-                payload: serde_json::json!({
-                    "engine": engine_clone,
+            events.emit(
+                "started",
+                serde_json::json!({
+                    "engine": attempt.engine,
                 })
                 .to_string(),
-            });
+            );
+            // Engines passed over before this one started
+            let successors = skipped.iter().map(|(engine, _)| engine).skip(1).chain([&attempt.engine]);
+            for ((from, error), to) in skipped.iter().zip(successors) {
+                events.emit("event", fallback_event(from, to, error));
+            }
+            if let Some(checkpoint) = before_run {
+                events.emit("event", checkpoint_event(&checkpoint));
+            }
 
-            // Process lines
-            while let Ok(Some(line)) = reader.next_line().await {
-                if let Ok(value) = serde_json::from_str::<Value>(&line) {
-                    if let Some(events) = parser.parse_value(&value) {
-                        for event in events {
-                            let _ = tx_clone.send(AgentEvent {
-                                session_id: session_id_clone.clone(),
-                                event_type: "event".to_string(),
-                                payload: event.to_string(),
-                            });
+            // Process lines, keeping the final answer for webhooks
+            let mut answer: Option<String> = None;
+            let mut agent_ok: Option<bool> = None;
+            let mut checked_commands = HashSet::new();
+            let mut blocked_by: Option<String> = None;
+            let mut saved_resume: Option<String> = None;
+            let mut usage = core::TokenUsage::default();
+            let mut payload = loop {
+                let mut reader = BufReader::new(stdout).lines();
+                let mut parser = AgentParser::new();
+                let custom = attempt.custom.clone();
+                let engine_name = attempt.engine.clone();
+                // Engines in a container aren't the ones on PATH here, so their version is unknown
+                let engine_version = match custom {
+                    None if attempt.launch.isolation.on_host() => {
+                        let name = engine_name.clone();
+                        tokio::task::spawn_blocking(move || core::engine_version(&name)).await.ok().flatten()
+                    }
+                    _ => None,
+                };
+                let mut provenance = core::RunProvenance { engine_version, model: None, base_sha: base_sha.clone() };
+                record_provenance(db.clone(), cwd.clone(), engine_name.clone(), provenance.clone()).await;
+                let passthrough =
+                    custom.as_ref().is_some_and(|custom| custom.parser == core::EngineParser::Passthrough);
+                let mut output = String::new();
+                // An engine that fails before acting can hand over to the next one; its
+                // failure is held back so clients don't see the run end
+                let mut acted = false;
+                let mut held_completion: Option<Value> = None;
+                while let Ok(Some(line)) = reader.next_line().await {
+                    if let Some(transcript) = &transcript {
+                        let _ = transcript.send(line.clone());
+                    }
+                    let parsed = if passthrough {
+                        // Plain output: the message so far, re-sent as it grows
+                        output.push_str(&line);
+                        output.push('\n');
+                        Some(vec![serde_json::json!({ "type": "agent.message", "text": output.trim_end() })])
+                    } else {
+                        serde_json::from_str::<Value>(&line).ok().and_then(|value| parser.parse_value(&value))
+                    };
+                    for mut event in parsed.into_iter().flatten() {
+                        // Custom engines parse as claude/codex but report their own name
+                        if let (Some(_), Some(map)) = (&custom, event.as_object_mut()) {
+                            map.insert("engine".to_string(), Value::String(engine_name.clone()));
+                        }
+                        let text = |key: &str| event.get(key).and_then(Value::as_str).filter(|t| !t.is_empty());
+                        match event.get("type").and_then(Value::as_str) {
+                            Some("agent.started") => {
+                                let resume = text("resume").filter(|resume| saved_resume.as_deref() != Some(*resume));
+                                if let Some(resume) = resume {
+                                    saved_resume = Some(resume.to_string());
+                                    let (db, cwd) = (db.clone(), cwd.clone());
+                                    record_resume_id(db, cwd, engine_name.clone(), resume.to_string()).await;
+                                }
+                                let model = event.pointer("/meta/model").and_then(Value::as_str).unwrap_or_default();
+                                if !model.is_empty() && provenance.model.as_deref() != Some(model) {
+                                    provenance.model = Some(model.to_string());
+                                    let (db, cwd) = (db.clone(), cwd.clone());
+                                    record_provenance(db, cwd, engine_name.clone(), provenance.clone()).await;
+                                }
+                            }
+                            Some("agent.action") => {
+                                acted = true;
+                                if record_actions && text("phase") == Some("completed") {
+                                    let failed = event.get("ok").and_then(Value::as_bool) == Some(false);
+                                    let mark = if failed { "✗" } else { "✓" };
+                                    if let Some(title) = event.pointer("/action/title").and_then(Value::as_str) {
+                                        record_chat(chat_path.clone(), "Action", format!("{mark} {title}")).await;
+                                    }
+                                }
+                            }
+                            Some("agent.message") => answer = text("text").map(str::to_string),
+                            Some("agent.completed") => {
+                                agent_ok = event.get("ok").and_then(Value::as_bool);
+                                if let Some(final_answer) = text("answer") {
+                                    answer = Some(final_answer.to_string());
+                                }
+                                // Codex reports each turn's usage
+                                if let Some(turn) = event.get("usage") {
+                                    usage += core::TokenUsage::from_engine(turn);
+                                }
+                                if agent_ok == Some(false) && !acted && !remaining.is_empty() {
+                                    held_completion = Some(event);
+                                    continue;
+                                }
+                            }
+                            _ => {}
+                        }
+                        events.emit("event", event.to_string());
+
+                        if checkpoint_mode == core::CheckpointMode::FileChange
+                            && event.get("phase").and_then(Value::as_str) == Some("completed")
+                            && event.pointer("/action/kind").and_then(Value::as_str) == Some("file_change")
+                        {
+                            let label = event.pointer("/action/title").and_then(Value::as_str).unwrap_or("file change");
+                            let checkpoint = checkpoint_workspace(db.clone(), cwd.clone(), label.to_string()).await;
+                            if let Some(checkpoint) = checkpoint {
+                                events.emit("event", checkpoint_event(&checkpoint));
+                            }
                         }
+
+                        // Each command action is checked once, whichever phase shows it first
+                        let field = |pointer: &str| event.pointer(pointer).and_then(Value::as_str);
+                        let is_command = field("/action/kind") == Some("command");
+                        let command = match field("/action/id") {
+                            Some(id) if is_command && checked_commands.insert(id.to_string()) => field("/action/title"),
+                            _ => None,
+                        };
+                        let Some(violation) = command.and_then(|command| policy.check(command)) else {
+                            continue;
+                        };
+                        warn!(
+                            "Agent {} ran {:?}: {} ({})",
+                            session_id_clone,
+                            violation.command,
+                            violation.rule,
+                            violation.action.as_str()
+                        );
+                        events.emit(
+                            "event",
+                            serde_json::json!({
+                                "type": "agent.policy_violation",
+                                "engine": engine_name,
+                                "rule": violation.rule,
+                                "decision": violation.action.as_str(),
+                                "command": violation.command,
+                                "action_id": event.pointer("/action/id"),
+                            })
+                            .to_string(),
+                        );
+                        // A gated engine was refused the command and carries on without it
+                        let stop = violation.action == core::policy::PolicyAction::Block && !attempt.policy_gate;
+                        if stop && blocked_by.is_none() {
+                            blocked_by = Some(violation.rule.clone());
+                            if let Some(handle) = agents_clone.lock().await.get_mut(&session_id_clone) {
+                                handle.start_kill();
+                            }
+                        }
+                        audit_policy_violation(db.clone(), session_id_clone.clone(), violation).await;
+                    }
+                }
+
+                // Reap the process for its exit status. If StopAgent already removed it,
+                // the agent was stopped
+                let child = agents_clone.lock().await.get_mut(&session_id_clone).and_then(|h| h.child.take());
+                let mut payload = match child {
+                    Some(mut child) => match child.wait().await {
+                        Ok(status) => serde_json::json!({
+                            "exit_code": status.code(),
+                            "success": status.success(),
+                        }),
+                        Err(e) => serde_json::json!({ "error": e.to_string() }),
+                    },
+                    None => serde_json::json!({ "stopped": true }),
+                };
+                let ok = payload.get("success").and_then(Value::as_bool).unwrap_or(false) && agent_ok != Some(false);
+                let failed_at_once = !ok
+                    && !acted
+                    && payload.get("stopped").is_none()
+                    && blocked_by.is_none()
+                    && attempt_started_at.elapsed() < FALLBACK_WINDOW;
+                if failed_at_once {
+                    let mut error = held_completion
+                        .as_ref()
+                        .and_then(|event| event.get("error").and_then(Value::as_str))
+                        .map(str::to_string)
+                        .unwrap_or_else(|| match payload.get("exit_code").and_then(Value::as_i64) {
+                            Some(code) => format!("exited with {code}"),
+                            None => "failed".to_string(),
+                        });
+                    let mut from = attempt.engine.clone();
+                    let switched = loop {
+                        let Some((name, next)) = remaining.pop_front() else { break None };
+                        events.emit("event", fallback_event(&from, &name, &error));
+                        warn!("Agent {} falling back from {} to {}: {}", session_id_clone, from, name, error);
+                        audit_fallback(db.clone(), session_id_clone.clone(), &from, &name, &error).await;
+                        let next = match next {
+                            Ok(next) => next,
+                            Err(e) => {
+                                (from, error) = (name, e.message().to_string());
+                                continue;
+                            }
+                        };
+                        let mut child = match spawn_attempt(&next, &cwd) {
+                            Ok(child) => child,
+                            Err(e) => {
+                                (from, error) = (name, e);
+                                continue;
+                            }
+                        };
+                        let Some(next_stdout) = child.stdout.take() else {
+                            let _ = child.start_kill();
+                            (from, error) = (name, "failed to capture stdout".to_string());
+                            continue;
+                        };
+                        let mut agents = agents_clone.lock().await;
+                        let Some(handle) = agents.get_mut(&session_id_clone) else {
+                            // Stopped while the failed engine was reaped
+                            let _ = child.start_kill();
+                            payload = serde_json::json!({ "stopped": true });
+                            break None;
+                        };
+                        handle.engine = next.engine.clone();
+                        handle.container = next.launch.container.clone();
+                        handle.child = Some(child);
+                        break Some((next, next_stdout));
+                    };
+                    if let Some((next, next_stdout)) = switched {
+                        (attempt, stdout, attempt_started_at) = (next, next_stdout, Instant::now());
+                        (answer, agent_ok) = (None, None);
+                        continue;
                     }
                 }
+                if let Some(completion) = held_completion {
+                    events.emit("event", completion.to_string());
+                }
+                break payload;
+            };
+            let engine_clone = attempt.engine.clone();
+            agents_clone.lock().await.remove(&session_id_clone);
+            if let (Some(rule), Value::Object(map)) = (&blocked_by, &mut payload) {
+                map.insert("blocked_by".to_string(), Value::String(rule.clone()));
+            }
+            if checkpoint_mode != core::CheckpointMode::Off {
+                if let Some(checkpoint) = checkpoint_workspace(db.clone(), cwd.clone(), "after run".to_string()).await {
+                    events.emit("event", checkpoint_event(&checkpoint));
+                }
+            }
+            // Stopped and blocked runs are abandoned; anything else gets the repo's checks
+            // before clients hear it completed
+            if payload.get("stopped").is_none() && blocked_by.is_none() {
+                let verification = verify_run(db.clone(), &home, &events, cwd.clone(), &session_id_clone).await;
+                if let (Some(verification), Value::Object(map)) = (verification, &mut payload) {
+                    map.insert("verification".to_string(), serde_json::json!(verification));
+                }
             }
 
-            // Send completed event
-            let _ = tx_clone.send(AgentEvent {
-                session_id: session_id_clone.clone(),
-                event_type: "completed".to_string(),
-                payload: "{}".to_string(),
-            });
+            if let Some(answer) = &answer {
+                record_chat(chat_path, "Assistant", answer.clone()).await;
+            }
+            if let Some(diffstat) = diffstat_event(db.clone(), cwd.clone()).await {
+                events.emit("event", diffstat);
+            }
 
-            // Remove from active agents (child will be killed via Drop)
-            let mut agents = agents_clone.lock().await;
-            agents.remove(&session_id_clone);
+            // Send completed event
+            events.emit("completed", payload.to_string());
             info!("Agent {} completed", session_id_clone);
-        });
-
-        // Create stream from broadcast receiver
-        let mut rx = tx.subscribe();
-        let stream = async_stream::stream! {
-            while let Ok(event) = rx.recv().await {
-                yield Ok(event);
+            tracing::Span::current().record("outcome", tracing::field::display(&payload));
+            record_agent_activity(db.clone(), cwd.clone(), true).await;
+            if usage.total() > 0 {
+                record_usage(db.clone(), cwd.clone(), usage).await;
             }
-        };
+            record_workspace_title(db.clone(), home.clone(), cwd.clone(), user_prompt, answer.clone()).await;
+
+            // Stopped runs were ended by the user, so nobody needs pinging
+            if payload.get("stopped").is_none() {
+                let exit_code = payload.get("exit_code").and_then(Value::as_i64).map(|code| code as i32);
+                let ok = payload.get("success").and_then(Value::as_bool).unwrap_or(false) && agent_ok != Some(false);
+                let run = core::webhooks::AgentRunEvent {
+                    status: if ok { "completed" } else { "failed" }.to_string(),
+                    session_id: session_id_clone,
+                    engine: engine_clone,
+                    cwd,
+                    exit_code,
+                    duration_secs: started_at.elapsed().as_secs(),
+                    answer: answer.as_deref().map(core::webhooks::summarize_answer),
+                    ..Default::default()
+                };
+                notify_webhooks(home, db, run).await;
+            }
+        }
+        .instrument(run_span));
 
-        Ok(Response::new(Box::pin(stream)))
+        // Create stream from broadcast receiver
+        Ok(Response::new(agent_event_stream(Vec::new(), rx)))
     }
 
     type AttachAgentStream = Pin<Box<dyn Stream<Item = Result<AgentEvent, Status>> + Send>>;
@@ -609,37 +3221,36 @@ impl Conductor for ConductorService {
             .get(&session_id)
             .ok_or_else(|| Status::not_found(format!("No running agent with session_id: {}", session_id)))?;
 
-        // Subscribe to the existing broadcast channel
-        let mut rx = handle.sender.subscribe();
+        // Subscribe to the existing broadcast channel (optionally replaying history first)
+        let (backlog, rx) = handle.events.subscribe(req.replay);
         info!("Client attached to agent {}", session_id);
 
-        // Create stream
-        let stream = async_stream::stream! {
-            while let Ok(event) = rx.recv().await {
-                yield Ok(event);
-            }
-        };
-
-        Ok(Response::new(Box::pin(stream)))
+        Ok(Response::new(agent_event_stream(backlog, rx)))
     }
 
     async fn stop_agent(
         &self,
         request: Request<StopAgentRequest>,
     ) -> Result<Response<StopAgentResponse>, Status> {
+        let client = client_meta(&request);
         let req = request.into_inner();
-        let mut agents = self.agents.lock().await;
+        let removed = self.agents.lock().await.remove(&req.session_id);
 
-        if let Some(mut handle) = agents.remove(&req.session_id) {
+        let result = if let Some(mut handle) = removed {
             // Kill child process explicitly
             if let Some(ref mut child) = handle.child {
                 let _ = child.kill().await;
             }
             info!("Stopped agent {}", req.session_id);
-            Ok(Response::new(StopAgentResponse { success: true }))
+            Ok(())
         } else {
             Err(Status::not_found("No agent with that session_id"))
-        }
+        };
+        self.audit("agent.stop", Some(req.session_id), &result, serde_json::json!({ "client": client }))
+            .await;
+        result?;
+
+        Ok(Response::new(StopAgentResponse { success: true }))
     }
 
     async fn list_active_agents(
@@ -649,13 +3260,69 @@ impl Conductor for ConductorService {
         let agents = self.agents.lock().await;
 
         Ok(Response::new(ListActiveAgentsResponse {
-            agents: agents
-                .iter()
-                .map(|(id, handle)| ActiveAgent {
-                    session_id: id.clone(),
-                    engine: handle.engine.clone(),
-                    cwd: handle.cwd.clone(),
-                    started_at: handle.started_at.elapsed().as_secs().to_string(),
+            agents: agents.iter().map(|(id, handle)| active_agent_proto(id, handle)).collect(),
+        }))
+    }
+
+    async fn list_engines(
+        &self,
+        _request: Request<ListEnginesRequest>,
+    ) -> Result<Response<ListEnginesResponse>, Status> {
+        // Probing runs each engine's --version
+        let custom = self.ctx().custom_engines.clone();
+        let engines = tokio::task::spawn_blocking(move || core::engine_list(&custom))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(ListEnginesResponse {
+            engines: engines
+                .into_iter()
+                .map(|engine| EngineInfo {
+                    name: engine.name,
+                    aliases: engine.aliases,
+                    program: engine.program,
+                    available: engine.available,
+                    path: engine.path,
+                    version: engine.version,
+                    authenticated: engine.authenticated,
+                    supports_resume: engine.supports_resume,
+                    supports_streaming: engine.supports_streaming,
+                })
+                .collect(),
+        }))
+    }
+
+    // =========================================================================
+    // Audit Log
+    // =========================================================================
+
+    async fn list_audit_events(
+        &self,
+        request: Request<ListAuditEventsRequest>,
+    ) -> Result<Response<ListAuditEventsResponse>, Status> {
+        let req = request.into_inner();
+        let filter = core::AuditFilter {
+            action: req.action,
+            target: req.target,
+            since: req.since,
+            limit: req.limit.map(|l| l as usize),
+        };
+
+        let events: Vec<core::AuditEvent> = self
+            .with_db(move |conn| core::audit_list(conn, &filter))
+            .await?;
+
+        Ok(Response::new(ListAuditEventsResponse {
+            events: events
+                .into_iter()
+                .map(|e| AuditEvent {
+                    id: e.id,
+                    created_at: e.created_at,
+                    source: e.source,
+                    action: e.action,
+                    target: e.target,
+                    ok: e.ok,
+                    metadata: e.metadata.to_string(),
                 })
                 .collect(),
         }))
@@ -672,22 +3339,86 @@ impl Conductor for ConductorService {
         }))
     }
 
+    async fn get_server_info(
+        &self,
+        _request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        Ok(Response::new(ServerInfo {
+            version: VERSION.to_string(),
+            protocol_version: compat::PROTOCOL_VERSION,
+            min_client_protocol_version: compat::MIN_CLIENT_PROTOCOL_VERSION,
+            pid: std::process::id(),
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<GetStatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        let home = self.ctx().home.clone();
+        // Walks the whole home for disk usage, so keep it off the async threads
+        let status: core::HomeStatus = self
+            .with_db(move |conn| core::home_status(conn, &home))
+            .await?;
+
+        let agents = self
+            .agents
+            .lock()
+            .await
+            .iter()
+            .map(|(id, handle)| AgentStatus {
+                session_id: id.clone(),
+                engine: handle.engine.clone(),
+                cwd: handle.cwd.clone(),
+                elapsed_secs: handle.started_at.elapsed().as_secs(),
+            })
+            .collect();
+        let recent_errors = self
+            .recent_errors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect();
+        let served_homes = self
+            .homes
+            .all()
+            .map(|context| context.home.display().to_string())
+            .collect();
+
+        Ok(Response::new(StatusResponse {
+            version: VERSION.to_string(),
+            uptime_secs: self.start_time.elapsed().as_secs() as i64,
+            home: status.home,
+            db_path: status.db_path,
+            schema_version: status.schema_version,
+            home_disk_bytes: status.disk_bytes,
+            repo_count: status.repo_count,
+            workspace_count: status.workspace_count,
+            archived_workspace_count: status.archived_workspace_count,
+            agents,
+            recent_errors,
+            served_homes,
+        }))
+    }
+
     async fn shutdown(
         &self,
-        _request: Request<ShutdownRequest>,
+        request: Request<ShutdownRequest>,
     ) -> Result<Response<ShutdownResponse>, Status> {
         info!("Shutdown requested");
+        let client = client_meta(&request);
+        let running = self.agents.lock().await.len();
+        self.audit::<()>(
+            "daemon.shutdown",
+            None,
+            &Ok(()),
+            serde_json::json!({ "running_agents": running, "client": client }),
+        )
+        .await;
 
         // Kill all running agents first
-        {
-            let mut agents = self.agents.lock().await;
-            for (id, mut handle) in agents.drain() {
-                if let Some(ref mut child) = handle.child {
-                    let _ = child.kill().await;
-                }
-                info!("Killed agent {} during shutdown", id);
-            }
-        }
+        self.stop_all_agents().await;
 
         // Send response before exiting
         tokio::spawn(async {
@@ -698,53 +3429,234 @@ impl Conductor for ConductorService {
     }
 }
 
+#[derive(Parser)]
+#[command(name = "conductor-daemon", version, about = "Conductor daemon")]
+struct Args {
+    /// Listen address: unix:///path/to.sock, or tcp://HOST:PORT (mutual TLS, served alongside the socket)
+    #[arg(long)]
+    listen: Option<String>,
+    /// Stop an already-running daemon on the same socket and take over
+    #[arg(long)]
+    replace: bool,
+    /// Also serve the REST gateway (JSON + SSE) on this loopback address, e.g. 127.0.0.1:7444
+    #[arg(long)]
+    http: Option<String>,
+    /// Claude PreToolUse hook: check the call on stdin against HOME's command_policy and
+    /// exit 2 to refuse it. Runs no daemon
+    #[arg(long, value_name = "HOME", hide = true)]
+    policy_hook: Option<PathBuf>,
+}
+
+// `--policy-hook`: the exit code Claude reads, 2 refusing the command with stderr as the
+// reason. Anything that keeps the policy from being checked refuses too
+fn policy_hook(home: &Path) -> i32 {
+    let mut input = String::new();
+    let verdict = std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| core::config_load(home))
+        .and_then(|config| core::policy::hook_verdict(&config.command_policy.unwrap_or_default(), &input));
+    match verdict {
+        Ok(None) => 0,
+        Ok(Some(violation)) => {
+            eprintln!("Blocked by conductor command policy rule {:?}: {}", violation.rule, violation.command);
+            2
+        }
+        Err(e) => {
+            eprintln!("Conductor could not check the command policy: {:#}", e);
+            2
+        }
+    }
+}
+
+// Single-quoted for the shell Claude runs hooks with
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
-
-    // Clean up stale socket
-    let socket_path = std::path::Path::new(SOCKET_PATH);
-    if socket_path.exists() {
-        warn!("Removing stale socket at {}", SOCKET_PATH);
-        std::fs::remove_file(socket_path)?;
+    let args = Args::parse();
+    if let Some(home) = &args.policy_hook {
+        std::process::exit(policy_hook(home));
     }
 
     // Get home directory
     let home = core::default_home();
+    let config = core::config_load(&home)?;
+
+    // Initialize logging, and trace export when config.json asks for it
+    let telemetry = conductor_daemon::telemetry::init(config.tracing.as_ref())?;
     info!("Using home directory: {:?}", home);
+    if let Some(tracing) = &config.tracing {
+        info!("Exporting traces to {}", tracing.endpoint);
+    }
+    let listen = match args.listen.as_deref().or(config.listen.as_deref()) {
+        Some(spec) => Some(Listen::parse(spec)?),
+        None => None,
+    };
+
+    // Remote clients must present a certificate signed by the configured CA
+    let tcp = match &listen {
+        Some(Listen::Tcp(addr)) => {
+            let tls = config.tls.as_ref().ok_or(
+                "tcp listener requires a \"tls\" section (cert, key, client_ca) in config.json",
+            )?;
+            Some((*addr, transport::server_tls(tls)?))
+        }
+        _ => None,
+    };
+
+    let http: Option<std::net::SocketAddr> = match args.http.as_deref().or(config.http_listen.as_deref()) {
+        Some(addr) => Some(addr.parse().map_err(|e| format!("invalid http address {addr}: {e}"))?),
+        None => None,
+    };
+
+    // Per-user socket path (env, config.json, then XDG runtime dir)
+    let socket_path = match &listen {
+        Some(Listen::Unix(path)) => path.clone(),
+        _ => core::socket_path(&home)?,
+    };
+    if let Some(dir) = socket_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // One daemon per socket; the socket is only ours to remove once we hold the lock
+    let lock_path = InstanceLock::path_for(&socket_path);
+    let _lock = match InstanceLock::try_acquire(&lock_path)? {
+        Some(lock) => lock,
+        None if args.replace => {
+            info!("Replacing running daemon (lock {})", lock_path.display());
+            instance::replace(&lock_path, Duration::from_secs(10)).await?
+        }
+        None => {
+            let pid = instance::holder_pid(&lock_path)
+                .map(|pid| pid.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            return Err(format!(
+                "another daemon (pid {}) is already serving {}; use --replace to take over",
+                pid,
+                socket_path.display()
+            )
+            .into());
+        }
+    };
+
+    // Clean up stale socket
+    if socket_path.exists() {
+        warn!("Removing stale socket at {}", socket_path.display());
+        std::fs::remove_file(&socket_path)?;
+    }
+
+    // Diff and changes calls resolve the base branch every time; watch events keep this fresh
+    core::base_ref_cache_enable();
 
-    // Ensure database is initialized (blocking is fine at startup)
-    let conn = core::connect(&home)?;
-    drop(conn);
+    // Open every home's database (blocking is fine at startup) and start their jobs
+    let homes = Arc::new(Homes::open(&home)?);
     info!("Database initialized");
+    let token = homes.primary().token().to_string();
 
-    // Create service
-    let service = ConductorService::new(home);
+    // Create service (shared between listeners)
+    let service = Arc::new(ConductorService::new(homes.clone()));
 
-    info!("Starting Conductor daemon v{} on {}", VERSION, SOCKET_PATH);
+    info!("Starting Conductor daemon v{} on {}", VERSION, socket_path.display());
 
     // Bind to Unix socket
-    let uds = tokio::net::UnixListener::bind(SOCKET_PATH)?;
+    let uds = tokio::net::UnixListener::bind(&socket_path)?;
 
     // Set socket permissions (user only)
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
-        std::fs::set_permissions(SOCKET_PATH, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))?;
     }
 
-    let uds_stream = tokio_stream::wrappers::UnixListenerStream::new(uds);
-
-    tonic::transport::Server::builder()
-        .add_service(ConductorServer::new(service))
-        .serve_with_incoming(uds_stream)
-        .await?;
-
+    // Connections from other users are dropped before any RPC is read
+    let peer_service = service.clone();
+    let uds_stream = tokio_stream::wrappers::UnixListenerStream::new(uds).filter(move |conn| match conn {
+        Ok(stream) => match auth::check_peer(stream) {
+            Ok(()) => true,
+            Err(reason) => {
+                warn!("Rejected socket connection: {}", reason);
+                peer_service.note_error("socket", &reason);
+                false
+            }
+        },
+        Err(_) => true,
+    });
+
+    // Standard health and reflection services skip the bearer token: probes can't be
+    // expected to carry it, and neither reveals more than the public proto
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<ConductorServer<ConductorService>>()
+        .await;
+    let reflection = || {
+        tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+    };
+    let reflection_service = reflection().build_v1()?;
+    // Older grpcurl/grpcui releases only speak v1alpha
+    let reflection_alpha_service = reflection().build_v1alpha()?;
+
+    let unix_server = tonic::transport::Server::builder()
+        .trace_fn(rpc_span)
+        .add_service(HomeScoped::new(ConductorServer::from_arc(service.clone()), homes.clone()))
+        .add_service(health_service.clone())
+        .add_service(reflection_service.clone())
+        .add_service(reflection_alpha_service.clone())
+        .serve_with_incoming(uds_stream);
+
+    let tcp_service = service.clone();
+    let grpc_servers = async move {
+        let Some((addr, tls)) = tcp else {
+            return unix_server.await;
+        };
+        info!("Also listening on tcp://{} (mutual TLS)", addr);
+        let tcp_server = tonic::transport::Server::builder()
+            .tls_config(tls)?
+            .trace_fn(rpc_span)
+            .add_service(HomeScoped::new(ConductorServer::from_arc(tcp_service), homes))
+            .add_service(health_service)
+            .add_service(reflection_service)
+            .add_service(reflection_alpha_service)
+            .serve(addr);
+        tokio::try_join!(unix_server, tcp_server).map(|_| ())
+    };
+
+    let http_service = service.clone();
+    let servers = async move {
+        let Some(addr) = http else {
+            return grpc_servers.await.map_err(|e| e.to_string());
+        };
+        info!("Also serving REST on http://{}", addr);
+        let http_server = conductor_daemon::rest::serve(addr, http_service, token);
+        tokio::try_join!(
+            async { grpc_servers.await.map_err(|e| e.to_string()) },
+            async { http_server.await.map_err(|e| format!("{e:#}")) },
+        )
+        .map(|_| ())
+    };
+
+    // SIGTERM (e.g. from `--replace`) and Ctrl-C stop agents and release the socket
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = servers => result?,
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+    }
+    health_reporter
+        .set_not_serving::<ConductorServer<ConductorService>>()
+        .await;
+    service.stop_all_agents().await;
+    let _ = std::fs::remove_file(&socket_path);
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
     Ok(())
 }
+
+// One span per RPC, named after its method, e.g. "/conductor.Conductor/RunAgent"
+fn rpc_span(request: &tonic::codegen::http::Request<()>) -> tracing::Span {
+    tracing::info_span!("rpc", otel.name = %request.uri().path(), rpc.method = %request.uri().path())
+}
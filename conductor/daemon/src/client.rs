@@ -0,0 +1,53 @@
+//! Connecting to a running daemon (shared by the CLI and desktop app)
+
+use crate::auth::{self, DaemonClient};
+use crate::{compat, transport};
+use anyhow::{anyhow, Context, Result};
+use hyper_util::rt::TokioIo;
+use std::path::Path;
+use tokio::net::UnixStream;
+use tonic::transport::{Endpoint, Uri};
+use tower::service_fn;
+
+/// Connect over the per-user unix socket of `home`, authenticating with its token
+pub async fn connect_local(home: &Path) -> Result<DaemonClient> {
+    let socket_path = conductor_core::socket_path(home)?;
+    if !socket_path.exists() {
+        return Err(anyhow!("daemon is not running (no socket at {})", socket_path.display()));
+    }
+
+    // The URI is ignored; every connection goes to the socket
+    let channel = Endpoint::try_from("http://[::]:50051")?
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let socket_path = socket_path.clone();
+            async move {
+                let stream = UnixStream::connect(socket_path).await?;
+                Ok::<_, std::io::Error>(TokioIo::new(stream))
+            }
+        }))
+        .await
+        .context("failed to connect to daemon socket")?;
+
+    // Shared with the daemon through the home dir (whichever starts first creates it).
+    // The socket may belong to a daemon that serves this home alongside its own
+    let token = conductor_core::auth_token_ensure(home)?;
+    auth::client_for_home(channel, &token, home)
+}
+
+/// Connect using config.json (`remote` if set, else the local socket) and check
+/// protocol compatibility. Never spawns a daemon.
+pub async fn connect(home: &Path) -> Result<DaemonClient> {
+    let config = conductor_core::config_load(home)?;
+    let mut client = match &config.remote {
+        Some(remote) => {
+            let token = remote
+                .token
+                .as_deref()
+                .ok_or_else(|| anyhow!("config.json remote needs the daemon's \"token\""))?;
+            auth::client(transport::connect_remote(remote).await?, token)?
+        }
+        None => connect_local(home).await?,
+    };
+    compat::negotiate(&mut client).await?;
+    Ok(client)
+}
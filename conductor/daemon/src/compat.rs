@@ -0,0 +1,116 @@
+//! Client/daemon protocol version negotiation
+
+use crate::auth::DaemonClient;
+use crate::proto::{GetServerInfoRequest, ServerInfo};
+use std::fmt;
+use tonic::{Code, Status};
+
+/// Wire protocol spoken by this build. Bump it with every RPC or request field added or
+/// changed, so a daemon that lacks them is caught when a client connects rather than
+/// by the first call it can't serve
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest client protocol this daemon still serves. Additions leave older clients
+/// working; raise it when a change breaks them
+pub const MIN_CLIENT_PROTOCOL_VERSION: u32 = 1;
+
+/// Client and daemon cannot talk to each other safely
+#[derive(Debug, Clone)]
+pub enum VersionMismatch {
+    /// Daemon predates this client; restarting it picks up the new binary
+    DaemonOlder { daemon_version: String, daemon_protocol: u32 },
+    /// Client predates the daemon; the app itself needs updating
+    ClientOlder { daemon_version: String, min_client_protocol: u32 },
+    /// The daemon has no such RPC: it is older than the client, whatever protocol it claims
+    Unsupported,
+}
+
+impl VersionMismatch {
+    pub fn daemon_is_older(&self) -> bool {
+        matches!(self, VersionMismatch::DaemonOlder { .. } | VersionMismatch::Unsupported)
+    }
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionMismatch::DaemonOlder {
+                daemon_version,
+                daemon_protocol,
+            } => write!(
+                f,
+                "daemon v{daemon_version} (protocol {daemon_protocol}) is older than this app \
+                 (protocol {PROTOCOL_VERSION}); restart it with `conductor-daemon --replace`"
+            ),
+            VersionMismatch::ClientOlder {
+                daemon_version,
+                min_client_protocol,
+            } => write!(
+                f,
+                "daemon v{daemon_version} requires client protocol {min_client_protocol} or newer \
+                 (this app speaks {PROTOCOL_VERSION}); update the app"
+            ),
+            VersionMismatch::Unsupported => write!(
+                f,
+                "the daemon doesn't support this request, so it is older than this app; \
+                 restart it with `conductor-daemon --replace`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// Fetch ServerInfo and check it against this build. Mismatches surface as a
+/// `VersionMismatch` inside the anyhow error so callers can decide to restart.
+pub async fn negotiate(client: &mut DaemonClient) -> anyhow::Result<ServerInfo> {
+    let info = match client.get_server_info(GetServerInfoRequest {}).await {
+        Ok(response) => response.into_inner(),
+        // Daemons from before GetServerInfo existed
+        Err(status) if status.code() == Code::Unimplemented => {
+            return Err(VersionMismatch::DaemonOlder {
+                daemon_version: "unknown".to_string(),
+                daemon_protocol: 0,
+            }
+            .into())
+        }
+        Err(status) => return Err(anyhow::anyhow!("GetServerInfo failed: {}", status.message())),
+    };
+    if info.protocol_version < PROTOCOL_VERSION {
+        return Err(VersionMismatch::DaemonOlder {
+            daemon_version: info.version,
+            daemon_protocol: info.protocol_version,
+        }
+        .into());
+    }
+    if PROTOCOL_VERSION < info.min_client_protocol_version {
+        return Err(VersionMismatch::ClientOlder {
+            daemon_version: info.version,
+            min_client_protocol: info.min_client_protocol_version,
+        }
+        .into());
+    }
+    Ok(info)
+}
+
+/// An RPC error as a client reports it. The daemon never answers Unimplemented itself,
+/// so that code means it lacks the RPC and gets the upgrade hint instead
+pub fn describe_status(status: &Status) -> String {
+    if status.code() == Code::Unimplemented {
+        VersionMismatch::Unsupported.to_string()
+    } else {
+        status.to_string()
+    }
+}
+
+/// `err` with the upgrade hint ahead of it when it comes from an RPC the daemon lacks
+pub fn explain(err: anyhow::Error) -> anyhow::Error {
+    let unsupported = err
+        .chain()
+        .any(|cause| cause.downcast_ref::<Status>().is_some_and(|status| status.code() == Code::Unimplemented));
+    if unsupported {
+        err.context(VersionMismatch::Unsupported)
+    } else {
+        err
+    }
+}
@@ -0,0 +1,62 @@
+//! Long-lived SQLite connections for the daemon. Opening one per RPC repeated the open,
+//! the PRAGMAs and the migration check on every call; the pool keeps a few idle
+//! connections around instead. Jobs still run on the blocking pool, because most of
+//! them also run git and must not queue behind each other.
+
+use anyhow::Result;
+use conductor_core as core;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Connections kept open between jobs; bursts past this open extra ones that are
+/// closed when they finish
+pub const MAX_IDLE: usize = 4;
+
+pub struct DbPool {
+    home: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl DbPool {
+    /// Blocking: opens (and migrates) the first connection, so a bad home fails here
+    pub fn open(home: &Path) -> Result<Arc<Self>> {
+        let conn = core::connect(home)?;
+        Ok(Arc::new(Self {
+            home: home.to_path_buf(),
+            idle: Mutex::new(vec![conn]),
+        }))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Connection>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Blocking: run `f` on an idle connection, opening a new one if all are busy
+    pub fn with<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let conn = self.lock().pop();
+        let conn = match conn {
+            Some(conn) => conn,
+            None => core::connect(&self.home)?,
+        };
+        // A panicking job drops its connection rather than returning it mid-transaction
+        let result = f(&conn);
+        let mut idle = self.lock();
+        if idle.len() < MAX_IDLE {
+            idle.push(conn);
+        }
+        result
+    }
+
+    /// `with` on the blocking pool
+    pub async fn run<F, T>(self: &Arc<Self>, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        // Keep the caller's span, so the git commands a job runs show up under its RPC
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || span.in_scope(|| pool.with(f))).await?
+    }
+}
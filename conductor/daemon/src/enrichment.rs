@@ -0,0 +1,152 @@
+//! Cached git enrichment (dirty state, ahead/behind) for `ListWorkspaces`. Entries are
+//! keyed by `workspace_state_key`, so commits, checkouts and staging invalidate them on
+//! read; worktree edits are caught by watch events, by daemon writes, and by the
+//! background refresh. Listings enrich their workspaces a few at a time, since each
+//! takes several git processes.
+
+use crate::db::DbPool;
+use conductor_core::{self as core, Workspace, WorkspaceEnrichment};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// How often cached entries are recomputed in the background
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Workspaces enriched at once; each runs its git commands one after another
+pub const MAX_PARALLEL: usize = 8;
+
+struct Entry {
+    git_dir: PathBuf,
+    key: String,
+    value: WorkspaceEnrichment,
+}
+
+#[derive(Default)]
+pub struct EnrichmentCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl EnrichmentCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Blocking: the cached value while the workspace's state key still matches,
+    /// otherwise freshly computed (and cached)
+    pub fn get(&self, ws: &Workspace) -> anyhow::Result<WorkspaceEnrichment> {
+        let git_dir = self.lock().get(&ws.id).map(|entry| entry.git_dir.clone());
+        if let Some(git_dir) = &git_dir {
+            let key = core::workspace_state_key(git_dir);
+            if let Some(entry) = self.lock().get(&ws.id).filter(|entry| entry.key == key) {
+                return Ok(entry.value.clone());
+            }
+        }
+        self.compute(ws, git_dir)
+    }
+
+    /// Blocking: `get` for each workspace, up to `MAX_PARALLEL` at a time, in order
+    pub fn get_many(&self, workspaces: &[&Workspace]) -> Vec<anyhow::Result<WorkspaceEnrichment>> {
+        parallel_map(workspaces, |ws| self.get(ws))
+    }
+
+    // The key is taken before the git calls, so a change racing them reads as stale
+    fn compute(&self, ws: &Workspace, git_dir: Option<PathBuf>) -> anyhow::Result<WorkspaceEnrichment> {
+        let git_dir = match git_dir {
+            Some(git_dir) => git_dir,
+            None => core::worktree_git_dir(std::path::Path::new(&ws.path))?,
+        };
+        let key = core::workspace_state_key(&git_dir);
+        let value = core::workspace_enrichment(ws)?;
+        self.lock().insert(
+            ws.id.clone(),
+            Entry {
+                git_dir,
+                key,
+                value: value.clone(),
+            },
+        );
+        Ok(value)
+    }
+
+    /// Forget workspaces whose id starts with `ws_ref` (RPCs accept unique id prefixes)
+    pub fn invalidate(&self, ws_ref: &str) {
+        if ws_ref.is_empty() {
+            return;
+        }
+        self.lock().retain(|id, _| !id.starts_with(ws_ref));
+    }
+
+    /// Blocking: recompute every cached workspace that still exists and is ready
+    fn refresh(&self, db: &DbPool) -> anyhow::Result<()> {
+        let cached: Vec<String> = self.lock().keys().cloned().collect();
+        if cached.is_empty() {
+            return Ok(());
+        }
+        let workspaces: HashMap<String, Workspace> = db
+            .with(|conn| core::workspace_list(conn, None))?
+            .into_iter()
+            .filter(|ws| ws.state.is_live())
+            .map(|ws| (ws.id.clone(), ws))
+            .collect();
+        self.lock().retain(|id, _| workspaces.contains_key(id));
+        let stale: Vec<&Workspace> = cached.iter().filter_map(|id| workspaces.get(id)).collect();
+        parallel_map(&stale, |ws| {
+            let git_dir = self.lock().get(&ws.id).map(|entry| entry.git_dir.clone());
+            if let Err(e) = self.compute(ws, git_dir) {
+                warn!("Failed to refresh enrichment for {}: {}", ws.id, e);
+                self.invalidate(&ws.id);
+            }
+        });
+        Ok(())
+    }
+}
+
+// `items.iter().map(f)` on up to MAX_PARALLEL scoped threads, keeping the order
+fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = MAX_PARALLEL.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap_or_else(|e| e.into_inner()).into_iter().flatten().collect()
+}
+
+/// Keep cached entries fresh for as long as the daemon runs
+pub fn spawn_refresher(cache: Arc<EnrichmentCache>, db: Arc<DbPool>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REFRESH_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let (cache, db) = (cache.clone(), db.clone());
+            match tokio::task::spawn_blocking(move || cache.refresh(&db)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Enrichment refresh failed: {}", e),
+                Err(e) => warn!("Enrichment refresh failed: {}", e),
+            }
+        }
+    });
+}
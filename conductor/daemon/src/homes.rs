@@ -0,0 +1,228 @@
+//! The conductor homes one daemon serves: its own, plus `homes` in its config.json.
+//! Clients name theirs in request metadata (`x-conductor-home-bin`); requests without one
+//! go to the daemon's own home, and ones naming a home it doesn't serve are refused
+//! rather than quietly run against the wrong database.
+
+use crate::auth::constant_time_eq;
+use crate::db::DbPool;
+use crate::enrichment::{self, EnrichmentCache};
+use crate::housekeeping;
+use conductor_core as core;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+use tonic::server::NamedService;
+use tonic::Status;
+use tower::Service;
+use tracing::{info, warn};
+
+/// Metadata key naming the client's home (binary, so any path fits)
+pub const HOME_METADATA: &str = "x-conductor-home-bin";
+
+/// Everything the daemon keeps per home
+pub struct HomeContext {
+    pub home: PathBuf,
+    pub db: Arc<DbPool>,
+    pub enrichment: Arc<EnrichmentCache>,
+    // config.json `engines`, read once at startup
+    pub custom_engines: Vec<core::CustomEngine>,
+    // By repo id; see `repo_lock`
+    repo_locks: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    // Every RPC must carry the bearer token stored in the home dir
+    token: String,
+}
+
+impl HomeContext {
+    /// Blocking: opens (and migrates) the home's DB and starts its background jobs
+    pub fn open(home: &Path) -> anyhow::Result<Arc<Self>> {
+        let db = DbPool::open(home)?;
+        let token = core::auth_token_ensure(home)?;
+        // Custom engines are registered once; a config that doesn't load has none
+        let custom_engines = match core::config_load(home) {
+            Ok(config) => {
+                let (engines, problems) = core::custom_engines(&config);
+                for problem in problems {
+                    warn!("Skipping custom {}", problem);
+                }
+                for engine in &engines {
+                    info!("Registered engine {} ({})", engine.name, engine.command[0]);
+                }
+                engines
+            }
+            Err(e) => {
+                warn!("No custom engines, config unreadable: {:#}", e);
+                Vec::new()
+            }
+        };
+        let context = Arc::new(Self {
+            home: home.to_path_buf(),
+            db,
+            enrichment: Arc::new(EnrichmentCache::new()),
+            custom_engines,
+            repo_locks: std::sync::Mutex::new(HashMap::new()),
+            token,
+        });
+        enrichment::spawn_refresher(context.enrichment.clone(), context.db.clone());
+        housekeeping::spawn(context.db.clone(), context.home.clone());
+        Ok(context)
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Held while a workspace is created in the repo, so concurrent creates can't pick
+    /// the same auto name or race each other's `git worktree add`
+    pub async fn repo_lock(&self, repo_id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.repo_locks.lock().unwrap_or_else(|e| e.into_inner());
+            locks.entry(repo_id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+pub struct Homes {
+    primary: Arc<HomeContext>,
+    // Keyed by canonical path
+    others: HashMap<PathBuf, Arc<HomeContext>>,
+}
+
+impl Homes {
+    /// Blocking: opens `home` and each of `homes` in its config.json. A listed home that
+    /// won't open is skipped with a warning; the daemon's own home must open
+    pub fn open(home: &Path) -> anyhow::Result<Self> {
+        let primary = HomeContext::open(home)?;
+        let mut others = HashMap::new();
+        for other in core::config_load(home).map(|config| config.homes).unwrap_or_default() {
+            let Ok(canonical) = other.canonicalize() else {
+                warn!("Not serving home {}: it does not exist", other.display());
+                continue;
+            };
+            if canonical == canonical_path(home) || others.contains_key(&canonical) {
+                continue;
+            }
+            match HomeContext::open(&canonical) {
+                Ok(context) => {
+                    info!("Also serving home {}", canonical.display());
+                    others.insert(canonical, context);
+                }
+                Err(e) => warn!("Not serving home {}: {:#}", canonical.display(), e),
+            }
+        }
+        Ok(Self { primary, others })
+    }
+
+    pub fn primary(&self) -> &Arc<HomeContext> {
+        &self.primary
+    }
+
+    /// The daemon's own home first
+    pub fn all(&self) -> impl Iterator<Item = &Arc<HomeContext>> {
+        std::iter::once(&self.primary).chain(self.others.values())
+    }
+
+    /// The context for a request naming `requested` (the daemon's own home when unset);
+    /// None for a home this daemon doesn't serve
+    pub fn resolve(&self, requested: Option<&Path>) -> Option<&Arc<HomeContext>> {
+        let Some(requested) = requested else {
+            return Some(&self.primary);
+        };
+        let requested = canonical_path(requested);
+        if requested == canonical_path(&self.primary.home) {
+            return Some(&self.primary);
+        }
+        self.others.get(&requested)
+    }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<HomeContext>;
+}
+
+/// The home of the request being handled, if it runs under `HomeScoped`. Tasks the
+/// handler spawns don't inherit it; give them what they need before spawning
+pub fn current() -> Option<Arc<HomeContext>> {
+    CURRENT.try_with(Arc::clone).ok()
+}
+
+/// Wraps the gRPC service: checks the bearer token against the requested home's and
+/// runs the request with that home as `current()`
+#[derive(Clone)]
+pub struct HomeScoped<S> {
+    inner: S,
+    homes: Arc<Homes>,
+}
+
+impl<S> HomeScoped<S> {
+    pub fn new(inner: S, homes: Arc<Homes>) -> Self {
+        Self { inner, homes }
+    }
+}
+
+impl<S: NamedService> NamedService for HomeScoped<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<http::Request<B>> for HomeScoped<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let context = match authorize(&self.homes, request.headers()) {
+            Ok(context) => context,
+            Err(status) => return Box::pin(async move { Ok(status.into_http()) }),
+        };
+        Box::pin(CURRENT.scope(context, self.inner.call(request)))
+    }
+}
+
+// Boxed: a bare Status makes the Result needlessly large on the happy path
+fn authorize(homes: &Homes, headers: &http::HeaderMap) -> Result<Arc<HomeContext>, Box<Status>> {
+    let metadata = MetadataMap::from_headers(headers.clone());
+    let requested = match metadata.get_bin(HOME_METADATA) {
+        Some(value) => {
+            let invalid = || Box::new(Status::invalid_argument(format!("invalid {HOME_METADATA}")));
+            let bytes = value.to_bytes().map_err(|_| invalid())?;
+            Some(PathBuf::from(String::from_utf8(bytes.to_vec()).map_err(|_| invalid())?))
+        }
+        None => None,
+    };
+    let Some(context) = homes.resolve(requested.as_deref()) else {
+        let primary = &homes.primary().home;
+        return Err(Box::new(Status::failed_precondition(format!(
+            "this daemon serves {}, not {}; add it to \"homes\" in {} and restart the daemon",
+            primary.display(),
+            requested.unwrap_or_default().display(),
+            core::config_path(primary).display()
+        ))));
+    };
+    let presented = metadata
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(token) if constant_time_eq(token.as_bytes(), context.token.as_bytes()) => Ok(context.clone()),
+        Some(_) => Err(Box::new(Status::unauthenticated("invalid token"))),
+        None => Err(Box::new(Status::unauthenticated("missing bearer token"))),
+    }
+}
@@ -0,0 +1,64 @@
+//! Cleanup the daemon does on its own schedule: purging archived workspaces older than
+//! `archive_retention` in config.json, and logging workspaces idle past `stale_after`.
+//! The config is re-read on each pass, so changes apply without a restart.
+
+use crate::db::DbPool;
+use conductor_core as core;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often housekeeping runs; the first pass is at startup
+pub const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn spawn(db: Arc<DbPool>, home: PathBuf) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(INTERVAL);
+        loop {
+            ticker.tick().await;
+            for task in [purge_archived, report_stale] {
+                let home = home.clone();
+                if let Err(e) = db.run(move |conn| task(conn, &home)).await {
+                    warn!("Housekeeping failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+// Stale workspaces are only suggested for cleanup (the desktop asks); nothing is removed
+fn report_stale(conn: &Connection, home: &Path) -> anyhow::Result<()> {
+    let Some(stale_after) = core::config_load(home)?.stale_after else {
+        return Ok(());
+    };
+    let idle = core::parse_age(&stale_after)?;
+    for ws in core::workspace_list(conn, None)? {
+        if core::workspace_is_stale(conn, &ws, idle)? {
+            info!("Workspace {}/{} ({}) has been idle for over {}", ws.repo, ws.name, ws.id, stale_after);
+        }
+    }
+    Ok(())
+}
+
+fn purge_archived(conn: &Connection, home: &Path) -> anyhow::Result<()> {
+    let Some(retention) = core::config_load(home)?.archive_retention else {
+        return Ok(());
+    };
+    let age = core::parse_age(&retention)?;
+    let result = core::workspace_purge(conn, home, age, false);
+    let mut metadata = serde_json::json!({ "older_than": retention });
+    match &result {
+        Ok(purged) if purged.is_empty() => return Ok(()),
+        Ok(purged) => {
+            for ws in purged {
+                info!("Purged archived workspace {}/{} ({})", ws.repo, ws.name, ws.id);
+            }
+            metadata["purged"] = purged.iter().map(|ws| ws.id.clone()).collect();
+        }
+        Err(e) => metadata["error"] = e.to_string().into(),
+    }
+    core::audit_record(conn, "daemon", "workspace.purge", None, result.is_ok(), &metadata)?;
+    result.map(|_| ())
+}
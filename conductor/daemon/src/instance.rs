@@ -0,0 +1,79 @@
+//! Single-instance lock: one daemon per socket, held via flock on a PID file
+
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Held for the daemon's lifetime; the OS releases the flock when the process exits
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Lock file that guards `socket_path` (e.g. daemon.sock -> daemon.lock)
+    pub fn path_for(socket_path: &Path) -> PathBuf {
+        socket_path.with_extension("lock")
+    }
+
+    /// Take the lock and record our PID, or return None if another daemon holds it
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))?;
+        // SAFETY: flock on a file descriptor we own; no memory is shared
+        let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if rc != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return Ok(None);
+            }
+            return Err(err).with_context(|| format!("failed to lock {}", path.display()));
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+/// PID recorded by the daemon currently holding the lock
+pub fn holder_pid(path: &Path) -> Option<i32> {
+    let mut text = String::new();
+    File::open(path).ok()?.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
+
+/// Ask the running daemon to exit (SIGTERM) and wait for its lock to free up
+pub async fn replace(path: &Path, wait: Duration) -> Result<InstanceLock> {
+    let Some(pid) = holder_pid(path) else {
+        bail!("lock {} is held but has no PID; stop the other daemon manually", path.display());
+    };
+    // kill(0) signals our process group and kill(-1) everything we own
+    if pid <= 0 || pid == std::process::id() as i32 {
+        bail!("lock {} names PID {pid}, which is not another daemon; stop it manually", path.display());
+    }
+    // SAFETY: plain signal delivery to another process
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        let err = std::io::Error::last_os_error();
+        return Err(err).with_context(|| format!("failed to signal daemon pid {pid}"));
+    }
+
+    let deadline = Instant::now() + wait;
+    loop {
+        if let Some(lock) = InstanceLock::try_acquire(path)? {
+            return Ok(lock);
+        }
+        if Instant::now() >= deadline {
+            bail!("daemon pid {pid} did not exit within {}s", wait.as_secs());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
@@ -2,10 +2,28 @@
 
 pub mod proto {
     tonic::include_proto!("conductor");
+
+    /// Encoded descriptors for conductor.proto (for gRPC reflection)
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("conductor_descriptor");
 }
 
+pub mod auth;
+pub mod client;
+pub mod compat;
+pub mod db;
+pub mod enrichment;
+pub mod homes;
+pub mod housekeeping;
+pub mod instance;
+pub mod rest;
+pub mod telemetry;
+pub mod transport;
+pub mod watch;
+
 pub use proto::conductor_client::ConductorClient;
 pub use proto::*;
 
-/// Socket path for the daemon
-pub const SOCKET_PATH: &str = "/tmp/conductor-daemon.sock";
+/// Socket path for the daemon in the default home (see `conductor_core::socket_path`)
+pub fn socket_path() -> anyhow::Result<std::path::PathBuf> {
+    conductor_core::socket_path(&conductor_core::default_home())
+}
@@ -0,0 +1,452 @@
+//! Optional REST gateway: JSON over HTTP, with Server-Sent Events for agent streams, for
+//! scripts, browsers and tooling that doesn't speak gRPC. Every route calls the same
+//! `Conductor` service as the gRPC listeners; bodies are the proto messages as JSON.
+
+use crate::auth::constant_time_eq;
+use crate::proto::conductor_server::Conductor;
+use crate::proto::*;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Status};
+
+/// Serve the gateway on `addr` (loopback only: the bearer token travels in clear text)
+pub async fn serve<S: Conductor>(addr: SocketAddr, service: Arc<S>, token: String) -> anyhow::Result<()> {
+    if !addr.ip().is_loopback() {
+        anyhow::bail!("the REST gateway only listens on loopback addresses (got {addr}); put a TLS proxy in front for remote use");
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router(service, token)).await?;
+    Ok(())
+}
+
+pub fn router<S: Conductor>(service: Arc<S>, token: String) -> Router {
+    Router::new()
+        .route("/v1/status", get(status::<S>))
+        .route("/v1/repos", get(list_repos::<S>).post(add_repo::<S>))
+        .route("/v1/workspaces", get(list_workspaces::<S>).post(create_workspace::<S>))
+        .route("/v1/workspaces/:id", get(workspace_status::<S>))
+        .route("/v1/workspaces/:id/summary", get(workspace_summary::<S>))
+        .route("/v1/workspaces/:id/stats", get(workspace_stats::<S>))
+        .route("/v1/workspaces/:id/context", get(repo_context::<S>))
+        .route("/v1/workspaces/:id/archive", post(archive_workspace::<S>))
+        .route("/v1/workspaces/:id/files", get(workspace_files::<S>))
+        .route("/v1/workspaces/:id/tree", get(workspace_tree::<S>))
+        .route("/v1/workspaces/:id/changes", get(workspace_changes::<S>))
+        .route("/v1/workspaces/:id/snapshot", get(workspace_snapshot::<S>))
+        .route("/v1/workspaces/:id/file", get(file_content::<S>).put(save_file::<S>))
+        .route("/v1/workspaces/:id/diff", get(workspace_diff::<S>))
+        .route("/v1/archives", get(list_archives::<S>))
+        .route("/v1/archives/:id/:timestamp", get(get_archive::<S>))
+        .route("/v1/agents", get(list_agents::<S>).post(run_agent::<S>))
+        .route("/v1/agents/:session_id", delete(stop_agent::<S>))
+        .route("/v1/agents/:session_id/events", get(attach_agent::<S>))
+        .route("/v1/engines", get(list_engines::<S>))
+        .layer(middleware::from_fn_with_state(Arc::new(token), require_token))
+        .with_state(service)
+}
+
+// `Authorization: Bearer <token>`, or `?token=` for clients that can't set headers (EventSource)
+async fn require_token(State(token): State<Arc<String>>, request: Request, next: Next) -> Response {
+    let from_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let from_query = request
+        .uri()
+        .query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("token=")));
+    match from_header.or(from_query) {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => next.run(request).await,
+        Some(_) => ApiError::new(StatusCode::UNAUTHORIZED, "invalid token").into_response(),
+        None => ApiError::new(StatusCode::UNAUTHORIZED, "missing bearer token").into_response(),
+    }
+}
+
+/// A failed call, as `{"error": "..."}` with the closest HTTP status
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        let code = match status.code() {
+            Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::FailedPrecondition => StatusCode::PRECONDITION_FAILED,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(code, status.message())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+type EventStream = Sse<Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>>;
+
+// Wrap a message as a gRPC request, passing the caller's User-Agent through for the audit log
+fn grpc<T>(headers: &HeaderMap, message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown");
+    if let Ok(value) = format!("rest ({user_agent})").parse() {
+        request.metadata_mut().insert("user-agent", value);
+    }
+    request
+}
+
+// Each agent event as one SSE message; the data is the AgentEvent JSON
+fn agent_events<St>(stream: St) -> EventStream
+where
+    St: Stream<Item = Result<AgentEvent, Status>> + Send + 'static,
+{
+    let events = stream.map(|item| {
+        let event = match item {
+            Ok(event) => Event::default()
+                .id(event.seq.to_string())
+                .json_data(&event)
+                .unwrap_or_else(|e| Event::default().event("error").data(e.to_string())),
+            Err(status) => Event::default().event("error").data(status.message()),
+        };
+        Ok(event)
+    });
+    Sse::new(Box::pin(events) as Pin<Box<dyn Stream<Item = _> + Send>>).keep_alive(KeepAlive::default())
+}
+
+async fn status<S: Conductor>(State(s): State<Arc<S>>, headers: HeaderMap) -> ApiResult<StatusResponse> {
+    Ok(Json(s.get_status(grpc(&headers, GetStatusRequest {})).await?.into_inner()))
+}
+
+async fn list_repos<S: Conductor>(State(s): State<Arc<S>>, headers: HeaderMap) -> ApiResult<ListReposResponse> {
+    Ok(Json(s.list_repos(grpc(&headers, ListReposRequest {})).await?.into_inner()))
+}
+
+async fn add_repo<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Json(body): Json<AddRepoRequest>,
+) -> ApiResult<Repo> {
+    Ok(Json(s.add_repo(grpc(&headers, body)).await?.into_inner()))
+}
+
+async fn list_workspaces<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Query(query): Query<ListWorkspacesRequest>,
+) -> ApiResult<ListWorkspacesResponse> {
+    Ok(Json(s.list_workspaces(grpc(&headers, query)).await?.into_inner()))
+}
+
+async fn create_workspace<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Json(body): Json<CreateWorkspaceRequest>,
+) -> ApiResult<Workspace> {
+    Ok(Json(s.create_workspace(grpc(&headers, body)).await?.into_inner()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct StatusQuery {
+    refresh_pr: bool,
+}
+
+async fn workspace_status<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<StatusQuery>,
+) -> ApiResult<WorkspaceStatus> {
+    let request = GetWorkspaceStatusRequest {
+        workspace_id,
+        refresh_pr: query.refresh_pr,
+    };
+    Ok(Json(s.get_workspace_status(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn workspace_summary<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+) -> ApiResult<WorkspaceSummary> {
+    let request = GetWorkspaceSummaryRequest { workspace_id };
+    Ok(Json(s.get_workspace_summary(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn workspace_stats<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+) -> ApiResult<WorkspaceStats> {
+    let request = GetWorkspaceStatsRequest { workspace_id };
+    Ok(Json(s.get_workspace_stats(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn repo_context<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+) -> ApiResult<RepoContext> {
+    let request = GetRepoContextRequest { workspace_id };
+    Ok(Json(s.get_repo_context(grpc(&headers, request)).await?.into_inner()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ArchiveQuery {
+    force: bool,
+}
+
+async fn archive_workspace<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<ArchiveQuery>,
+) -> ApiResult<ArchiveWorkspaceResponse> {
+    let request = ArchiveWorkspaceRequest {
+        workspace_id,
+        force: query.force,
+    };
+    Ok(Json(s.archive_workspace(grpc(&headers, request)).await?.into_inner()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FilesQuery {
+    prefix: Option<String>,
+    glob: Option<String>,
+    offset: u32,
+    limit: Option<u32>,
+}
+
+async fn workspace_files<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<FilesQuery>,
+) -> ApiResult<GetWorkspaceFilesResponse> {
+    let request = GetWorkspaceFilesRequest {
+        workspace_id,
+        prefix: query.prefix,
+        glob: query.glob,
+        offset: query.offset,
+        limit: query.limit,
+    };
+    Ok(Json(s.get_workspace_files(grpc(&headers, request)).await?.into_inner()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TreeQuery {
+    dir: String,
+}
+
+async fn workspace_tree<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<TreeQuery>,
+) -> ApiResult<GetWorkspaceTreeResponse> {
+    let request = GetWorkspaceTreeRequest { workspace_id, dir: query.dir };
+    Ok(Json(s.get_workspace_tree(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn workspace_changes<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+) -> ApiResult<GetWorkspaceChangesResponse> {
+    let request = GetWorkspaceChangesRequest { workspace_id };
+    Ok(Json(s.get_workspace_changes(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn workspace_snapshot<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+) -> ApiResult<WorkspaceSnapshot> {
+    let request = GetWorkspaceSnapshotRequest { workspace_id };
+    Ok(Json(s.get_workspace_snapshot(grpc(&headers, request)).await?.into_inner()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FileQuery {
+    path: Option<String>,
+    /// Answer with the file itself rather than JSON
+    raw: bool,
+    // Diff shaping, as in GetFileDiffRequest
+    context: Option<u32>,
+    max_lines: Option<u32>,
+    hunk: Option<u32>,
+}
+
+async fn file_content<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<FileQuery>,
+) -> Result<Response, ApiError> {
+    let file_path = query
+        .path
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "missing ?path="))?;
+    let request = GetFileContentRequest { workspace_id, file_path, raw: query.raw };
+    let response = s.get_file_content(grpc(&headers, request)).await?.into_inner();
+    if !query.raw {
+        return Ok(Json(response).into_response());
+    }
+    // The hash doubles as the ETag that PUT takes back in If-Match
+    let content_type = match response.encoding.as_str() {
+        "utf-8" => "text/plain; charset=utf-8",
+        "text" => "text/plain",
+        _ => "application/octet-stream",
+    };
+    let headers = [(header::CONTENT_TYPE, content_type.to_string()), (header::ETAG, format!("\"{}\"", response.hash))];
+    Ok((headers, response.data).into_response())
+}
+
+// The raw body becomes the file; an If-Match header carries the expected hash
+async fn save_file<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<FileQuery>,
+    body: axum::body::Bytes,
+) -> ApiResult<PutFileContentResponse> {
+    let file_path = query
+        .path
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "missing ?path="))?;
+    let expected_hash = headers
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim_matches('"').to_string());
+    let request = PutFileContentRequest {
+        workspace_id,
+        file_path,
+        content: body.to_vec(),
+        expected_hash,
+    };
+    Ok(Json(s.put_file_content(grpc(&headers, request)).await?.into_inner()))
+}
+
+// The whole workspace diff, or one file's with ?path= (shaped by ?context=, ?max_lines=, ?hunk=)
+async fn workspace_diff<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(workspace_id): Path<String>,
+    Query(query): Query<FileQuery>,
+) -> ApiResult<serde_json::Value> {
+    let Some(file_path) = query.path else {
+        let request = GetWorkspaceDiffRequest { workspace_id };
+        let diff = s.get_workspace_diff(grpc(&headers, request)).await?.into_inner().diff;
+        return Ok(Json(serde_json::json!({ "diff": diff })));
+    };
+    let request = GetFileDiffRequest {
+        workspace_id,
+        file_path,
+        context: query.context,
+        max_lines: query.max_lines,
+        hunk: query.hunk,
+    };
+    let diff = s.get_file_diff(grpc(&headers, request)).await?.into_inner();
+    Ok(Json(serde_json::to_value(diff).unwrap_or_default()))
+}
+
+async fn list_archives<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Query(query): Query<ListArchivesRequest>,
+) -> ApiResult<ListArchivesResponse> {
+    Ok(Json(s.list_archives(grpc(&headers, query)).await?.into_inner()))
+}
+
+async fn get_archive<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path((workspace_id, timestamp)): Path<(String, String)>,
+) -> ApiResult<ArchivedSession> {
+    let request = GetArchiveRequest { workspace_id, timestamp };
+    Ok(Json(s.get_archive(grpc(&headers, request)).await?.into_inner()))
+}
+
+async fn list_agents<S: Conductor>(State(s): State<Arc<S>>, headers: HeaderMap) -> ApiResult<ListActiveAgentsResponse> {
+    Ok(Json(s.list_active_agents(grpc(&headers, ListActiveAgentsRequest {})).await?.into_inner()))
+}
+
+async fn list_engines<S: Conductor>(State(s): State<Arc<S>>, headers: HeaderMap) -> ApiResult<ListEnginesResponse> {
+    Ok(Json(s.list_engines(grpc(&headers, ListEnginesRequest {})).await?.into_inner()))
+}
+
+// Starts the run and streams its events; the session keeps running if the client disconnects
+async fn run_agent<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Json(mut body): Json<RunAgentRequest>,
+) -> Result<EventStream, ApiError> {
+    if body.session_id.is_empty() {
+        body.session_id = uuid::Uuid::new_v4().to_string();
+    }
+    let stream = s.run_agent(grpc(&headers, body)).await?.into_inner();
+    Ok(agent_events(stream))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct AttachQuery {
+    replay: bool,
+}
+
+async fn attach_agent<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+    Query(query): Query<AttachQuery>,
+) -> Result<EventStream, ApiError> {
+    let request = AttachAgentRequest {
+        session_id,
+        replay: query.replay,
+    };
+    let stream = s.attach_agent(grpc(&headers, request)).await?.into_inner();
+    Ok(agent_events(stream))
+}
+
+async fn stop_agent<S: Conductor>(
+    State(s): State<Arc<S>>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> ApiResult<StopAgentResponse> {
+    Ok(Json(s.stop_agent(grpc(&headers, StopAgentRequest { session_id })).await?.into_inner()))
+}
@@ -0,0 +1,68 @@
+//! Logging, plus OTLP trace export when `tracing` is set in config.json. Spans cover
+//! RPCs (named after the method), the git commands core runs for them, and agent runs
+//! from start to exit, so a collector such as Jaeger shows where slow calls go.
+
+use anyhow::{Context, Result};
+use conductor_core as core;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Flushes exported spans on shutdown; only returned when export is on
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush traces: {e}");
+        }
+    }
+}
+
+/// Install the global subscriber: log lines on stdout, and spans sent to the collector
+/// when configured. Must run inside the tokio runtime, which the exporter batches on.
+pub fn init(config: Option<&core::TracingConfig>) -> Result<Option<Telemetry>> {
+    let filter = EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into());
+    let provider = config.map(exporter).transpose()?;
+    let otel = provider.as_ref().map(|provider| {
+        // The exporter's own transport must not be traced, or every export makes more spans
+        let quiet = Targets::new()
+            .with_default(LevelFilter::TRACE)
+            .with_target("h2", LevelFilter::OFF)
+            .with_target("hyper", LevelFilter::OFF)
+            .with_target("tonic", LevelFilter::OFF)
+            .with_target("tower", LevelFilter::OFF)
+            .with_target("opentelemetry", LevelFilter::OFF);
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("conductor-daemon"))
+            .with_filter(quiet)
+    });
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+    Ok(provider.map(|provider| Telemetry { provider }))
+}
+
+fn exporter(config: &core::TracingConfig) -> Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .with_context(|| format!("failed to set up trace export to {}", config.endpoint))?;
+    let service_name = config.service_name.clone().unwrap_or_else(|| "conductor-daemon".to_string());
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build())
+}
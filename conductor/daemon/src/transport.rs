@@ -0,0 +1,63 @@
+//! Listener addresses and TLS setup for serving the daemon beyond the local socket
+
+use anyhow::{anyhow, bail, Context, Result};
+use conductor_core::{RemoteConfig, TlsConfig};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig};
+
+/// Where the daemon accepts connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listen {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl Listen {
+    /// Parse "tcp://HOST:PORT" or "unix:///path/to.sock"
+    pub fn parse(spec: &str) -> Result<Self> {
+        if let Some(addr) = spec.strip_prefix("tcp://") {
+            let addr = addr
+                .parse()
+                .with_context(|| format!("invalid tcp listen address: {addr}"))?;
+            return Ok(Listen::Tcp(addr));
+        }
+        if let Some(path) = spec.strip_prefix("unix://") {
+            if path.is_empty() {
+                bail!("unix listen address needs a path");
+            }
+            return Ok(Listen::Unix(PathBuf::from(path)));
+        }
+        Err(anyhow!("listen address must start with tcp:// or unix://: {spec}"))
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Server TLS that requires clients to present a certificate signed by `client_ca`
+pub fn server_tls(tls: &TlsConfig) -> Result<ServerTlsConfig> {
+    let identity = Identity::from_pem(read_pem(&tls.cert)?, read_pem(&tls.key)?);
+    let client_ca = Certificate::from_pem(read_pem(&tls.client_ca)?);
+    Ok(ServerTlsConfig::new()
+        .identity(identity)
+        .client_ca_root(client_ca))
+}
+
+/// Open a mutual-TLS channel to a remote daemon
+pub async fn connect_remote(remote: &RemoteConfig) -> Result<Channel> {
+    let mut tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(read_pem(&remote.ca)?))
+        .identity(Identity::from_pem(read_pem(&remote.cert)?, read_pem(&remote.key)?));
+    if let Some(domain) = &remote.domain {
+        tls = tls.domain_name(domain.clone());
+    }
+    let channel = Endpoint::from_shared(remote.endpoint.clone())
+        .with_context(|| format!("invalid remote endpoint: {}", remote.endpoint))?
+        .tls_config(tls)?
+        .connect()
+        .await
+        .with_context(|| format!("failed to connect to {}", remote.endpoint))?;
+    Ok(channel)
+}
@@ -0,0 +1,134 @@
+//! Filesystem watching for workspaces (backs the WatchWorkspace RPC)
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration, Instant};
+
+/// Longest a batch keeps collecting after its first change, so a file written
+/// continuously (a log, a build in the tree) can't hold events back forever
+pub const MAX_BATCH_WAIT: Duration = Duration::from_secs(2);
+
+/// Changes collected over one debounce window
+#[derive(Debug, Default)]
+pub struct WatchBatch {
+    /// Changed paths relative to the worktree root (unfiltered by .gitignore)
+    pub paths: Vec<String>,
+    /// HEAD or the worktree index was touched (branch switch, commit, reset)
+    pub head_changed: bool,
+    /// The repository's refs moved under a fetch or pack-refs
+    pub refs_changed: bool,
+}
+
+/// Watches a worktree recursively plus its git dir (for HEAD moves) and the repository's
+/// common git dir (for fetches)
+pub struct WorkspaceWatcher {
+    _watcher: RecommendedWatcher,
+    rx: mpsc::UnboundedReceiver<PathBuf>,
+    root: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+}
+
+impl WorkspaceWatcher {
+    pub fn new(root: PathBuf, git_dir: PathBuf) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if event.kind.is_access() {
+                    return;
+                }
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        watcher.watch(&git_dir, RecursiveMode::NonRecursive)?;
+        let common_dir = common_dir(&git_dir);
+        if common_dir != git_dir {
+            watcher.watch(&common_dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            root,
+            git_dir,
+            common_dir,
+        })
+    }
+
+    /// Wait for the next change, then keep collecting until `debounce` passes quietly,
+    /// or `MAX_BATCH_WAIT` (at least `debounce`) after the first change at the latest.
+    /// Returns None once the underlying watcher has gone away.
+    pub async fn next_batch(&mut self, debounce: Duration) -> Option<WatchBatch> {
+        let first = self.rx.recv().await?;
+        let deadline = Instant::now() + MAX_BATCH_WAIT.max(debounce);
+        let mut batch = WatchBatch::default();
+        let mut paths = BTreeSet::new();
+        self.record(first, &mut batch, &mut paths);
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            match timeout(debounce.min(left), self.rx.recv()).await {
+                Ok(Some(path)) => self.record(path, &mut batch, &mut paths),
+                _ => break,
+            }
+        }
+        batch.paths = paths.into_iter().collect();
+        Some(batch)
+    }
+
+    fn record(&self, path: PathBuf, batch: &mut WatchBatch, paths: &mut BTreeSet<String>) {
+        // A linked worktree's git dir sits inside the common dir, so it goes first
+        if let Ok(rel) = path.strip_prefix(&self.git_dir) {
+            if is_head_path(rel) {
+                batch.head_changed = true;
+            }
+            if is_refs_path(rel) {
+                batch.refs_changed = true;
+            }
+            return;
+        }
+        if let Ok(rel) = path.strip_prefix(&self.common_dir) {
+            if is_refs_path(rel) {
+                batch.refs_changed = true;
+            }
+            return;
+        }
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return;
+        };
+        if rel.as_os_str().is_empty() || is_git_internal(rel) {
+            return;
+        }
+        paths.insert(rel.to_string_lossy().to_string());
+    }
+}
+
+fn is_head_path(rel: &Path) -> bool {
+    // git writes via <file>.lock + rename, so match both forms
+    matches!(rel.to_str(), Some("HEAD" | "HEAD.lock" | "index" | "index.lock"))
+}
+
+fn is_refs_path(rel: &Path) -> bool {
+    // Every fetch rewrites FETCH_HEAD, even when the loose refs it updates sit deeper
+    matches!(rel.to_str(), Some("FETCH_HEAD" | "packed-refs" | "packed-refs.lock"))
+}
+
+/// The repository's shared git dir: named by the `commondir` file in a linked
+/// worktree's git dir, otherwise the git dir itself
+fn common_dir(git_dir: &Path) -> PathBuf {
+    std::fs::read_to_string(git_dir.join("commondir"))
+        .ok()
+        .and_then(|rel| git_dir.join(rel.trim()).canonicalize().ok())
+        .unwrap_or_else(|| git_dir.to_path_buf())
+}
+
+fn is_git_internal(rel: &Path) -> bool {
+    rel.components()
+        .any(|c| matches!(c, Component::Normal(name) if name == ".git"))
+}
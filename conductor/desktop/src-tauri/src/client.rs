@@ -1,29 +1,50 @@
 //! gRPC client for communicating with conductor-daemon
 
-use conductor_daemon::{ConductorClient, SOCKET_PATH};
-use hyper_util::rt::TokioIo;
+use conductor_daemon::auth::DaemonClient;
+use conductor_daemon::client as daemon_client;
+use conductor_daemon::compat::{self, VersionMismatch};
+use conductor_daemon::ShutdownRequest;
 use std::path::Path;
 use std::process::Stdio;
-use tokio::net::UnixStream;
 use tokio::process::Command;
 use tokio::time::{sleep, Duration};
-use tonic::transport::{Channel, Endpoint, Uri};
-use tower::service_fn;
 
-/// Connect to the daemon, spawning it if necessary
-pub async fn connect() -> Result<ConductorClient<Channel>, String> {
-    // Try to connect first
-    if let Ok(client) = try_connect().await {
-        return Ok(client);
+/// Connect to the daemon, spawning it if necessary.
+/// A `remote` endpoint in config.json takes precedence and is never spawned locally.
+pub async fn connect() -> Result<DaemonClient, String> {
+    let home = conductor_core::default_home();
+    let config = conductor_core::config_load(&home).map_err(|e| e.to_string())?;
+    if config.remote.is_some() {
+        return daemon_client::connect(&home)
+            .await
+            .map_err(|e| format!("{:#}", e));
     }
 
-    // Socket doesn't exist or connection failed - try spawning daemon
-    spawn_daemon().await?;
+    // Try to connect first
+    if let Ok(mut client) = daemon_client::connect_local(&home).await {
+        match compat::negotiate(&mut client).await {
+            Ok(_) => return Ok(client),
+            Err(e) => {
+                let outdated = e
+                    .downcast_ref::<VersionMismatch>()
+                    .is_some_and(|m| m.daemon_is_older());
+                if !(outdated && config.restart_outdated_daemon) {
+                    return Err(format!("{:#}", e));
+                }
+                // Upgrade path: stop the old daemon and let a fresh binary take over its lock
+                let _ = client.shutdown(ShutdownRequest {}).await;
+                spawn_daemon(true).await?;
+            }
+        }
+    } else {
+        // Socket doesn't exist or connection failed - try spawning daemon
+        spawn_daemon(false).await?;
+    }
 
     // Wait for daemon to start and retry connection
     for _ in 0..30 {
         sleep(Duration::from_millis(100)).await;
-        if let Ok(client) = try_connect().await {
+        if let Ok(client) = daemon_client::connect(&home).await {
             return Ok(client);
         }
     }
@@ -31,32 +52,17 @@ pub async fn connect() -> Result<ConductorClient<Channel>, String> {
     Err("Failed to connect to daemon after spawning".to_string())
 }
 
-/// Try to connect to the daemon without spawning
-async fn try_connect() -> Result<ConductorClient<Channel>, String> {
-    if !Path::new(SOCKET_PATH).exists() {
-        return Err("Socket does not exist".to_string());
-    }
-
-    // Create a channel that connects via Unix socket
-    let channel = Endpoint::try_from("http://[::]:50051")
-        .map_err(|e| e.to_string())?
-        .connect_with_connector(service_fn(|_: Uri| async {
-            let stream = UnixStream::connect(SOCKET_PATH).await?;
-            Ok::<_, std::io::Error>(TokioIo::new(stream))
-        }))
-        .await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
-
-    Ok(ConductorClient::new(channel))
-}
-
-/// Spawn the daemon as a detached process
-async fn spawn_daemon() -> Result<(), String> {
+/// Spawn the daemon as a detached process (`replace` takes over from a running one)
+async fn spawn_daemon(replace: bool) -> Result<(), String> {
     // Find the daemon binary
     let daemon_path = find_daemon_binary()?;
 
     // Spawn detached
-    Command::new(&daemon_path)
+    let mut command = Command::new(&daemon_path);
+    if replace {
+        command.arg("--replace");
+    }
+    command
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -92,10 +98,10 @@ fn find_daemon_binary() -> Result<String, String> {
 use std::sync::OnceLock;
 use tokio::sync::Mutex;
 
-static CLIENT: OnceLock<Mutex<Option<ConductorClient<Channel>>>> = OnceLock::new();
+static CLIENT: OnceLock<Mutex<Option<DaemonClient>>> = OnceLock::new();
 
 /// Get or create the global client
-pub async fn get_client() -> Result<ConductorClient<Channel>, String> {
+pub async fn get_client() -> Result<DaemonClient, String> {
     let mutex = CLIENT.get_or_init(|| Mutex::new(None));
     let mut guard = mutex.lock().await;
 
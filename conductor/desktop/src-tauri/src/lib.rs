@@ -1,10 +1,10 @@
 mod client;
+mod notifications;
 
-use conductor_core::{Repo, SessionState, Workspace, WorkspaceChange, ArchiveResult};
-use conductor_daemon::proto;
+use conductor_core::{ApplyResult, ChatEntry, DiffHunk, DiffLine, DiffStat, FileDiff, LogCommit, StructuredDiff, NotificationSettings, Repo, ReviewComment, SearchHit, SessionState, TreeEntry, Workspace, WorkspaceChange, WorkspaceSnapshot, ArchiveResult};
+use conductor_daemon::{compat, proto};
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
 use std::collections::HashMap;
-use std::env;
 use std::io::{Read, Write};
 use std::sync::LazyLock;
 use tauri::Emitter;
@@ -25,8 +25,15 @@ struct ShellInstance {
 static SHELL_PROCESSES: LazyLock<Mutex<HashMap<String, ShellInstance>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-fn map_err(err: impl std::fmt::Display) -> String {
-    err.to_string()
+// Forwarding tasks for WatchWorkspace streams, keyed by workspace id
+static WORKSPACE_WATCHERS: LazyLock<Mutex<HashMap<String, tokio::task::JoinHandle<()>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn map_err(err: impl std::fmt::Display + 'static) -> String {
+    match (&err as &dyn std::any::Any).downcast_ref::<tonic::Status>() {
+        Some(status) => compat::describe_status(status),
+        None => err.to_string(),
+    }
 }
 
 // =============================================================================
@@ -55,6 +62,29 @@ async fn list_repos(_home: Option<String>) -> Result<Vec<Repo>, String> {
         .collect())
 }
 
+/// List `repos` (ids) first, in that order; returns the new listing
+#[tauri::command]
+async fn reorder_repos(repos: Vec<String>) -> Result<Vec<Repo>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .reorder_repos(proto::ReorderReposRequest { repo_ids: repos })
+        .await
+        .map_err(map_err)?;
+
+    Ok(response
+        .into_inner()
+        .repos
+        .into_iter()
+        .map(|r| Repo {
+            id: r.id,
+            name: r.name,
+            root_path: r.root_path,
+            default_branch: r.default_branch,
+            remote_url: r.remote_url,
+        })
+        .collect())
+}
+
 #[tauri::command]
 async fn add_repo(
     _home: Option<String>,
@@ -98,6 +128,10 @@ async fn add_repo_url(
         .add_repo_url(proto::AddRepoUrlRequest {
             url,
             parent_dir: None,
+            depth: None,
+            filter: None,
+            single_branch: false,
+            bare: false,
         })
         .await
         .map_err(map_err)?;
@@ -116,34 +150,114 @@ async fn add_repo_url(
 // Workspace Commands (via daemon)
 // =============================================================================
 
+fn activity_from_proto(a: proto::WorkspaceActivity) -> conductor_core::WorkspaceActivity {
+    conductor_core::WorkspaceActivity {
+        last_agent_run_at: a.last_agent_run_at,
+        last_commit_at: a.last_commit_at,
+        last_opened_at: a.last_opened_at,
+    }
+}
+
+fn verification_from_proto(v: proto::WorkspaceVerification) -> conductor_core::WorkspaceVerification {
+    conductor_core::WorkspaceVerification {
+        ok: v.ok,
+        failed_command: v.failed_command,
+        session_id: v.session_id,
+        finished_at: v.finished_at,
+    }
+}
+
+fn workspace_from_proto(w: proto::Workspace) -> Workspace {
+    Workspace {
+        id: w.id,
+        repo_id: w.repository_id,
+        repo: String::new(), // Not returned by daemon
+        name: w.directory_name,
+        branch: w.branch,
+        base_branch: w.base_branch,
+        state: w.state.parse().unwrap_or(conductor_core::WorkspaceState::Ready),
+        path: w.path,
+        pinned: w.pinned,
+        enrichment: w.enrichment.map(|e| conductor_core::WorkspaceEnrichment {
+            head: e.head,
+            dirty: e.dirty,
+            changed_files: e.changed_files,
+            ahead: e.ahead,
+            behind: e.behind,
+        }),
+        link_error: w.link_error,
+        error: w.error,
+        activity: w.activity.map(activity_from_proto).unwrap_or_default(),
+        title: w.title,
+        summary: w.summary,
+        verification: w.verification.map(verification_from_proto),
+    }
+}
+
 #[tauri::command]
-async fn list_workspaces(_home: Option<String>, repo: Option<String>) -> Result<Vec<Workspace>, String> {
+async fn list_workspaces(
+    _home: Option<String>,
+    repo: Option<String>,
+    sort: Option<String>,
+    states: Option<Vec<String>>,
+) -> Result<Vec<Workspace>, String> {
     let mut client = client::get_client().await?;
     let response = client
-        .list_workspaces(proto::ListWorkspacesRequest { repo_id: repo })
+        .list_workspaces(proto::ListWorkspacesRequest {
+            repo_id: repo,
+            enriched: true,
+            sort: sort.unwrap_or_default(),
+            states: states.unwrap_or_default(),
+            stale: None,
+        })
         .await
         .map_err(map_err)?;
 
-    Ok(response
-        .into_inner()
-        .workspaces
-        .into_iter()
-        .map(|w| Workspace {
-            id: w.id,
-            repo_id: w.repository_id,
-            repo: String::new(), // Not returned by daemon
-            name: w.directory_name,
-            branch: w.branch,
-            base_branch: w.base_branch,
-            state: match w.state.as_str() {
-                "ready" => conductor_core::WorkspaceState::Ready,
-                "archived" => conductor_core::WorkspaceState::Archived,
-                "error" => conductor_core::WorkspaceState::Error,
-                _ => conductor_core::WorkspaceState::Ready,
-            },
-            path: w.path,
+    Ok(response.into_inner().workspaces.into_iter().map(workspace_from_proto).collect())
+}
+
+/// Workspaces idle past `stale_after` in config.json, to suggest cleaning up (none when unset)
+#[tauri::command]
+async fn list_stale_workspaces() -> Result<serde_json::Value, String> {
+    let config = conductor_core::config_load(&conductor_core::default_home()).map_err(map_err)?;
+    let Some(stale_after) = config.stale_after else {
+        return Ok(serde_json::json!({ "stale_after": null, "workspaces": [] }));
+    };
+    let mut client = client::get_client().await?;
+    let response = client
+        .list_workspaces(proto::ListWorkspacesRequest {
+            repo_id: None,
+            enriched: false,
+            sort: "recent".to_string(),
+            states: Vec::new(),
+            stale: Some(stale_after.clone()),
         })
-        .collect())
+        .await
+        .map_err(map_err)?;
+    let workspaces: Vec<Workspace> = response.into_inner().workspaces.into_iter().map(workspace_from_proto).collect();
+    Ok(serde_json::json!({ "stale_after": stale_after, "workspaces": workspaces }))
+}
+
+/// Move a workspace along its pipeline ("ready", "in_review", "merged" or "abandoned")
+#[tauri::command]
+async fn set_workspace_state(workspace: String, state: String) -> Result<Workspace, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .set_workspace_state(proto::SetWorkspaceStateRequest { workspace_id: workspace, state })
+        .await
+        .map_err(map_err)?;
+    Ok(workspace_from_proto(response.into_inner()))
+}
+
+/// Keep a workspace at the top of listings, or stop doing so
+#[tauri::command]
+async fn set_workspace_pinned(workspace: String, pinned: bool) -> Result<Workspace, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .set_workspace_pinned(proto::SetWorkspacePinnedRequest { workspace_id: workspace, pinned })
+        .await
+        .map_err(map_err)?;
+    Ok(workspace_from_proto(response.into_inner()))
 }
 
 #[tauri::command]
@@ -163,26 +277,13 @@ async fn create_workspace(
         .create_workspace(proto::CreateWorkspaceRequest {
             repo_id: repo,
             name,
+            auto_suffix: false,
+            carry_changes: false,
         })
         .await
         .map_err(map_err)?;
 
-    let w = response.into_inner();
-    Ok(Workspace {
-        id: w.id,
-        repo_id: w.repository_id,
-        repo: String::new(),
-        name: w.directory_name,
-        branch: w.branch,
-        base_branch: w.base_branch,
-        state: match w.state.as_str() {
-            "ready" => conductor_core::WorkspaceState::Ready,
-            "archived" => conductor_core::WorkspaceState::Archived,
-            "error" => conductor_core::WorkspaceState::Error,
-            _ => conductor_core::WorkspaceState::Ready,
-        },
-        path: w.path,
-    })
+    Ok(workspace_from_proto(response.into_inner()))
 }
 
 #[tauri::command]
@@ -212,6 +313,7 @@ async fn archive_workspace(
             ok: true,
             removed: true,
             message: "archived".to_string(),
+            checks: Vec::new(),
         })
     } else {
         Err(r.error.unwrap_or_else(|| "Archive failed".to_string()))
@@ -219,11 +321,123 @@ async fn archive_workspace(
 }
 
 #[tauri::command]
-async fn workspace_files(_home: Option<String>, workspace: String) -> Result<Vec<String>, String> {
+async fn apply_patch(workspace: String, patch: String, three_way: Option<bool>) -> Result<ApplyResult, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .apply_patch(proto::ApplyPatchRequest {
+            workspace_id: workspace,
+            patch,
+            three_way: three_way.unwrap_or(false),
+            bundle_path: None,
+        })
+        .await
+        .map_err(map_err)?;
+
+    let r = response.into_inner();
+    Ok(ApplyResult {
+        applied: r.applied,
+        files: r.files,
+        conflicts: r.conflicts,
+        message: r.message,
+    })
+}
+
+/// Returns the new commit's sha
+#[tauri::command]
+async fn commit_workspace(workspace: String, message: String) -> Result<String, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .commit_workspace(proto::CommitWorkspaceRequest { workspace_id: workspace, message })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().sha)
+}
+
+#[tauri::command]
+async fn suggest_commit_message(workspace: String) -> Result<String, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .suggest_commit_message(proto::SuggestCommitMessageRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().message)
+}
+
+#[tauri::command]
+async fn open_workspace(workspace: String) -> Result<String, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .open_workspace(proto::OpenWorkspaceRequest {
+            workspace_id: workspace,
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().editor)
+}
+
+// Workspace path as the daemon knows it, for launching local apps there
+async fn workspace_path(workspace: String) -> Result<String, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .get_workspace_status(proto::GetWorkspaceStatusRequest {
+            workspace_id: workspace,
+            refresh_pr: false,
+        })
+        .await
+        .map_err(map_err)?;
+    response
+        .into_inner()
+        .workspace
+        .map(|ws| ws.path)
+        .ok_or_else(|| "workspace not found".to_string())
+}
+
+fn launch_detached(command: &[String], cwd: &str) -> Result<(), String> {
+    let mut child = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(cwd)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to launch {}: {e}", command[0]))?;
+    // Reap in the background so the launcher doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[tauri::command]
+async fn open_workspace_terminal(workspace: String) -> Result<(), String> {
+    let path = workspace_path(workspace).await?;
+    let config = conductor_core::config_load(&conductor_core::default_home()).map_err(map_err)?;
+    let command = conductor_core::terminal_command(&config, std::path::Path::new(&path)).map_err(map_err)?;
+    launch_detached(&command, &path)
+}
+
+#[tauri::command]
+async fn reveal_workspace(workspace: String) -> Result<(), String> {
+    let path = workspace_path(workspace).await?;
+    let command = conductor_core::reveal_command(std::path::Path::new(&path));
+    launch_detached(&command, &path)
+}
+
+#[tauri::command]
+async fn workspace_files(
+    _home: Option<String>,
+    workspace: String,
+    prefix: Option<String>,
+    glob: Option<String>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> Result<Vec<String>, String> {
     let mut client = client::get_client().await?;
     let response = client
         .get_workspace_files(proto::GetWorkspaceFilesRequest {
             workspace_id: workspace,
+            prefix,
+            glob,
+            offset: offset.unwrap_or(0),
+            limit,
         })
         .await
         .map_err(map_err)?;
@@ -236,6 +450,31 @@ async fn workspace_files(_home: Option<String>, workspace: String) -> Result<Vec
         .collect())
 }
 
+#[tauri::command]
+async fn workspace_tree(workspace: String, dir: String) -> Result<Vec<TreeEntry>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .get_workspace_tree(proto::GetWorkspaceTreeRequest {
+            workspace_id: workspace,
+            dir,
+        })
+        .await
+        .map_err(map_err)?;
+
+    Ok(response
+        .into_inner()
+        .entries
+        .into_iter()
+        .map(|e| TreeEntry {
+            name: e.name,
+            path: e.path,
+            kind: e.kind,
+            size: e.size,
+            status: e.status,
+        })
+        .collect())
+}
+
 #[tauri::command]
 async fn workspace_changes(_home: Option<String>, workspace: String) -> Result<Vec<WorkspaceChange>, String> {
     let mut client = client::get_client().await?;
@@ -251,13 +490,115 @@ async fn workspace_changes(_home: Option<String>, workspace: String) -> Result<V
         .changes
         .into_iter()
         .map(|c| WorkspaceChange {
-            old_path: None,
+            old_path: c.old_path,
             path: c.path,
             status: c.status,
         })
         .collect())
 }
 
+/// Files, worktree statuses and changes in one call, for the file list and changes panel
+#[tauri::command]
+async fn workspace_snapshot(workspace: String) -> Result<WorkspaceSnapshot, String> {
+    let mut client = client::get_client().await?;
+    let snapshot = client
+        .get_workspace_snapshot(proto::GetWorkspaceSnapshotRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    Ok(WorkspaceSnapshot {
+        files: snapshot.files,
+        statuses: snapshot.statuses.into_iter().collect(),
+        changes: snapshot
+            .changes
+            .into_iter()
+            .map(|c| WorkspaceChange {
+                old_path: c.old_path,
+                path: c.path,
+                status: c.status,
+            })
+            .collect(),
+    })
+}
+
+/// Commits, line counts, agent runs, token usage and age, for the workspace header
+#[tauri::command]
+async fn workspace_stats(workspace: String) -> Result<conductor_core::WorkspaceStats, String> {
+    let mut client = client::get_client().await?;
+    let stats = client
+        .get_workspace_stats(proto::GetWorkspaceStatsRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+    Ok(conductor_core::WorkspaceStats {
+        workspace_id: stats.workspace_id,
+        commits: stats.commits,
+        diff: DiffStat {
+            files: stats.files_changed,
+            insertions: stats.insertions,
+            deletions: stats.deletions,
+        },
+        agent_runs: stats.agent_runs,
+        usage: conductor_core::TokenUsage {
+            input_tokens: stats.input_tokens,
+            output_tokens: stats.output_tokens,
+        },
+        created_at: stats.created_at,
+        age_secs: stats.age_secs,
+    })
+}
+
+/// What the workspace view needs on selection, from one `GetWorkspaceSummary` call
+#[derive(serde::Serialize)]
+struct WorkspaceSummary {
+    changes: Vec<WorkspaceChange>,
+    session: Option<SessionState>,
+    last_message: Option<ChatEntry>,
+    /// Session ids of agents running in the workspace
+    running_agents: Vec<String>,
+}
+
+#[tauri::command]
+async fn workspace_summary(workspace: String) -> Result<WorkspaceSummary, String> {
+    let mut client = client::get_client().await?;
+    let summary = client
+        .get_workspace_summary(proto::GetWorkspaceSummaryRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    Ok(WorkspaceSummary {
+        changes: summary
+            .changes
+            .into_iter()
+            .map(|c| WorkspaceChange {
+                old_path: c.old_path,
+                path: c.path,
+                status: c.status,
+            })
+            .collect(),
+        session: summary.session.and_then(|s| {
+            Some(SessionState {
+                agent_id: s.agent_id?,
+                resume_id: s.resume_id,
+                started_at: s.started_at.unwrap_or_default(),
+                updated_at: s.updated_at.unwrap_or_default(),
+                engine_version: s.engine_version,
+                model: s.model,
+                base_sha: s.base_sha,
+                conductor_version: s.conductor_version,
+            })
+        }),
+        last_message: summary.last_message.map(|m| ChatEntry {
+            role: m.role,
+            content: m.content,
+            timestamp: m.timestamp,
+        }),
+        running_agents: summary.agents.into_iter().map(|a| a.session_id).collect(),
+    })
+}
+
 #[tauri::command]
 async fn workspace_file_content(
     _home: Option<String>,
@@ -269,6 +610,7 @@ async fn workspace_file_content(
         .get_file_content(proto::GetFileContentRequest {
             workspace_id: workspace,
             file_path: path,
+            raw: false,
         })
         .await
         .map_err(map_err)?;
@@ -276,22 +618,503 @@ async fn workspace_file_content(
     Ok(response.into_inner().content)
 }
 
+/// A file exactly as stored, with a hint for decoding it (or hex-viewing a binary)
+#[derive(serde::Serialize)]
+struct FileBytes {
+    data: Vec<u8>,
+    /// "utf-8", "binary" or "text" (some other encoding)
+    encoding: String,
+    hash: String,
+}
+
 #[tauri::command]
-async fn workspace_file_diff(
-    _home: Option<String>,
+async fn workspace_file_bytes(workspace: String, path: String) -> Result<FileBytes, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .get_file_content(proto::GetFileContentRequest {
+            workspace_id: workspace,
+            file_path: path,
+            raw: true,
+        })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    Ok(FileBytes {
+        data: response.data,
+        encoding: response.encoding,
+        hash: response.hash,
+    })
+}
+
+/// What the file viewer shows: text up to a size cap, or just facts about binaries
+#[derive(serde::Serialize)]
+struct FilePreview {
+    content: Option<String>,
+    binary: bool,
+    total_size: u64,
+    truncated: bool,
+    /// Set when `content` is the whole file, exactly; saving edits needs it
+    hash: Option<String>,
+}
+
+// Enough for any source file; bigger logs show their head
+const PREVIEW_MAX_BYTES: u64 = 2 * 1024 * 1024;
+
+#[tauri::command]
+async fn workspace_file_preview(workspace: String, path: String, max_bytes: Option<u64>) -> Result<FilePreview, String> {
+    let mut client = client::get_client().await?;
+    let mut stream = client
+        .stream_file_content(proto::StreamFileContentRequest {
+            workspace_id: workspace,
+            file_path: path,
+            max_bytes: Some(max_bytes.unwrap_or(PREVIEW_MAX_BYTES)),
+            chunk_size: None,
+        })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    let mut bytes = Vec::new();
+    let mut preview = FilePreview {
+        content: None,
+        binary: false,
+        total_size: 0,
+        truncated: false,
+        hash: None,
+    };
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(map_err)?;
+        preview.binary = chunk.binary;
+        preview.total_size = chunk.total_size;
+        preview.truncated = chunk.truncated;
+        // Binary files are described, not shown; stop pulling them
+        if chunk.binary {
+            break;
+        }
+        bytes.extend_from_slice(&chunk.data);
+        if chunk.last {
+            break;
+        }
+    }
+    if !preview.binary {
+        if !preview.truncated && std::str::from_utf8(&bytes).is_ok() {
+            preview.hash = Some(conductor_core::content_hash(&bytes));
+        }
+        preview.content = Some(String::from_utf8_lossy(&bytes).into_owned());
+    }
+    Ok(preview)
+}
+
+/// Save editor content; fails without writing if the file changed since `expected_hash`
+#[tauri::command]
+async fn workspace_file_save(
     workspace: String,
     path: String,
+    content: String,
+    expected_hash: Option<String>,
 ) -> Result<String, String> {
     let mut client = client::get_client().await?;
     let response = client
+        .put_file_content(proto::PutFileContentRequest {
+            workspace_id: workspace,
+            file_path: path,
+            content: content.into_bytes(),
+            expected_hash,
+        })
+        .await
+        .map_err(|status| match status.code() {
+            tonic::Code::Aborted => format!("{}; reload it before saving", status.message()),
+            _ => map_err(status),
+        })?;
+
+    Ok(response.into_inner().hash)
+}
+
+/// Rename or move a file or directory; resolves to whether git staged it
+#[tauri::command]
+async fn workspace_file_rename(workspace: String, from: String, to: String) -> Result<bool, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .rename_file(proto::RenameFileRequest {
+            workspace_id: workspace,
+            from_path: from,
+            to_path: to,
+        })
+        .await
+        .map_err(map_err)?;
+
+    Ok(response.into_inner().staged)
+}
+
+#[tauri::command]
+async fn workspace_file_delete(workspace: String, path: String) -> Result<bool, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .delete_file(proto::DeleteFileRequest {
+            workspace_id: workspace,
+            file_path: path,
+        })
+        .await
+        .map_err(map_err)?;
+
+    Ok(response.into_inner().staged)
+}
+
+#[tauri::command]
+async fn workspace_dir_create(workspace: String, path: String) -> Result<(), String> {
+    let mut client = client::get_client().await?;
+    client
+        .create_directory(proto::CreateDirectoryRequest {
+            workspace_id: workspace,
+            dir_path: path,
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(())
+}
+
+/// A changed file's diff as hunks, for side-by-side rendering and per-hunk actions
+#[tauri::command]
+async fn workspace_file_diff_structured(
+    workspace: String,
+    path: String,
+    max_lines: Option<u32>,
+    hunk: Option<u32>,
+) -> Result<StructuredDiff, String> {
+    let mut client = client::get_client().await?;
+    let diff = client
+        .get_file_diff_structured(proto::GetFileDiffRequest {
+            workspace_id: workspace,
+            file_path: path,
+            context: None,
+            max_lines,
+            hunk,
+        })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    Ok(StructuredDiff {
+        binary: diff.binary,
+        omitted_hunks: diff.omitted_hunks,
+        hunks: diff
+            .hunks
+            .into_iter()
+            .map(|h| DiffHunk {
+                old_start: h.old_start,
+                old_lines: h.old_lines,
+                new_start: h.new_start,
+                new_lines: h.new_lines,
+                header: h.header,
+                lines: h
+                    .lines
+                    .into_iter()
+                    .map(|l| DiffLine {
+                        kind: l.kind,
+                        content: l.content,
+                        old_line: l.old_line,
+                        new_line: l.new_line,
+                        no_newline: l.no_newline,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    })
+}
+
+#[tauri::command]
+async fn workspace_stage(workspace: String, paths: Vec<String>) -> Result<(), String> {
+    let mut client = client::get_client().await?;
+    client
+        .stage_files(proto::StageFilesRequest {
+            workspace_id: workspace,
+            paths,
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn workspace_unstage(workspace: String, paths: Vec<String>) -> Result<(), String> {
+    let mut client = client::get_client().await?;
+    client
+        .unstage_files(proto::UnstageFilesRequest {
+            workspace_id: workspace,
+            paths,
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn workspace_discard(workspace: String, paths: Vec<String>, include_untracked: bool) -> Result<(), String> {
+    let mut client = client::get_client().await?;
+    client
+        .discard_changes(proto::DiscardChangesRequest {
+            workspace_id: workspace,
+            paths,
+            include_untracked,
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(())
+}
+
+/// The branch's own commits since its base, newest first
+#[tauri::command]
+async fn workspace_log(workspace: String, limit: Option<u32>) -> Result<Vec<LogCommit>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .get_workspace_log(proto::GetWorkspaceLogRequest {
+            workspace_id: workspace,
+            limit,
+            since_base: Some(true),
+        })
+        .await
+        .map_err(map_err)?;
+
+    Ok(response
+        .into_inner()
+        .commits
+        .into_iter()
+        .map(|c| LogCommit {
+            sha: c.sha,
+            author: c.author,
+            author_email: c.author_email,
+            date: c.date,
+            subject: c.subject,
+            stat: DiffStat {
+                files: c.files,
+                insertions: c.insertions,
+                deletions: c.deletions,
+            },
+        })
+        .collect())
+}
+
+/// A file's patch; with `max_lines` big ones come back cut, and `hunk` fetches one whole
+#[tauri::command]
+async fn workspace_file_diff(
+    _home: Option<String>,
+    workspace: String,
+    path: String,
+    max_lines: Option<u32>,
+    hunk: Option<u32>,
+) -> Result<FileDiff, String> {
+    let mut client = client::get_client().await?;
+    let diff = client
         .get_file_diff(proto::GetFileDiffRequest {
             workspace_id: workspace,
             file_path: path,
+            context: None,
+            max_lines,
+            hunk,
+        })
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    Ok(FileDiff {
+        diff: diff.diff,
+        total_lines: diff.total_lines as usize,
+        omitted_hunks: diff.omitted_hunks,
+    })
+}
+
+fn review_comment_from_proto(comment: proto::ReviewComment) -> ReviewComment {
+    ReviewComment {
+        id: comment.id,
+        workspace_id: comment.workspace_id,
+        file_path: comment.file_path,
+        start_line: comment.start_line,
+        end_line: comment.end_line,
+        author: comment.author,
+        body: comment.body,
+        resolved: comment.resolved,
+        created_at: comment.created_at,
+        updated_at: comment.updated_at,
+        url: comment.url,
+    }
+}
+
+/// Repos, workspaces, files and chat matching `query`, best first
+#[tauri::command]
+async fn search(query: String, limit: Option<u32>) -> Result<Vec<SearchHit>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .search(proto::SearchRequest { query, limit })
+        .await
+        .map_err(map_err)?;
+    response
+        .into_inner()
+        .hits
+        .into_iter()
+        .map(|hit| {
+            Ok(SearchHit {
+                kind: hit.kind.parse::<conductor_core::SearchHitKind>().map_err(|e| e.to_string())?,
+                score: hit.score,
+                repo_id: hit.repo_id,
+                repo: hit.repo,
+                workspace_id: hit.workspace_id,
+                workspace: hit.workspace,
+                label: hit.label,
+                snippet: hit.snippet,
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+async fn review_comments(workspace: String, include_resolved: Option<bool>) -> Result<Vec<ReviewComment>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .list_review_comments(proto::ListReviewCommentsRequest {
+            workspace_id: workspace,
+            include_resolved: include_resolved.unwrap_or(false),
+        })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().comments.into_iter().map(review_comment_from_proto).collect())
+}
+
+#[tauri::command]
+async fn review_comment_add(
+    workspace: String,
+    path: String,
+    start_line: u32,
+    end_line: Option<u32>,
+    body: String,
+) -> Result<ReviewComment, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .add_review_comment(proto::AddReviewCommentRequest {
+            workspace_id: workspace,
+            file_path: path,
+            start_line,
+            end_line: end_line.unwrap_or(start_line),
+            author: None,
+            body,
         })
         .await
         .map_err(map_err)?;
+    Ok(review_comment_from_proto(response.into_inner()))
+}
 
-    Ok(response.into_inner().diff)
+#[tauri::command]
+async fn review_comment_update(id: String, body: Option<String>, resolved: Option<bool>) -> Result<ReviewComment, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .update_review_comment(proto::UpdateReviewCommentRequest { id, body, resolved })
+        .await
+        .map_err(map_err)?;
+    Ok(review_comment_from_proto(response.into_inner()))
+}
+
+#[tauri::command]
+async fn review_comment_delete(id: String) -> Result<(), String> {
+    let mut client = client::get_client().await?;
+    client
+        .delete_review_comment(proto::DeleteReviewCommentRequest { id })
+        .await
+        .map_err(map_err)?;
+    Ok(())
+}
+
+/// The unresolved comments as a follow-up prompt; null when there are none
+#[tauri::command]
+async fn review_prompt(workspace: String) -> Result<Option<String>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .get_review_prompt(proto::GetReviewPromptRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().prompt)
+}
+
+/// Fetches reviewers' comments from the workspace's PR; returns the ones not seen before
+#[tauri::command]
+async fn sync_review_comments(workspace: String) -> Result<Vec<ReviewComment>, String> {
+    let mut client = client::get_client().await?;
+    let response = client
+        .sync_review_comments(proto::SyncReviewCommentsRequest { workspace_id: workspace })
+        .await
+        .map_err(map_err)?;
+    Ok(response.into_inner().added.into_iter().map(review_comment_from_proto).collect())
+}
+
+#[tauri::command]
+async fn watch_workspace(app: tauri::AppHandle, workspace: String) -> Result<(), String> {
+    let mut watchers = WORKSPACE_WATCHERS.lock().await;
+    if watchers.get(&workspace).is_some_and(|task| !task.is_finished()) {
+        return Ok(());
+    }
+
+    let mut client = client::get_client().await?;
+    let response = client
+        .watch_workspace(proto::WatchWorkspaceRequest {
+            workspace_id: workspace.clone(),
+            debounce_ms: None,
+        })
+        .await
+        .map_err(map_err)?;
+
+    // Name for notification titles; the stream itself only carries the id
+    let label = client
+        .get_workspace_status(proto::GetWorkspaceStatusRequest {
+            workspace_id: workspace.clone(),
+            refresh_pr: false,
+        })
+        .await
+        .ok()
+        .and_then(|status| status.into_inner().workspace)
+        .map_or_else(|| workspace.clone(), |ws| ws.directory_name);
+
+    let mut stream = response.into_inner();
+    let task = tokio::spawn(async move {
+        while let Some(Ok(event)) = stream.next().await {
+            if event.event_type == "conflicts_detected" {
+                notifications::merge_conflict(&app, &label, &event.paths);
+            }
+            let _ = app.emit(
+                "workspace_event",
+                serde_json::json!({
+                    "workspace_id": event.workspace_id,
+                    "type": event.event_type,
+                    "paths": event.paths,
+                    "branch": event.branch,
+                    "head": event.head,
+                }),
+            );
+        }
+    });
+    watchers.insert(workspace, task);
+    Ok(())
+}
+
+#[tauri::command]
+async fn unwatch_workspace(workspace: String) -> Result<(), String> {
+    let mut watchers = WORKSPACE_WATCHERS.lock().await;
+    if let Some(task) = watchers.remove(&workspace) {
+        task.abort();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_notification_settings() -> Result<NotificationSettings, String> {
+    let config = conductor_core::config_load(&conductor_core::default_home()).map_err(map_err)?;
+    Ok(config.notifications)
+}
+
+#[tauri::command]
+fn set_notification_settings(settings: NotificationSettings) -> Result<(), String> {
+    let home = conductor_core::default_home();
+    let mut config = conductor_core::config_load(&home).map_err(map_err)?;
+    config.notifications = settings;
+    conductor_core::config_save(&home, &config).map_err(map_err)
 }
 
 #[tauri::command]
@@ -321,6 +1144,10 @@ async fn session_read(workspace_path: String) -> Result<Option<SessionState>, St
         resume_id: s.resume_id,
         started_at: s.started_at.unwrap_or_default(),
         updated_at: s.updated_at.unwrap_or_default(),
+        engine_version: s.engine_version,
+        model: s.model,
+        base_sha: s.base_sha,
+        conductor_version: s.conductor_version,
     }))
 }
 
@@ -341,6 +1168,10 @@ async fn session_create(workspace_path: String, agent_id: String) -> Result<Sess
         resume_id: s.resume_id,
         started_at: s.started_at.unwrap_or_default(),
         updated_at: s.updated_at.unwrap_or_default(),
+        engine_version: s.engine_version,
+        model: s.model,
+        base_sha: s.base_sha,
+        conductor_version: s.conductor_version,
     })
 }
 
@@ -361,6 +1192,10 @@ async fn session_set_resume_id(workspace_path: String, resume_id: String) -> Res
         resume_id: s.resume_id,
         started_at: s.started_at.unwrap_or_default(),
         updated_at: s.updated_at.unwrap_or_default(),
+        engine_version: s.engine_version,
+        model: s.model,
+        base_sha: s.base_sha,
+        conductor_version: s.conductor_version,
     })
 }
 
@@ -436,10 +1271,13 @@ async fn run_agent(
     cwd: String,
     session_id: String,
     resume_id: Option<String>,
+    isolation: Option<String>,
+    attachments: Option<Vec<String>>,
 ) -> Result<(), String> {
     let mut client = client::get_client().await?;
 
     // Start the agent stream
+    let mut notifier = notifications::AgentRunNotifier::new(&cwd, &engine);
     let response = client
         .run_agent(proto::RunAgentRequest {
             engine: engine.clone(),
@@ -447,6 +1285,10 @@ async fn run_agent(
             cwd,
             session_id: session_id.clone(),
             resume_id,
+            isolation: isolation.unwrap_or_default(),
+            skip_context: false,
+            attachments: attachments.unwrap_or_default(),
+            force: false,
         })
         .await
         .map_err(map_err)?;
@@ -475,9 +1317,11 @@ async fn run_agent(
                         }
                     }
 
+                    notifier.observe(&app_clone, &event_obj);
                     let _ = app_clone.emit("agent_event", event_obj);
                 }
                 Err(e) => {
+                    notifier.stream_failed(&app_clone, e.message());
                     let _ = app_clone.emit(
                         "agent_event",
                         serde_json::json!({
@@ -526,6 +1370,86 @@ async fn stop_agent(app: tauri::AppHandle, session_id: String) -> Result<(), Str
     Ok(())
 }
 
+// =============================================================================
+// Daemon Status (via daemon)
+// =============================================================================
+
+#[tauri::command]
+async fn daemon_status() -> Result<serde_json::Value, String> {
+    let mut client = client::get_client().await?;
+    let status = client
+        .get_status(proto::GetStatusRequest {})
+        .await
+        .map_err(map_err)?
+        .into_inner();
+
+    let agents: Vec<_> = status
+        .agents
+        .into_iter()
+        .map(|a| {
+            serde_json::json!({
+                "session_id": a.session_id,
+                "engine": a.engine,
+                "cwd": a.cwd,
+                "elapsed_secs": a.elapsed_secs,
+            })
+        })
+        .collect();
+    let recent_errors: Vec<_> = status
+        .recent_errors
+        .into_iter()
+        .map(|e| {
+            serde_json::json!({
+                "timestamp": e.timestamp,
+                "context": e.context,
+                "message": e.message,
+            })
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "version": status.version,
+        "uptime_secs": status.uptime_secs,
+        "home": status.home,
+        "db_path": status.db_path,
+        "schema_version": status.schema_version,
+        "home_disk_bytes": status.home_disk_bytes,
+        "repo_count": status.repo_count,
+        "workspace_count": status.workspace_count,
+        "archived_workspace_count": status.archived_workspace_count,
+        "agents": agents,
+        "recent_errors": recent_errors,
+    }))
+}
+
+#[tauri::command]
+async fn list_engines() -> Result<serde_json::Value, String> {
+    let mut client = client::get_client().await?;
+    let engines = client
+        .list_engines(proto::ListEnginesRequest {})
+        .await
+        .map_err(map_err)?
+        .into_inner()
+        .engines;
+
+    Ok(engines
+        .into_iter()
+        .map(|e| {
+            serde_json::json!({
+                "name": e.name,
+                "aliases": e.aliases,
+                "program": e.program,
+                "available": e.available,
+                "path": e.path,
+                "version": e.version,
+                "authenticated": e.authenticated,
+                "supports_resume": e.supports_resume,
+                "supports_streaming": e.supports_streaming,
+            })
+        })
+        .collect())
+}
+
 // =============================================================================
 // Snapshot (kept local - macOS specific)
 // =============================================================================
@@ -533,7 +1457,27 @@ async fn stop_agent(app: tauri::AppHandle, session_id: String) -> Result<(), Str
 const SNAPSHOT_PATH: &str = "/tmp/conductor-snapshot.png";
 
 #[tauri::command]
-async fn capture_snapshot(webview: tauri::Webview) -> Result<String, String> {
+async fn capture_snapshot(webview: tauri::Webview, workspace_path: Option<String>) -> Result<String, String> {
+    let path = snapshot_write(webview).await?;
+    // Kept in the workspace so it can be attached to an agent run there
+    match workspace_path {
+        Some(ws_path) => {
+            let data = std::fs::read(&path).map_err(|e| e.to_string())?;
+            let millis = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis())
+                .unwrap_or_default();
+            let name = format!("snapshot-{millis}.png");
+            conductor_core::attachment_save(std::path::Path::new(&ws_path), &name, &data)
+                .map(|saved| saved.display().to_string())
+                .map_err(|e| e.to_string())
+        }
+        None => Ok(path),
+    }
+}
+
+// Snapshot the webview into SNAPSHOT_PATH
+async fn snapshot_write(webview: tauri::Webview) -> Result<String, String> {
     #[cfg(target_os = "macos")]
     {
         use block::ConcreteBlock;
@@ -614,9 +1558,14 @@ async fn spawn_shell(app: tauri::AppHandle, cwd: String, _session_id: String) ->
         })
         .map_err(|e| format!("Failed to open PTY: {e}"))?;
 
-    let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+    // Inside the dev container when the workspace's repo opts in
+    let home = conductor_core::default_home();
+    let shell = conductor_core::connect(&home)
+        .and_then(|conn| conductor_core::isolation::interactive_shell(&conn, &home, std::path::Path::new(&cwd)))
+        .map_err(map_err)?;
 
-    let mut cmd = CommandBuilder::new(&shell);
+    let mut cmd = CommandBuilder::new(&shell[0]);
+    cmd.args(&shell[1..]);
     cmd.cwd(&cwd);
 
     let _child = pair
@@ -722,20 +1671,59 @@ pub fn run() {
     let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_notification::init())
         .invoke_handler(tauri::generate_handler![
             list_repos,
+            reorder_repos,
             add_repo,
             add_repo_url,
             list_workspaces,
+            list_stale_workspaces,
+            workspace_stats,
             create_workspace,
             archive_workspace,
+            set_workspace_state,
+            set_workspace_pinned,
+            open_workspace,
+            open_workspace_terminal,
+            reveal_workspace,
+            apply_patch,
+            commit_workspace,
+            suggest_commit_message,
             workspace_files,
+            workspace_tree,
+            workspace_log,
             workspace_changes,
+            workspace_snapshot,
+            workspace_summary,
             workspace_file_content,
+            workspace_file_bytes,
+            workspace_file_preview,
+            workspace_file_save,
+            workspace_file_rename,
+            workspace_file_delete,
+            workspace_dir_create,
+            workspace_file_diff_structured,
+            workspace_stage,
+            workspace_unstage,
+            workspace_discard,
             workspace_file_diff,
+            review_comments,
+            search,
+            review_comment_add,
+            review_comment_update,
+            review_comment_delete,
+            review_prompt,
+            sync_review_comments,
+            watch_workspace,
+            unwatch_workspace,
             resolve_home_path,
+            get_notification_settings,
+            set_notification_settings,
             run_agent,
             stop_agent,
+            daemon_status,
+            list_engines,
             capture_snapshot,
             session_read,
             session_create,
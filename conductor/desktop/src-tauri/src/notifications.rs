@@ -0,0 +1,134 @@
+//! Native notifications for daemon events, gated by the per-event toggles under
+//! `notifications` in config.json
+
+use conductor_core::NotificationSettings;
+use serde_json::Value;
+use std::path::Path;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+// Re-read on every event so toggles apply without restarting the app
+fn settings() -> NotificationSettings {
+    conductor_core::config_load(&conductor_core::default_home())
+        .map(|config| config.notifications)
+        .unwrap_or_default()
+}
+
+fn show(app: &AppHandle, title: &str, body: &str) {
+    // Best effort: a missing notification must never break the event stream
+    let _ = app.notification().builder().title(title).body(body).show();
+}
+
+fn workspace_label(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or_else(|| path.to_string(), |name| name.to_string_lossy().to_string())
+}
+
+// First line of an answer, short enough for a notification banner
+fn headline(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or("");
+    match line.char_indices().nth(120) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Follows one agent run's events (as forwarded to the UI) and notifies once it ends
+pub struct AgentRunNotifier {
+    workspace: String,
+    engine: String,
+    agent_ok: Option<bool>,
+    answer: Option<String>,
+    error: Option<String>,
+}
+
+impl AgentRunNotifier {
+    pub fn new(cwd: &str, engine: &str) -> Self {
+        Self {
+            workspace: workspace_label(cwd),
+            engine: engine.to_string(),
+            agent_ok: None,
+            answer: None,
+            error: None,
+        }
+    }
+
+    pub fn observe(&mut self, app: &AppHandle, event: &Value) {
+        let text = |key: &str| {
+            event
+                .get(key)
+                .and_then(Value::as_str)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+        };
+        match event.get("type").and_then(Value::as_str) {
+            Some("agent.completed") => {
+                self.agent_ok = event.get("ok").and_then(Value::as_bool);
+                self.answer = text("answer");
+                self.error = text("error");
+            }
+            Some("agent.approval") => {
+                if !settings().approval_requested {
+                    return;
+                }
+                let tools: Vec<&str> = event
+                    .get("tools")
+                    .and_then(Value::as_array)
+                    .map(|tools| tools.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                let title = format!("{}: approval needed", self.workspace);
+                show(app, &title, &format!("{} wants to use {}", self.engine, tools.join(", ")));
+            }
+            // The daemon's end-of-process event; stopped runs were ended by the user
+            Some("completed") if event.get("stopped").is_none() => {
+                let exited_ok = event.get("success").and_then(Value::as_bool).unwrap_or(false);
+                if exited_ok && self.agent_ok != Some(false) {
+                    self.completed(app);
+                } else {
+                    let reason = self.error.clone().unwrap_or_else(|| match event.get("exit_code") {
+                        Some(Value::Number(code)) => format!("exited with code {code}"),
+                        _ => "exited abnormally".to_string(),
+                    });
+                    self.failed(app, &reason);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The event stream broke before the run ended
+    pub fn stream_failed(&self, app: &AppHandle, error: &str) {
+        self.failed(app, error);
+    }
+
+    fn completed(&self, app: &AppHandle) {
+        if !settings().agent_completed {
+            return;
+        }
+        let title = format!("{}: {} finished", self.workspace, self.engine);
+        show(app, &title, &headline(self.answer.as_deref().unwrap_or("Done")));
+    }
+
+    fn failed(&self, app: &AppHandle, reason: &str) {
+        if !settings().agent_failed {
+            return;
+        }
+        let title = format!("{}: {} failed", self.workspace, self.engine);
+        show(app, &title, &headline(reason));
+    }
+}
+
+/// A watched workspace now has unmerged paths
+pub fn merge_conflict(app: &AppHandle, workspace: &str, paths: &[String]) {
+    if !settings().merge_conflict {
+        return;
+    }
+    let title = format!("{workspace}: merge conflict");
+    let body = match paths {
+        [only] => format!("Conflict in {only}"),
+        [first, rest @ ..] => format!("Conflicts in {first} and {} more", rest.len()),
+        [] => return,
+    };
+    show(app, &title, &body);
+}
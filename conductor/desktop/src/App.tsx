@@ -8,23 +8,50 @@ import { codeToHtml } from "shiki";
 import "./App.css";
 import { ActionMessage } from "./lib/tool-registry";
 import { CommandPalette } from "./components/CommandPalette";
+import { DaemonStatusPanel } from "./components/DaemonStatusPanel";
+import { NotificationSettingsPanel } from "./components/NotificationSettingsPanel";
 import {
   useRepos,
   useWorkspaces,
   useWorkspaceFiles,
-  useWorkspaceChanges,
+  useWorkspaceSummary,
+  useWorkspaceTree,
+  useWorkspaceLog,
+  useWorkspaceStats,
+  useWorkspaceWatch,
   useFileDiff,
-  useFileContent,
+  useFilePreview,
   useAddRepo,
   useCreateWorkspace,
-  useSession,
+  useOpenWorkspace,
+  useOpenWorkspaceTerminal,
+  useRevealWorkspace,
+  useApplyPatch,
+  useCommitWorkspace,
+  useSuggestCommitMessage,
+  useSetWorkspaceState,
+  useSetWorkspacePinned,
+  useReorderRepos,
+  useSaveFile,
+  useRenameFile,
+  useDeleteFile,
+  useCreateDirectory,
+  useStageFiles,
+  useUnstageFiles,
+  useDiscardChanges,
+  useReviewComments,
+  useAddReviewComment,
+  useUpdateReviewComment,
+  useDeleteReviewComment,
+  useSyncReviewComments,
   useChat,
-  useUpsertResumeId,
-  useAppendChat,
+  useEngines,
+  useStaleWorkspaces,
 } from "./lib/hooks";
 import { parseChatMd } from "./lib/chat-parser";
 import { Terminal } from "./components/Terminal";
-import { queryKeys } from "./lib/query";
+import { queryFns, queryKeys } from "./lib/query";
+import { PIPELINE_STATES, type FileDiff, type ReviewComment, type WorkspaceState } from "./types";
 
 // Play a gentle bell notification sound when agent completes
 function playNotificationSound() {
@@ -72,6 +99,7 @@ type Workspace = {
   base_branch: string;
   state: string;
   path: string;
+  pinned?: boolean;
 };
 
 type WorkspaceChange = {
@@ -111,6 +139,18 @@ type AgentEvent = {
   answer?: string;
   error?: string;
   resume?: string;
+  // agent.fallback: the engine that failed; `engine` is the one taking over
+  from?: string;
+  // agent.policy_violation; verification (with command, ok and exit_code or error)
+  rule?: string;
+  decision?: "flag" | "block";
+  command?: string;
+  exit_code?: number | null;
+  // agent.diffstat
+  files?: number;
+  insertions?: number;
+  deletions?: number;
+  untracked?: string[];
   action?: {
     id: string;
     kind: string;
@@ -379,7 +419,8 @@ function statusLabel(status: string) {
     case "D": return "deleted";
     case "M": return "modified";
     case "R": return "renamed";
-    case "?": return "new";
+    case "?":
+    case "??": return "new";
     default: return status;
   }
 }
@@ -392,6 +433,12 @@ function statusClass(status: string) {
   return "status modified";
 }
 
+function formatBytes(bytes: number): string {
+  if (bytes < 1024) return `${bytes} B`;
+  if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+  return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
 function getLangFromPath(path: string): string {
   const ext = path.split(".").pop()?.toLowerCase() ?? "";
   const langMap: Record<string, string> = {
@@ -412,6 +459,11 @@ const diffOptions = {
   themeType: "light",
 } as const;
 
+// Diff lines rendered before a big diff (a lockfile, generated code) is cut short
+const DIFF_MAX_LINES = 2000;
+// Cut hunks offered one by one; past this, only the whole diff
+const MAX_HUNK_BUTTONS = 10;
+
 // =============================================================================
 // Components
 // =============================================================================
@@ -464,7 +516,7 @@ function Rail({
   homeDraft, homeResolved, homeDirty, filter, creating, createError, repoUrl, repoError,
   collapsedRepoIds, workspacesByRepo, filteredWorkspaces, repoUrlInputRef, showHomePopover,
   onHomeDraftChange, onApplyHome, onRefresh, onFilterChange, onCreateWorkspaceForRepo,
-  onRepoUrlChange, onAddRepo, onToggleRepo, onOpenWorkspace, onToggleHomePopover,
+  onRepoUrlChange, onAddRepo, onToggleRepo, onMoveRepoUp, onOpenWorkspace, onToggleHomePopover,
 }: {
   repos: Repo[]; workspaces: Workspace[]; openWorkspaceIds: string[];
   activeWorkspaceId: string | null; loading: boolean; repoAdding: boolean;
@@ -476,7 +528,7 @@ function Rail({
   onHomeDraftChange: (v: string) => void; onApplyHome: () => void; onRefresh: () => void;
   onFilterChange: (v: string) => void; onCreateWorkspaceForRepo: (id: string) => void;
   onRepoUrlChange: (v: string) => void; onAddRepo: () => void;
-  onToggleRepo: (id: string) => void; onOpenWorkspace: (id: string) => void;
+  onToggleRepo: (id: string) => void; onMoveRepoUp: (id: string) => void; onOpenWorkspace: (id: string) => void;
   onToggleHomePopover: () => void;
 }) {
   return (
@@ -495,7 +547,7 @@ function Rail({
             onChange={(e) => onFilterChange(e.currentTarget.value)} style={{ width: 100 }} />
         </div>
         <div className="repo-list">
-          {repos.map((repo) => {
+          {repos.map((repo, index) => {
             const repoWorkspaces = workspacesByRepo.get(repo.id) ?? [];
             if (filter && repoWorkspaces.length === 0) return null;
             const isCollapsed = collapsedRepoIds.has(repo.id);
@@ -509,6 +561,9 @@ function Rail({
                     </div>
                     <span className={`repo-toggle${isCollapsed ? " collapsed" : ""}`}>▾</span>
                   </button>
+                  {index > 0 && (
+                    <button className="btn ghost small repo-add" onClick={() => onMoveRepoUp(repo.id)} title="Move up">↑</button>
+                  )}
                   <button className="btn ghost small repo-add" onClick={() => onCreateWorkspaceForRepo(repo.id)}
                     disabled={creating || repoAdding} title="New workspace">+</button>
                 </div>
@@ -521,12 +576,28 @@ function Rail({
                         <button key={ws.id} className={`workspace-item${isActive ? " active" : ""}`}
                           onClick={() => onOpenWorkspace(ws.id)}>
                           <div className="workspace-row">
-                            <span className="workspace-name">{ws.name}</span>
+                            {ws.pinned && <span className="workspace-pin" title="Pinned">★</span>}
+                            <span className="workspace-name" title={ws.summary ?? ws.name}>{ws.title ?? ws.name}</span>
                             {isActive && <span className="badge active">Active</span>}
                             {!isActive && isOpen && <span className="badge open">Open</span>}
                           </div>
                           <div className="workspace-meta">
-                            <span>{ws.branch}</span><span className="sep">·</span><span>{ws.state}</span>
+                            <span>{ws.branch}</span><span className="sep">·</span><span title={ws.error}>{ws.state}</span>
+                            {ws.link_error && (
+                              <><span className="sep">·</span><span title={`${ws.link_error} (run conductor workspace repair)`}>broken</span></>
+                            )}
+                            {ws.verification && (
+                              <><span className="sep">·</span>
+                                <span title={ws.verification.ok ? "Post-run checks passed" : `Failed: ${ws.verification.failed_command}`}>
+                                  {ws.verification.ok ? "✓ checks" : "✗ checks"}
+                                </span></>
+                            )}
+                            {ws.enrichment?.dirty && (
+                              <><span className="sep">·</span><span title="Uncommitted files">{ws.enrichment.changed_files} changed</span></>
+                            )}
+                            {ws.enrichment && (ws.enrichment.ahead > 0 || ws.enrichment.behind > 0) && (
+                              <><span className="sep">·</span><span title="Commits ahead/behind the base branch">↑{ws.enrichment.ahead} ↓{ws.enrichment.behind}</span></>
+                            )}
                           </div>
                         </button>
                       );
@@ -582,6 +653,14 @@ function Rail({
                     Apply
                   </button>
                 </div>
+                <div className="home-popover-header">
+                  <span className="home-popover-title">Status</span>
+                </div>
+                <DaemonStatusPanel />
+                <div className="home-popover-header">
+                  <span className="home-popover-title">Notifications</span>
+                </div>
+                <NotificationSettingsPanel />
               </div>
             )}
           </div>
@@ -625,6 +704,37 @@ function TabsHeader({ openGroups, activeWorkspaceId, canPrev, canNext, onActivat
   );
 }
 
+// Suggests cleaning up workspaces idle past `stale_after`; dismissed for the session
+function StaleBanner({ onOpenWorkspace }: { onOpenWorkspace: (id: string) => void }) {
+  const { data } = useStaleWorkspaces();
+  const setState = useSetWorkspaceState();
+  const [dismissed, setDismissed] = useState(false);
+  const stale = data?.workspaces ?? [];
+  if (dismissed || stale.length === 0) return null;
+
+  const abandonAll = () => {
+    for (const ws of stale) setState.mutate({ wsId: ws.id, state: "abandoned" });
+  };
+  return (
+    <div className="stale-banner">
+      <span>
+        {stale.length === 1 ? "1 workspace has" : `${stale.length} workspaces have`} had no commits or agent runs in {data?.stale_after}. Clean up?
+      </span>
+      <span className="stale-names">
+        {stale.map((ws) => (
+          <button key={ws.id} className="chip" title={`${ws.repo} / ${ws.branch}`} onClick={() => onOpenWorkspace(ws.id)}>
+            {ws.name}
+          </button>
+        ))}
+      </span>
+      <button className="chip" disabled={setState.isPending} title="Mark them abandoned; archive them from the CLI" onClick={abandonAll}>
+        Mark abandoned
+      </button>
+      <button className="chip" onClick={() => setDismissed(true)}>Dismiss</button>
+    </div>
+  );
+}
+
 function WelcomeHero({ hasRepos, onAddRepoFocus }: { hasRepos: boolean; onAddRepoFocus: () => void }) {
   return (
     <div className="welcome-hero">
@@ -665,7 +775,72 @@ function WelcomeHero({ hasRepos, onAddRepoFocus }: { hasRepos: boolean; onAddRep
   );
 }
 
+// The branch's commits since its base, newest first: what the agent has committed so far
+function CommitTimeline({ wsId }: { wsId: string }) {
+  const { data: commits = [], isLoading, error } = useWorkspaceLog(wsId);
+
+  return (
+    <div className="panel-item">
+      <span className="panel-label">Commits</span>
+      {isLoading && <span className="muted">Loading...</span>}
+      {error && <span className="inline-error">{String(error)}</span>}
+      {!isLoading && !error && commits.length === 0 && <span className="muted">No commits since base</span>}
+      <ol className="commit-timeline">
+        {commits.map((commit) => (
+          <li key={commit.sha} className="commit-item" title={`${commit.author} <${commit.author_email}>`}>
+            <div className="commit-subject">{commit.subject}</div>
+            <div className="commit-meta">
+              <span className="mono">{commit.sha.slice(0, 7)}</span>
+              <span>{new Date(commit.date).toLocaleString()}</span>
+              <span className="status added">+{commit.stat.insertions}</span>
+              <span className="status deleted">-{commit.stat.deletions}</span>
+            </div>
+          </li>
+        ))}
+      </ol>
+    </div>
+  );
+}
+
+function formatCount(n: number): string {
+  if (n >= 1_000_000) return `${(n / 1_000_000).toFixed(1)}M`;
+  if (n >= 1_000) return `${(n / 1_000).toFixed(1)}k`;
+  return String(n);
+}
+
+// One line of totals under the workspace header
+function WorkspaceStatsLine({ wsId }: { wsId: string }) {
+  const { data: stats } = useWorkspaceStats(wsId);
+  if (!stats) return null;
+  const days = Math.floor(stats.age_secs / 86400);
+  const age = days > 0 ? `${days}d` : `${Math.floor(stats.age_secs / 3600)}h`;
+  const tokens = stats.usage.input_tokens + stats.usage.output_tokens;
+  return (
+    <div className="panel-item">
+      <span className="panel-label">Stats</span>
+      <span className="mono" title={`${stats.diff.files} files changed vs base; ${stats.usage.input_tokens} tokens in, ${stats.usage.output_tokens} out`}>
+        {stats.commits} commit{stats.commits === 1 ? "" : "s"} · +{stats.diff.insertions} −{stats.diff.deletions} · {stats.agent_runs} run{stats.agent_runs === 1 ? "" : "s"} · {formatCount(tokens)} tokens · {age} old
+      </span>
+    </div>
+  );
+}
+
 function WorkspacePanel({ activeWorkspace }: { activeWorkspace: Workspace | null }) {
+  const openWorkspace = useOpenWorkspace();
+  const openTerminal = useOpenWorkspaceTerminal();
+  const revealWorkspace = useRevealWorkspace();
+  const applyPatch = useApplyPatch();
+  const setState = useSetWorkspaceState();
+  const setPinned = useSetWorkspacePinned();
+  const [patchOpen, setPatchOpen] = useState(false);
+  const [patchText, setPatchText] = useState("");
+  const [threeWay, setThreeWay] = useState(true);
+  const applied = applyPatch.data;
+  const commit = useCommitWorkspace();
+  const suggest = useSuggestCommitMessage();
+  const [commitOpen, setCommitOpen] = useState(false);
+  const [commitMessage, setCommitMessage] = useState("");
+
   return (
     <div className="panel-card primary">
       {activeWorkspace ? (
@@ -676,14 +851,125 @@ function WorkspacePanel({ activeWorkspace }: { activeWorkspace: Workspace | null
               <div className="panel-route">{activeWorkspace.repo} / {activeWorkspace.branch}</div>
             </div>
             <div className="chip-row">
-              <span className="chip">{activeWorkspace.state}</span>
+              {(PIPELINE_STATES as readonly string[]).includes(activeWorkspace.state) ? (
+                <select className="chip" value={activeWorkspace.state} disabled={setState.isPending}
+                  title="Where this workspace is in its pipeline"
+                  onChange={(e) => setState.mutate({ wsId: activeWorkspace.id, state: e.currentTarget.value as WorkspaceState })}>
+                  {PIPELINE_STATES.map((state) => <option key={state} value={state}>{state.replace("_", " ")}</option>)}
+                </select>
+              ) : (
+                <span className="chip" title={activeWorkspace.error}>{activeWorkspace.state}</span>
+              )}
               <span className="chip">← {activeWorkspace.base_branch}</span>
+              <button className="chip" disabled={setPinned.isPending}
+                title={activeWorkspace.pinned ? "Stop listing this workspace first" : "List this workspace first"}
+                onClick={() => setPinned.mutate({ wsId: activeWorkspace.id, pinned: !activeWorkspace.pinned })}>
+                {activeWorkspace.pinned ? "★ pinned" : "☆ pin"}
+              </button>
             </div>
           </div>
+          {setState.error && <div className="inline-error">{String(setState.error)}</div>}
+          {setPinned.error && <div className="inline-error">{String(setPinned.error)}</div>}
           <div className="panel-item">
             <span className="panel-label">Path</span>
             <span className="mono">{activeWorkspace.path}</span>
           </div>
+          {activeWorkspace.state !== "archived" && <WorkspaceStatsLine wsId={activeWorkspace.id} />}
+          <div className="panel-item">
+            <button
+              className="btn ghost small"
+              onClick={() => openWorkspace.mutate(activeWorkspace.id)}
+              disabled={openWorkspace.isPending}
+            >
+              Open in editor
+            </button>
+            {openWorkspace.error && <span className="inline-error">{String(openWorkspace.error)}</span>}
+            <button
+              className="btn ghost small"
+              onClick={() => openTerminal.mutate(activeWorkspace.id)}
+              disabled={openTerminal.isPending}
+            >
+              Terminal
+            </button>
+            {openTerminal.error && <span className="inline-error">{String(openTerminal.error)}</span>}
+            <button
+              className="btn ghost small"
+              onClick={() => revealWorkspace.mutate(activeWorkspace.id)}
+              disabled={revealWorkspace.isPending}
+            >
+              Reveal
+            </button>
+            {revealWorkspace.error && <span className="inline-error">{String(revealWorkspace.error)}</span>}
+            <button className="btn ghost small" onClick={() => setPatchOpen((open) => !open)}>
+              {patchOpen ? "Cancel patch" : "Apply patch…"}
+            </button>
+            <button className="btn ghost small" onClick={() => setCommitOpen((open) => !open)}>
+              {commitOpen ? "Cancel commit" : "Commit…"}
+            </button>
+          </div>
+          {commitOpen && (
+            <div className="panel-item">
+              <textarea
+                className="input textarea mono"
+                placeholder="Commit message (commits what's staged, or every change when nothing is)"
+                value={commitMessage}
+                onChange={(e) => setCommitMessage(e.target.value)}
+              />
+              <button
+                className="btn ghost small"
+                disabled={suggest.isPending}
+                title="Draft a Conventional Commits message from the pending diff"
+                onClick={() => suggest.mutate(activeWorkspace.id, { onSuccess: setCommitMessage })}
+              >
+                {suggest.isPending ? "Suggesting…" : "Suggest"}
+              </button>
+              <button
+                className="btn small"
+                disabled={!commitMessage.trim() || commit.isPending}
+                onClick={() => commit.mutate({ wsId: activeWorkspace.id, message: commitMessage }, {
+                  onSuccess: () => {
+                    setCommitMessage("");
+                    setCommitOpen(false);
+                  },
+                })}
+              >
+                Commit
+              </button>
+              {suggest.error && <span className="inline-error">{String(suggest.error)}</span>}
+              {commit.error && <span className="inline-error">{String(commit.error)}</span>}
+            </div>
+          )}
+          {patchOpen && (
+            <div className="panel-item">
+              <textarea
+                className="input textarea mono"
+                placeholder="Paste a unified diff"
+                value={patchText}
+                onChange={(e) => setPatchText(e.target.value)}
+              />
+              <label className="muted">
+                <input type="checkbox" checked={threeWay} onChange={(e) => setThreeWay(e.target.checked)} /> 3-way merge
+              </label>
+              <button
+                className="btn small"
+                disabled={!patchText.trim() || applyPatch.isPending}
+                onClick={() => applyPatch.mutate({ wsId: activeWorkspace.id, patch: patchText, threeWay })}
+              >
+                Apply
+              </button>
+              {applyPatch.error && <span className="inline-error">{String(applyPatch.error)}</span>}
+              {applied && !applied.applied && (
+                <span className="inline-error">Not applied: {applied.conflicts.join(", ") || applied.message}</span>
+              )}
+              {applied?.applied && applied.conflicts.length > 0 && (
+                <span className="inline-error">Conflicts to resolve: {applied.conflicts.join(", ")}</span>
+              )}
+              {applied?.applied && applied.conflicts.length === 0 && (
+                <span className="muted">Applied to {applied.files.length} file(s)</span>
+              )}
+            </div>
+          )}
+          <CommitTimeline wsId={activeWorkspace.id} />
         </>
       ) : (
         <div className="panel-empty">Select a workspace</div>
@@ -693,27 +979,39 @@ function WorkspacePanel({ activeWorkspace }: { activeWorkspace: Workspace | null
 }
 
 function ChatPanel({
-  activeWorkspace, tabs, activeTabId, chatDraft, running, startTime, files,
-  onTabChange, onTabClose, onTabAdd, onAgentChange, onDraftChange, onSend, onStop, chatEndRef,
+  activeWorkspace, tabs, activeTabId, chatDraft, running, startTime, files, attachments,
+  onTabChange, onTabClose, onTabAdd, onAgentChange, onDraftChange, onSend, onStop,
+  onAttachSnapshot, onRemoveAttachment, chatEndRef,
 }: {
   activeWorkspace: Workspace | null;
   tabs: AgentTab[]; activeTabId: string | null; chatDraft: string; running: boolean;
-  startTime?: number; files: string[];
+  startTime?: number; files: string[]; attachments: string[];
   onTabChange: (id: string) => void; onTabClose: (id: string) => void;
   onTabAdd: () => void; onAgentChange: (id: string) => void;
   onDraftChange: (v: string) => void; onSend: () => void; onStop: () => void;
+  onAttachSnapshot: () => void; onRemoveAttachment: (path: string) => void;
   chatEndRef: { current: HTMLDivElement | null };
 }) {
   const activeTab = tabs.find((t) => t.id === activeTabId) ?? null;
-  const activeAgent = AGENTS.find((a) => a.id === activeTab?.agentId) ?? AGENTS[0];
   const elapsedTime = useElapsedTime(startTime, running);
+  const { data: engines } = useEngines();
+  // Unknown until the daemon answers; only a confirmed miss disables an engine
+  const engineFor = (agentId: string) => engines?.find((e) => e.name === agentId || e.aliases.includes(agentId));
+  // Engines from config.json `engines` follow the built-in ones
+  const agents: Agent[] = [
+    ...AGENTS,
+    ...(engines ?? [])
+      .filter((e) => !AGENTS.some((a) => engineFor(a.id) === e))
+      .map((e) => ({ id: e.name, name: e.name, description: e.program })),
+  ];
+  const activeAgent = agents.find((a) => a.id === activeTab?.agentId) ?? AGENTS[0];
 
   return (
     <div className="panel-card chat">
       <div className="agent-tabs">
         <div className="agent-tabs-list">
           {tabs.map((tab) => {
-            const agent = AGENTS.find((a) => a.id === tab.agentId) ?? AGENTS[0];
+            const agent = agents.find((a) => a.id === tab.agentId) ?? AGENTS[0];
             const isActive = tab.id === activeTabId;
             return (
               <div key={tab.id} className={`agent-tab${isActive ? " active" : ""}${tab.running ? " running" : ""}`}>
@@ -756,10 +1054,28 @@ function ChatPanel({
       </div>
 
       <div className="chat-input">
+        {attachments.length > 0 && (
+          <div className="chat-attachments">
+            {attachments.map((path) => (
+              <span key={path} className="badge" title={path}>
+                {path.split("/").pop()}
+                <button className="btn ghost small" onClick={() => onRemoveAttachment(path)}>×</button>
+              </span>
+            ))}
+          </div>
+        )}
         <div className="chat-input-row">
           <select className="agent-picker" value={activeTab?.agentId ?? "claude-code"}
             onChange={(e) => onAgentChange(e.currentTarget.value)} disabled={!activeWorkspace || !activeTab || running}>
-            {AGENTS.map((agent) => <option key={agent.id} value={agent.id}>{agent.name}</option>)}
+            {agents.map((agent) => {
+              const engine = engineFor(agent.id);
+              const missing = engine !== undefined && !engine.available;
+              return (
+                <option key={agent.id} value={agent.id} disabled={missing}>
+                  {agent.name}{missing ? " (not installed)" : engine?.authenticated === false ? " (not logged in)" : ""}
+                </option>
+              );
+            })}
           </select>
           <ChatInput
             value={chatDraft}
@@ -771,24 +1087,321 @@ function ChatPanel({
             running={running}
             files={files}
           />
+          <button className="btn ghost small" onClick={onAttachSnapshot}
+            disabled={!activeWorkspace || !activeTab} title="Attach a screenshot of the app">
+            📎
+          </button>
         </div>
       </div>
     </div>
   );
 }
 
+// One directory of the "All" file tree; subdirectories load when expanded
+function FileTreeDir({ wsId, dir, depth, selectedFile, onSelectFile }: {
+  wsId: string; dir: string; depth: number;
+  selectedFile: string | null; onSelectFile: (p: string) => void;
+}) {
+  const { data: entries = [], isLoading, error } = useWorkspaceTree(wsId, dir);
+  const renameFile = useRenameFile();
+  const deleteFile = useDeleteFile();
+  const [expanded, setExpanded] = useState<Set<string>>(new Set());
+  const [renaming, setRenaming] = useState<{ path: string; name: string } | null>(null);
+  // Delete takes a second click on the same entry
+  const [confirmDelete, setConfirmDelete] = useState<string | null>(null);
+  const indent = { paddingLeft: `${depth * 12 + 8}px` };
+  const opError = renameFile.error ?? deleteFile.error;
+
+  function toggle(path: string) {
+    setExpanded((prev) => {
+      const next = new Set(prev);
+      if (next.has(path)) next.delete(path); else next.add(path);
+      return next;
+    });
+  }
+
+  function submitRename() {
+    if (!renaming) return;
+    const name = renaming.name.trim();
+    const from = renaming.path;
+    setRenaming(null);
+    if (!name || name === from.slice(from.lastIndexOf("/") + 1)) return;
+    renameFile.mutate({ wsId, from, to: dir ? `${dir}/${name}` : name });
+  }
+
+  if (isLoading) return <div className="muted" style={indent}>Loading...</div>;
+  if (error) return <div className="inline-error" style={indent}>{String(error)}</div>;
+  return (
+    <>
+      {opError && <div className="inline-error" style={indent}>{String(opError)}</div>}
+      {entries.map((entry) => {
+        const isDir = entry.kind === "dir";
+        const isOpen = expanded.has(entry.path);
+        const deleted = entry.status === "D";
+        return (
+          <div key={entry.path}>
+            {renaming?.path === entry.path ? (
+              <input className="input small" style={indent} autoFocus value={renaming.name}
+                onChange={(e) => setRenaming({ path: entry.path, name: e.currentTarget.value })}
+                onBlur={() => setRenaming(null)}
+                onKeyDown={(e) => {
+                  if (e.key === "Enter") submitRename();
+                  if (e.key === "Escape") setRenaming(null);
+                }} />
+            ) : (
+              <div className="file-tree-row">
+                <button className={`file-item${entry.path === selectedFile ? " active" : ""}`} style={indent}
+                  onClick={() => (isDir ? toggle(entry.path) : onSelectFile(entry.path))}>
+                  <div className="file-main">
+                    <span className="file-path">
+                      {isDir && <span className="file-dir">{isOpen ? "▾ " : "▸ "}</span>}
+                      <span className="file-name">{entry.name}{isDir ? "/" : ""}</span>
+                    </span>
+                  </div>
+                  {entry.status && (
+                    <span className={`file-status ${statusClass(entry.status)}`} title={statusLabel(entry.status)}>
+                      {statusLabel(entry.status)}
+                    </span>
+                  )}
+                </button>
+                {!deleted && (
+                  <span className="file-tree-actions">
+                    <button className="btn ghost small" title="Rename"
+                      onClick={() => setRenaming({ path: entry.path, name: entry.name })}>✎</button>
+                    <button className="btn ghost small" title="Delete" disabled={deleteFile.isPending}
+                      onBlur={() => setConfirmDelete(null)}
+                      onClick={() => {
+                        if (confirmDelete !== entry.path) return setConfirmDelete(entry.path);
+                        setConfirmDelete(null);
+                        deleteFile.mutate({ wsId, path: entry.path });
+                      }}>
+                      {confirmDelete === entry.path ? "Delete?" : "✕"}
+                    </button>
+                  </span>
+                )}
+              </div>
+            )}
+            {isDir && isOpen && (
+              <FileTreeDir wsId={wsId} dir={entry.path} depth={depth + 1}
+                selectedFile={selectedFile} onSelectFile={onSelectFile} />
+            )}
+          </div>
+        );
+      })}
+    </>
+  );
+}
+
+// Path input for a new folder, relative to the workspace root
+function NewFolderInput({ wsId, onDone }: { wsId: string; onDone: () => void }) {
+  const createDirectory = useCreateDirectory();
+  const [path, setPath] = useState("");
+
+  async function submit() {
+    if (!path.trim()) return onDone();
+    try {
+      await createDirectory.mutateAsync({ wsId, path: path.trim() });
+      onDone();
+    } catch {
+      // Shown below; keep the input for a corrected path
+    }
+  }
+
+  return (
+    <div>
+      <input className="input small" autoFocus placeholder="new/folder" value={path}
+        onChange={(e) => setPath(e.currentTarget.value)}
+        onKeyDown={(e) => {
+          if (e.key === "Enter") submit();
+          if (e.key === "Escape") onDone();
+        }} />
+      {createDirectory.error && <div className="inline-error">{String(createDirectory.error)}</div>}
+    </div>
+  );
+}
+
+// What a cut diff left out: each hunk loads whole on click, or the entire diff at once
+function OmittedHunks({ wsId, path, diff, onShowAll }: {
+  wsId: string; path: string; diff: FileDiff; onShowAll: () => void;
+}) {
+  const omitted = diff.omitted_hunks ?? [];
+  return (
+    <div className="diff-omitted">
+      <div className="chip-row">
+        <span className="muted">
+          Large diff ({diff.total_lines.toLocaleString()} lines): showing the first {DIFF_MAX_LINES.toLocaleString()}
+        </span>
+        <button className="btn ghost small" onClick={onShowAll}>Show full diff</button>
+      </div>
+      {omitted.slice(0, MAX_HUNK_BUTTONS).map((hunk) => (
+        <OmittedHunk key={hunk} wsId={wsId} path={path} hunk={hunk} />
+      ))}
+    </div>
+  );
+}
+
+function OmittedHunk({ wsId, path, hunk }: { wsId: string; path: string; hunk: number }) {
+  const [open, setOpen] = useState(false);
+  const { data, isLoading, error } = useFileDiff(undefined, open ? wsId : null, path, { hunk });
+  if (!open) {
+    return <button className="btn ghost small" onClick={() => setOpen(true)}>Show hunk {hunk + 1}</button>;
+  }
+  if (error) return <div className="inline-error">{String(error)}</div>;
+  if (isLoading || !data) return <div className="muted">Loading hunk {hunk + 1}...</div>;
+  return <div className="diff-viewer"><PatchDiff patch={data.diff} options={diffOptions} /></div>;
+}
+
+// Review comments on the selected file, and sending every unresolved one to the agent
+// "L12", "L12-20", or where a comment synced from the PR points
+function commentAnchor(c: ReviewComment): string {
+  if (c.file_path === "") return "PR";
+  if (c.start_line === 0) return "File";
+  return c.start_line === c.end_line ? `L${c.start_line}` : `L${c.start_line}-${c.end_line}`;
+}
+
+function ReviewComments({ wsId, file, onSendReview }: {
+  wsId: string; file: string; onSendReview: (prompt: string) => void;
+}) {
+  const { data: comments = [] } = useReviewComments(wsId);
+  const addComment = useAddReviewComment();
+  const updateComment = useUpdateReviewComment();
+  const deleteComment = useDeleteReviewComment();
+  const syncComments = useSyncReviewComments();
+  const [startLine, setStartLine] = useState("");
+  const [endLine, setEndLine] = useState("");
+  const [body, setBody] = useState("");
+  const [sendError, setSendError] = useState<string | null>(null);
+  // Comments synced from the PR as a whole show alongside every file's
+  const fileComments = comments.filter((c) => c.file_path === file || c.file_path === "");
+  const unresolved = comments.filter((c) => !c.resolved).length;
+  const error = addComment.error ?? updateComment.error ?? deleteComment.error ?? syncComments.error ?? sendError;
+
+  async function add() {
+    const start = Number(startLine);
+    if (!Number.isInteger(start) || start < 1 || !body.trim()) return;
+    const end = endLine.trim() ? Number(endLine) : start;
+    try {
+      await addComment.mutateAsync({ wsId, path: file, startLine: start, endLine: end, body });
+      setBody("");
+      setStartLine("");
+      setEndLine("");
+    } catch {
+      // Shown from addComment.error; the draft stays
+    }
+  }
+
+  async function sendReview() {
+    setSendError(null);
+    try {
+      const prompt = await queryFns.reviewPrompt(wsId);
+      if (prompt) onSendReview(prompt);
+    } catch (e) {
+      setSendError(String(e));
+    }
+  }
+
+  return (
+    <div className="review-comments">
+      <div className="card-row">
+        <span className="card-title">Comments</span>
+        <div className="chip-row">
+          <button className="btn ghost small" disabled={syncComments.isPending}
+            onClick={() => syncComments.mutate(wsId)}
+            title="Fetch reviewers' comments from this workspace's pull request">
+            {syncComments.isPending ? "Syncing…" : "Sync from PR"}
+          </button>
+          <button className="btn ghost small" onClick={sendReview} disabled={unresolved === 0}
+            title="Ask the agent to address every unresolved comment in this workspace">
+            Send {unresolved} to agent
+          </button>
+        </div>
+      </div>
+      {fileComments.map((c) => (
+        <div key={c.id} className={`review-comment${c.resolved ? " resolved" : ""}`}>
+          <div className="card-row">
+            <span className="card-meta">
+              {commentAnchor(c)} · {c.author}
+              {c.url && <> · <a href={c.url} target="_blank" rel="noopener noreferrer">GitHub</a></>}
+            </span>
+            <div className="chip-row">
+              <button className="btn ghost small" disabled={updateComment.isPending}
+                onClick={() => updateComment.mutate({ id: c.id, resolved: !c.resolved })}>
+                {c.resolved ? "Reopen" : "Resolve"}
+              </button>
+              <button className="btn ghost small" disabled={deleteComment.isPending}
+                onClick={() => deleteComment.mutate({ id: c.id, wsId })}>Delete</button>
+            </div>
+          </div>
+          <div className="review-comment-body">{c.body}</div>
+        </div>
+      ))}
+      <div className="review-comment-form">
+        <input className="input small" placeholder="Line" value={startLine} inputMode="numeric"
+          onChange={(e) => setStartLine(e.currentTarget.value)} />
+        <input className="input small" placeholder="to" value={endLine} inputMode="numeric"
+          onChange={(e) => setEndLine(e.currentTarget.value)} />
+        <input className="input small" placeholder="Comment..." value={body}
+          onChange={(e) => setBody(e.currentTarget.value)}
+          onKeyDown={(e) => { if (e.key === "Enter") add(); }} />
+        <button className="btn small" onClick={add} disabled={addComment.isPending || !startLine.trim() || !body.trim()}>Add</button>
+      </div>
+      {error && <div className="inline-error">{String(error)}</div>}
+    </div>
+  );
+}
+
 function FilesPanel({
   activeWorkspace, files, changes, filteredChanges, filteredAllFiles, filesLoading,
-  fileFilter, showAllFiles, selectedFile, fileError, fileDiff, fileContent, fileViewLoading,
-  onFileFilterChange, onToggleShowAll, onSelectFile,
+  fileFilter, showAllFiles, selectedFile, fileError, fileDiff, diffCut, fileContent, fileHash, fileNotice, fileViewLoading,
+  onFileFilterChange, onToggleShowAll, onSelectFile, onSendReview, onShowFullDiff,
 }: {
   activeWorkspace: Workspace | null; files: string[]; changes: WorkspaceChange[];
   filteredChanges: WorkspaceChange[]; filteredAllFiles: string[];
   filesLoading: boolean; fileFilter: string; showAllFiles: boolean;
   selectedFile: string | null; fileError: string | null;
-  fileDiff: string | null; fileContent: string | null; fileViewLoading: boolean;
+  fileDiff: string | null; diffCut: FileDiff | null; fileContent: string | null; fileHash: string | null;
+  fileNotice: string | null; fileViewLoading: boolean;
   onFileFilterChange: (v: string) => void; onToggleShowAll: () => void; onSelectFile: (p: string) => void;
+  onSendReview: (prompt: string) => void; onShowFullDiff: () => void;
 }) {
+  const saveFile = useSaveFile();
+  const stageFiles = useStageFiles();
+  const unstageFiles = useUnstageFiles();
+  const discardChanges = useDiscardChanges();
+  const [confirmDiscard, setConfirmDiscard] = useState(false);
+  const selectedChange = changes.find((c) => c.path === selectedFile) ?? null;
+  const gitError = stageFiles.error ?? unstageFiles.error ?? discardChanges.error;
+  const [newFolder, setNewFolder] = useState(false);
+  const [draft, setDraft] = useState<string | null>(null);
+  const editing = draft !== null;
+  // A different file (or workspace) drops the unsaved draft
+  useEffect(() => {
+    setDraft(null);
+    saveFile.reset();
+    setConfirmDiscard(false);
+  }, [activeWorkspace?.id, selectedFile]);
+
+  function discard() {
+    if (!activeWorkspace || !selectedChange) return;
+    if (!confirmDiscard) return setConfirmDiscard(true);
+    setConfirmDiscard(false);
+    discardChanges.mutate({
+      wsId: activeWorkspace.id,
+      paths: [selectedChange.path],
+      includeUntracked: selectedChange.status.startsWith("?"),
+    });
+  }
+
+  async function save() {
+    if (!activeWorkspace || !selectedFile || draft === null) return;
+    try {
+      await saveFile.mutateAsync({ wsId: activeWorkspace.id, path: selectedFile, content: draft, expectedHash: fileHash });
+      setDraft(null);
+    } catch {
+      // Shown from saveFile.error; the draft stays so nothing is lost
+    }
+  }
+
   return (
     <aside className="files-panel">
       <div className="panel-card">
@@ -834,7 +1447,18 @@ function FilesPanel({
                   })}
                 </div>
               )}
-              {showAllFiles && filteredAllFiles.length > 0 && (
+              {showAllFiles && !fileFilter.trim() && activeWorkspace && (
+                <div className="file-section">
+                  <div className="file-section-title card-row">
+                    <span>All</span>
+                    <button className="btn ghost small" onClick={() => setNewFolder((open) => !open)}>New folder</button>
+                  </div>
+                  {newFolder && <NewFolderInput wsId={activeWorkspace.id} onDone={() => setNewFolder(false)} />}
+                  <FileTreeDir wsId={activeWorkspace.id} dir="" depth={0}
+                    selectedFile={selectedFile} onSelectFile={onSelectFile} />
+                </div>
+              )}
+              {showAllFiles && fileFilter.trim() && filteredAllFiles.length > 0 && (
                 <div className="file-section">
                   <div className="file-section-title">All ({filteredAllFiles.length})</div>
                   {filteredAllFiles.map((file) => {
@@ -863,7 +1487,32 @@ function FilesPanel({
         <div className="card-row">
           <span className="card-title">Preview</span>
           {fileViewLoading && <span className="badge">Loading</span>}
+          {!editing && activeWorkspace && selectedChange && (
+            <div className="chip-row">
+              <button className="btn ghost small" disabled={stageFiles.isPending}
+                onClick={() => stageFiles.mutate({ wsId: activeWorkspace.id, paths: [selectedChange.path] })}>Stage</button>
+              <button className="btn ghost small" disabled={unstageFiles.isPending}
+                onClick={() => unstageFiles.mutate({ wsId: activeWorkspace.id, paths: [selectedChange.path] })}>Unstage</button>
+              <button className="btn ghost small" disabled={discardChanges.isPending}
+                onClick={discard} onBlur={() => setConfirmDiscard(false)}>
+                {confirmDiscard ? "Discard?" : "Discard"}
+              </button>
+            </div>
+          )}
+          {!editing && !fileDiff && fileContent !== null && fileHash && (
+            <button className="btn ghost small" onClick={() => setDraft(fileContent)}>Edit</button>
+          )}
+          {editing && (
+            <div className="chip-row">
+              <button className="btn small" onClick={save} disabled={saveFile.isPending || draft === fileContent}>
+                {saveFile.isPending ? "Saving..." : "Save"}
+              </button>
+              <button className="btn ghost small" onClick={() => { setDraft(null); saveFile.reset(); }}>Cancel</button>
+            </div>
+          )}
         </div>
+        {saveFile.error && <div className="inline-error">{String(saveFile.error)}</div>}
+        {gitError && <div className="inline-error">{String(gitError)}</div>}
         {selectedFile && <div className="card-meta mono">{selectedFile}</div>}
         <div className="diff-body">
           {fileError && <div className="inline-error">{fileError}</div>}
@@ -871,11 +1520,22 @@ function FilesPanel({
           {!fileError && selectedFile && fileDiff && (
             <div className="diff-viewer"><PatchDiff patch={fileDiff} options={diffOptions} /></div>
           )}
-          {!fileError && selectedFile && !fileDiff && fileContent && (
+          {!fileError && activeWorkspace && selectedFile && fileDiff && diffCut && (
+            <OmittedHunks wsId={activeWorkspace.id} path={selectedFile} diff={diffCut} onShowAll={onShowFullDiff} />
+          )}
+          {editing && (
+            <textarea className="input textarea mono" value={draft} spellCheck={false}
+              onChange={(e) => setDraft(e.currentTarget.value)} />
+          )}
+          {!editing && !fileError && selectedFile && !fileDiff && fileContent && (
             <CodePreview code={fileContent} lang={getLangFromPath(selectedFile)} />
           )}
-          {!fileError && selectedFile && !fileDiff && !fileContent && <div className="muted">No preview</div>}
+          {!fileError && selectedFile && !fileDiff && fileNotice && <div className="muted">{fileNotice}</div>}
+          {!editing && !fileError && selectedFile && !fileDiff && !fileContent && !fileNotice && <div className="muted">No preview</div>}
         </div>
+        {activeWorkspace && selectedFile && !editing && (
+          <ReviewComments wsId={activeWorkspace.id} file={selectedFile} onSendReview={onSendReview} />
+        )}
       </div>
     </aside>
   );
@@ -905,9 +1565,13 @@ function App() {
   const [repoUrl, setRepoUrl] = useState("");
   const [collapsedRepoIds, setCollapsedRepoIds] = useState<Set<string>>(new Set());
   const [selectedFile, setSelectedFile] = useState<string | null>(null);
+  // A file to select once its workspace becomes active (search results)
+  const pendingFileRef = useRef<string | null>(null);
   const [fileFilter, setFileFilter] = useState("");
   const [showAllFiles, setShowAllFiles] = useState(false);
   const [chatDraft, setChatDraft] = useState("");
+  // Files (in the active workspace) to hand the agent with the next message
+  const [attachments, setAttachments] = useState<string[]>([]);
   const [queuedMessage, setQueuedMessage] = useState<string | null>(null);
   const queuedMessageRef = useRef<string | null>(null);
   const [agentTabs, setAgentTabs] = useState<AgentTab[]>([]);
@@ -920,6 +1584,7 @@ function App() {
 
   // Mutations
   const addRepoMutation = useAddRepo(home || undefined);
+  const reorderRepos = useReorderRepos();
   const createWorkspaceMutation = useCreateWorkspace(home || undefined);
   const repoAdding = addRepoMutation.isPending;
   const repoError = addRepoMutation.error?.message ?? null;
@@ -932,6 +1597,8 @@ function App() {
 
   // Keep ref in sync with state (for use in event handlers)
   useEffect(() => { queuedMessageRef.current = queuedMessage; }, [queuedMessage]);
+  // Attachments belong to the workspace they were captured in
+  useEffect(() => { setAttachments([]); }, [activeWorkspaceId]);
 
   // State to trigger sending a queued message (avoids stale closure issues)
   const [pendingSendMessage, setPendingSendMessage] = useState<string | null>(null);
@@ -989,13 +1656,14 @@ function App() {
 
   // Workspace files and changes queries (depend on activeWorkspace)
   const { data: files = [], isLoading: filesLoading } = useWorkspaceFiles(home || undefined, activeWorkspaceId);
-  const { data: changes = [] } = useWorkspaceChanges(home || undefined, activeWorkspaceId);
+  // Changes and session come from one summary call instead of a request each
+  const { data: summary } = useWorkspaceSummary(activeWorkspaceId);
+  const changes = useMemo(() => summary?.changes ?? [], [summary]);
+  const sessionState = summary?.session;
+  useWorkspaceWatch(home || undefined, activeWorkspaceId);
 
   // Session persistence hooks
-  const { data: sessionState } = useSession(activeWorkspace?.path ?? null);
   const { data: chatHistory } = useChat(activeWorkspace?.path ?? null);
-  const upsertResumeIdMutation = useUpsertResumeId();
-  const appendChatMutation = useAppendChat();
 
   // Auto-select first file when files change
   const prevFilesRef = useRef<string[]>([]);
@@ -1009,17 +1677,25 @@ function App() {
 
   // File content and diff queries (depend on selectedFile)
   const isChangedFile = selectedFile ? changes.some(c => c.path === selectedFile) : false;
+  // Diffs come cut at DIFF_MAX_LINES unless the whole one was asked for
+  const [fullDiffFile, setFullDiffFile] = useState<string | null>(null);
   const { data: fileDiff, isLoading: diffLoading, error: diffError } = useFileDiff(
     home || undefined,
     activeWorkspaceId,
-    isChangedFile ? selectedFile : null
+    isChangedFile ? selectedFile : null,
+    fullDiffFile === selectedFile ? {} : { max_lines: DIFF_MAX_LINES }
   );
-  const { data: fileContent, isLoading: contentLoading, error: contentError } = useFileContent(
-    home || undefined,
+  const { data: filePreview, isLoading: contentLoading, error: contentError } = useFilePreview(
     activeWorkspaceId,
     // Only fetch content if no diff or diff is empty
-    (!isChangedFile || (fileDiff !== undefined && !fileDiff?.trim())) ? selectedFile : null
+    (!isChangedFile || (fileDiff !== undefined && !fileDiff.diff.trim())) ? selectedFile : null
   );
+  const fileContent = filePreview?.content;
+  const fileNotice = filePreview?.binary
+    ? `Binary file, ${formatBytes(filePreview.total_size)}`
+    : filePreview?.truncated
+      ? `Large file (${formatBytes(filePreview.total_size)}): showing the beginning`
+      : null;
   const fileViewLoading = diffLoading || contentLoading;
   const fileError = diffError?.message ?? contentError?.message ?? null;
 
@@ -1028,6 +1704,7 @@ function App() {
     if (!query) return workspaces;
     return workspaces.filter((ws) =>
       ws.name.toLowerCase().includes(query) ||
+      (ws.title ?? "").toLowerCase().includes(query) ||
       ws.repo.toLowerCase().includes(query) ||
       ws.branch.toLowerCase().includes(query)
     );
@@ -1105,8 +1782,10 @@ function App() {
   // Invalidate workspace files (for after agent changes)
   const invalidateWorkspaceFiles = useCallback(() => {
     if (activeWorkspaceId) {
-      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceFiles(home || undefined, activeWorkspaceId) });
-      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceChanges(home || undefined, activeWorkspaceId) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSnapshot(home || undefined, activeWorkspaceId) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary(activeWorkspaceId) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceTree(activeWorkspaceId) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceStats(activeWorkspaceId) });
     }
   }, [queryClient, home, activeWorkspaceId]);
 
@@ -1138,6 +1817,15 @@ function App() {
     setActiveWorkspaceId(id);
   }
 
+  function openWorkspaceFile(id: string, path: string) {
+    if (id === activeWorkspaceId) {
+      setSelectedFile(path);
+      return;
+    }
+    pendingFileRef.current = path;
+    openWorkspace(id);
+  }
+
   function closeWorkspace(id: string) {
     setOpenWorkspaceIds((prev) => {
       const idx = prev.indexOf(id);
@@ -1157,6 +1845,15 @@ function App() {
     if (next) setActiveWorkspaceId(next);
   }
 
+  // Swap with the repo above; sends the whole order so every repo keeps its place
+  function moveRepoUp(repoId: string) {
+    const ids = repos.map((repo) => repo.id);
+    const index = ids.indexOf(repoId);
+    if (index <= 0) return;
+    [ids[index - 1], ids[index]] = [ids[index], ids[index - 1]];
+    reorderRepos.mutate(ids);
+  }
+
   function toggleRepo(repoId: string) {
     setCollapsedRepoIds((prev) => {
       const next = new Set(prev);
@@ -1230,11 +1927,7 @@ function App() {
     const userMsg: ChatMessage = { id: `msg-${Date.now()}`, role: "user", content: trimmed, meta: "you" };
     updateTabMessages(activeTabId, [...currentTab.messages, userMsg], { running: true, startTime: Date.now() });
     setChatDraft("");
-
-    // Persist user message to chat.md
-    if (activeWorkspace?.path) {
-      appendChatMutation.mutate({ wsPath: activeWorkspace.path, role: "User", content: trimmed });
-    }
+    setAttachments([]);
 
     const sessionId = `${activeWorkspaceId}-${activeTabId}-${Date.now()}`;
     agentSessionRef.current = { wsId: activeWorkspaceId, tabId: activeTabId, sessionId };
@@ -1246,6 +1939,7 @@ function App() {
         cwd: activeWorkspace.path,
         sessionId,
         resumeId: currentTab.resumeId ?? null, // Pass resume ID for session continuity
+        attachments,
       });
     } catch (e) {
       const errorMsg: ChatMessage = {
@@ -1264,6 +1958,16 @@ function App() {
     }
   }
 
+  async function attachSnapshot() {
+    if (!activeWorkspace) return;
+    try {
+      const path = await invoke<string>("capture_snapshot", { workspacePath: activeWorkspace.path });
+      setAttachments((prev) => [...prev, path]);
+    } catch (e) {
+      console.error("Failed to capture snapshot:", e);
+    }
+  }
+
   async function stopAgent() {
     const session = agentSessionRef.current;
     if (!session) return;
@@ -1297,9 +2001,10 @@ function App() {
     }
   }, [workspaces, openWorkspaceIds, activeWorkspaceId]);
 
-  // Clear selected file when workspace changes
+  // Reset the selected file when the workspace changes, to a pending search pick if any
   useEffect(() => {
-    setSelectedFile(null);
+    setSelectedFile(pendingFileRef.current);
+    pendingFileRef.current = null;
   }, [activeWorkspaceId]);
 
   useEffect(() => {
@@ -1330,6 +2035,8 @@ function App() {
   // Ref to store the latest invalidation function (avoids stale closures in event listener)
   const invalidateFilesRef = useRef(invalidateWorkspaceFiles);
   useEffect(() => { invalidateFilesRef.current = invalidateWorkspaceFiles; }, [invalidateWorkspaceFiles]);
+  const refreshRef = useRef(refresh);
+  useEffect(() => { refreshRef.current = refresh; }, [refresh]);
 
   // Listen for agent events
   useEffect(() => {
@@ -1356,6 +2063,8 @@ function App() {
         // Play notification sound only on final session end (not agent.completed which may come first)
         if (agentEvent.type === "session_ended") {
           playNotificationSound();
+          // Picks up the title and verification the run left on the workspace
+          refreshRef.current();
         }
 
         setAgentTabs((prev) => {
@@ -1412,6 +2121,33 @@ function App() {
                 actionDetail: action.detail, ok,
               };
             }
+          } else if (agentEvent.type === "agent.fallback") {
+            newMsg = {
+              id: `msg-${Date.now()}-fallback`, role: "system",
+              content: `${agentEvent.from} failed (${agentEvent.error}); falling back to ${agentEvent.engine}`,
+              meta: "error",
+            };
+          } else if (agentEvent.type === "agent.policy_violation") {
+            const verb = agentEvent.decision === "block" ? "Blocked" : "Flagged";
+            newMsg = {
+              id: `msg-${Date.now()}-policy`, role: "system",
+              content: `${verb} by policy ${agentEvent.rule}: ${agentEvent.command}`, meta: "error",
+            };
+          } else if (agentEvent.type === "agent.diffstat") {
+            const files = agentEvent.files ?? 0;
+            const untracked = agentEvent.untracked?.length ?? 0;
+            newMsg = {
+              id: `msg-${Date.now()}-diffstat`, role: "system",
+              content: `${files} file${files === 1 ? "" : "s"} changed, +${agentEvent.insertions ?? 0} -${agentEvent.deletions ?? 0}`
+                + (untracked > 0 ? ` (${untracked} new)` : ""),
+              meta: "diffstat",
+            };
+          } else if (agentEvent.type === "verification" && agentEvent.phase === "completed") {
+            const why = agentEvent.ok ? "" : ` (${agentEvent.exit_code != null ? `exit ${agentEvent.exit_code}` : agentEvent.error})`;
+            newMsg = {
+              id: `msg-${Date.now()}-verify`, role: "system",
+              content: `${agentEvent.ok ? "✓" : "✗"} ${agentEvent.command}${why}`, meta: agentEvent.ok ? "checks" : "error",
+            };
           } else if (agentEvent.type === "agent.completed" || agentEvent.type === "session_ended" || agentEvent.type === "session_stopped") {
             updates.running = false;
             // Clear actions on completion
@@ -1426,11 +2162,6 @@ function App() {
               const updatedMsgs = [...tab.messages];
               const streamMsg = updatedMsgs[streamMsgIdx];
               updatedMsgs[streamMsgIdx] = { ...streamMsg, id: `msg-final-${Date.now()}` };
-              // Persist assistant message to chat.md
-              const ws = workspaceById.get(session.wsId);
-              if (ws?.path && streamMsg.content) {
-                appendChatMutation.mutate({ wsPath: ws.path, role: "Assistant", content: streamMsg.content });
-              }
               const next = prev.map((t) => t.id === session.tabId ? { ...t, messages: updatedMsgs, actions: new Map(), running: false } : t);
               tabStore.current.set(session.wsId, next);
               return next;
@@ -1442,14 +2173,10 @@ function App() {
           } else if (agentEvent.type === "agent.started" || agentEvent.type === "session_started") {
             // Clear previous actions on new session (don't show a message)
             updatedActions = new Map();
-            // Capture resume token for session continuity (Takopi pattern)
+            // Capture resume token for session continuity (Takopi pattern); the
+            // daemon persists it to .conductor-app/session.json
             if (agentEvent.resume) {
               updates.resumeId = agentEvent.resume;
-              // Persist resume ID to .conductor-app/session.json (upsert creates if missing)
-              const ws = workspaceById.get(session.wsId);
-              if (ws?.path) {
-                upsertResumeIdMutation.mutate({ wsPath: ws.path, agentId: tab.agentId, resumeId: agentEvent.resume });
-              }
             }
           }
 
@@ -1505,7 +2232,7 @@ function App() {
           onHomeDraftChange={setHomeDraft} onApplyHome={applyHome} onRefresh={() => void refresh()}
           onFilterChange={setFilter} onCreateWorkspaceForRepo={(id) => void createWorkspaceForRepo(id)}
           onRepoUrlChange={(v) => { setRepoUrl(v); addRepoMutation.reset(); }}
-          onAddRepo={() => void addRepo()} onToggleRepo={toggleRepo} onOpenWorkspace={openWorkspace}
+          onAddRepo={() => void addRepo()} onToggleRepo={toggleRepo} onMoveRepoUp={moveRepoUp} onOpenWorkspace={openWorkspace}
           onToggleHomePopover={() => setShowHomePopover(p => !p)}
         />
       )}
@@ -1518,6 +2245,7 @@ function App() {
             <div className="error-body">{error}</div>
           </div>
         )}
+        <StaleBanner onOpenWorkspace={openWorkspace} />
 
         <TabsHeader
           openGroups={openGroups} activeWorkspaceId={activeWorkspaceId}
@@ -1544,6 +2272,8 @@ function App() {
                 onTabChange={setActiveTabId} onTabClose={closeAgentTab} onTabAdd={addAgentTab}
                 onAgentChange={changeTabAgent} onDraftChange={setChatDraft}
                 onSend={() => void sendChat()} onStop={() => void stopAgent()}
+                attachments={attachments} onAttachSnapshot={() => void attachSnapshot()}
+                onRemoveAttachment={(path) => setAttachments((prev) => prev.filter((p) => p !== path))}
                 chatEndRef={chatEndRef}
               />
               {/* Terminal toggle and panel */}
@@ -1573,10 +2303,12 @@ function App() {
                 activeWorkspace={activeWorkspace} files={files} changes={changes}
                 filteredChanges={filteredChanges} filteredAllFiles={filteredAllFiles}
                 filesLoading={filesLoading} fileFilter={fileFilter} showAllFiles={showAllFiles}
-                selectedFile={selectedFile} fileError={fileError} fileDiff={fileDiff ?? null}
-                fileContent={fileContent ?? null} fileViewLoading={fileViewLoading}
+                selectedFile={selectedFile} fileError={fileError} fileDiff={fileDiff?.diff ?? null}
+                diffCut={fileDiff?.omitted_hunks?.length ? fileDiff : null}
+                fileContent={fileContent ?? null} fileHash={filePreview?.hash ?? null} fileNotice={fileNotice} fileViewLoading={fileViewLoading}
                 onFileFilterChange={setFileFilter} onToggleShowAll={() => setShowAllFiles((p) => !p)}
-                onSelectFile={setSelectedFile}
+                onSelectFile={setSelectedFile} onSendReview={(prompt) => sendChat(prompt)}
+                onShowFullDiff={() => setFullDiffFile(selectedFile)}
               />
             )}
           </section>
@@ -1598,6 +2330,7 @@ function App() {
         repos={repos}
         workspaces={workspaces}
         onOpenWorkspace={openWorkspace}
+        onOpenFile={openWorkspaceFile}
         onCreateWorkspace={(repoId) => void createWorkspaceForRepo(repoId)}
         onRefresh={() => void refresh()}
       />
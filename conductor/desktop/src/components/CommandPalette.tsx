@@ -1,13 +1,14 @@
 import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import Fuse from "fuse.js";
-import type { Repo, Workspace } from "../types";
+import type { Repo, SearchHit, Workspace } from "../types";
+import { useSearch } from "../lib/hooks";
 
 export type CommandAction = {
   id: string;
   label: string;
   description?: string;
   shortcut?: string;
-  category: "workspace" | "repo" | "action" | "navigation";
+  category: "workspace" | "repo" | "file" | "chat" | "action" | "navigation";
   onSelect: () => void;
 };
 
@@ -17,6 +18,7 @@ type Props = {
   repos: Repo[];
   workspaces: Workspace[];
   onOpenWorkspace: (id: string) => void;
+  onOpenFile: (workspaceId: string, path: string) => void;
   onCreateWorkspace: (repoId: string) => void;
   onRefresh: () => void;
 };
@@ -27,13 +29,22 @@ export function CommandPalette({
   repos,
   workspaces,
   onOpenWorkspace,
+  onOpenFile,
   onCreateWorkspace,
   onRefresh,
 }: Props) {
   const [query, setQuery] = useState("");
+  const [searchQuery, setSearchQuery] = useState("");
   const [selectedIndex, setSelectedIndex] = useState(0);
   const inputRef = useRef<HTMLInputElement>(null);
 
+  // Files and chat come from the daemon; wait for a pause in typing before asking
+  useEffect(() => {
+    const timer = setTimeout(() => setSearchQuery(query), 150);
+    return () => clearTimeout(timer);
+  }, [query]);
+  const { data: hits = [] } = useSearch(open ? searchQuery : "");
+
   // Build command list
   const commands = useMemo<CommandAction[]>(() => {
     const cmds: CommandAction[] = [];
@@ -86,8 +97,15 @@ export function CommandPalette({
 
   const results = useMemo(() => {
     if (!query.trim()) return commands.slice(0, 15);
-    return fuse.search(query).slice(0, 15).map((r) => r.item);
-  }, [fuse, commands, query]);
+    const local = fuse.search(query).slice(0, 15).map((r) => r.item);
+    // Daemon hits add workspaces found by title, files and chat; repo hits are already
+    // covered by the local commands
+    const seen = new Set(local.map((cmd) => cmd.id));
+    const remote = hits
+      .map((hit) => hitCommand(hit, onOpenWorkspace, onOpenFile))
+      .filter((cmd): cmd is CommandAction => !!cmd && !seen.has(cmd.id));
+    return [...local, ...remote.slice(0, 15)];
+  }, [fuse, commands, query, hits, onOpenWorkspace, onOpenFile]);
 
   // Reset on open
   useEffect(() => {
@@ -129,6 +147,8 @@ export function CommandPalette({
   const categoryLabels: Record<string, string> = {
     workspace: "Workspaces",
     repo: "Repositories",
+    file: "Files",
+    chat: "Chat",
     action: "Actions",
     navigation: "Navigation",
   };
@@ -153,7 +173,7 @@ export function CommandPalette({
             ref={inputRef}
             type="text"
             className="command-palette-input"
-            placeholder="Search workspaces, actions..."
+            placeholder="Search workspaces, files, chat, actions..."
             value={query}
             onChange={(e) => {
               setQuery(e.target.value);
@@ -203,3 +223,42 @@ export function CommandPalette({
     </div>
   );
 }
+
+// A palette entry for a daemon search hit; null for repo hits
+function hitCommand(
+  hit: SearchHit,
+  onOpenWorkspace: (id: string) => void,
+  onOpenFile: (workspaceId: string, path: string) => void,
+): CommandAction | null {
+  const wsId = hit.workspace_id;
+  if (!wsId) return null;
+  const where = `${hit.repo} · ${hit.workspace}`;
+  switch (hit.kind) {
+    case "workspace":
+      return {
+        id: `open-ws-${wsId}`,
+        label: hit.label,
+        description: hit.snippet ? `${hit.repo} · ${hit.snippet}` : hit.repo,
+        category: "workspace",
+        onSelect: () => onOpenWorkspace(wsId),
+      };
+    case "file":
+      return {
+        id: `file-${wsId}-${hit.label}`,
+        label: hit.label,
+        description: where,
+        category: "file",
+        onSelect: () => onOpenFile(wsId, hit.label),
+      };
+    case "chat":
+      return {
+        id: `chat-${wsId}-${hit.label}`,
+        label: hit.snippet ?? hit.label,
+        description: `${where} · ${hit.label}`,
+        category: "chat",
+        onSelect: () => onOpenWorkspace(wsId),
+      };
+    default:
+      return null;
+  }
+}
@@ -0,0 +1,60 @@
+import { useDaemonStatus } from "../lib/hooks";
+
+function formatBytes(bytes: number) {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return unit === 0 ? `${bytes} B` : `${value.toFixed(1)} ${units[unit]}`;
+}
+
+export function DaemonStatusPanel() {
+  const { data: status, error, isLoading } = useDaemonStatus();
+
+  if (isLoading) return <div className="daemon-status muted">Checking daemon...</div>;
+  if (error || !status) {
+    return <div className="daemon-status inline-error">Daemon unavailable: {String(error ?? "no response")}</div>;
+  }
+
+  return (
+    <div className="daemon-status">
+      <div className="panel-item">
+        <span className="panel-label">Daemon</span>
+        <span className="mono">v{status.version} · up {status.uptime_secs}s</span>
+      </div>
+      <div className="panel-item">
+        <span className="panel-label">Database</span>
+        <span className="mono">schema {status.schema_version}</span>
+      </div>
+      <div className="panel-item">
+        <span className="panel-label">Disk</span>
+        <span className="mono">{formatBytes(status.home_disk_bytes)}</span>
+      </div>
+      <div className="panel-item">
+        <span className="panel-label">Repos</span>
+        <span className="mono">{status.repo_count}</span>
+      </div>
+      <div className="panel-item">
+        <span className="panel-label">Workspaces</span>
+        <span className="mono">{status.workspace_count} ({status.archived_workspace_count} archived)</span>
+      </div>
+      <div className="panel-item">
+        <span className="panel-label">Agents</span>
+        <span className="mono">{status.agents.length}</span>
+      </div>
+      {status.agents.map((agent) => (
+        <div key={agent.session_id} className="daemon-status-row mono">
+          {agent.engine} · {agent.elapsed_secs}s · {agent.cwd}
+        </div>
+      ))}
+      {status.recent_errors.slice(0, 5).map((err, i) => (
+        <div key={`${err.timestamp}-${i}`} className="daemon-status-row inline-error" title={err.timestamp}>
+          {err.context}: {err.message}
+        </div>
+      ))}
+    </div>
+  );
+}
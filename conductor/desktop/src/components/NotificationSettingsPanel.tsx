@@ -0,0 +1,34 @@
+import { useNotificationSettings, useSetNotificationSettings } from "../lib/hooks";
+import type { NotificationSettings } from "../types";
+
+const TOGGLES: { key: keyof NotificationSettings; label: string }[] = [
+  { key: "agent_completed", label: "Agent finished" },
+  { key: "agent_failed", label: "Agent failed" },
+  { key: "approval_requested", label: "Approval needed" },
+  { key: "merge_conflict", label: "Merge conflict" },
+];
+
+export function NotificationSettingsPanel() {
+  const { data: settings, error } = useNotificationSettings();
+  const save = useSetNotificationSettings();
+
+  if (error) return <div className="daemon-status inline-error">{String(error)}</div>;
+  if (!settings) return null;
+
+  return (
+    <div className="daemon-status">
+      {TOGGLES.map(({ key, label }) => (
+        <label key={key} className="panel-item">
+          <input
+            type="checkbox"
+            checked={settings[key]}
+            disabled={save.isPending}
+            onChange={(e) => save.mutate({ ...settings, [key]: e.target.checked })}
+          />
+          <span className="panel-label">{label}</span>
+        </label>
+      ))}
+      {save.error && <div className="inline-error">{String(save.error)}</div>}
+    </div>
+  );
+}
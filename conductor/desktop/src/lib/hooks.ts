@@ -1,5 +1,9 @@
-import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
+import { useEffect } from "react";
+import { keepPreviousData, useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
+import { invoke } from "@tauri-apps/api/core";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
 import { queryKeys, queryFns } from "./query";
+import type { DiffLimit, NotificationSettings, WorkspaceEvent, WorkspaceState } from "../types";
 
 // Hook for repos list
 export function useRepos(home?: string) {
@@ -17,29 +21,142 @@ export function useWorkspaces(home?: string) {
   });
 }
 
-// Hook for workspace files
+// Workspaces to suggest cleaning up; checked now and then, not on every refresh
+export function useStaleWorkspaces() {
+  return useQuery({
+    queryKey: queryKeys.staleWorkspaces(),
+    queryFn: queryFns.staleWorkspaces,
+    staleTime: 10 * 60 * 1000,
+  });
+}
+
+// Hook for workspace files, selected from the shared snapshot
 export function useWorkspaceFiles(home: string | undefined, wsId: string | null) {
   return useQuery({
-    queryKey: queryKeys.workspaceFiles(home, wsId ?? ""),
-    queryFn: () => queryFns.workspaceFiles(home, wsId!),
+    queryKey: queryKeys.workspaceSnapshot(home, wsId ?? ""),
+    queryFn: () => queryFns.workspaceSnapshot(wsId!),
+    select: (snapshot) => snapshot.files,
+    enabled: !!wsId,
+  });
+}
+
+// Hook for one directory level of the workspace file tree
+export function useWorkspaceTree(wsId: string | null, dir: string) {
+  return useQuery({
+    queryKey: queryKeys.workspaceTree(wsId ?? "", dir),
+    queryFn: () => queryFns.workspaceTree(wsId!, dir),
+    enabled: !!wsId,
+  });
+}
+
+// Hook for a changed file's diff as hunks (see useFileDiff for the patch text)
+export function useStructuredFileDiff(wsId: string | null, path: string | null) {
+  return useQuery({
+    queryKey: queryKeys.workspaceFileDiffStructured(wsId ?? "", path ?? ""),
+    queryFn: () => queryFns.workspaceFileDiffStructured(wsId!, path!),
+    enabled: !!wsId && !!path,
+  });
+}
+
+// Hook for the commits an agent made on the workspace branch
+export function useWorkspaceLog(wsId: string | null) {
+  return useQuery({
+    queryKey: queryKeys.workspaceLog(wsId ?? ""),
+    queryFn: () => queryFns.workspaceLog(wsId!, 50),
+    enabled: !!wsId,
+  });
+}
+
+export function useWorkspaceStats(wsId: string | null) {
+  return useQuery({
+    queryKey: queryKeys.workspaceStats(wsId ?? ""),
+    queryFn: () => queryFns.workspaceStats(wsId!),
     enabled: !!wsId,
   });
 }
 
-// Hook for workspace changes
+// Hooks for review comments on the workspace's files (resolved ones included)
+// Daemon-side search across repos, workspaces, files and chat; keeps the last hits
+// while the next query loads so the palette doesn't flicker
+export function useSearch(query: string) {
+  const trimmed = query.trim();
+  return useQuery({
+    queryKey: queryKeys.search(trimmed),
+    queryFn: () => queryFns.search(trimmed, 30),
+    enabled: trimmed.length >= 2,
+    placeholderData: keepPreviousData,
+  });
+}
+
+export function useReviewComments(wsId: string | null) {
+  return useQuery({
+    queryKey: queryKeys.reviewComments(wsId ?? ""),
+    queryFn: () => queryFns.reviewComments(wsId!, true),
+    enabled: !!wsId,
+  });
+}
+
+export function useAddReviewComment() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, path, startLine, endLine, body }: { wsId: string; path: string; startLine: number; endLine: number; body: string }) =>
+      queryFns.addReviewComment(wsId, path, startLine, endLine, body),
+    onSuccess: (_, { wsId }) => queryClient.invalidateQueries({ queryKey: queryKeys.reviewComments(wsId) }),
+  });
+}
+
+export function useUpdateReviewComment() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ id, body, resolved }: { id: string; body?: string; resolved?: boolean }) =>
+      queryFns.updateReviewComment(id, { body, resolved }),
+    onSuccess: (comment) => queryClient.invalidateQueries({ queryKey: queryKeys.reviewComments(comment.workspace_id) }),
+  });
+}
+
+export function useSyncReviewComments() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: (wsId: string) => queryFns.syncReviewComments(wsId),
+    onSuccess: (_, wsId) => {
+      queryClient.invalidateQueries({ queryKey: queryKeys.reviewComments(wsId) });
+      queryClient.invalidateQueries({ queryKey: ["chat"] });
+    },
+  });
+}
+
+export function useDeleteReviewComment() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ id }: { id: string; wsId: string }) => queryFns.deleteReviewComment(id),
+    onSuccess: (_, { wsId }) => queryClient.invalidateQueries({ queryKey: queryKeys.reviewComments(wsId) }),
+  });
+}
+
+// Hook for workspace changes, selected from the same snapshot as the files
 export function useWorkspaceChanges(home: string | undefined, wsId: string | null) {
   return useQuery({
-    queryKey: queryKeys.workspaceChanges(home, wsId ?? ""),
-    queryFn: () => queryFns.workspaceChanges(home, wsId!),
+    queryKey: queryKeys.workspaceSnapshot(home, wsId ?? ""),
+    queryFn: () => queryFns.workspaceSnapshot(wsId!),
+    select: (snapshot) => snapshot.changes,
+    enabled: !!wsId,
+  });
+}
+
+// Hook for changes, session and latest chat message in one daemon call
+export function useWorkspaceSummary(wsId: string | null) {
+  return useQuery({
+    queryKey: queryKeys.workspaceSummary(wsId ?? ""),
+    queryFn: () => queryFns.workspaceSummary(wsId!),
     enabled: !!wsId,
   });
 }
 
-// Hook for file diff
-export function useFileDiff(home: string | undefined, wsId: string | null, path: string | null) {
+// Hook for file diff; a max_lines limit keeps a giant one from freezing the viewer
+export function useFileDiff(home: string | undefined, wsId: string | null, path: string | null, limit: DiffLimit = {}) {
   return useQuery({
-    queryKey: queryKeys.workspaceFileDiff(home, wsId ?? "", path ?? ""),
-    queryFn: () => queryFns.workspaceFileDiff(home, wsId!, path!),
+    queryKey: queryKeys.workspaceFileDiff(home, wsId ?? "", path ?? "", limit),
+    queryFn: () => queryFns.workspaceFileDiff(home, wsId!, path!, limit),
     enabled: !!wsId && !!path,
   });
 }
@@ -53,6 +170,15 @@ export function useFileContent(home: string | undefined, wsId: string | null, pa
   });
 }
 
+// Hook for the file viewer: capped text, or size only for binaries
+export function useFilePreview(wsId: string | null, path: string | null) {
+  return useQuery({
+    queryKey: queryKeys.workspaceFilePreview(wsId ?? "", path ?? ""),
+    queryFn: () => queryFns.workspaceFilePreview(wsId!, path!),
+    enabled: !!wsId && !!path,
+  });
+}
+
 // Hook for adding repo
 export function useAddRepo(home?: string) {
   const queryClient = useQueryClient();
@@ -76,15 +202,245 @@ export function useCreateWorkspace(home?: string) {
   });
 }
 
+// Hook for moving a workspace along its pipeline (ready, in review, merged, abandoned)
+export function useSetWorkspaceState() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, state }: { wsId: string; state: WorkspaceState }) => queryFns.setWorkspaceState(wsId, state),
+    onSuccess: () => queryClient.invalidateQueries({ queryKey: ["workspaces"] }),
+  });
+}
+
+export function useSetWorkspacePinned() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, pinned }: { wsId: string; pinned: boolean }) => queryFns.setWorkspacePinned(wsId, pinned),
+    onSuccess: () => queryClient.invalidateQueries({ queryKey: ["workspaces"] }),
+  });
+}
+
+export function useReorderRepos() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: (repoIds: string[]) => queryFns.reorderRepos(repoIds),
+    onSuccess: () => queryClient.invalidateQueries({ queryKey: ["repos"] }),
+  });
+}
+
+// Hook for applying a pasted diff to a workspace
+export function useApplyPatch() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, patch, threeWay }: { wsId: string; patch: string; threeWay: boolean }) =>
+      queryFns.applyPatch(wsId, patch, threeWay),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["workspace-changes"] });
+    },
+  });
+}
+
+export function useCommitWorkspace() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, message }: { wsId: string; message: string }) => queryFns.commitWorkspace(wsId, message),
+    onSuccess: (_, { wsId }) => {
+      queryClient.invalidateQueries({ queryKey: ["workspace-changes"] });
+      queryClient.invalidateQueries({ queryKey: ["workspace-snapshot"] });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceLog(wsId) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceStats(wsId) });
+    },
+  });
+}
+
+export function useSuggestCommitMessage() {
+  return useMutation({ mutationFn: (wsId: string) => queryFns.suggestCommitMessage(wsId) });
+}
+
+// Hook for saving an edited workspace file
+export function useSaveFile() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ wsId, path, content, expectedHash }: { wsId: string; path: string; content: string; expectedHash: string | null }) =>
+      queryFns.saveFile(wsId, path, content, expectedHash),
+    onSuccess: (_, { wsId, path }) => {
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceFilePreview(wsId, path) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceTree(wsId) });
+      queryClient.invalidateQueries({ queryKey: ["workspace-changes"] });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary(wsId) });
+      queryClient.invalidateQueries({ queryKey: ["workspace-file-diff"] });
+    },
+  });
+}
+
+// Hooks for file management from the file tree; each refreshes the tree and changes
+function useInvalidateFileTree() {
+  const queryClient = useQueryClient();
+  return (wsId: string) => {
+    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceTree(wsId) });
+    queryClient.invalidateQueries({ queryKey: ["workspace-files"] });
+    queryClient.invalidateQueries({ queryKey: ["workspace-changes"] });
+    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary(wsId) });
+  };
+}
+
+export function useRenameFile() {
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, from, to }: { wsId: string; from: string; to: string }) => queryFns.renameFile(wsId, from, to),
+    onSuccess: (_, { wsId }) => invalidate(wsId),
+  });
+}
+
+export function useDeleteFile() {
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, path }: { wsId: string; path: string }) => queryFns.deleteFile(wsId, path),
+    onSuccess: (_, { wsId }) => invalidate(wsId),
+  });
+}
+
+export function useCreateDirectory() {
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, path }: { wsId: string; path: string }) => queryFns.createDirectory(wsId, path),
+    onSuccess: (_, { wsId }) => invalidate(wsId),
+  });
+}
+
+// Hooks for staging and discarding from the diff view
+export function useStageFiles() {
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, paths }: { wsId: string; paths: string[] }) => queryFns.stageFiles(wsId, paths),
+    onSuccess: (_, { wsId }) => invalidate(wsId),
+  });
+}
+
+export function useUnstageFiles() {
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, paths }: { wsId: string; paths: string[] }) => queryFns.unstageFiles(wsId, paths),
+    onSuccess: (_, { wsId }) => invalidate(wsId),
+  });
+}
+
+export function useDiscardChanges() {
+  const queryClient = useQueryClient();
+  const invalidate = useInvalidateFileTree();
+  return useMutation({
+    mutationFn: ({ wsId, paths, includeUntracked }: { wsId: string; paths: string[]; includeUntracked: boolean }) =>
+      queryFns.discardChanges(wsId, paths, includeUntracked),
+    onSuccess: (_, { wsId }) => {
+      invalidate(wsId);
+      queryClient.invalidateQueries({ queryKey: ["workspace-file-diff"] });
+      queryClient.invalidateQueries({ queryKey: ["workspace-file-preview", wsId] });
+    },
+  });
+}
+
+// Hook for opening a workspace in the user's editor
+export function useOpenWorkspace() {
+  return useMutation({
+    mutationFn: (wsId: string) => queryFns.openWorkspace(wsId),
+  });
+}
+
+export function useOpenWorkspaceTerminal() {
+  return useMutation({
+    mutationFn: (wsId: string) => queryFns.openWorkspaceTerminal(wsId),
+  });
+}
+
+export function useRevealWorkspace() {
+  return useMutation({
+    mutationFn: (wsId: string) => queryFns.revealWorkspace(wsId),
+  });
+}
+
+// Hook for daemon health (polled while mounted)
+export function useDaemonStatus(enabled = true) {
+  return useQuery({
+    queryKey: queryKeys.daemonStatus(),
+    queryFn: () => queryFns.daemonStatus(),
+    enabled,
+    refetchInterval: 5000,
+  });
+}
+
+// Hook for which engines can run here (probing spawns each one, so cache it)
+export function useEngines() {
+  return useQuery({
+    queryKey: queryKeys.engines(),
+    queryFn: () => queryFns.listEngines(),
+    staleTime: 60_000,
+  });
+}
+
+// Hooks for the desktop notification toggles
+export function useNotificationSettings() {
+  return useQuery({
+    queryKey: queryKeys.notificationSettings(),
+    queryFn: () => queryFns.notificationSettings(),
+  });
+}
+
+export function useSetNotificationSettings() {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: (settings: NotificationSettings) => queryFns.setNotificationSettings(settings),
+    onSuccess: (_, settings) => {
+      queryClient.setQueryData(queryKeys.notificationSettings(), settings);
+    },
+  });
+}
+
 // Hook to invalidate workspace files (for refreshing after agent changes)
 export function useInvalidateWorkspaceFiles() {
   const queryClient = useQueryClient();
   return (home: string | undefined, wsId: string) => {
-    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceFiles(home, wsId) });
-    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceChanges(home, wsId) });
+    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSnapshot(home, wsId) });
+    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary(wsId) });
+    queryClient.invalidateQueries({ queryKey: queryKeys.workspaceTree(wsId) });
   };
 }
 
+// Hook that subscribes to daemon file/branch events for a workspace and
+// refreshes its files and changes instead of polling
+export function useWorkspaceWatch(home: string | undefined, wsId: string | null) {
+  const queryClient = useQueryClient();
+  useEffect(() => {
+    if (!wsId) return;
+    let unlisten: UnlistenFn | null = null;
+    let cancelled = false;
+
+    const setup = async () => {
+      unlisten = await listen<WorkspaceEvent>("workspace_event", (event) => {
+        if (event.payload.workspace_id !== wsId) return;
+        queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSnapshot(home, wsId) });
+        queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary(wsId) });
+        queryClient.invalidateQueries({ queryKey: queryKeys.workspaceTree(wsId) });
+        queryClient.invalidateQueries({ queryKey: queryKeys.workspaceStats(wsId) });
+        if (event.payload.type === "branch_changed") {
+          queryClient.invalidateQueries({ queryKey: queryKeys.workspaces(home) });
+          queryClient.invalidateQueries({ queryKey: queryKeys.workspaceLog(wsId) });
+        }
+      });
+      if (cancelled) {
+        unlisten();
+        return;
+      }
+      await invoke("watch_workspace", { workspace: wsId }).catch(console.error);
+    };
+    setup();
+
+    return () => {
+      cancelled = true;
+      if (unlisten) unlisten();
+      invoke("unwatch_workspace", { workspace: wsId }).catch(console.error);
+    };
+  }, [home, wsId, queryClient]);
+}
+
 // =============================================================================
 // Session Persistence Hooks
 // =============================================================================
@@ -106,6 +462,7 @@ export function useCreateSession() {
       queryFns.sessionCreate(wsPath, agentId),
     onSuccess: (_, { wsPath }) => {
       queryClient.invalidateQueries({ queryKey: queryKeys.session(wsPath) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary() });
     },
   });
 }
@@ -118,6 +475,7 @@ export function useSetResumeId() {
       queryFns.sessionSetResumeId(wsPath, resumeId),
     onSuccess: (_, { wsPath }) => {
       queryClient.invalidateQueries({ queryKey: queryKeys.session(wsPath) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary() });
     },
   });
 }
@@ -130,6 +488,7 @@ export function useUpsertResumeId() {
       queryFns.sessionUpsertResumeId(wsPath, agentId, resumeId),
     onSuccess: (_, { wsPath }) => {
       queryClient.invalidateQueries({ queryKey: queryKeys.session(wsPath) });
+      queryClient.invalidateQueries({ queryKey: queryKeys.workspaceSummary() });
     },
   });
 }
@@ -1,6 +1,6 @@
 import { QueryClient } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/core";
-import type { Repo, SessionState, Workspace, WorkspaceChange } from "../types";
+import type { ApplyResult, DaemonStatus, DiffLimit, EngineInfo, FileBytes, FileDiff, FileFilter, FilePreview, LogCommit, NotificationSettings, Repo, ReviewComment, SearchHit, SessionState, StaleWorkspaces, StructuredDiff, TreeEntry, Workspace, WorkspaceChange, WorkspaceSnapshot, WorkspaceState, WorkspaceStats, WorkspaceSummary } from "../types";
 
 // Query client with sensible defaults
 export const queryClient = new QueryClient({
@@ -22,12 +22,32 @@ async function tauriInvoke<T>(cmd: string, args?: Record<string, unknown>): Prom
 export const queryKeys = {
   repos: (home?: string) => ["repos", home] as const,
   workspaces: (home?: string) => ["workspaces", home] as const,
-  workspaceFiles: (home: string | undefined, wsId: string) => ["workspace-files", home, wsId] as const,
-  workspaceChanges: (home: string | undefined, wsId: string) => ["workspace-changes", home, wsId] as const,
-  workspaceFileDiff: (home: string | undefined, wsId: string, path: string) => ["workspace-file-diff", home, wsId, path] as const,
+  staleWorkspaces: () => ["workspaces", "stale"] as const,
+  // Files and changes share one snapshot so the panel costs a single status pass
+  workspaceSnapshot: (home: string | undefined, wsId: string) => ["workspace-snapshot", home, wsId] as const,
+  // Without a workspace: every summary, for invalidation
+  workspaceSummary: (wsId?: string) =>
+    wsId === undefined ? ["workspace-summary"] as const : ["workspace-summary", wsId] as const,
+  // Without a dir: every loaded level of the workspace, for invalidation
+  workspaceTree: (wsId: string, dir?: string) =>
+    dir === undefined ? ["workspace-tree", wsId] as const : ["workspace-tree", wsId, dir] as const,
+  workspaceLog: (wsId: string) => ["workspace-log", wsId] as const,
+  workspaceStats: (wsId: string) => ["workspace-stats", wsId] as const,
+  reviewComments: (wsId: string) => ["review-comments", wsId] as const,
+  search: (query: string) => ["search", query] as const,
+  workspaceFileDiffStructured: (wsId: string, path: string) => ["workspace-file-diff", "structured", wsId, path] as const,
+  // Without a limit: every shape of the file's diff, for invalidation
+  workspaceFileDiff: (home: string | undefined, wsId: string, path: string, limit?: DiffLimit) =>
+    limit === undefined
+      ? ["workspace-file-diff", home, wsId, path] as const
+      : ["workspace-file-diff", home, wsId, path, limit] as const,
   workspaceFileContent: (home: string | undefined, wsId: string, path: string) => ["workspace-file-content", home, wsId, path] as const,
+  workspaceFilePreview: (wsId: string, path: string) => ["workspace-file-preview", wsId, path] as const,
   session: (wsPath: string) => ["session", wsPath] as const,
   chat: (wsPath: string) => ["chat", wsPath] as const,
+  daemonStatus: () => ["daemon-status"] as const,
+  engines: () => ["engines"] as const,
+  notificationSettings: () => ["notification-settings"] as const,
 };
 
 // Query functions
@@ -35,20 +55,97 @@ export const queryFns = {
   listRepos: (home?: string) =>
     tauriInvoke<Repo[]>("list_repos", home ? { home } : {}),
 
+  // Most recently active first, so each repo's busy workspaces lead its group
   listWorkspaces: (home?: string) =>
-    tauriInvoke<Workspace[]>("list_workspaces", { ...(home ? { home } : {}), repo: null }),
+    tauriInvoke<Workspace[]>("list_workspaces", { ...(home ? { home } : {}), repo: null, sort: "recent" }),
 
-  workspaceFiles: (home: string | undefined, wsId: string) =>
-    tauriInvoke<string[]>("workspace_files", { ...(home ? { home } : {}), workspace: wsId }),
+  // Idle past stale_after in config.json; empty when that's unset
+  staleWorkspaces: () =>
+    tauriInvoke<StaleWorkspaces>("list_stale_workspaces"),
+
+  // The filter narrows the listing daemon-side; glob is a git pathspec ("**/" crosses dirs)
+  workspaceFiles: (home: string | undefined, wsId: string, filter?: FileFilter) =>
+    tauriInvoke<string[]>("workspace_files", { ...(home ? { home } : {}), workspace: wsId, ...filter }),
 
   workspaceChanges: (home: string | undefined, wsId: string) =>
     tauriInvoke<WorkspaceChange[]>("workspace_changes", { ...(home ? { home } : {}), workspace: wsId }),
 
-  workspaceFileDiff: (home: string | undefined, wsId: string, path: string) =>
-    tauriInvoke<string>("workspace_file_diff", { ...(home ? { home } : {}), workspace: wsId, path }),
+  workspaceSnapshot: (wsId: string) =>
+    tauriInvoke<WorkspaceSnapshot>("workspace_snapshot", { workspace: wsId }),
+
+  workspaceSummary: (wsId: string) =>
+    tauriInvoke<WorkspaceSummary>("workspace_summary", { workspace: wsId }),
+
+  workspaceFileDiffStructured: (wsId: string, path: string) =>
+    tauriInvoke<StructuredDiff>("workspace_file_diff_structured", { workspace: wsId, path }),
+
+  workspaceLog: (wsId: string, limit?: number) =>
+    tauriInvoke<LogCommit[]>("workspace_log", { workspace: wsId, limit }),
+
+  workspaceStats: (wsId: string) =>
+    tauriInvoke<WorkspaceStats>("workspace_stats", { workspace: wsId }),
+
+  workspaceTree: (wsId: string, dir: string) =>
+    tauriInvoke<TreeEntry[]>("workspace_tree", { workspace: wsId, dir }),
+
+  workspaceFileDiff: (home: string | undefined, wsId: string, path: string, limit?: DiffLimit) =>
+    tauriInvoke<FileDiff>("workspace_file_diff", {
+      ...(home ? { home } : {}), workspace: wsId, path, maxLines: limit?.max_lines, hunk: limit?.hunk,
+    }),
 
   workspaceFileContent: (home: string | undefined, wsId: string, path: string) =>
     tauriInvoke<string>("workspace_file_content", { ...(home ? { home } : {}), workspace: wsId, path }),
+  workspaceFilePreview: (wsId: string, path: string) =>
+    tauriInvoke<FilePreview>("workspace_file_preview", { workspace: wsId, path }),
+  // Any encoding; decode by the hint, or hex-view binaries
+  workspaceFileBytes: (wsId: string, path: string) =>
+    tauriInvoke<FileBytes>("workspace_file_bytes", { workspace: wsId, path }),
+
+  // Resolves to the new content hash; rejects if the file changed since expectedHash
+  saveFile: (wsId: string, path: string, content: string, expectedHash: string | null) =>
+    tauriInvoke<string>("workspace_file_save", { workspace: wsId, path, content, expectedHash }),
+
+  // File management resolves to whether git staged the change
+  renameFile: (wsId: string, from: string, to: string) =>
+    tauriInvoke<boolean>("workspace_file_rename", { workspace: wsId, from, to }),
+
+  deleteFile: (wsId: string, path: string) =>
+    tauriInvoke<boolean>("workspace_file_delete", { workspace: wsId, path }),
+
+  createDirectory: (wsId: string, path: string) =>
+    tauriInvoke<void>("workspace_dir_create", { workspace: wsId, path }),
+
+  stageFiles: (wsId: string, paths: string[]) =>
+    tauriInvoke<void>("workspace_stage", { workspace: wsId, paths }),
+
+  unstageFiles: (wsId: string, paths: string[]) =>
+    tauriInvoke<void>("workspace_unstage", { workspace: wsId, paths }),
+
+  discardChanges: (wsId: string, paths: string[], includeUntracked: boolean) =>
+    tauriInvoke<void>("workspace_discard", { workspace: wsId, paths, includeUntracked }),
+
+  // Unresolved only unless includeResolved
+  search: (query: string, limit?: number) =>
+    tauriInvoke<SearchHit[]>("search", { query, limit: limit ?? null }),
+
+  reviewComments: (wsId: string, includeResolved: boolean) =>
+    tauriInvoke<ReviewComment[]>("review_comments", { workspace: wsId, includeResolved }),
+
+  addReviewComment: (wsId: string, path: string, startLine: number, endLine: number, body: string) =>
+    tauriInvoke<ReviewComment>("review_comment_add", { workspace: wsId, path, startLine, endLine, body }),
+
+  updateReviewComment: (id: string, update: { body?: string; resolved?: boolean }) =>
+    tauriInvoke<ReviewComment>("review_comment_update", { id, body: update.body ?? null, resolved: update.resolved ?? null }),
+
+  deleteReviewComment: (id: string) =>
+    tauriInvoke<void>("review_comment_delete", { id }),
+
+  // The unresolved comments compiled into a follow-up prompt; null when there are none
+  reviewPrompt: (wsId: string) =>
+    tauriInvoke<string | null>("review_prompt", { workspace: wsId }),
+  // Reviewers' comments from the workspace's GitHub PR; resolves to the new ones
+  syncReviewComments: (wsId: string) =>
+    tauriInvoke<ReviewComment[]>("sync_review_comments", { workspace: wsId }),
 
   resolveHome: (path: string) =>
     tauriInvoke<string>("resolve_home_path", path ? { home: path } : {}),
@@ -56,9 +153,47 @@ export const queryFns = {
   addRepoUrl: (home: string | undefined, url: string) =>
     tauriInvoke<Repo>("add_repo_url", { ...(home ? { home } : {}), url }),
 
+  setWorkspaceState: (wsId: string, state: WorkspaceState) =>
+    tauriInvoke<Workspace>("set_workspace_state", { workspace: wsId, state }),
+
+  setWorkspacePinned: (wsId: string, pinned: boolean) =>
+    tauriInvoke<Workspace>("set_workspace_pinned", { workspace: wsId, pinned }),
+
+  // The listed repos go first, in order; the rest follow newest first
+  reorderRepos: (repoIds: string[]) =>
+    tauriInvoke<Repo[]>("reorder_repos", { repos: repoIds }),
+
   createWorkspace: (home: string | undefined, repoId: string, name?: string) =>
     tauriInvoke<Workspace>("create_workspace", { ...(home ? { home } : {}), repo: repoId, name: name || null }),
 
+  daemonStatus: () =>
+    tauriInvoke<DaemonStatus>("daemon_status"),
+
+  listEngines: () =>
+    tauriInvoke<EngineInfo[]>("list_engines"),
+
+  notificationSettings: () =>
+    tauriInvoke<NotificationSettings>("get_notification_settings"),
+
+  setNotificationSettings: (settings: NotificationSettings) =>
+    tauriInvoke<void>("set_notification_settings", { settings }),
+
+  applyPatch: (wsId: string, patch: string, threeWay: boolean) =>
+    tauriInvoke<ApplyResult>("apply_patch", { workspace: wsId, patch, threeWay }),
+  // Commits what's staged, or everything when nothing is; resolves to the sha
+  commitWorkspace: (wsId: string, message: string) =>
+    tauriInvoke<string>("commit_workspace", { workspace: wsId, message }),
+  suggestCommitMessage: (wsId: string) =>
+    tauriInvoke<string>("suggest_commit_message", { workspace: wsId }),
+
+  // Launches the configured editor; resolves to the program name
+  openWorkspace: (wsId: string) =>
+    tauriInvoke<string>("open_workspace", { workspace: wsId }),
+  openWorkspaceTerminal: (wsId: string) =>
+    tauriInvoke<void>("open_workspace_terminal", { workspace: wsId }),
+  revealWorkspace: (wsId: string) =>
+    tauriInvoke<void>("reveal_workspace", { workspace: wsId }),
+
   // Session persistence
   sessionRead: (wsPath: string) =>
     tauriInvoke<SessionState | null>("session_read", { workspacePath: wsPath }),
@@ -17,8 +17,54 @@ export type Workspace = {
   name: string;
   branch: string;
   base_branch: string;
-  state: string;
+  state: WorkspaceState;
   path: string;
+  // Listed ahead of the repo's other workspaces
+  pinned: boolean;
+  enrichment?: WorkspaceEnrichment;
+  // Set when git can't use the worktree (e.g. the home was moved)
+  link_error?: string;
+  // What went wrong, when state is "error"
+  error?: string;
+  activity?: WorkspaceActivity;
+  // Set after agent runs: what the conversation is about, and where it got to
+  title?: string;
+  summary?: string;
+  verification?: WorkspaceVerification;
+};
+
+// Workspaces with no commits or agent runs for `stale_after` (config.json; null when unset)
+export type StaleWorkspaces = {
+  stale_after: string | null;
+  workspaces: Workspace[];
+};
+
+// The first four are set by hand as work moves along; archive and reconcile set the rest
+export type WorkspaceState = "ready" | "in_review" | "merged" | "abandoned" | "archived" | "error";
+
+export const PIPELINE_STATES = ["ready", "in_review", "merged", "abandoned"] as const satisfies readonly WorkspaceState[];
+
+// How the repo's post-run `verify` commands went after the last agent run
+export type WorkspaceVerification = {
+  ok: boolean;
+  failed_command?: string | null;
+  session_id?: string | null;
+  finished_at: string;
+};
+
+// RFC 3339 times; null until it first happens
+export type WorkspaceActivity = {
+  last_agent_run_at?: string | null;
+  last_commit_at?: string | null;
+  last_opened_at?: string | null;
+};
+
+export type WorkspaceEnrichment = {
+  head: string;
+  dirty: boolean;
+  changed_files: number;
+  ahead: number;
+  behind: number;
 };
 
 export type SessionState = {
@@ -26,6 +72,11 @@ export type SessionState = {
   resume_id?: string | null;
   started_at: string;
   updated_at: string;
+  // What the latest run started from
+  engine_version?: string | null;
+  model?: string | null;
+  base_sha?: string | null;
+  conductor_version?: string | null;
 };
 
 export type WorkspaceChange = {
@@ -34,6 +85,116 @@ export type WorkspaceChange = {
   status: string;
 };
 
+// Files and changes from one status pass
+export type WorkspaceSnapshot = {
+  files: string[];
+  // Dirty path -> porcelain code, as in TreeEntry.status
+  statuses: Record<string, string>;
+  changes: WorkspaceChange[];
+};
+
+export type FileFilter = {
+  prefix?: string;
+  glob?: string;
+  offset?: number;
+  limit?: number;
+};
+
+export type ChatEntry = {
+  role: string;
+  content: string;
+  timestamp: string;
+};
+
+// Fetched in one call when a workspace is selected
+export type WorkspaceSummary = {
+  changes: WorkspaceChange[];
+  session: SessionState | null;
+  last_message: ChatEntry | null;
+  running_agents: string[];
+};
+
+// A note on lines of a workspace file, open until resolved
+export type ReviewComment = {
+  id: string;
+  workspace_id: string;
+  // Empty for a comment on the whole PR
+  file_path: string;
+  // 1-based, inclusive; 0 for a comment on the whole file or PR
+  start_line: number;
+  end_line: number;
+  author: string;
+  body: string;
+  resolved: boolean;
+  created_at: string;
+  updated_at: string;
+  // The comment on GitHub, for comments synced from the PR
+  url?: string;
+};
+
+// One hit of the daemon's global search; workspace_id is unset for repo hits
+export type SearchHit = {
+  kind: "repo" | "workspace" | "file" | "chat";
+  score: number;
+  repo_id: string;
+  repo: string;
+  workspace_id?: string;
+  workspace?: string;
+  // Name, file path in the workspace, or the chat entry's "role (timestamp)"
+  label: string;
+  snippet?: string;
+};
+
+export type ApplyResult = {
+  applied: boolean;
+  files: string[];
+  conflicts: string[];
+  message: string;
+};
+
+export type WorkspaceEvent = {
+  workspace_id: string;
+  type: "files_changed" | "branch_changed" | "conflicts_detected" | "refs_changed";
+  paths: string[];
+  branch?: string | null;
+  head?: string | null;
+};
+
+export type DaemonStatus = {
+  version: string;
+  uptime_secs: number;
+  home: string;
+  db_path: string;
+  schema_version: number;
+  home_disk_bytes: number;
+  repo_count: number;
+  workspace_count: number;
+  archived_workspace_count: number;
+  agents: { session_id: string; engine: string; cwd: string; elapsed_secs: number }[];
+  recent_errors: { timestamp: string; context: string; message: string }[];
+};
+
+// From the daemon's ListEngines; `authenticated` is null when login can't be detected
+export type EngineInfo = {
+  name: string;
+  aliases: string[];
+  program: string;
+  available: boolean;
+  path?: string | null;
+  version?: string | null;
+  authenticated?: boolean | null;
+  supports_resume: boolean;
+  supports_streaming: boolean;
+};
+
+// Per-event toggles for native notifications (config.json `notifications`)
+export type NotificationSettings = {
+  agent_completed: boolean;
+  agent_failed: boolean;
+  approval_requested: boolean;
+  merge_conflict: boolean;
+};
+
 // =============================================================================
 // Chat Types
 // =============================================================================
@@ -59,6 +220,88 @@ export type ActionState = {
   firstSeen: number;
 };
 
+export type DiffLine = {
+  kind: "context" | "add" | "delete";
+  content: string;
+  old_line?: number;
+  new_line?: number;
+  no_newline?: boolean;
+};
+
+export type DiffHunk = {
+  old_start: number;
+  old_lines: number;
+  new_start: number;
+  new_lines: number;
+  header: string;
+  lines: DiffLine[];
+};
+
+export type StructuredDiff = {
+  binary: boolean;
+  hunks: DiffHunk[];
+  // Indexes into the whole diff, as in FileDiff
+  omitted_hunks?: number[];
+};
+
+// A file's patch, cut short past max_lines (it still parses as one)
+export type FileDiff = {
+  diff: string;
+  total_lines: number;
+  // Hunks shortened or left out; each can be fetched whole by index
+  omitted_hunks?: number[];
+};
+
+// How to shape a file diff: a line cap, or one whole hunk
+export type DiffLimit = {
+  max_lines?: number;
+  hunk?: number;
+};
+
+// Totals for the workspace header; diff counts include uncommitted work
+export type WorkspaceStats = {
+  workspace_id: string;
+  commits: number;
+  diff: { files: number; insertions: number; deletions: number };
+  agent_runs: number;
+  usage: { input_tokens: number; output_tokens: number };
+  created_at: string;
+  age_secs: number;
+};
+
+export type LogCommit = {
+  sha: string;
+  author: string;
+  author_email: string;
+  date: string;
+  subject: string;
+  stat: { files: number; insertions: number; deletions: number };
+};
+
+export type TreeEntry = {
+  name: string;
+  path: string;
+  kind: "file" | "dir" | "symlink";
+  size?: number;
+  status?: string;
+};
+
+export type FilePreview = {
+  content: string | null;
+  binary: boolean;
+  total_size: number;
+  truncated: boolean;
+  // Only when content is the whole file; required to save edits
+  hash: string | null;
+};
+
+// A file exactly as stored
+export type FileBytes = {
+  data: number[];
+  encoding: "utf-8" | "binary" | "text";
+  hash: string;
+};
+
 export type AgentEvent = {
   type: string;
   engine?: string;
@@ -69,6 +312,18 @@ export type AgentEvent = {
   error?: string;
   resume?: string;
   session_id?: string;
+  // agent.fallback: the engine that failed; `engine` is the one taking over
+  from?: string;
+  // agent.policy_violation; verification (with command, ok and exit_code or error)
+  rule?: string;
+  decision?: "flag" | "block";
+  command?: string;
+  exit_code?: number | null;
+  // agent.diffstat
+  files?: number;
+  insertions?: number;
+  deletions?: number;
+  untracked?: string[];
   action?: {
     id: string;
     kind: string;